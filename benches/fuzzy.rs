@@ -0,0 +1,103 @@
+//! Fuzzy matching over a large synthetic candidate set.
+//!
+//! Run with `cargo bench --bench fuzzy`. Each query keeps the best `LIMIT`
+//! matches; the run fails if any query's median time exceeds the budget.
+
+use std::time::{Duration, Instant};
+
+#[path = "../src/fuzzy.rs"]
+#[allow(dead_code)]
+mod fuzzy;
+
+const CANDIDATES: usize = 50_000;
+const ITERATIONS: usize = 25;
+/// Matches kept per query, about what a completion menu can show
+const LIMIT: usize = 100;
+/// Per-query budget for filtering the full candidate set
+const BUDGET: Duration = Duration::from_millis(5);
+
+const SEGMENTS: &[&str] = &[
+    "src",
+    "lib",
+    "target",
+    "debug",
+    "node_modules",
+    "config",
+    "docs",
+    "tests",
+    "build",
+    "Cargo",
+    "main",
+    "index",
+    "utils",
+    "daemon",
+    "parser",
+    "README",
+    "assets",
+    "scripts",
+];
+const EXTENSIONS: &[&str] = &["rs", "toml", "md", "json", "ts", "js", "lock", "sh"];
+
+/// Deterministic path-like candidates, so runs are comparable
+fn candidates() -> Vec<String> {
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut next = move |bound: usize| {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        (seed % bound as u64) as usize
+    };
+    (0..CANDIDATES)
+        .map(|i| {
+            let depth = 1 + next(4);
+            let mut path: Vec<&str> = (0..depth).map(|_| SEGMENTS[next(SEGMENTS.len())]).collect();
+            let file = format!(
+                "{}_{i}.{}",
+                SEGMENTS[next(SEGMENTS.len())],
+                EXTENSIONS[next(EXTENSIONS.len())]
+            );
+            path.push(&file);
+            path.join("/")
+        })
+        .collect()
+}
+
+fn main() {
+    let candidates = candidates();
+    let mut over_budget = false;
+
+    for query in [
+        "s",
+        "src",
+        "dmn",
+        "cfgtoml",
+        "srcparsermain",
+        "zzzz",
+        "tests/utils_42",
+    ] {
+        let mut times = Vec::with_capacity(ITERATIONS);
+        let matched = fuzzy::filter(query, &candidates, String::as_str).len();
+        for _ in 0..ITERATIONS {
+            let started = Instant::now();
+            fuzzy::filter_top(query, &candidates, LIMIT, String::as_str).len();
+            times.push(started.elapsed());
+        }
+        times.sort();
+        let median = times[ITERATIONS / 2];
+        let verdict = if median <= BUDGET {
+            "ok"
+        } else {
+            "OVER BUDGET"
+        };
+        over_budget |= median > BUDGET;
+        println!(
+            "{query:>16}: {matched:>6} of {CANDIDATES} matched, median {median:?} (min {:?}) {verdict}",
+            times[0]
+        );
+    }
+
+    if over_budget {
+        eprintln!("fuzzy filtering exceeded the {BUDGET:?} budget");
+        std::process::exit(1);
+    }
+}
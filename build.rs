@@ -1,22 +1,123 @@
-use anyhow::Result;
+use anyhow::{Context, Result, bail};
+use std::collections::BTreeMap;
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 
+/// Checkout (or `build/` directory) of withfig/autocomplete to bundle specs from
+const FIG_SPECS_VAR: &str = "AUTOCOMPLETE_RS_FIG_SPECS";
+/// First bytes of the spec bundle, versioning its layout
+const BUNDLE_MAGIC: &[u8; 8] = b"ACSPEC01";
+
+/// Convert the bundled JSON specs to MessagePack and pack them into the
+/// bundle the spec loader embeds.
+///
+/// The specs in `specs/` are always bundled. With `AUTOCOMPLETE_RS_FIG_SPECS`
+/// pointing at a withfig/autocomplete checkout whose specs have been compiled
+/// (`npm run build`), its specs are exported to JSON with Node and bundled
+/// too; the ones in `specs/` take precedence. The compact specs of everyday
+/// commands in `specs/coreutils/` come last, so a build without Fig still
+/// describes `ls`, `grep` or `tar` well, and one with Fig keeps its fuller
+/// specs of them.
 fn main() -> Result<()> {
     println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=specs");
+    println!("cargo:rerun-if-changed=scripts/fig-specs.mjs");
+    println!("cargo:rerun-if-env-changed={FIG_SPECS_VAR}");
 
-    // TODO: Phase 2 - Parse Fig TypeScript specs at build time
-    // 1. Clone withfig/autocomplete repo if not exists
-    // 2. Parse TypeScript specs using deno_ast
-    // 3. Convert to Rust structs or MessagePack format
-    // 4. Embed in binary for runtime use
-
-    // For now, we'll create a placeholder
     let out_dir = PathBuf::from(env::var("OUT_DIR")?);
-    let specs_path = out_dir.join("specs");
-    std::fs::create_dir_all(&specs_path)?;
 
-    println!("Build script completed. Spec parsing will be implemented in Phase 2.");
+    // Command name → MessagePack spec, sorted for binary search
+    let mut specs = BTreeMap::new();
+    convert_dir(Path::new("specs"), &mut specs)?;
+    if let Some(fig) = env::var_os(FIG_SPECS_VAR) {
+        let exported = export_fig_specs(Path::new(&fig), &out_dir.join("fig"))?;
+        convert_dir(&exported, &mut specs)?;
+    }
+    convert_dir(Path::new("specs/coreutils"), &mut specs)?;
+    std::fs::write(out_dir.join("specs.bundle"), bundle(&specs)?)?;
 
     Ok(())
 }
+
+/// Lay out `specs` as one blob the loader can search without decoding:
+///
+/// ```text
+/// magic     b"ACSPEC01"
+/// count     u32
+/// entries   count × (name offset, name length, spec offset, spec length), u32 each
+/// data      names and specs, at the offsets above
+/// ```
+///
+/// Numbers are little-endian and offsets count from the start of the blob.
+/// Unlike a table of `include_bytes!` slices, the blob needs no relocations
+/// when the binary is loaded, however many specs it holds.
+fn bundle(specs: &BTreeMap<String, Vec<u8>>) -> Result<Vec<u8>> {
+    const ENTRY_LEN: usize = 16;
+    let mut index = Vec::with_capacity(12 + specs.len() * ENTRY_LEN);
+    index.extend_from_slice(BUNDLE_MAGIC);
+    index.extend_from_slice(&u32::try_from(specs.len())?.to_le_bytes());
+
+    let data_start = index.len() + specs.len() * ENTRY_LEN;
+    let mut data = Vec::new();
+    for (name, spec) in specs {
+        for part in [name.as_bytes(), spec.as_slice()] {
+            let offset = u32::try_from(data_start + data.len())?;
+            index.extend_from_slice(&offset.to_le_bytes());
+            index.extend_from_slice(&u32::try_from(part.len())?.to_le_bytes());
+            data.extend_from_slice(part);
+        }
+    }
+    index.append(&mut data);
+    Ok(index)
+}
+
+/// Convert every `<command>.json` in `dir` not already in `specs`
+fn convert_dir(dir: &Path, specs: &mut BTreeMap<String, Vec<u8>>) -> Result<()> {
+    let entries =
+        std::fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        let Some(name) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".json"))
+        else {
+            continue;
+        };
+        if specs.contains_key(name) {
+            continue;
+        }
+        let json = std::fs::read(&path)?;
+        let value: serde_json::Value = serde_json::from_slice(&json)
+            .with_context(|| format!("Invalid JSON in {}", path.display()))?;
+        specs.insert(name.to_string(), rmp_serde::to_vec(&value)?);
+    }
+    Ok(())
+}
+
+/// Export the compiled specs of a Fig checkout to JSON files in `out`.
+///
+/// Fig specs are TypeScript modules that compute parts of themselves (spread
+/// helpers, shared constants), so they are evaluated rather than parsed: the
+/// checkout's `npm run build` compiles them to JavaScript, and Node imports
+/// each one and writes out the data.
+fn export_fig_specs(fig: &Path, out: &Path) -> Result<PathBuf> {
+    let build = if fig.join("build").is_dir() {
+        fig.join("build")
+    } else {
+        fig.to_path_buf()
+    };
+    println!("cargo:rerun-if-changed={}", build.display());
+
+    let status = Command::new("node")
+        .arg("scripts/fig-specs.mjs")
+        .arg(&build)
+        .arg(out)
+        .status()
+        .context("Failed to run node, which exporting Fig specs needs")?;
+    if !status.success() {
+        bail!("Exporting Fig specs from {} failed", build.display());
+    }
+    Ok(out.to_path_buf())
+}
@@ -0,0 +1,83 @@
+// Export compiled Fig completion specs as JSON the spec engine understands.
+//
+// Usage: node scripts/fig-specs.mjs <withfig/autocomplete build dir> <out dir>
+//
+// Each top-level `<command>.js` module is imported and its default export
+// written to `<out dir>/<command>.json`; `<command>.ts` sources are imported
+// too when Node strips their types (`--experimental-strip-types`). JavaScript
+// functions (`postProcess`, `custom`, function scripts) can't be carried over
+// and are dropped, as are templates other than `filepaths` and `folders` and
+// suggestions without a name. Run by build.rs when AUTOCOMPLETE_RS_FIG_SPECS
+// is set, and by `autocomplete-rs spec convert`.
+
+import { mkdirSync, readdirSync, writeFileSync } from "node:fs";
+import { join, resolve } from "node:path";
+import { pathToFileURL } from "node:url";
+
+const TEMPLATES = new Set(["filepaths", "folders"]);
+
+const [buildDir, outDir] = process.argv.slice(2);
+if (!buildDir || !outDir) {
+  console.error("usage: fig-specs.mjs <build dir> <out dir>");
+  process.exit(2);
+}
+mkdirSync(outDir, { recursive: true });
+
+let exported = 0;
+let skipped = 0;
+for (const file of readdirSync(buildDir).sort()) {
+  const extension = [".js", ".ts"].find((ext) => file.endsWith(ext));
+  if (!extension || file.endsWith(".d.ts") || file.startsWith("_")) {
+    continue;
+  }
+  const command = file.slice(0, -extension.length);
+  let spec;
+  try {
+    spec = (await import(pathToFileURL(resolve(buildDir, file)))).default;
+  } catch (error) {
+    console.error(`skipping ${file}: ${error.message}`);
+    skipped++;
+    continue;
+  }
+  // Versioned specs export a function of the installed version instead
+  if (typeof spec !== "object" || spec === null || spec.name === undefined) {
+    skipped++;
+    continue;
+  }
+  writeFileSync(join(outDir, `${command}.json`), JSON.stringify(clean(spec)));
+  exported++;
+}
+console.error(`exported ${exported} Fig specs, skipped ${skipped}`);
+
+// Drop what the spec engine can't use, keeping the rest of the shape as is
+function clean(value) {
+  if (Array.isArray(value)) {
+    return value.map(clean).filter((item) => item !== undefined);
+  }
+  if (typeof value === "function") {
+    return undefined;
+  }
+  if (typeof value !== "object" || value === null) {
+    return value;
+  }
+  const result = {};
+  for (const [key, field] of Object.entries(value)) {
+    if (key === "template") {
+      const templates = [field].flat().filter((t) => TEMPLATES.has(t));
+      if (templates.length > 0) {
+        result.template = templates;
+      }
+    } else if (key === "suggestions") {
+      result.suggestions = [field]
+        .flat()
+        .filter((s) => typeof s === "string" || (s && s.name !== undefined))
+        .map(clean);
+    } else {
+      const cleaned = clean(field);
+      if (cleaned !== undefined) {
+        result[key] = cleaned;
+      }
+    }
+  }
+  return result;
+}
@@ -0,0 +1,148 @@
+use crate::daemon::{
+    self, Accepted, AcceptedRequest, BatchRequest, BatchResponse, CompletionRequest,
+    CompletionResponse, ContextRequest, ContextResponse, ControlCommand, ControlRequest,
+    ControlResponse, Endpoint, EventsRequest, StatusRequest, StatusResponse, VersionedResponse,
+    Visited, VisitedRequest,
+};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+use std::path::Path;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+/// Environment variables forwarded to the daemon with each request.
+///
+/// Only variables that change what completions are correct are sent; the
+/// rest of the environment (tokens, credentials) never leaves the client.
+const FORWARDED_ENV: &[&str] = &[
+    "HOME",
+    "PATH",
+    "PWD",
+    "OLDPWD",
+    "USER",
+    "SHELL",
+    "CDPATH",
+    "GIT_DIR",
+    "GIT_WORK_TREE",
+    "VIRTUAL_ENV",
+    "CONDA_DEFAULT_ENV",
+    "KUBECONFIG",
+    "DOCKER_HOST",
+    "DOCKER_CONTEXT",
+    "AWS_PROFILE",
+    "AWS_REGION",
+];
+
+/// The subset of `vars` forwarded to the daemon
+pub fn context_env(vars: impl Iterator<Item = (String, String)>) -> BTreeMap<String, String> {
+    vars.filter(|(name, _)| FORWARDED_ENV.contains(&name.as_str()))
+        .collect()
+}
+
+/// Send a completion request to the daemon and wait for its response
+pub async fn request(
+    socket_path: &Path,
+    request: &CompletionRequest,
+) -> Result<CompletionResponse> {
+    round_trip(socket_path, request).await
+}
+
+/// Send several completion requests in one round trip, returning the
+/// responses in the same order
+pub async fn batch(
+    socket_path: &Path,
+    requests: Vec<CompletionRequest>,
+) -> Result<Vec<CompletionResponse>> {
+    let response: BatchResponse =
+        round_trip(socket_path, &BatchRequest { batch: requests }).await?;
+    Ok(response
+        .responses
+        .into_iter()
+        .map(VersionedResponse::into_latest)
+        .collect())
+}
+
+/// Ask the daemon what the command line of `request` expects at the cursor
+pub async fn context(socket_path: &Path, request: CompletionRequest) -> Result<ContextResponse> {
+    round_trip(socket_path, &ContextRequest { context: request }).await
+}
+
+/// Send a control message to the daemon
+pub async fn control(socket_path: &Path, command: ControlCommand) -> Result<ControlResponse> {
+    round_trip(socket_path, &ControlRequest { control: command }).await
+}
+
+/// Ask the daemon how it is doing
+pub async fn status(socket_path: &Path) -> Result<StatusResponse> {
+    round_trip(socket_path, &StatusRequest { status: true }).await
+}
+
+/// Tell the daemon a suggestion was accepted, for its hooks and subscribers
+pub async fn accepted(socket_path: &Path, accepted: Accepted) -> Result<ControlResponse> {
+    round_trip(socket_path, &AcceptedRequest { accepted }).await
+}
+
+/// Tell the daemon the shell changed to a directory, for completing `cd`
+pub async fn visited(socket_path: &Path, visited: Visited) -> Result<ControlResponse> {
+    round_trip(socket_path, &VisitedRequest { visited }).await
+}
+
+/// Subscribe to the daemon's events named in `wanted`, or all of them, and
+/// hand each JSON line to `on_event` until the daemon hangs up
+pub async fn events(
+    socket_path: &Path,
+    wanted: Vec<String>,
+    mut on_event: impl FnMut(&str) -> Result<()>,
+) -> Result<()> {
+    let endpoint = endpoint(socket_path)?;
+    let stream = daemon::connect(&endpoint)
+        .await
+        .context("Failed to connect to daemon. Is it running?")?;
+    let (reader, mut writer) = tokio::io::split(stream);
+    let request_json = serde_json::to_string(&EventsRequest { events: wanted })?;
+    writer.write_all(request_json.as_bytes()).await?;
+    writer.write_all(b"\n").await?;
+    writer.flush().await?;
+
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        on_event(&line)?;
+    }
+    Ok(())
+}
+
+/// The endpoint a `--socket` value names
+fn endpoint(socket_path: &Path) -> Result<Endpoint> {
+    match socket_path.to_str() {
+        Some(socket) => socket.parse(),
+        None => Ok(Endpoint::Unix(socket_path.to_path_buf())),
+    }
+}
+
+/// Write one JSON line to the daemon and parse the single-line reply
+async fn round_trip<T: Serialize, R: DeserializeOwned>(
+    socket_path: &Path,
+    message: &T,
+) -> Result<R> {
+    // Connect to daemon; the socket may also be an `abstract:` or `tcp:` endpoint
+    let endpoint = endpoint(socket_path)?;
+    let stream = daemon::connect(&endpoint)
+        .await
+        .context("Failed to connect to daemon. Is it running?")?;
+
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut reader = BufReader::new(reader);
+
+    // Send request
+    let request_json = serde_json::to_string(message)?;
+    writer.write_all(request_json.as_bytes()).await?;
+    writer.write_all(b"\n").await?;
+    writer.flush().await?;
+
+    // Read response
+    let mut response_line = String::new();
+    reader.read_line(&mut response_line).await?;
+
+    serde_json::from_str(&response_line).context("Failed to parse daemon response")
+}
@@ -0,0 +1,147 @@
+use super::{Config, LoadedConfig, join, toml};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Problem found while validating a config file
+#[derive(Debug)]
+pub struct Issue {
+    /// Dotted key path the issue refers to
+    pub key: String,
+    /// Line in the config file, when known
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: `{}`: {}", line, self.key, self.message),
+            None => write!(f, "`{}`: {}", self.key, self.message),
+        }
+    }
+}
+
+/// Validate config file contents against the config schema.
+///
+/// Reports syntax errors, unknown keys, and values of the wrong type. Every
+/// leaf is checked on its own so one bad value doesn't hide the others.
+pub fn check(contents: &str) -> Vec<Issue> {
+    let (value, lines) = match toml::parse_with_lines(contents) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            return vec![Issue {
+                key: String::new(),
+                line: Some(e.line),
+                message: e.message,
+            }];
+        }
+    };
+
+    let schema = serde_json::to_value(Config::default()).unwrap_or_default();
+    let mut issues = Vec::new();
+    check_value(&value, &schema, String::new(), &lines, &mut issues);
+    issues.sort_by_key(|issue| issue.line);
+    issues
+}
+
+fn check_value(
+    value: &Value,
+    schema: &Value,
+    prefix: String,
+    lines: &HashMap<String, usize>,
+    issues: &mut Vec<Issue>,
+) {
+    match (value, schema) {
+        // An empty object in the defaults is a free-form map: each entry is
+        // type-checked but any key is allowed
+        (Value::Object(map), Value::Object(fields)) if !fields.is_empty() => {
+            for (key, child) in map {
+                let path = join(&prefix, key);
+                match fields.get(key) {
+                    Some(child_schema) => check_value(child, child_schema, path, lines, issues),
+                    None => issues.push(Issue {
+                        line: lines.get(&path).copied(),
+                        key: path,
+                        message: "unknown key".to_string(),
+                    }),
+                }
+            }
+        }
+        (Value::Object(map), Value::Object(_)) => {
+            for (key, child) in map {
+                check_leaf(child, join(&prefix, key), lines, issues);
+            }
+        }
+        _ => check_leaf(value, prefix, lines, issues),
+    }
+}
+
+/// Deserialize a config containing only this one value to surface type errors
+fn check_leaf(
+    value: &Value,
+    path: String,
+    lines: &HashMap<String, usize>,
+    issues: &mut Vec<Issue>,
+) {
+    let single = path.rsplit('.').fold(value.clone(), |inner, key| {
+        let mut map = Map::new();
+        map.insert(key.to_string(), inner);
+        Value::Object(map)
+    });
+    if let Err(e) = serde_json::from_value::<Config>(single) {
+        issues.push(Issue {
+            line: lines.get(&path).copied(),
+            key: path,
+            message: e.to_string(),
+        });
+    }
+}
+
+/// Render the effective configuration as TOML, annotating each value with its origin
+pub fn render(loaded: &LoadedConfig) -> String {
+    let mut out = String::new();
+    if let Some(path) = &loaded.path {
+        let _ = writeln!(out, "# Config file: {}", path.display());
+    } else {
+        let _ = writeln!(out, "# No config file found, showing defaults");
+    }
+    for name in &loaded.unknown_env {
+        let _ = writeln!(out, "# {} matches no config key and is ignored", name);
+    }
+    if let Value::Object(root) = &loaded.value {
+        render_table(root, String::new(), loaded, &mut out);
+    }
+    out
+}
+
+fn render_table(
+    table: &Map<String, Value>,
+    prefix: String,
+    loaded: &LoadedConfig,
+    out: &mut String,
+) {
+    let (tables, values): (Vec<_>, Vec<_>) = table.iter().partition(|(_, value)| value.is_object());
+
+    if !prefix.is_empty() {
+        let _ = writeln!(out, "\n[{}]", prefix);
+    }
+    for (key, value) in values {
+        let path = join(&prefix, key);
+        let origin = loaded
+            .provenance
+            .get(&path)
+            .map(ToString::to_string)
+            .unwrap_or_default();
+        if value.is_null() {
+            let _ = writeln!(out, "# {} is unset  # {}", key, origin);
+        } else {
+            let _ = writeln!(out, "{} = {}  # {}", key, toml::to_inline(value), origin);
+        }
+    }
+    for (key, value) in tables {
+        if let Value::Object(map) = value {
+            render_table(map, join(&prefix, key), loaded, out);
+        }
+    }
+}
@@ -0,0 +1,191 @@
+//! Upgrades of on-disk state written by older versions.
+//!
+//! The config file records the schema version it was written for as a
+//! top-level `version` key (files without one are version 0), and the cache
+//! directory holds a `format` file naming its layout. On daemon startup and
+//! with `autocomplete-rs migrate`, anything older is brought up to date:
+//!
+//! - config files are rewritten step by step, one [`CONFIG_STEPS`] entry per
+//!   version, after a backup is taken;
+//! - caches in an older format are cleared, since they are rebuilt anyway;
+//! - files left at locations older versions used are cleaned up.
+//!
+//! To change the config schema, bump [`CONFIG_VERSION`] and append a step
+//! that rewrites the previous version's document.
+
+use super::toml;
+use crate::{install, paths};
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// Config schema version written by this build
+pub const CONFIG_VERSION: u32 = 1;
+
+/// Cache layout version written by this build
+pub const CACHE_FORMAT: u32 = 1;
+
+/// Name of the file recording the cache layout version
+const CACHE_FORMAT_FILE: &str = "format";
+
+/// Socket location used before sockets moved to the runtime directory
+const LEGACY_SOCKET: &str = "/tmp/autocomplete-rs.sock";
+
+/// Rewrites a config document from version `i` to `i + 1`
+type Step = fn(&str) -> String;
+
+/// Config rewrites, indexed by the version they upgrade from
+const CONFIG_STEPS: [Step; CONFIG_VERSION as usize] = [stamp_version_1];
+
+/// A pending change to on-disk state
+#[derive(Debug)]
+pub enum Action {
+    /// Rewrite the config file from schema version `from`
+    UpgradeConfig { path: PathBuf, from: u32 },
+    /// Remove everything in a cache directory of an older format
+    ClearCache { dir: PathBuf, from: u32 },
+    /// Delete a stale file at a location older versions used
+    RemoveLegacy(PathBuf),
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Action::UpgradeConfig { path, from } => write!(
+                f,
+                "upgrade {} from config version {} to {}",
+                path.display(),
+                from,
+                CONFIG_VERSION
+            ),
+            Action::ClearCache { dir, from } => write!(
+                f,
+                "clear {} (cache format {} → {})",
+                dir.display(),
+                from,
+                CACHE_FORMAT
+            ),
+            Action::RemoveLegacy(path) => write!(f, "remove stale {}", path.display()),
+        }
+    }
+}
+
+/// Work needed to bring the on-disk state up to date
+pub fn plan() -> Result<Vec<Action>> {
+    let mut actions = Vec::new();
+
+    if let Some(path) = paths::config_file().filter(|path| path.exists()) {
+        let contents = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+        let from = config_version(&contents)
+            .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
+        if from > CONFIG_VERSION {
+            anyhow::bail!(
+                "{} is for config version {}, but this autocomplete-rs only understands up to {}; \
+                 upgrade autocomplete-rs",
+                path.display(),
+                from,
+                CONFIG_VERSION
+            );
+        }
+        if from < CONFIG_VERSION {
+            actions.push(Action::UpgradeConfig { path, from });
+        }
+    }
+
+    if let Some(dir) = paths::cache_dir().filter(|dir| dir.is_dir()) {
+        let from = cache_format(&dir);
+        if from != CACHE_FORMAT {
+            actions.push(Action::ClearCache { dir, from });
+        }
+    }
+
+    let legacy_socket = PathBuf::from(LEGACY_SOCKET);
+    if legacy_socket != paths::socket_path() && is_stale_socket(&legacy_socket) {
+        actions.push(Action::RemoveLegacy(legacy_socket));
+    }
+
+    Ok(actions)
+}
+
+/// Carry out `action`
+pub fn apply(action: &Action) -> Result<()> {
+    match action {
+        Action::UpgradeConfig { path, from } => {
+            let mut contents = std::fs::read_to_string(path)?;
+            for step in &CONFIG_STEPS[*from as usize..] {
+                contents = step(&contents);
+            }
+            install::backup_file(path)?;
+            std::fs::write(path, contents)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+        Action::ClearCache { dir, .. } => {
+            for entry in std::fs::read_dir(dir)?.flatten() {
+                let path = entry.path();
+                let removed = if path.is_dir() {
+                    std::fs::remove_dir_all(&path)
+                } else {
+                    std::fs::remove_file(&path)
+                };
+                removed.with_context(|| format!("Failed to remove {}", path.display()))?;
+            }
+            write_cache_format(dir)?;
+        }
+        Action::RemoveLegacy(path) => std::fs::remove_file(path)
+            .with_context(|| format!("Failed to remove {}", path.display()))?,
+    }
+    Ok(())
+}
+
+/// Apply every pending action, as done on daemon startup; returns what was done
+pub fn run() -> Result<Vec<Action>> {
+    let actions = plan()?;
+    for action in &actions {
+        apply(action).with_context(|| format!("Failed to {}", action))?;
+    }
+    if let Some(dir) = paths::cache_dir() {
+        write_cache_format(&dir)?;
+    }
+    Ok(actions)
+}
+
+/// Record the current cache format, so a fresh cache isn't mistaken for an old one
+fn write_cache_format(dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir)?;
+    std::fs::write(dir.join(CACHE_FORMAT_FILE), format!("{}\n", CACHE_FORMAT))
+        .with_context(|| format!("Failed to write cache format in {}", dir.display()))
+}
+
+fn config_version(contents: &str) -> Result<u32> {
+    let value = toml::parse(contents)?;
+    match value.get("version") {
+        None => Ok(0),
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .context("`version` must be a non-negative integer"),
+    }
+}
+
+/// Cache format recorded in `dir`; caches from before formats were recorded are 0
+fn cache_format(dir: &Path) -> u32 {
+    std::fs::read_to_string(dir.join(CACHE_FORMAT_FILE))
+        .ok()
+        .and_then(|contents| contents.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// A socket file nobody is listening on
+fn is_stale_socket(path: &Path) -> bool {
+    path.exists() && std::os::unix::net::UnixStream::connect(path).is_err()
+}
+
+/// Version 0 → 1: the schema is unchanged, the file just starts recording its version
+fn stamp_version_1(contents: &str) -> String {
+    let version = Value::from(1);
+    if contents.trim().is_empty() {
+        return format!("version = {}\n", toml::to_inline(&version));
+    }
+    format!("version = {}\n\n{}", toml::to_inline(&version), contents)
+}
@@ -0,0 +1,831 @@
+use crate::daemon::SuggestionSource;
+use crate::generators::GeneratorFamily;
+use crate::paths;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+pub mod inspect;
+pub mod migrate;
+pub mod toml;
+
+/// User configuration loaded from `$XDG_CONFIG_HOME/autocomplete-rs/config.toml`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Schema version the file was written for, see [`migrate`]
+    pub version: u32,
+    /// General settings
+    pub general: GeneralConfig,
+    /// Maximum number of suggestions each source may contribute
+    pub source_limits: BTreeMap<SuggestionSource, usize>,
+    /// Colors used by the completion popup
+    pub theme: ThemeConfig,
+    /// Key bindings installed by the shell integration
+    pub keybindings: KeybindingsConfig,
+    /// Daemon behavior
+    pub daemon: DaemonConfig,
+    /// Whole-command predictions on an empty buffer
+    pub predictions: PredictionsConfig,
+    /// Abbreviation → expansion, e.g. `gco = "git checkout"`
+    pub abbreviations: BTreeMap<String, String>,
+    /// Time budgets of the individual suggestion sources
+    pub timeouts: TimeoutsConfig,
+    /// Example invocations after a command name
+    pub examples: ExamplesConfig,
+    /// Whole commands from the shell's history file
+    pub history: HistoryConfig,
+    /// Visited directories offered for `cd`
+    pub directories: DirectoriesConfig,
+    /// How much usage data to keep
+    pub retention: RetentionConfig,
+    /// How suggestions are ordered
+    pub ranking: RankingConfig,
+    /// Commands run on completion events
+    pub hooks: HooksConfig,
+    /// Output for screen readers and dumb terminals
+    pub accessibility: AccessibilityConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            version: migrate::CONFIG_VERSION,
+            general: GeneralConfig::default(),
+            source_limits: BTreeMap::new(),
+            theme: ThemeConfig::default(),
+            keybindings: KeybindingsConfig::default(),
+            daemon: DaemonConfig::default(),
+            predictions: PredictionsConfig::default(),
+            abbreviations: BTreeMap::new(),
+            timeouts: TimeoutsConfig::default(),
+            examples: ExamplesConfig::default(),
+            history: HistoryConfig::default(),
+            directories: DirectoriesConfig::default(),
+            retention: RetentionConfig::default(),
+            ranking: RankingConfig::default(),
+            hooks: HooksConfig::default(),
+            accessibility: AccessibilityConfig::default(),
+        }
+    }
+}
+
+/// `[general]` section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GeneralConfig {
+    /// Maximum number of suggestions returned for a single request
+    pub max_suggestions: usize,
+    /// Locale for descriptions, overriding the one reported by the shell
+    pub locale: Option<String>,
+}
+
+impl Default for GeneralConfig {
+    fn default() -> Self {
+        Self {
+            max_suggestions: 10,
+            locale: None,
+        }
+    }
+}
+
+/// `[theme]` section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThemeConfig {
+    /// Built-in theme name: mocha, macchiato, frappe, latte
+    pub name: String,
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        Self {
+            name: "mocha".to_string(),
+        }
+    }
+}
+
+/// `[accessibility]` section
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccessibilityConfig {
+    /// List suggestions as numbered plain text and read the number of the
+    /// choice, instead of drawing the popup
+    pub plain: bool,
+}
+
+/// `[keybindings]` section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeybindingsConfig {
+    /// Key sequence that triggers completion, in zsh `bindkey` notation
+    pub trigger: String,
+}
+
+impl Default for KeybindingsConfig {
+    fn default() -> Self {
+        Self {
+            trigger: "^[ ".to_string(),
+        }
+    }
+}
+
+/// `[daemon]` section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DaemonConfig {
+    /// Commands whose specs are loaded in the background right after startup
+    pub warm_up: Vec<String>,
+    /// Also warm up this many of the most frequently completed commands
+    pub warm_up_from_usage: usize,
+    /// Rebuild the command index this often, in seconds (0 disables refreshing)
+    pub index_refresh_secs: u64,
+    /// Maximum number of generator commands running at once
+    pub generator_concurrency: usize,
+    /// Kill a generator (and everything it spawned) after this many milliseconds
+    pub generator_timeout_ms: u64,
+    /// Reuse a generator's output for this many milliseconds, in the same
+    /// directory, unless its spec says otherwise (0 runs it every time)
+    pub generator_cache_ttl_ms: u64,
+    /// Answer the git spec's generators from the files in `.git`, running
+    /// `git` only for what can't be read there
+    pub native_git: bool,
+    /// Answer the docker spec's generators through the Docker daemon's
+    /// socket and the compose file, running `docker` only when they can't be
+    pub native_docker: bool,
+    /// Endpoints served in addition to the main socket: `unix:/path`,
+    /// `abstract:name` (Linux) or `tcp:host:port`
+    pub listen: Vec<String>,
+    /// Complete commands without a spec from the options in their man page
+    pub man_page_specs: bool,
+    /// Remember the values given to free-form flags (`--profile`,
+    /// `--namespace`) in successful commands, and suggest them again
+    pub remember_flag_values: bool,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self {
+            warm_up: Vec::new(),
+            warm_up_from_usage: 10,
+            index_refresh_secs: 3600,
+            generator_concurrency: 4,
+            generator_timeout_ms: 2000,
+            generator_cache_ttl_ms: 2000,
+            native_git: true,
+            native_docker: true,
+            listen: Vec::new(),
+            man_page_specs: true,
+            remember_flag_values: true,
+        }
+    }
+}
+
+/// `[predictions]` section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PredictionsConfig {
+    /// Record executed commands and predict whole commands on an empty buffer
+    pub enabled: bool,
+    /// Maximum number of predicted commands shown
+    pub max_predictions: usize,
+}
+
+impl Default for PredictionsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_predictions: 5,
+        }
+    }
+}
+
+/// `[examples]` section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExamplesConfig {
+    /// Offer example invocations when the buffer is just a command name
+    pub enabled: bool,
+    /// Maximum number of examples shown
+    pub max_examples: usize,
+}
+
+impl Default for ExamplesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_examples: 5,
+        }
+    }
+}
+
+/// `[history]` section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    /// Offer commands from the zsh history that start like the buffer
+    pub enabled: bool,
+    /// Maximum number of commands from the history shown
+    pub max_commands: usize,
+    /// History file to read instead of `$HISTFILE` or `~/.zsh_history`
+    pub file: Option<PathBuf>,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_commands: 5,
+            file: None,
+        }
+    }
+}
+
+/// `[directories]` section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DirectoriesConfig {
+    /// Offer the directories the shell changed to before for `cd` and `pushd`
+    pub enabled: bool,
+    /// Maximum number of visited directories shown
+    pub max_directories: usize,
+}
+
+impl Default for DirectoriesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_directories: 5,
+        }
+    }
+}
+
+/// `[retention]` section: limits on the usage stats and command history,
+/// enforced by `stats prune` and hourly by the daemon
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionConfig {
+    /// Forget commands not used in this many days (0 keeps them forever)
+    pub max_age_days: u64,
+    /// Commands with usage stats kept, and directories and pick contexts, the
+    /// least frecent dropped first
+    pub max_commands: usize,
+    /// Recorded commands kept for predictions, the least used dropped first
+    pub max_history_entries: usize,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            max_age_days: 365,
+            max_commands: 5000,
+            max_history_entries: 2000,
+        }
+    }
+}
+
+/// `[ranking]` section: how much each signal counts towards a suggestion's
+/// place, every signal being between 0 and 1
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RankingConfig {
+    /// Position among the suggestions gathered, which follows the spec
+    pub order: f64,
+    /// How well the typed word matches, relative to the best match
+    pub fuzzy: f64,
+    /// The weight of the suggestion's source, from `sources`
+    pub source: f64,
+    /// How often and recently the command is used, or after it the
+    /// suggestion picked, relative to the most used
+    pub frecency: f64,
+    /// Weight per source; sources without an entry count 1
+    pub sources: BTreeMap<SuggestionSource, f64>,
+}
+
+impl Default for RankingConfig {
+    fn default() -> Self {
+        Self {
+            order: 0.2,
+            fuzzy: 1.0,
+            source: 0.1,
+            frecency: 0.3,
+            sources: BTreeMap::new(),
+        }
+    }
+}
+
+/// `[hooks]` section: shell commands run by the daemon on an event, each
+/// given the event as one line of JSON on stdin
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HooksConfig {
+    /// Run when a suggestion is accepted
+    pub accepted: Vec<String>,
+    /// Run the first time a command without a spec is completed
+    pub unknown_command: Vec<String>,
+}
+
+/// `[timeouts]` section: how long a source may take before its results are
+/// left out of the response, in milliseconds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimeoutsConfig {
+    /// Looking up recorded commands for predictions
+    pub history_ms: u64,
+    /// Reading directories for path completion
+    pub files_ms: u64,
+    /// Asking external completion engines
+    pub bridges_ms: u64,
+    /// Per generator family (`git`, `docker`, `kubernetes`), overriding
+    /// `daemon.generator_timeout_ms`
+    pub generators: BTreeMap<GeneratorFamily, u64>,
+}
+
+impl Default for TimeoutsConfig {
+    fn default() -> Self {
+        Self {
+            history_ms: 20,
+            files_ms: 50,
+            bridges_ms: 500,
+            generators: BTreeMap::new(),
+        }
+    }
+}
+
+/// Where an effective config value came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provenance {
+    /// Built-in default
+    Default,
+    /// The user's config file
+    File,
+    /// An `AUTOCOMPLETE_RS_*` environment variable
+    Env,
+    /// A command-line option, such as `--plain`
+    Flag,
+}
+
+impl std::fmt::Display for Provenance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Provenance::Default => write!(f, "default"),
+            Provenance::File => write!(f, "file"),
+            Provenance::Env => write!(f, "env"),
+            Provenance::Flag => write!(f, "flag"),
+        }
+    }
+}
+
+/// Effective configuration together with the origin of every value
+#[derive(Debug)]
+pub struct LoadedConfig {
+    pub config: Config,
+    /// Config file that was read, if any
+    pub path: Option<PathBuf>,
+    /// Origin of each leaf value, keyed by dotted path (`general.max_suggestions`)
+    pub provenance: BTreeMap<String, Provenance>,
+    /// Merged value tree the config was deserialized from
+    pub value: Value,
+    /// `AUTOCOMPLETE_RS_*` variables that match no config key
+    pub unknown_env: Vec<String>,
+}
+
+impl Config {
+    /// Load the config from the default location, falling back to defaults
+    /// when no config file exists
+    pub fn load() -> Result<Self> {
+        Ok(Self::load_layered(None, Value::Null)?.config)
+    }
+
+    /// Resolve the effective config: defaults, then the config file, then
+    /// `AUTOCOMPLETE_RS_*` environment variables, then `flags`, an overlay
+    /// of the command-line options that override config keys (see
+    /// [`flag_overlay`])
+    pub fn load_layered(path: Option<&Path>, flags: Value) -> Result<LoadedConfig> {
+        let mut value = serde_json::to_value(Config::default())?;
+        let schema = value.clone();
+        let mut provenance = BTreeMap::new();
+        record_leaves(&value, String::new(), Provenance::Default, &mut provenance);
+
+        let path = path.map(Path::to_path_buf).or_else(paths::config_file);
+        let path = path.filter(|path| path.exists());
+        if let Some(path) = &path {
+            let file_value = read_file(path)?;
+            merge(
+                &mut value,
+                file_value,
+                String::new(),
+                Provenance::File,
+                &mut provenance,
+            );
+        }
+
+        let (env_value, unknown_env) = env_overlay(std::env::vars(), &schema);
+        for name in &unknown_env {
+            tracing::warn!("{} matches no config key and is ignored", name);
+        }
+        merge(
+            &mut value,
+            env_value,
+            String::new(),
+            Provenance::Env,
+            &mut provenance,
+        );
+        if flags.is_object() {
+            merge(
+                &mut value,
+                flags,
+                String::new(),
+                Provenance::Flag,
+                &mut provenance,
+            );
+        }
+        // Keys the schema doesn't know have no effect, so they aren't shown as if they had
+        retain_schema(&mut value, &schema, String::new(), &mut provenance);
+
+        let config = serde_json::from_value(value.clone()).with_context(|| match &path {
+            Some(path) => format!("Invalid configuration (file: {})", path.display()),
+            None => "Invalid configuration".to_string(),
+        })?;
+
+        Ok(LoadedConfig {
+            config,
+            path,
+            provenance,
+            value,
+            unknown_env,
+        })
+    }
+}
+
+/// Overlay setting each dotted config key in `overrides` to its value, for
+/// the command-line options that were given
+pub fn flag_overlay<'a>(overrides: impl IntoIterator<Item = (&'a str, Value)>) -> Value {
+    let mut overlay = Value::Object(Default::default());
+    for (path, value) in overrides {
+        insert_path(&mut overlay, path, value);
+    }
+    overlay
+}
+
+/// Set dotted `path` in `root` to `value`, creating the tables on the way
+fn insert_path(root: &mut Value, path: &str, value: Value) {
+    let mut table = root;
+    let mut keys = path.split('.').peekable();
+    while let Some(key) = keys.next() {
+        let Value::Object(map) = table else { break };
+        if keys.peek().is_none() {
+            map.insert(key.to_string(), value);
+            break;
+        }
+        table = map
+            .entry(key.to_string())
+            .or_insert_with(|| Value::Object(Default::default()));
+    }
+}
+
+/// Drop the keys of `value` that `schema` doesn't have, with their
+/// provenance; free-form maps (empty in the schema) keep every key
+fn retain_schema(
+    value: &mut Value,
+    schema: &Value,
+    prefix: String,
+    provenance: &mut BTreeMap<String, Provenance>,
+) {
+    let (Value::Object(map), Value::Object(fields)) = (value, schema) else {
+        return;
+    };
+    if fields.is_empty() {
+        return;
+    }
+    map.retain(|key, _| {
+        let known = fields.contains_key(key);
+        if !known {
+            let path = join(&prefix, key);
+            provenance.retain(|leaf, _| !is_within(leaf, &path));
+        }
+        known
+    });
+    for (key, child) in map.iter_mut() {
+        retain_schema(child, &fields[key], join(&prefix, key), provenance);
+    }
+}
+
+fn read_file(path: &Path) -> Result<Value> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+    toml::parse(&contents)
+        .with_context(|| format!("Failed to parse config file: {}", path.display()))
+}
+
+/// Prefix shared by all config environment variables
+const ENV_PREFIX: &str = "AUTOCOMPLETE_RS_";
+
+/// `AUTOCOMPLETE_RS_*` variables of this process that match no config key
+pub fn unknown_env() -> Vec<String> {
+    let schema = serde_json::to_value(Config::default()).unwrap_or_default();
+    env_overlay(std::env::vars(), &schema).1
+}
+
+/// `AUTOCOMPLETE_RS_*` variables that are not config keys: those of the
+/// shell integration, and those the daemon sets for its children
+const ENV_NOT_CONFIG: &[&str] = &[
+    "SOCKET",
+    "TRIGGER",
+    "ENABLE",
+    "SESSION",
+    "EVENT",
+    "LISTEN_FDS",
+    "FIG_SPECS",
+];
+
+/// Other names for config keys, as they would be spelled after the prefix
+const ENV_ALIASES: &[(&str, &str)] = &[("tui_theme", "theme.name")];
+
+/// Build an overlay from `AUTOCOMPLETE_RS_*` variables, and list the
+/// variables that match no config key.
+///
+/// Variable names are matched against the config schema, so
+/// `AUTOCOMPLETE_RS_GENERAL_MAX_SUGGESTIONS` maps to `general.max_suggestions`
+/// and `AUTOCOMPLETE_RS_SOURCE_LIMITS_FILES` to `source_limits.files`. Values
+/// are read as TOML values and fall back to plain strings. The variables in
+/// [`ENV_NOT_CONFIG`] are skipped without being reported.
+fn env_overlay(
+    vars: impl Iterator<Item = (String, String)>,
+    schema: &Value,
+) -> (Value, Vec<String>) {
+    let mut targets = Vec::new();
+    env_targets(schema, String::new(), &mut targets);
+
+    let mut overlay = Value::Object(Default::default());
+    let mut unknown = Vec::new();
+    for (name, raw) in vars {
+        let Some(rest) = name.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        if ENV_NOT_CONFIG.contains(&rest) {
+            continue;
+        }
+        let rest = rest.to_ascii_lowercase();
+        let alias = ENV_ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == rest)
+            .map(|(_, path)| path.to_string());
+        let path = alias.or_else(|| {
+            targets.iter().find_map(|(path, is_map)| {
+                let flat = path.replace('.', "_");
+                if *is_map {
+                    rest.strip_prefix(&flat)
+                        .and_then(|key| key.strip_prefix('_'))
+                        .filter(|key| !key.is_empty())
+                        .map(|key| join(path, key))
+                } else {
+                    (rest == flat).then(|| path.clone())
+                }
+            })
+        });
+        let Some(path) = path else {
+            unknown.push(name);
+            continue;
+        };
+
+        let value = toml::parse_value(&raw).unwrap_or(Value::String(raw));
+        insert_path(&mut overlay, &path, value);
+    }
+    unknown.sort();
+    (overlay, unknown)
+}
+
+/// Collect config paths an environment variable can target, flagging free-form maps
+fn env_targets(schema: &Value, prefix: String, targets: &mut Vec<(String, bool)>) {
+    match schema {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                env_targets(value, join(&prefix, key), targets);
+            }
+        }
+        Value::Object(_) => targets.push((prefix, true)),
+        _ => targets.push((prefix, false)),
+    }
+}
+
+/// Overlay `overlay` onto `base`, recording the origin of every replaced leaf
+fn merge(
+    base: &mut Value,
+    overlay: Value,
+    prefix: String,
+    source: Provenance,
+    provenance: &mut BTreeMap<String, Provenance>,
+) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                let path = join(&prefix, &key);
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value, path, source, provenance),
+                    None => {
+                        record_leaves(&value, path, source, provenance);
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => {
+            provenance.retain(|key, _| !is_within(key, &prefix));
+            record_leaves(&overlay, prefix, source, provenance);
+            *base = overlay;
+        }
+    }
+}
+
+fn record_leaves(
+    value: &Value,
+    prefix: String,
+    source: Provenance,
+    provenance: &mut BTreeMap<String, Provenance>,
+) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                record_leaves(value, join(&prefix, key), source, provenance);
+            }
+        }
+        // Empty maps (e.g. `source_limits`) have no leaves to attribute
+        Value::Object(_) => {}
+        _ => {
+            provenance.insert(prefix, source);
+        }
+    }
+}
+
+fn join(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+/// Whether dotted `path` is `prefix` itself or nested below it
+fn is_within(path: &str, prefix: &str) -> bool {
+    path == prefix
+        || path
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.starts_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schema() -> Value {
+        serde_json::to_value(Config::default()).unwrap()
+    }
+
+    #[test]
+    fn test_retain_schema_drops_unknown_keys() {
+        let mut value = schema();
+        let mut provenance = BTreeMap::new();
+        merge(
+            &mut value,
+            json!({ "tui": { "theme": "dark" }, "theme": { "name": "latte", "extra": 1 } }),
+            String::new(),
+            Provenance::File,
+            &mut provenance,
+        );
+        retain_schema(&mut value, &schema(), String::new(), &mut provenance);
+        assert!(value.get("tui").is_none());
+        assert!(value["theme"].get("extra").is_none());
+        assert_eq!(value["theme"]["name"], "latte");
+        assert_eq!(
+            provenance.keys().collect::<Vec<_>>(),
+            ["theme.name"],
+            "only known keys keep their origin"
+        );
+    }
+
+    #[test]
+    fn test_retain_schema_keeps_free_form_maps() {
+        let mut value = schema();
+        let mut provenance = BTreeMap::new();
+        merge(
+            &mut value,
+            json!({ "abbreviations": { "gco": "git checkout" } }),
+            String::new(),
+            Provenance::File,
+            &mut provenance,
+        );
+        retain_schema(&mut value, &schema(), String::new(), &mut provenance);
+        assert_eq!(value["abbreviations"]["gco"], "git checkout");
+        assert_eq!(provenance["abbreviations.gco"], Provenance::File);
+    }
+
+    fn env(vars: &[(&str, &str)]) -> (Value, Vec<String>) {
+        let vars = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()));
+        env_overlay(vars, &schema())
+    }
+
+    #[test]
+    fn test_env_overlay_maps_names_to_dotted_keys() {
+        let (overlay, unknown) = env(&[
+            ("AUTOCOMPLETE_RS_GENERAL_MAX_SUGGESTIONS", "20"),
+            ("AUTOCOMPLETE_RS_DAEMON_NATIVE_GIT", "false"),
+            ("AUTOCOMPLETE_RS_TIMEOUTS_HISTORY_MS", "5"),
+        ]);
+        assert_eq!(
+            overlay,
+            json!({
+                "general": { "max_suggestions": 20 },
+                "daemon": { "native_git": false },
+                "timeouts": { "history_ms": 5 },
+            })
+        );
+        assert!(unknown.is_empty());
+    }
+
+    #[test]
+    fn test_env_overlay_keys_of_free_form_maps() {
+        let (overlay, _) = env(&[
+            ("AUTOCOMPLETE_RS_SOURCE_LIMITS_FILES", "50"),
+            ("AUTOCOMPLETE_RS_TIMEOUTS_GENERATORS_GIT", "300"),
+            ("AUTOCOMPLETE_RS_ABBREVIATIONS_G_S", "git status"),
+        ]);
+        assert_eq!(overlay["source_limits"]["files"], 50);
+        assert_eq!(overlay["timeouts"]["generators"]["git"], 300);
+        // The rest of the name is the key, underscores and all
+        assert_eq!(overlay["abbreviations"]["g_s"], "git status");
+    }
+
+    #[test]
+    fn test_env_overlay_map_prefix_needs_a_key() {
+        let (overlay, unknown) = env(&[("AUTOCOMPLETE_RS_SOURCE_LIMITS", "5")]);
+        assert_eq!(overlay, json!({}));
+        assert_eq!(unknown, ["AUTOCOMPLETE_RS_SOURCE_LIMITS"]);
+    }
+
+    #[test]
+    fn test_env_overlay_values_fall_back_to_strings() {
+        let (overlay, _) = env(&[
+            ("AUTOCOMPLETE_RS_THEME_NAME", "latte"),
+            ("AUTOCOMPLETE_RS_DAEMON_WARM_UP", r#"["git", "cargo"]"#),
+        ]);
+        assert_eq!(overlay["theme"]["name"], "latte");
+        assert_eq!(overlay["daemon"]["warm_up"], json!(["git", "cargo"]));
+    }
+
+    #[test]
+    fn test_env_overlay_tui_theme_alias() {
+        let (overlay, unknown) = env(&[("AUTOCOMPLETE_RS_TUI_THEME", "latte")]);
+        assert_eq!(overlay, json!({ "theme": { "name": "latte" } }));
+        assert!(unknown.is_empty());
+    }
+
+    #[test]
+    fn test_env_overlay_reports_unknown_variables() {
+        let (overlay, unknown) = env(&[
+            ("AUTOCOMPLETE_RS_THEME", "latte"),
+            ("AUTOCOMPLETE_RS_SOCKET", "/tmp/a.sock"),
+            ("AUTOCOMPLETE_RS_TRIGGER", "^ "),
+            ("AUTOCOMPLETE_RS_ENABLE", "1"),
+            ("AUTOCOMPLETE_RS_GENERAL_MAX_SUGGESTION", "3"),
+            ("OTHER_VARIABLE", "x"),
+        ]);
+        assert_eq!(overlay, json!({}));
+        assert_eq!(
+            unknown,
+            [
+                "AUTOCOMPLETE_RS_GENERAL_MAX_SUGGESTION",
+                "AUTOCOMPLETE_RS_THEME"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_flag_overlay_wins_over_the_file() {
+        let mut value = schema();
+        let mut provenance = BTreeMap::new();
+        let file = json!({ "accessibility": { "plain": false } });
+        merge(
+            &mut value,
+            file,
+            String::new(),
+            Provenance::File,
+            &mut provenance,
+        );
+        let flags = flag_overlay([("accessibility.plain", Value::Bool(true))]);
+        merge(
+            &mut value,
+            flags,
+            String::new(),
+            Provenance::Flag,
+            &mut provenance,
+        );
+        assert_eq!(value["accessibility"]["plain"], true);
+        assert_eq!(provenance["accessibility.plain"], Provenance::Flag);
+    }
+}
@@ -0,0 +1,631 @@
+//! Minimal TOML reader for the configuration file.
+//!
+//! Supports the subset of TOML the config file actually uses: `[table]` and
+//! `[[array-of-tables]]` headers, dotted keys, basic and literal strings,
+//! integers, floats, booleans, arrays, and inline tables. The document is
+//! converted into a `serde_json::Value` so the typed config structs can be
+//! deserialized with serde.
+
+use serde_json::{Map, Number, Value};
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// Error raised when the config file is not valid TOML
+#[derive(Debug, Error)]
+#[error("line {line}: {message}")]
+pub struct TomlError {
+    /// 1-based line number where the error occurred
+    pub line: usize,
+    /// Human readable description
+    pub message: String,
+}
+
+/// Parse a TOML document into a JSON value tree
+pub fn parse(input: &str) -> Result<Value, TomlError> {
+    parse_with_lines(input).map(|(value, _)| value)
+}
+
+/// Parse a TOML document, also returning the line each key was defined on.
+///
+/// Keys are reported as dotted paths (`general.max_suggestions`); table
+/// headers are reported for the table path itself.
+pub fn parse_with_lines(input: &str) -> Result<(Value, HashMap<String, usize>), TomlError> {
+    let mut root = Map::new();
+    let mut key_lines = HashMap::new();
+    // Path of the table that subsequent key/value pairs are written to
+    let mut current: Vec<String> = Vec::new();
+    let mut lines = input.lines().enumerate().peekable();
+
+    while let Some((index, raw)) = lines.next() {
+        let line_no = index + 1;
+        let line = strip_comment(raw).trim().to_string();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(header) = line.strip_prefix("[[") {
+            let header = header
+                .strip_suffix("]]")
+                .ok_or_else(|| error(line_no, "unterminated array-of-tables header"))?;
+            let path = parse_key_path(header, line_no)?;
+            push_array_table(&mut root, &path, line_no)?;
+            key_lines.entry(path.join(".")).or_insert(line_no);
+            current = path;
+            continue;
+        }
+
+        if let Some(header) = line.strip_prefix('[') {
+            let header = header
+                .strip_suffix(']')
+                .ok_or_else(|| error(line_no, "unterminated table header"))?;
+            let path = parse_key_path(header, line_no)?;
+            table_at(&mut root, &path, line_no)?;
+            key_lines.entry(path.join(".")).or_insert(line_no);
+            current = path;
+            continue;
+        }
+
+        // Key/value pair; values such as arrays may span several lines
+        let (key, value_src) =
+            split_assignment(&line).ok_or_else(|| error(line_no, "expected `key = value`"))?;
+        let key_path = parse_key_path(key, line_no)?;
+        let mut value_src = value_src.trim().to_string();
+        while !is_balanced(&value_src) {
+            match lines.next() {
+                Some((_, next)) => {
+                    value_src.push(' ');
+                    value_src.push_str(strip_comment(next).trim());
+                }
+                None => return Err(error(line_no, "unterminated value")),
+            }
+        }
+
+        let mut cursor = Cursor::new(&value_src, line_no);
+        let value = cursor.value()?;
+        cursor.skip_ws();
+        if !cursor.at_end() {
+            return Err(error(line_no, "unexpected characters after value"));
+        }
+
+        let table = table_at(&mut root, &current, line_no)?;
+        insert(table, &key_path, value, line_no)?;
+        let full_path: Vec<&str> = current
+            .iter()
+            .chain(&key_path)
+            .map(String::as_str)
+            .collect();
+        key_lines.entry(full_path.join(".")).or_insert(line_no);
+    }
+
+    Ok((Value::Object(root), key_lines))
+}
+
+/// Parse a single TOML value expression such as `10`, `true`, or `["a", "b"]`
+pub fn parse_value(src: &str) -> Result<Value, TomlError> {
+    let mut cursor = Cursor::new(src, 1);
+    let value = cursor.value()?;
+    cursor.skip_ws();
+    if !cursor.at_end() {
+        return Err(error(1, "unexpected characters after value"));
+    }
+    Ok(value)
+}
+
+fn error(line: usize, message: &str) -> TomlError {
+    TomlError {
+        line,
+        message: message.to_string(),
+    }
+}
+
+/// Remove a trailing `# comment`, ignoring `#` characters inside strings
+fn strip_comment(line: &str) -> &str {
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for (i, ch) in line.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, ch) {
+            (Some('"'), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (None, '"' | '\'') => quote = Some(ch),
+            (None, '#') => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Split `key = value` at the first `=` outside a quoted key
+fn split_assignment(line: &str) -> Option<(&str, &str)> {
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for (i, ch) in line.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, ch) {
+            (Some('"'), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (None, '"' | '\'') => quote = Some(ch),
+            (None, '=') => return Some((&line[..i], &line[i + 1..])),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// A key as written in a document: bare when it can be, quoted otherwise
+fn key_to_toml(key: &str) -> String {
+    let bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if bare {
+        key.to_string()
+    } else {
+        Value::String(key.to_string()).to_string()
+    }
+}
+
+/// Whether all brackets and braces outside strings are closed
+fn is_balanced(src: &str) -> bool {
+    let mut depth = 0i32;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for ch in src.chars() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, ch) {
+            (Some('"'), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (None, '"' | '\'') => quote = Some(ch),
+            (None, '[' | '{') => depth += 1,
+            (None, ']' | '}') => depth -= 1,
+            _ => {}
+        }
+    }
+    depth <= 0
+}
+
+fn parse_key_path(src: &str, line: usize) -> Result<Vec<String>, TomlError> {
+    let mut cursor = Cursor::new(src, line);
+    let path = cursor.key_path()?;
+    cursor.skip_ws();
+    if !cursor.at_end() {
+        return Err(error(line, "invalid key"));
+    }
+    Ok(path)
+}
+
+/// Walk (and create) nested tables along `path`
+fn table_at<'a>(
+    root: &'a mut Map<String, Value>,
+    path: &[String],
+    line: usize,
+) -> Result<&'a mut Map<String, Value>, TomlError> {
+    let mut table = root;
+    for key in path {
+        let entry = table
+            .entry(key.clone())
+            .or_insert_with(|| Value::Object(Map::new()));
+        table = match entry {
+            Value::Object(map) => map,
+            // `[[a]]` followed by `[a.b]` refers to the last element
+            Value::Array(items) => match items.last_mut() {
+                Some(Value::Object(map)) => map,
+                _ => return Err(error(line, &format!("`{}` is not a table", key))),
+            },
+            _ => return Err(error(line, &format!("`{}` is not a table", key))),
+        };
+    }
+    Ok(table)
+}
+
+fn push_array_table(
+    root: &mut Map<String, Value>,
+    path: &[String],
+    line: usize,
+) -> Result<(), TomlError> {
+    let (last, parent) = path
+        .split_last()
+        .ok_or_else(|| error(line, "empty table header"))?;
+    let table = table_at(root, parent, line)?;
+    let entry = table
+        .entry(last.clone())
+        .or_insert_with(|| Value::Array(Vec::new()));
+    match entry {
+        Value::Array(items) => {
+            items.push(Value::Object(Map::new()));
+            Ok(())
+        }
+        _ => Err(error(
+            line,
+            &format!("`{}` is not an array of tables", last),
+        )),
+    }
+}
+
+fn insert(
+    table: &mut Map<String, Value>,
+    path: &[String],
+    value: Value,
+    line: usize,
+) -> Result<(), TomlError> {
+    let (last, parent) = path.split_last().ok_or_else(|| error(line, "empty key"))?;
+    let table = table_at(table, parent, line)?;
+    if table.contains_key(last) {
+        return Err(error(line, &format!("duplicate key `{}`", last)));
+    }
+    table.insert(last.clone(), value);
+    Ok(())
+}
+
+/// Character cursor over a single (possibly joined) value
+struct Cursor<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(src: &'a str, line: usize) -> Self {
+        Self {
+            chars: src.chars().peekable(),
+            line,
+        }
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.chars.peek().is_none()
+    }
+
+    fn skip_ws(&mut self) {
+        while matches!(self.chars.peek(), Some(c) if c.is_whitespace()) {
+            self.chars.next();
+        }
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        if self.chars.peek() == Some(&expected) {
+            self.chars.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn key_path(&mut self) -> Result<Vec<String>, TomlError> {
+        let mut path = Vec::new();
+        loop {
+            self.skip_ws();
+            let key = match self.chars.peek() {
+                Some('"') => {
+                    self.chars.next();
+                    self.basic_string()?
+                }
+                Some('\'') => {
+                    self.chars.next();
+                    self.literal_string()?
+                }
+                _ => {
+                    let mut key = String::new();
+                    while let Some(&c) = self.chars.peek() {
+                        if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                            key.push(c);
+                            self.chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                    if key.is_empty() {
+                        return Err(error(self.line, "expected a key"));
+                    }
+                    key
+                }
+            };
+            path.push(key);
+            self.skip_ws();
+            if !self.eat('.') {
+                return Ok(path);
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, TomlError> {
+        self.skip_ws();
+        match self.chars.peek() {
+            Some('"') => {
+                self.chars.next();
+                Ok(Value::String(self.basic_string()?))
+            }
+            Some('\'') => {
+                self.chars.next();
+                Ok(Value::String(self.literal_string()?))
+            }
+            Some('[') => {
+                self.chars.next();
+                self.array()
+            }
+            Some('{') => {
+                self.chars.next();
+                self.inline_table()
+            }
+            Some(_) => self.scalar(),
+            None => Err(error(self.line, "missing value")),
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<String, TomlError> {
+        let mut out = String::new();
+        while let Some(c) = self.chars.next() {
+            match c {
+                '"' => return Ok(out),
+                '\\' => match self.chars.next() {
+                    Some('n') => out.push('\n'),
+                    Some('t') => out.push('\t'),
+                    Some('r') => out.push('\r'),
+                    Some('"') => out.push('"'),
+                    Some('\\') => out.push('\\'),
+                    Some('u') => {
+                        let hex: String = self.chars.by_ref().take(4).collect();
+                        let ch = u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| error(self.line, "invalid unicode escape"))?;
+                        out.push(ch);
+                    }
+                    _ => return Err(error(self.line, "invalid escape sequence")),
+                },
+                _ => out.push(c),
+            }
+        }
+        Err(error(self.line, "unterminated string"))
+    }
+
+    fn literal_string(&mut self) -> Result<String, TomlError> {
+        let mut out = String::new();
+        for c in self.chars.by_ref() {
+            if c == '\'' {
+                return Ok(out);
+            }
+            out.push(c);
+        }
+        Err(error(self.line, "unterminated string"))
+    }
+
+    fn array(&mut self) -> Result<Value, TomlError> {
+        let mut items = Vec::new();
+        loop {
+            self.skip_ws();
+            if self.eat(']') {
+                return Ok(Value::Array(items));
+            }
+            items.push(self.value()?);
+            self.skip_ws();
+            if !self.eat(',') {
+                self.skip_ws();
+                if self.eat(']') {
+                    return Ok(Value::Array(items));
+                }
+                return Err(error(self.line, "expected `,` or `]` in array"));
+            }
+        }
+    }
+
+    fn inline_table(&mut self) -> Result<Value, TomlError> {
+        let mut table = Map::new();
+        loop {
+            self.skip_ws();
+            if self.eat('}') {
+                return Ok(Value::Object(table));
+            }
+            let path = self.key_path()?;
+            self.skip_ws();
+            if !self.eat('=') {
+                return Err(error(self.line, "expected `=` in inline table"));
+            }
+            let value = self.value()?;
+            insert(&mut table, &path, value, self.line)?;
+            self.skip_ws();
+            if !self.eat(',') {
+                self.skip_ws();
+                if self.eat('}') {
+                    return Ok(Value::Object(table));
+                }
+                return Err(error(self.line, "expected `,` or `}` in inline table"));
+            }
+        }
+    }
+
+    fn scalar(&mut self) -> Result<Value, TomlError> {
+        let mut raw = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if c == ',' || c == ']' || c == '}' || c.is_whitespace() {
+                break;
+            }
+            raw.push(c);
+            self.chars.next();
+        }
+        match raw.as_str() {
+            "true" => return Ok(Value::Bool(true)),
+            "false" => return Ok(Value::Bool(false)),
+            _ => {}
+        }
+        let digits = raw.replace('_', "");
+        if let Ok(int) = digits.parse::<i64>() {
+            return Ok(Value::Number(int.into()));
+        }
+        if let Some(float) = digits.parse::<f64>().ok().and_then(Number::from_f64) {
+            return Ok(Value::Number(float));
+        }
+        Err(error(self.line, &format!("invalid value `{}`", raw)))
+    }
+}
+
+/// Render a value as an inline TOML expression
+pub fn to_inline(value: &Value) -> String {
+    match value {
+        // JSON string escapes are valid TOML basic-string escapes
+        Value::String(_) | Value::Number(_) | Value::Bool(_) => value.to_string(),
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(to_inline).collect();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Object(map) => {
+            let entries: Vec<String> = map
+                .iter()
+                .map(|(key, value)| format!("{} = {}", key_to_toml(key), to_inline(value)))
+                .collect();
+            format!("{{ {} }}", entries.join(", "))
+        }
+        Value::Null => "\"\"".to_string(),
+    }
+}
+
+/// Set `section.key` to `value` in a TOML document, preserving everything else.
+///
+/// Replaces the existing assignment when present, otherwise appends the key to
+/// the section (creating the section at the end of the file if needed).
+pub fn set_value(contents: &str, section: &str, key: &str, value: &Value) -> String {
+    let assignment = format!("{} = {}", key_to_toml(key), to_inline(value));
+    let mut lines: Vec<String> = contents.lines().map(String::from).collect();
+    let mut current = String::new();
+    // Index of the last line belonging to the target section
+    let mut section_end: Option<usize> = None;
+
+    for (index, line) in lines.iter_mut().enumerate() {
+        let trimmed = strip_comment(line).trim();
+        if let Some(header) = trimmed.strip_prefix('[') {
+            current = header
+                .trim_matches(|c| c == '[' || c == ']')
+                .trim()
+                .to_string();
+            if current == section {
+                section_end = Some(index);
+            }
+            continue;
+        }
+        if current != section {
+            continue;
+        }
+        if !trimmed.is_empty() {
+            section_end = Some(index);
+        }
+        let assigned = split_assignment(trimmed).is_some_and(|(name, _)| {
+            parse_key_path(name, index + 1).is_ok_and(|path| path == [key])
+        });
+        if assigned {
+            *line = assignment;
+            return lines.join("\n") + "\n";
+        }
+    }
+
+    match section_end {
+        Some(index) => lines.insert(index + 1, assignment),
+        None => {
+            if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+                lines.push(String::new());
+            }
+            lines.push(format!("[{}]", section));
+            lines.push(assignment);
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_quoted_key_containing_equals() {
+        let value = parse("[abbreviations]\n\"g=s\" = \"git status\"\n").unwrap();
+        assert_eq!(value, json!({ "abbreviations": { "g=s": "git status" } }));
+    }
+
+    #[test]
+    fn test_parse_literal_quoted_key() {
+        let value = parse("'a = b' = 1").unwrap();
+        assert_eq!(value, json!({ "a = b": 1 }));
+    }
+
+    #[test]
+    fn test_parse_hash_inside_strings() {
+        let value = parse("color = \"#ff0000\"\nliteral = '# not a comment'").unwrap();
+        assert_eq!(
+            value,
+            json!({ "color": "#ff0000", "literal": "# not a comment" })
+        );
+    }
+
+    #[test]
+    fn test_parse_trailing_comments() {
+        let input = "[general] # the basics\nmax_suggestions = 10 # at most\n# whole line\n";
+        let value = parse(input).unwrap();
+        assert_eq!(value, json!({ "general": { "max_suggestions": 10 } }));
+    }
+
+    #[test]
+    fn test_parse_multiline_array() {
+        let input = "sources = [\n  \"spec\", # first\n  \"history\",\n]\nafter = true\n";
+        let value = parse(input).unwrap();
+        assert_eq!(
+            value,
+            json!({ "sources": ["spec", "history"], "after": true })
+        );
+    }
+
+    #[test]
+    fn test_parse_unterminated_array_reports_its_line() {
+        let err = parse("ok = 1\nlist = [1,\n2").unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+
+    #[test]
+    fn test_parse_with_lines_reports_dotted_paths() {
+        let (_, lines) = parse_with_lines("[general]\n\nmax_suggestions = 5\n").unwrap();
+        assert_eq!(lines["general"], 1);
+        assert_eq!(lines["general.max_suggestions"], 3);
+    }
+
+    #[test]
+    fn test_parse_duplicate_key_is_an_error() {
+        assert!(parse("a = 1\na = 2").is_err());
+    }
+
+    #[test]
+    fn test_to_inline_round_trips() {
+        let value = json!({
+            "plain": "text with \"quotes\" and # hash",
+            "g=s": "git status",
+            "list": [1, 2.5, true],
+            "nested": { "dotted.key": "x" },
+        });
+        assert_eq!(parse_value(&to_inline(&value)).unwrap(), value);
+    }
+
+    #[test]
+    fn test_set_value_replaces_quoted_key() {
+        let contents = "[abbreviations]\n\"g=s\" = \"git status\"\ngc = \"git commit\"\n";
+        let updated = set_value(contents, "abbreviations", "g=s", &json!("git switch"));
+        assert_eq!(
+            parse(&updated).unwrap(),
+            json!({ "abbreviations": { "g=s": "git switch", "gc": "git commit" } })
+        );
+    }
+
+    #[test]
+    fn test_set_value_appends_missing_section() {
+        let updated = set_value("[general]\na = 1\n", "limits", "total", &json!(20));
+        assert_eq!(
+            parse(&updated).unwrap(),
+            json!({ "general": { "a": 1 }, "limits": { "total": 20 } })
+        );
+    }
+}
@@ -0,0 +1,28 @@
+use super::{Suggestion, SuggestionSource};
+use std::collections::BTreeMap;
+
+/// Expansions of the abbreviations starting with `word`, which must be the
+/// first word of the buffer; an exact match comes first.
+///
+/// The suggestion text is the expansion as configured, placeholders included,
+/// for the client to fill in.
+pub fn suggest(word: &str, abbreviations: &BTreeMap<String, String>) -> Vec<Suggestion> {
+    if word.is_empty() {
+        return Vec::new();
+    }
+    let mut matches: Vec<(&String, &String)> = abbreviations
+        .iter()
+        .filter(|(name, _)| name.starts_with(word))
+        .collect();
+    matches.sort_by_key(|(name, _)| (name.as_str() != word, name.len()));
+
+    matches
+        .into_iter()
+        .map(|(name, expansion)| Suggestion {
+            text: expansion.clone(),
+            description: format!("Abbreviation `{name}`"),
+            source: SuggestionSource::Abbreviation,
+            ..Default::default()
+        })
+        .collect()
+}
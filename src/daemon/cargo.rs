@@ -0,0 +1,540 @@
+//! Targets, features and packages of the Cargo project being worked on.
+//!
+//! The cargo spec leaves the values of `--bin`, `--example`, `--test`,
+//! `--bench`, `--features` and `--package` open, since they are the
+//! project's own. They are read here from the nearest `Cargo.toml` up from
+//! the working directory, and the workspace it belongs to: targets declared
+//! in the manifest and those Cargo discovers by their place (`src/bin/*.rs`,
+//! `examples/*.rs` and so on), features and optional dependencies, and the
+//! workspace's members. Cargo isn't run, since `cargo metadata` can take
+//! seconds when it resolves dependencies; a manifest the TOML reader can't
+//! read offers nothing.
+
+use super::{Suggestion, SuggestionSource};
+use crate::config::toml;
+use crate::specs::SpecOption;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// What an option of cargo takes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Wanted {
+    Target(Kind),
+    Features,
+    Packages,
+}
+
+/// Kinds of targets, by the table that declares them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Bin,
+    Example,
+    Test,
+    Bench,
+}
+
+impl Kind {
+    fn table(self) -> &'static str {
+        match self {
+            Kind::Bin => "bin",
+            Kind::Example => "example",
+            Kind::Test => "test",
+            Kind::Bench => "bench",
+        }
+    }
+
+    /// Directory of the targets Cargo discovers, and the key turning that off
+    fn discovered(self) -> (&'static str, &'static str) {
+        match self {
+            Kind::Bin => ("src/bin", "autobins"),
+            Kind::Example => ("examples", "autoexamples"),
+            Kind::Test => ("tests", "autotests"),
+            Kind::Bench => ("benches", "autobenches"),
+        }
+    }
+}
+
+/// A manifest and the directory it is in
+#[derive(Debug)]
+struct Manifest {
+    dir: PathBuf,
+    value: Value,
+}
+
+impl Manifest {
+    fn read(dir: &Path) -> Option<Self> {
+        let text = std::fs::read_to_string(dir.join("Cargo.toml")).ok()?;
+        Some(Self {
+            dir: dir.to_path_buf(),
+            value: toml::parse(&text).ok()?,
+        })
+    }
+
+    fn package_name(&self) -> Option<&str> {
+        self.value.get("package")?.get("name")?.as_str()
+    }
+}
+
+/// Values of `option` for the project around `cwd`; nothing for options
+/// taking something else, or outside a Cargo project
+pub fn suggest(option: &SpecOption<'_>, cwd: &Path) -> Vec<Suggestion> {
+    let Some(wanted) = wanted(option) else {
+        return Vec::new();
+    };
+    let Some(current) = cwd
+        .ancestors()
+        .find(|dir| dir.join("Cargo.toml").is_file())
+        .and_then(Manifest::read)
+    else {
+        return Vec::new();
+    };
+    // With a virtual manifest, as at the root of most workspaces, cargo
+    // works on all members
+    let packages = if current.package_name().is_some() {
+        vec![current]
+    } else {
+        members(&current)
+    };
+
+    let mut found: Vec<(String, String)> = Vec::new();
+    match wanted {
+        Wanted::Target(kind) => {
+            for package in &packages {
+                let targets = targets(package, kind);
+                match package.package_name() {
+                    Some(name) if packages.len() > 1 => found.extend(
+                        targets
+                            .into_iter()
+                            .map(|(target, path)| (target, format!("{name}: {path}"))),
+                    ),
+                    _ => found.extend(targets),
+                }
+            }
+        }
+        Wanted::Features => {
+            match packages.as_slice() {
+                [package] => found.extend(features(package)),
+                // Which member's is then named with it
+                packages => {
+                    for package in packages {
+                        let Some(name) = package.package_name() else {
+                            continue;
+                        };
+                        found.extend(features(package).into_iter().map(
+                            |(feature, description)| (format!("{name}/{feature}"), description),
+                        ));
+                    }
+                }
+            }
+        }
+        Wanted::Packages => {
+            let root = packages
+                .first()
+                .and_then(|package| workspace_root(&package.dir))
+                .unwrap_or_else(|| Manifest {
+                    dir: cwd.to_path_buf(),
+                    value: Value::Null,
+                });
+            let members = if root.value.get("workspace").is_some() {
+                members(&root)
+            } else {
+                packages
+            };
+            for member in &members {
+                if let Some(name) = member.package_name() {
+                    let path = member.dir.strip_prefix(&root.dir).unwrap_or(&member.dir);
+                    let path = if path.as_os_str().is_empty() {
+                        ".".to_string()
+                    } else {
+                        path.display().to_string()
+                    };
+                    found.push((name.to_string(), format!("Package in {path}")));
+                }
+            }
+        }
+    }
+
+    let mut seen = Vec::new();
+    found
+        .into_iter()
+        .filter(|(name, _)| {
+            let new = !seen.contains(name);
+            seen.push(name.clone());
+            new
+        })
+        .map(|(text, description)| Suggestion {
+            text,
+            description,
+            source: SuggestionSource::Spec,
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn wanted(option: &SpecOption<'_>) -> Option<Wanted> {
+    option.name.iter().find_map(|name| match name.as_ref() {
+        "--bin" => Some(Wanted::Target(Kind::Bin)),
+        "--example" => Some(Wanted::Target(Kind::Example)),
+        "--test" => Some(Wanted::Target(Kind::Test)),
+        "--bench" => Some(Wanted::Target(Kind::Bench)),
+        "--features" => Some(Wanted::Features),
+        "--package" | "--exclude" => Some(Wanted::Packages),
+        _ => None,
+    })
+}
+
+/// The manifest with the `[workspace]` that `dir`'s package belongs to: its
+/// own, or the nearest one above
+fn workspace_root(dir: &Path) -> Option<Manifest> {
+    dir.ancestors()
+        .filter(|dir| dir.join("Cargo.toml").is_file())
+        .filter_map(Manifest::read)
+        .find(|manifest| manifest.value.get("workspace").is_some())
+}
+
+/// The packages of the workspace `root`, itself included if it is one
+fn members(root: &Manifest) -> Vec<Manifest> {
+    let workspace = root.value.get("workspace");
+    let patterns = |key: &str| -> Vec<&str> {
+        workspace
+            .and_then(|workspace| workspace.get(key))
+            .and_then(Value::as_array)
+            .map(|patterns| patterns.iter().filter_map(Value::as_str).collect())
+            .unwrap_or_default()
+    };
+    let excluded: Vec<PathBuf> = patterns("exclude")
+        .into_iter()
+        .map(|path| root.dir.join(path))
+        .collect();
+
+    let mut members = Vec::new();
+    if root.package_name().is_some() {
+        members.push(Manifest {
+            dir: root.dir.clone(),
+            value: root.value.clone(),
+        });
+    }
+    for pattern in patterns("members") {
+        for dir in expand(&root.dir, pattern) {
+            if excluded.contains(&dir) || dir == root.dir {
+                continue;
+            }
+            members.extend(Manifest::read(&dir));
+        }
+    }
+    members
+}
+
+/// The directories matching `pattern`, whose components may hold `*`
+fn expand(base: &Path, pattern: &str) -> Vec<PathBuf> {
+    let mut dirs = vec![base.to_path_buf()];
+    for component in pattern.split('/').filter(|component| !component.is_empty()) {
+        if !component.contains('*') {
+            dirs = dirs.into_iter().map(|dir| dir.join(component)).collect();
+            continue;
+        }
+        let mut matched = Vec::new();
+        for dir in dirs {
+            let Ok(entries) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+            let mut names: Vec<String> = entries
+                .flatten()
+                .filter(|entry| entry.path().is_dir())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .filter(|name| wildcard(component, name))
+                .collect();
+            names.sort();
+            matched.extend(names.into_iter().map(|name| dir.join(name)));
+        }
+        dirs = matched;
+    }
+    dirs.retain(|dir| dir.join("Cargo.toml").is_file());
+    dirs
+}
+
+/// Whether `name` matches `pattern`, `*` standing for any run of characters
+fn wildcard(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((start, rest)) => {
+            let Some(name) = name.strip_prefix(start) else {
+                return false;
+            };
+            (0..=name.len())
+                .filter(|&at| name.is_char_boundary(at))
+                .any(|at| wildcard(rest, &name[at..]))
+        }
+    }
+}
+
+/// Targets of `kind` in `package`, with the file each is built from
+fn targets(package: &Manifest, kind: Kind) -> Vec<(String, String)> {
+    let mut found = Vec::new();
+    let declared = package
+        .value
+        .get(kind.table())
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten();
+    for target in declared {
+        let Some(name) = target.get("name").and_then(Value::as_str) else {
+            continue;
+        };
+        let path = target
+            .get("path")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        found.push((name.to_string(), path.to_string()));
+    }
+
+    let (dir, auto) = kind.discovered();
+    let discovering = package
+        .value
+        .get("package")
+        .and_then(|package| package.get(auto))
+        .and_then(Value::as_bool)
+        .unwrap_or(true);
+    if !discovering {
+        return found;
+    }
+    if kind == Kind::Bin
+        && package.dir.join("src/main.rs").is_file()
+        && let Some(name) = package.package_name()
+    {
+        found.push((name.to_string(), "src/main.rs".to_string()));
+    }
+    let Ok(entries) = std::fs::read_dir(package.dir.join(dir)) else {
+        return found;
+    };
+    let mut discovered: Vec<(String, String)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().into_owned();
+            // `name.rs`, or `name/main.rs` for a target of several files
+            if path.is_dir() {
+                path.join("main.rs")
+                    .is_file()
+                    .then(|| (name.clone(), format!("{dir}/{name}/main.rs")))
+            } else {
+                let stem = name.strip_suffix(".rs")?;
+                Some((stem.to_string(), format!("{dir}/{name}")))
+            }
+        })
+        .collect();
+    discovered.sort();
+    found.extend(discovered);
+    found
+}
+
+/// Features of `package`, with those they enable, and its optional
+/// dependencies that no feature hides behind `dep:`
+fn features(package: &Manifest) -> Vec<(String, String)> {
+    let features = package.value.get("features").and_then(Value::as_object);
+    let mut found: Vec<(String, String)> = features
+        .into_iter()
+        .flatten()
+        .map(|(name, enables)| {
+            let enables: Vec<&str> = enables
+                .as_array()
+                .map(|enables| enables.iter().filter_map(Value::as_str).collect())
+                .unwrap_or_default();
+            let description = if enables.is_empty() {
+                String::new()
+            } else {
+                format!("Enables {}", enables.join(", "))
+            };
+            (name.clone(), description)
+        })
+        .collect();
+
+    let hidden = |dependency: &str| {
+        features.into_iter().flatten().any(|(_, enables)| {
+            enables.as_array().is_some_and(|enables| {
+                enables
+                    .iter()
+                    .any(|enable| enable.as_str() == Some(&format!("dep:{dependency}")))
+            })
+        })
+    };
+    let dependencies = package
+        .value
+        .get("dependencies")
+        .and_then(Value::as_object)
+        .into_iter()
+        .flatten();
+    for (name, dependency) in dependencies {
+        let optional = dependency.get("optional").and_then(Value::as_bool) == Some(true);
+        if optional && !hidden(name) && !found.iter().any(|(feature, _)| feature == name) {
+            found.push((name.clone(), "Optional dependency".to_string()));
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use std::borrow::Cow;
+
+    fn suggested(flag: &'static str, cwd: &Path) -> Vec<(String, String)> {
+        let option = SpecOption {
+            name: vec![Cow::Borrowed(flag)],
+            ..Default::default()
+        };
+        suggest(&option, cwd)
+            .into_iter()
+            .map(|suggestion| (suggestion.text, suggestion.description))
+            .collect()
+    }
+
+    fn pairs(expected: &[(&str, &str)]) -> Vec<(String, String)> {
+        expected
+            .iter()
+            .map(|(text, description)| (text.to_string(), description.to_string()))
+            .collect()
+    }
+
+    fn package() -> TempDir {
+        let dir = TempDir::new();
+        dir.write(
+            "Cargo.toml",
+            r#"[package]
+name = "tool"
+version = "0.1.0"
+
+[[bin]]
+name = "helper"
+path = "tools/helper.rs"
+
+[features]
+default = ["fast"]
+fast = []
+tls = ["dep:rustls"]
+
+[dependencies]
+rustls = { version = "0.23", optional = true }
+serde = { version = "1", optional = true }
+anyhow = "1"
+"#,
+        );
+        dir.write("src/main.rs", "");
+        dir.write("src/bin/extra.rs", "");
+        dir.write("src/bin/multi/main.rs", "");
+        dir.write("src/bin/multi/part.rs", "");
+        dir.write("examples/demo.rs", "");
+        dir.write("examples/notes.txt", "");
+        dir
+    }
+
+    #[test]
+    fn test_declared_and_discovered_targets() {
+        let dir = package();
+        assert_eq!(
+            suggested("--bin", &dir.path().join("src")),
+            pairs(&[
+                ("helper", "tools/helper.rs"),
+                ("tool", "src/main.rs"),
+                ("extra", "src/bin/extra.rs"),
+                ("multi", "src/bin/multi/main.rs"),
+            ])
+        );
+        assert_eq!(
+            suggested("--example", dir.path()),
+            pairs(&[("demo", "examples/demo.rs")])
+        );
+        assert!(suggested("--bench", dir.path()).is_empty());
+    }
+
+    #[test]
+    fn test_discovery_can_be_turned_off() {
+        let dir = TempDir::new();
+        dir.write(
+            "Cargo.toml",
+            "[package]\nname = \"quiet\"\nautoexamples = false\n\n[[example]]\nname = \"only\"\n",
+        );
+        dir.write("examples/skipped.rs", "");
+        assert_eq!(suggested("--example", dir.path()), pairs(&[("only", "")]));
+    }
+
+    #[test]
+    fn test_features_and_optional_dependencies() {
+        let dir = package();
+        // `rustls` hides behind `dep:`, `anyhow` isn't optional
+        assert_eq!(
+            suggested("--features", dir.path()),
+            pairs(&[
+                ("default", "Enables fast"),
+                ("fast", ""),
+                ("tls", "Enables dep:rustls"),
+                ("serde", "Optional dependency"),
+            ])
+        );
+    }
+
+    fn workspace() -> TempDir {
+        let dir = TempDir::new();
+        dir.write(
+            "Cargo.toml",
+            "[workspace]\nmembers = [\"crates/*\", \"app\"]\nexclude = [\"crates/old\"]\n",
+        );
+        dir.write(
+            "crates/core/Cargo.toml",
+            "[package]\nname = \"core\"\n\n[features]\nsimd = []\n",
+        );
+        dir.write("crates/core/tests/api.rs", "");
+        dir.write("crates/old/Cargo.toml", "[package]\nname = \"old\"\n");
+        dir.write("crates/notes/README", "");
+        dir.write("app/Cargo.toml", "[package]\nname = \"app\"\n");
+        dir.write("app/tests/cli.rs", "");
+        dir
+    }
+
+    #[test]
+    fn test_workspace_members() {
+        let dir = workspace();
+        let packages = pairs(&[
+            ("core", "Package in crates/core"),
+            ("app", "Package in app"),
+        ]);
+        assert_eq!(suggested("--package", dir.path()), packages);
+        // From inside a member, the whole workspace
+        assert_eq!(suggested("--exclude", &dir.path().join("app")), packages);
+    }
+
+    #[test]
+    fn test_virtual_manifest_covers_all_members() {
+        let dir = workspace();
+        assert_eq!(
+            suggested("--test", dir.path()),
+            pairs(&[("api", "core: tests/api.rs"), ("cli", "app: tests/cli.rs"),])
+        );
+        assert_eq!(
+            suggested("--features", dir.path()),
+            pairs(&[("core/simd", "")])
+        );
+        // Inside a member, only its own
+        assert_eq!(
+            suggested("--test", &dir.path().join("app")),
+            pairs(&[("cli", "tests/cli.rs")])
+        );
+    }
+
+    #[test]
+    fn test_other_options_and_projects() {
+        let dir = package();
+        assert!(suggested("--target", dir.path()).is_empty());
+        assert!(suggested("--bin", TempDir::new().path()).is_empty());
+    }
+
+    #[test]
+    fn test_wildcard() {
+        assert!(wildcard("*", "anything"));
+        assert!(wildcard("crate-*", "crate-a"));
+        assert!(wildcard("a*b*c", "a-b-c"));
+        assert!(!wildcard("a*b", "a-c"));
+        assert!(!wildcard("crates", "crate"));
+    }
+}
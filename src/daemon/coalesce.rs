@@ -0,0 +1,58 @@
+//! One computation for identical requests from several shells.
+//!
+//! Panes with synchronized input (tmux `synchronize-panes`, a terminal's
+//! broadcast mode) send the same request from every shell at once, and each
+//! would run the same generators. A request identical to one still being
+//! answered, or to one that started less than [`WINDOW`] ago, waits for that
+//! one's response instead of computing its own. Requests that differ only in
+//! their session count as identical, so only the first of them is recorded
+//! in per-session state such as the command history.
+
+use super::{CompletionRequest, CompletionResponse};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+
+/// How long after a request started an identical one still gets its response
+const WINDOW: Duration = Duration::from_millis(250);
+
+/// A response being computed, shared by the requests waiting for it
+pub type Flight = Arc<OnceCell<CompletionResponse>>;
+
+/// Recent requests and their responses, by the request without its session
+#[derive(Debug, Default)]
+pub struct Coalescer {
+    flights: Mutex<HashMap<String, (Instant, Flight)>>,
+}
+
+impl Coalescer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The response `request` shares with identical ones, and whether it was
+    /// already started by one of them
+    pub fn join(&self, request: &CompletionRequest) -> (Flight, bool) {
+        let key = serde_json::to_string(&CompletionRequest {
+            session_id: None,
+            ..request.clone()
+        })
+        .unwrap_or_default();
+        let mut flights = self.flights.lock().unwrap_or_else(|e| e.into_inner());
+        // Finished responses are kept for the window; unfinished ones while
+        // a request is still computing or waiting for them
+        flights.retain(|_, (started, flight)| {
+            if flight.initialized() {
+                started.elapsed() < WINDOW
+            } else {
+                Arc::strong_count(flight) > 1
+            }
+        });
+        let joined = flights.contains_key(&key);
+        let (_, flight) = flights
+            .entry(key)
+            .or_insert_with(|| (Instant::now(), Flight::default()));
+        (Arc::clone(flight), joined)
+    }
+}
@@ -0,0 +1,57 @@
+//! Dropping flag suggestions the rest of the command line rules out.
+//!
+//! Prefix matching alone would keep offering `--force` after it was typed,
+//! or any flag after `--`. Only guessed flags are checked here: the spec
+//! resolver already filters spec flags, knowing which ones repeat, and paths
+//! that happen to start with `-` are left alone.
+
+use super::{Suggestion, SuggestionSource};
+use crate::parser::Token;
+use std::collections::HashSet;
+
+/// Remove flags that are invalid given the completed `args` after the command
+pub fn retain_valid(suggestions: &mut Vec<Suggestion>, args: &[Token]) {
+    // Everything after `--` is an operand
+    if args.iter().any(|token| token.text == "--") {
+        suggestions.retain(|suggestion| !is_flag(suggestion));
+        return;
+    }
+    let given = given_flags(args);
+    if given.is_empty() {
+        return;
+    }
+    suggestions
+        .retain(|suggestion| !is_flag(suggestion) || !given.contains(flag_name(&suggestion.text)));
+}
+
+fn is_flag(suggestion: &Suggestion) -> bool {
+    suggestion.source == SuggestionSource::Guessed && suggestion.text.starts_with('-')
+}
+
+/// `--output` of `--output=file`
+fn flag_name(text: &str) -> &str {
+    text.split_once('=').map_or(text, |(name, _)| name)
+}
+
+/// Flags present in `args`: `--name[=value]`, and `-abc` both as itself
+/// (for single-dash long options like `find -name`) and as `-a -b -c`
+fn given_flags(args: &[Token]) -> HashSet<String> {
+    let mut given = HashSet::new();
+    for token in args {
+        let text = token.text.as_str();
+        if text.starts_with("--") {
+            given.insert(flag_name(text).to_string());
+        } else if let Some(shorts) = text.strip_prefix('-')
+            && !shorts.is_empty()
+        {
+            given.insert(text.to_string());
+            given.extend(
+                shorts
+                    .chars()
+                    .take_while(char::is_ascii_alphanumeric)
+                    .map(|c| format!("-{c}")),
+            );
+        }
+    }
+    given
+}
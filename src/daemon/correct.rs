@@ -0,0 +1,260 @@
+use super::followup::{edit_distance, max_typo_distance};
+use super::{Suggestion, SuggestionSource, executables};
+use crate::index::CommandIndex;
+use crate::install::Shell;
+use crate::stats::UsageStats;
+
+/// Shorter words are within one edit of too many commands to be worth correcting
+const MIN_WORD_CHARS: usize = 3;
+/// Corrections offered for one word
+const MAX_CORRECTIONS: usize = 3;
+
+/// "Did you mean" suggestions for a command name that doesn't exist.
+///
+/// `word` is the first token of the buffer, still under the cursor. Nothing
+/// is offered while it is a known command or the prefix of one, since the
+/// user may simply not have finished typing. Candidates within typo distance,
+/// the shell's `functions` and the builtins of its `shell` among them, are
+/// ranked by how much, and how recently, they have been used.
+pub fn suggest(
+    word: &str,
+    index: &CommandIndex,
+    functions: &[String],
+    shell: Option<Shell>,
+    stats: &UsageStats,
+) -> Vec<Suggestion> {
+    if word.chars().count() < MIN_WORD_CHARS || word.contains('/') {
+        return Vec::new();
+    }
+    let known = || {
+        index
+            .executables
+            .keys()
+            .chain(&index.specs)
+            .chain(functions)
+            .map(String::as_str)
+            .chain(executables::builtin_names(shell))
+    };
+    if known().any(|command| command.starts_with(word)) {
+        return Vec::new();
+    }
+
+    let max = max_typo_distance(word);
+    let mut candidates: Vec<(usize, u64, &str)> = known()
+        .filter_map(|command| {
+            let distance = edit_distance(word, command);
+            (distance <= max).then(|| (distance, stats.frecency(command), command))
+        })
+        .collect();
+    // Most used first; ties go to the closer, then the alphabetically first, match
+    candidates.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)).then(a.2.cmp(b.2)));
+    candidates.dedup_by_key(|(_, _, command)| *command);
+
+    candidates
+        .into_iter()
+        .take(MAX_CORRECTIONS)
+        .map(|(_, _, command)| Suggestion {
+            text: command.to_string(),
+            description: format!("Correction: did you mean `{command}`?"),
+            source: SuggestionSource::Correction,
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// Near spellings of the long flag being typed, for when none of the spec's
+/// `flags` matches it.
+///
+/// `word` is compared with the start of each flag as long as it, give or
+/// take a character, so a typo is caught before the flag is typed in full:
+/// `--vreb` is offered `--verbose`. The closest come first, then those of
+/// the spec's order.
+pub fn flags(word: &str, flags: &[Suggestion]) -> Vec<Suggestion> {
+    let Some(typed) = word.strip_prefix("--") else {
+        return Vec::new();
+    };
+    if typed.chars().count() < MIN_WORD_CHARS || typed.contains('=') {
+        return Vec::new();
+    }
+    let max = max_typo_distance(typed);
+    let length = typed.chars().count();
+    let mut candidates: Vec<(usize, &Suggestion)> = flags
+        .iter()
+        .filter_map(|flag| {
+            let name = flag.text.strip_prefix("--")?;
+            let distance = (length.saturating_sub(1)..=length + 1)
+                .map(|prefix| {
+                    let start: String = name.chars().take(prefix).collect();
+                    edit_distance(typed, &start)
+                })
+                .min()?;
+            (distance <= max).then_some((distance, flag))
+        })
+        .collect();
+    candidates.sort_by_key(|(distance, _)| *distance);
+    candidates.dedup_by(|a, b| a.1.text == b.1.text);
+
+    candidates
+        .into_iter()
+        .take(MAX_CORRECTIONS)
+        .map(|(_, flag)| Suggestion {
+            text: flag.text.clone(),
+            description: if flag.description.is_empty() {
+                format!("Correction: did you mean `{}`?", flag.text)
+            } else {
+                format!(
+                    "Correction: did you mean `{}`? {}",
+                    flag.text, flag.description
+                )
+            },
+            source: SuggestionSource::Correction,
+            ..Default::default()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(commands: &[&str]) -> CommandIndex {
+        CommandIndex {
+            executables: commands
+                .iter()
+                .map(|command| (command.to_string(), format!("/usr/bin/{command}").into()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn texts(suggestions: &[Suggestion]) -> Vec<&str> {
+        suggestions
+            .iter()
+            .map(|suggestion| suggestion.text.as_str())
+            .collect()
+    }
+
+    fn commands(word: &str, index: &CommandIndex, stats: &UsageStats) -> Vec<String> {
+        let suggestions = suggest(word, index, &[], None, stats);
+        texts(&suggestions).into_iter().map(String::from).collect()
+    }
+
+    fn flag_list(names: &[&str]) -> Vec<Suggestion> {
+        names
+            .iter()
+            .map(|name| Suggestion {
+                text: name.to_string(),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_suggest_distance_threshold_grows_with_length() {
+        let index = index(&["make", "docker", "cargo"]);
+        let stats = UsageStats::default();
+        // One edit for words of four characters or fewer
+        assert_eq!(commands("mkae", &index, &stats), ["make"]);
+        assert!(commands("mqqe", &index, &stats).is_empty());
+        // Two for longer ones
+        assert_eq!(commands("dcoekr", &index, &stats), ["docker"]);
+        assert!(commands("dxxxer", &index, &stats).is_empty());
+    }
+
+    #[test]
+    fn test_suggest_short_words_paths_and_prefixes_get_nothing() {
+        let index = index(&["go", "gofmt", "kubectl"]);
+        let stats = UsageStats::default();
+        assert!(commands("og", &index, &stats).is_empty());
+        assert!(commands("./kubectk", &index, &stats).is_empty());
+        // Perhaps still being typed
+        assert!(commands("kube", &index, &stats).is_empty());
+    }
+
+    #[test]
+    fn test_suggest_frecency_orders_corrections() {
+        let index = index(&["docker", "docked"]);
+        let mut stats = UsageStats::default();
+        // Unused, the closer match comes first
+        assert_eq!(commands("dcoker", &index, &stats), ["docker", "docked"]);
+        stats.record_command("docked");
+        assert_eq!(commands("dcoker", &index, &stats), ["docked", "docker"]);
+    }
+
+    #[test]
+    fn test_suggest_ties_go_alphabetically_and_are_capped() {
+        let index = index(&["hut", "got", "git", "get", "gat"]);
+        let stats = UsageStats::default();
+        assert_eq!(commands("gut", &index, &stats), ["gat", "get", "git"]);
+    }
+
+    #[test]
+    fn test_suggest_functions_and_shell_builtins_are_candidates() {
+        let index = index(&["make", "deploy"]);
+        let stats = UsageStats::default();
+        let functions = vec!["deploy".to_string(), "greet".to_string()];
+        let corrections = suggest("gerte", &index, &functions, None, &stats);
+        assert_eq!(texts(&corrections), ["greet"]);
+        // Offered once, though both an executable and a function
+        let corrections = suggest("depoly", &index, &functions, None, &stats);
+        assert_eq!(texts(&corrections), ["deploy"]);
+        assert_eq!(corrections[0].source, SuggestionSource::Correction);
+        assert_eq!(
+            corrections[0].description,
+            "Correction: did you mean `deploy`?"
+        );
+        let corrections = suggest("stirng", &index, &[], Some(Shell::Fish), &stats);
+        assert_eq!(texts(&corrections), ["string"]);
+        assert!(suggest("stirng", &index, &[], Some(Shell::Bash), &stats).is_empty());
+    }
+
+    #[test]
+    fn test_flags_prefix_typo_is_corrected() {
+        let flags = flag_list(&["--version", "--verbose", "--quiet"]);
+        assert_eq!(texts(&super::flags("--vreb", &flags)), ["--verbose"]);
+        assert_eq!(texts(&super::flags("--qiuet", &flags)), ["--quiet"]);
+    }
+
+    #[test]
+    fn test_flags_distance_threshold_grows_with_length() {
+        let flags = flag_list(&["--verbose", "--message"]);
+        // One edit for four characters typed
+        assert!(super::flags("--vxrx", &flags).is_empty());
+        // Two for more
+        assert_eq!(texts(&super::flags("--mxsaage", &flags)), ["--message"]);
+        assert!(super::flags("--mxsxxge", &flags).is_empty());
+    }
+
+    #[test]
+    fn test_flags_closest_first_then_spec_order() {
+        let flags = flag_list(&["--merge", "--message"]);
+        assert_eq!(
+            texts(&super::flags("--mesage", &flags)),
+            ["--message", "--merge"]
+        );
+        let flags = flag_list(&["--colour", "--color"]);
+        assert_eq!(
+            texts(&super::flags("--colr", &flags)),
+            ["--colour", "--color"]
+        );
+    }
+
+    #[test]
+    fn test_flags_short_values_and_short_options_get_nothing() {
+        let flags = flag_list(&["--verbose", "-v"]);
+        assert!(super::flags("--ve", &flags).is_empty());
+        assert!(super::flags("--vreb=1", &flags).is_empty());
+        assert!(super::flags("-vreb", &flags).is_empty());
+    }
+
+    #[test]
+    fn test_flags_description_follows_the_question() {
+        let mut flags = flag_list(&["--verbose"]);
+        flags[0].description = "Print more".to_string();
+        let corrections = super::flags("--vreb", &flags);
+        assert_eq!(
+            corrections[0].description,
+            "Correction: did you mean `--verbose`? Print more"
+        );
+    }
+}
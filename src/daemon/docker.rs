@@ -0,0 +1,410 @@
+//! Docker completions asked of the Docker daemon itself.
+//!
+//! The docker spec lists containers, images, volumes, networks and compose
+//! services with generators that run the `docker` CLI, which takes a good
+//! part of a second to start. Those scripts are recognized here and answered
+//! through the Engine API on the daemon's unix socket instead, keeping the
+//! answer for the generator's time to live, and compose services are read
+//! from the compose file. A context or `DOCKER_HOST` other than a local
+//! socket, a compose file using `include` or YAML this reader doesn't
+//! support, and anything else not understood, is left to the script.
+
+use super::{CompletionRequest, Suggestion, SuggestionSource};
+use crate::paths;
+use crate::specs::Generator;
+use crate::specs::yaml;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+
+/// Where the Docker daemon listens unless `DOCKER_HOST` says otherwise
+const DEFAULT_SOCKET: &str = "/var/run/docker.sock";
+/// How long the Docker daemon gets to answer before the script is run
+const API_TIMEOUT: Duration = Duration::from_millis(500);
+/// Longest API response read
+const MAX_RESPONSE_BYTES: u64 = 16 * 1024 * 1024;
+/// Names of the compose file, in the order `docker compose` looks for them
+const COMPOSE_FILES: &[&str] = &[
+    "compose.yaml",
+    "compose.yml",
+    "docker-compose.yaml",
+    "docker-compose.yml",
+];
+/// Shortest typed word matched against container IDs
+const MIN_ID_PREFIX: usize = 2;
+/// Length of a container ID as `docker ps` shows it
+const SHORT_ID: usize = 12;
+/// Answers kept before the cache is reset
+const MAX_CACHED_ANSWERS: usize = 64;
+
+/// What a recognized generator lists
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Query {
+    /// `docker ps`, with `--all`, or of the containers in these states
+    Containers { all: bool, states: Vec<String> },
+    /// `docker images`
+    Images,
+    /// `docker volume ls`
+    Volumes,
+    /// `docker network ls`
+    Networks,
+    /// `docker compose config --services`
+    Services,
+}
+
+/// A container, image, volume or network, and the ID of a container
+#[derive(Debug, Clone)]
+struct Found {
+    suggestion: Suggestion,
+    id: Option<String>,
+}
+
+/// What an answer is kept under
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Key {
+    socket: PathBuf,
+    query: Query,
+}
+
+/// What the Docker daemon listed, and when
+#[derive(Debug)]
+struct Cached {
+    found: Vec<Found>,
+    stored: Instant,
+}
+
+/// What the Docker daemon listed, per socket and query
+#[derive(Debug, Default)]
+pub struct Answers {
+    cached: Mutex<HashMap<Key, Cached>>,
+}
+
+impl Answers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn get(&self, key: &Key, ttl: Duration) -> Option<Vec<Found>> {
+        let cached = self.cached.lock().unwrap_or_else(|e| e.into_inner());
+        let cached = cached.get(key)?;
+        (cached.stored.elapsed() < ttl).then(|| cached.found.clone())
+    }
+
+    fn store(&self, key: Key, found: Vec<Found>) {
+        let mut cached = self.cached.lock().unwrap_or_else(|e| e.into_inner());
+        if cached.len() >= MAX_CACHED_ANSWERS && !cached.contains_key(&key) {
+            cached.clear();
+        }
+        let stored = Instant::now();
+        cached.insert(key, Cached { found, stored });
+    }
+}
+
+/// Answer `generator` for `request` without running it, reusing an answer
+/// for `ttl`; `None` when its script isn't one of docker's known here or the
+/// Docker daemon can't be asked. Container IDs are offered too once `word`
+/// looks like the start of one.
+pub async fn answer(
+    generator: &Generator<'_>,
+    request: &CompletionRequest,
+    cwd: Option<&Path>,
+    word: &str,
+    ttl: Duration,
+    answers: &Answers,
+) -> Option<Vec<Suggestion>> {
+    if generator.split_on.is_some() {
+        return None;
+    }
+    let script: Vec<&str> = generator.script.iter().map(|part| part.as_ref()).collect();
+    let query = recognize(&script)?;
+    if query == Query::Services {
+        return services(cwd?);
+    }
+
+    let key = Key {
+        socket: request.local_path(&socket(request)?),
+        query,
+    };
+    let found = match answers.get(&key, ttl) {
+        Some(found) => found,
+        None => {
+            let found = ask(&key.socket, &key.query).await?;
+            answers.store(key, found.clone());
+            found
+        }
+    };
+    Some(with_ids(found, word))
+}
+
+fn recognize(script: &[&str]) -> Option<Query> {
+    let (query, flags) = match script {
+        ["docker", "ps", flags @ ..] | ["docker", "container", "ls", flags @ ..] => {
+            let mut all = false;
+            let mut states = Vec::new();
+            let mut flags = flags.iter();
+            while let Some(&flag) = flags.next() {
+                match flag {
+                    "-a" | "--all" => all = true,
+                    "--filter" => states.push(flags.next()?.strip_prefix("status=")?.to_string()),
+                    "--format" => {
+                        flags.next()?;
+                    }
+                    _ => return None,
+                }
+            }
+            return Some(Query::Containers { all, states });
+        }
+        ["docker", "images", flags @ ..] | ["docker", "image", "ls", flags @ ..] => {
+            (Query::Images, flags)
+        }
+        ["docker", "volume", "ls" | "list", flags @ ..] => (Query::Volumes, flags),
+        ["docker", "network", "ls" | "list", flags @ ..] => (Query::Networks, flags),
+        ["docker", "compose", "config", "--services"]
+        | ["docker-compose", "config", "--services"] => (Query::Services, &[][..]),
+        _ => return None,
+    };
+    // Only the format may be chosen, which the answer doesn't depend on
+    match flags {
+        [] | ["--format", _] => Some(query),
+        _ => None,
+    }
+}
+
+/// The Docker daemon's socket for `request`, if it is a local one the
+/// default context talks to
+fn socket(request: &CompletionRequest) -> Option<PathBuf> {
+    if request.env.contains_key("DOCKER_CONTEXT") {
+        return None;
+    }
+    if let Some(host) = request.env.get("DOCKER_HOST") {
+        return host.strip_prefix("unix://").map(PathBuf::from);
+    }
+    // Docker Desktop and rootless setups switch contexts in the CLI's config
+    let home = request
+        .env
+        .get("HOME")
+        .map(PathBuf::from)
+        .or_else(paths::home_dir);
+    if let Some(home) = home
+        && let Ok(config) = std::fs::read(request.local_path(&home.join(".docker/config.json")))
+        && let Ok(config) = serde_json::from_slice::<Value>(&config)
+        && let Some(context) = config.get("currentContext").and_then(Value::as_str)
+        && context != "default"
+    {
+        return None;
+    }
+    Some(PathBuf::from(DEFAULT_SOCKET))
+}
+
+/// Ask the Docker daemon on `socket` for what `query` lists
+async fn ask(socket: &Path, query: &Query) -> Option<Vec<Found>> {
+    match query {
+        Query::Containers { all, states } => {
+            let path = if *all || !states.is_empty() {
+                "/containers/json?all=1"
+            } else {
+                "/containers/json"
+            };
+            let containers = get(socket, path).await?;
+            Some(
+                containers
+                    .as_array()?
+                    .iter()
+                    .filter(|container| {
+                        states.is_empty()
+                            || container
+                                .get("State")
+                                .and_then(Value::as_str)
+                                .is_some_and(|state| states.iter().any(|s| s == state))
+                    })
+                    .filter_map(container)
+                    .collect(),
+            )
+        }
+        Query::Images => {
+            let images = get(socket, "/images/json").await?;
+            let mut found = Vec::new();
+            for image in images.as_array()? {
+                let size = image.get("Size").and_then(Value::as_u64).unwrap_or(0);
+                let tags = image.get("RepoTags").and_then(Value::as_array);
+                for tag in tags.into_iter().flatten().filter_map(Value::as_str) {
+                    if tag != "<none>:<none>" {
+                        found.push(Found {
+                            suggestion: suggestion(tag, &human_size(size)),
+                            id: None,
+                        });
+                    }
+                }
+            }
+            Some(found)
+        }
+        Query::Volumes => {
+            let volumes = get(socket, "/volumes").await?;
+            Some(named(volumes.get("Volumes")?, "Driver", "volume"))
+        }
+        Query::Networks => Some(named(&get(socket, "/networks").await?, "Driver", "network")),
+        Query::Services => None,
+    }
+}
+
+/// `GET path` of the Engine API, the body of a successful response
+async fn get(socket: &Path, path: &str) -> Option<Value> {
+    let fetch = async {
+        let mut stream = UnixStream::connect(socket).await.ok()?;
+        // HTTP/1.0, so the body ends with the connection rather than in chunks
+        let request = format!("GET {path} HTTP/1.0\r\nHost: docker\r\n\r\n");
+        stream.write_all(request.as_bytes()).await.ok()?;
+        let mut response = Vec::new();
+        stream
+            .take(MAX_RESPONSE_BYTES)
+            .read_to_end(&mut response)
+            .await
+            .ok()?;
+        let end = response.windows(4).position(|bytes| bytes == b"\r\n\r\n")?;
+        let status = std::str::from_utf8(&response[..end]).ok()?.lines().next()?;
+        if status.split_whitespace().nth(1) != Some("200") {
+            return None;
+        }
+        serde_json::from_slice(&response[end + 4..]).ok()
+    };
+    tokio::time::timeout(API_TIMEOUT, fetch)
+        .await
+        .ok()
+        .flatten()
+}
+
+/// `web`, described like `nginx:latest, Up 2 hours`
+fn container(container: &Value) -> Option<Found> {
+    let name = container
+        .get("Names")?
+        .as_array()?
+        .first()?
+        .as_str()?
+        .trim_start_matches('/');
+    let image = container
+        .get("Image")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    let status = container
+        .get("Status")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    Some(Found {
+        suggestion: suggestion(name, &format!("{image}, {status}")),
+        id: container
+            .get("Id")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+    })
+}
+
+/// The suggestions, or the short IDs of the containers whose ID starts with
+/// `word` when no name does
+fn with_ids(found: Vec<Found>, word: &str) -> Vec<Suggestion> {
+    let looks_like_id =
+        word.len() >= MIN_ID_PREFIX && word.bytes().all(|byte| byte.is_ascii_hexdigit());
+    if !looks_like_id
+        || found
+            .iter()
+            .any(|found| found.suggestion.text.starts_with(word))
+    {
+        return found.into_iter().map(|found| found.suggestion).collect();
+    }
+    found
+        .into_iter()
+        .filter_map(|found| {
+            let id = found.id.filter(|id| id.starts_with(word))?;
+            let Suggestion {
+                text, description, ..
+            } = found.suggestion;
+            Some(suggestion(
+                &id.chars().take(SHORT_ID).collect::<String>(),
+                &format!("{text}, {description}"),
+            ))
+        })
+        .collect()
+}
+
+/// Objects with a `Name`, described by their `field` and `kind`, like
+/// `local volume`
+fn named(objects: &Value, field: &str, kind: &str) -> Vec<Found> {
+    objects
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|object| {
+            let name = object.get("Name")?.as_str()?;
+            let description = match object.get(field).and_then(Value::as_str) {
+                Some(value) => format!("{value} {kind}"),
+                None => kind.to_string(),
+            };
+            Some(Found {
+                suggestion: suggestion(name, &description),
+                id: None,
+            })
+        })
+        .collect()
+}
+
+/// The services of the compose file in `cwd` or its parents, described by
+/// their image or build context
+fn services(cwd: &Path) -> Option<Vec<Suggestion>> {
+    let file = cwd.ancestors().find_map(|dir| {
+        COMPOSE_FILES
+            .iter()
+            .map(|name| dir.join(name))
+            .find(|file| file.is_file())
+    })?;
+    let compose = yaml::parse(&std::fs::read_to_string(file).ok()?).ok()?;
+    // Included files bring services of their own
+    if compose.get("include").is_some() {
+        return None;
+    }
+    let services = compose.get("services")?.as_object()?;
+    Some(
+        services
+            .iter()
+            .map(|(name, service)| {
+                let build = service.get("build").and_then(|build| match build {
+                    Value::String(context) => Some(context.as_str()),
+                    build => build.get("context").and_then(Value::as_str),
+                });
+                let description = match (service.get("image").and_then(Value::as_str), build) {
+                    (Some(image), _) => image.to_string(),
+                    (None, Some(context)) => format!("Built from {context}"),
+                    (None, None) => String::new(),
+                };
+                suggestion(name, &description)
+            })
+            .collect(),
+    )
+}
+
+/// `142 MB`
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "kB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1000.0 && unit < UNITS.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+    if unit == 0 || size >= 10.0 {
+        format!("{size:.0} {}", UNITS[unit])
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+fn suggestion(text: &str, description: &str) -> Suggestion {
+    Suggestion {
+        text: text.to_string(),
+        description: description.to_string(),
+        source: SuggestionSource::Spec,
+        ..Default::default()
+    }
+}
@@ -0,0 +1,70 @@
+//! Example invocations of a command, for when only its name has been typed.
+//!
+//! Rarely used tools are easier to run from an example than from a list of
+//! flags. Examples come from a small dataset adapted from tldr-pages and are
+//! offered as the arguments to insert after the command, their tldr
+//! `{{placeholders}}` turned into snippet fields to fill in.
+
+use super::{Suggestion, SuggestionSource};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::OnceLock;
+
+/// `page<TAB>example<TAB>explanation` lines
+const BUNDLED: &str = include_str!("../../data/tldr-examples.tsv");
+
+/// Up to `limit` examples for the command made of `words` (`["git",
+/// "commit"]`), without those words
+pub fn suggest(words: &[&str], limit: usize) -> Vec<Suggestion> {
+    let Some(examples) = bundled().get(words.join("-").as_str()) else {
+        return Vec::new();
+    };
+    let prefix = format!("{} ", words.join(" "));
+
+    examples
+        .iter()
+        .filter_map(|(example, explanation)| {
+            Some(Suggestion {
+                text: snippet(example.strip_prefix(&prefix)?),
+                description: explanation.to_string(),
+                source: SuggestionSource::Example,
+                ..Default::default()
+            })
+        })
+        .take(limit)
+        .collect()
+}
+
+fn bundled() -> &'static HashMap<&'static str, Vec<(&'static str, &'static str)>> {
+    static TABLE: OnceLock<HashMap<&'static str, Vec<(&'static str, &'static str)>>> =
+        OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table: HashMap<_, Vec<_>> = HashMap::new();
+        for line in BUNDLED.lines().filter(|line| !line.starts_with('#')) {
+            let mut fields = line.splitn(3, '\t');
+            if let (Some(page), Some(example), Some(explanation)) =
+                (fields.next(), fields.next(), fields.next())
+            {
+                table.entry(page).or_default().push((example, explanation));
+            }
+        }
+        table
+    })
+}
+
+/// `{{placeholder}}` → `${n:placeholder}`, numbered in order of appearance
+fn snippet(example: &str) -> String {
+    let mut text = String::new();
+    let mut rest = example;
+    let mut field = 0;
+    while let Some(start) = rest.find("{{")
+        && let Some(len) = rest[start + 2..].find("}}")
+    {
+        field += 1;
+        text.push_str(&rest[..start]);
+        let _ = write!(text, "${{{field}:{}}}", &rest[start + 2..start + 2 + len]);
+        rest = &rest[start + 2 + len + 2..];
+    }
+    text.push_str(rest);
+    text
+}
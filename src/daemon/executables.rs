@@ -0,0 +1,235 @@
+//! Command names for the first word of a command.
+//!
+//! Executables come from the command index, which follows the client's
+//! `PATH` and is rescanned in the background, so completing a command name
+//! never lists a directory. Builtins and keywords aren't on `PATH`; the
+//! common ones of the client's shell, as named by the request, are listed
+//! here with a description of their own, those of zsh and bash when it names
+//! none. The shell's functions come with the request, when the integration
+//! sends them.
+
+use super::{Suggestion, SuggestionSource};
+use crate::index::CommandIndex;
+use crate::install::Shell;
+use crate::parser::Token;
+use std::collections::BTreeMap;
+
+/// Builtins of both zsh and bash that have no executable of the same name
+const BUILTINS: &[(&str, &str)] = &[
+    ("alias", "Define or list aliases"),
+    ("bg", "Resume a job in the background"),
+    ("builtin", "Run a shell builtin"),
+    ("cd", "Change the working directory"),
+    ("command", "Run a command, bypassing functions"),
+    ("declare", "Declare variables and their attributes"),
+    ("dirs", "List the directory stack"),
+    ("disown", "Remove a job from the job table"),
+    ("eval", "Run arguments as a shell command"),
+    ("exec", "Replace the shell with a command"),
+    ("exit", "Exit the shell"),
+    ("export", "Set environment variables"),
+    ("fc", "Edit and rerun commands from history"),
+    ("fg", "Bring a job to the foreground"),
+    ("getopts", "Parse positional options"),
+    ("hash", "Remember or forget command locations"),
+    ("history", "List the command history"),
+    ("jobs", "List jobs"),
+    ("let", "Evaluate arithmetic"),
+    ("local", "Declare variables local to a function"),
+    ("popd", "Pop a directory off the stack and change to it"),
+    ("pushd", "Push a directory onto the stack and change to it"),
+    ("read", "Read a line into variables"),
+    ("readonly", "Make variables read-only"),
+    ("return", "Return from a function"),
+    ("set", "Set shell options and positional parameters"),
+    ("shift", "Shift positional parameters"),
+    ("source", "Run commands from a file in this shell"),
+    ("trap", "Run a command on a signal"),
+    ("type", "Tell how a name would be run"),
+    ("typeset", "Declare variables and their attributes"),
+    ("ulimit", "Get or set resource limits"),
+    ("umask", "Get or set the file creation mask"),
+    ("unalias", "Remove aliases"),
+    ("unset", "Unset variables and functions"),
+    ("wait", "Wait for jobs to finish"),
+];
+
+/// Builtins of zsh alone
+const ZSH_BUILTINS: &[(&str, &str)] = &[
+    ("autoload", "Mark functions to be loaded on first use"),
+    ("bindkey", "Bind keys to editor widgets"),
+    ("emulate", "Emulate another shell's behavior"),
+    ("noglob", "Run a command without filename expansion"),
+    ("print", "Print arguments, with formatting options"),
+    ("rehash", "Rebuild the table of commands"),
+    ("setopt", "Turn shell options on"),
+    ("unsetopt", "Turn shell options off"),
+    ("whence", "Tell how a name would be run"),
+    ("zle", "Control the line editor"),
+    ("zmodload", "Load shell modules"),
+    ("zstyle", "Define styles for completion and widgets"),
+];
+
+/// Builtins of bash alone
+const BASH_BUILTINS: &[(&str, &str)] = &[
+    ("bind", "Bind keys to readline functions"),
+    ("caller", "Print the context of a function call"),
+    ("compgen", "Generate completion matches"),
+    ("complete", "Define how arguments are completed"),
+    ("help", "Describe builtin commands"),
+    ("mapfile", "Read lines into an array"),
+    ("shopt", "Set or unset shell options"),
+];
+
+/// Keywords of zsh and bash, which start or continue a compound command
+const SH_KEYWORDS: &[(&str, &str)] = &[
+    ("case", "Run commands for the pattern a word matches"),
+    ("do", "Start the body of a loop"),
+    ("done", "End a loop"),
+    ("elif", "Test another condition"),
+    ("else", "Run commands when no condition held"),
+    ("esac", "End a case statement"),
+    ("fi", "End an if statement"),
+    ("for", "Run commands for each word"),
+    ("function", "Define a function"),
+    ("if", "Run commands when a condition holds"),
+    ("select", "Run commands for a word chosen from a menu"),
+    ("then", "Start the commands for a condition"),
+    ("time", "Time a pipeline"),
+    ("until", "Run commands until a condition holds"),
+    ("while", "Run commands while a condition holds"),
+];
+
+/// Builtins of fish that have no executable of the same name
+const FISH_BUILTINS: &[(&str, &str)] = &[
+    ("abbr", "Manage abbreviations"),
+    ("argparse", "Parse the options of a function"),
+    ("bg", "Resume a job in the background"),
+    ("bind", "Bind keys to commands"),
+    ("builtin", "Run a fish builtin"),
+    ("cd", "Change the working directory"),
+    ("command", "Run a command, bypassing functions"),
+    ("commandline", "Read or change the command line"),
+    ("complete", "Define how arguments are completed"),
+    ("contains", "Tell whether a word is among the arguments"),
+    ("count", "Count the arguments"),
+    ("emit", "Emit an event"),
+    ("eval", "Run arguments as a fish command"),
+    ("exec", "Replace the shell with a command"),
+    ("exit", "Exit the shell"),
+    ("fg", "Bring a job to the foreground"),
+    ("functions", "List or change functions"),
+    ("history", "Search or edit the command history"),
+    ("jobs", "List jobs"),
+    ("math", "Evaluate arithmetic"),
+    ("read", "Read a line into variables"),
+    ("return", "Return from a function"),
+    ("set", "Set or list variables"),
+    ("set_color", "Set the terminal's colors"),
+    ("source", "Run commands from a file in this shell"),
+    ("status", "Query the shell's state"),
+    ("string", "Manipulate strings"),
+    ("type", "Tell how a name would be run"),
+    ("ulimit", "Get or set resource limits"),
+    ("wait", "Wait for jobs to finish"),
+];
+
+/// Keywords of fish
+const FISH_KEYWORDS: &[(&str, &str)] = &[
+    ("and", "Run a command if the previous one succeeded"),
+    ("begin", "Start a block"),
+    ("break", "Leave the loop"),
+    ("case", "Run commands for a matching pattern"),
+    ("continue", "Go on with the next iteration"),
+    ("else", "Run commands when no condition held"),
+    ("end", "End a block"),
+    ("for", "Run commands for each word"),
+    ("function", "Define a function"),
+    ("if", "Run commands when a condition holds"),
+    ("not", "Negate a command's status"),
+    ("or", "Run a command if the previous one failed"),
+    ("switch", "Run commands for the pattern a word matches"),
+    ("time", "Time a command"),
+    ("while", "Run commands while a condition holds"),
+];
+
+/// Keywords after which a command is named, by dialect
+const SH_COMMAND_KEYWORDS: &[&str] = &[
+    "!", "{", "do", "elif", "else", "if", "then", "time", "until", "while",
+];
+const FISH_COMMAND_KEYWORDS: &[&str] =
+    &["and", "begin", "else", "if", "not", "or", "time", "while"];
+
+/// Builtins and keywords of `shell`, zsh's and bash's when unknown
+fn builtins(shell: Option<Shell>) -> impl Iterator<Item = &'static (&'static str, &'static str)> {
+    let lists: &[&[(&str, &str)]] = match shell {
+        Some(Shell::Fish) => &[FISH_BUILTINS, FISH_KEYWORDS],
+        Some(Shell::Zsh) => &[BUILTINS, ZSH_BUILTINS, SH_KEYWORDS],
+        Some(Shell::Bash) => &[BUILTINS, BASH_BUILTINS, SH_KEYWORDS],
+        None => &[BUILTINS, SH_KEYWORDS],
+    };
+    lists.iter().flat_map(|list| list.iter())
+}
+
+/// Names of the builtins and keywords of `shell`
+pub fn builtin_names<'a>(shell: Option<Shell>) -> impl Iterator<Item = &'a str> {
+    builtins(shell).map(|&(name, _)| -> &'a str { name })
+}
+
+/// The words of a command without the keywords before its name, as in
+/// `if git diff --quiet`
+pub fn skip_keywords(mut tokens: Vec<Token>, shell: Option<Shell>) -> Vec<Token> {
+    let keywords = match shell {
+        Some(Shell::Fish) => FISH_COMMAND_KEYWORDS,
+        _ => SH_COMMAND_KEYWORDS,
+    };
+    let keywords = tokens
+        .iter()
+        .take_while(|token| keywords.contains(&token.text.as_str()))
+        .count();
+    tokens.drain(..keywords);
+    tokens
+}
+
+/// Executables, builtins and keywords of `shell` and the shell's `functions`
+/// starting with `word`, in alphabetical order; all of them when nothing is
+/// typed yet
+pub fn suggest(
+    word: &str,
+    index: &CommandIndex,
+    functions: &[String],
+    shell: Option<Shell>,
+) -> Vec<Suggestion> {
+    // `FOO=bar cmd` assigns a variable rather than naming a command
+    if word.contains('=') {
+        return Vec::new();
+    }
+    let mut names: BTreeMap<&str, &str> = index
+        .executables
+        .keys()
+        .filter(|name| name.starts_with(word))
+        .map(|name| (name.as_str(), ""))
+        .collect();
+    // What the shell runs for a builtin's name is the builtin
+    for &(name, description) in builtins(shell) {
+        if name.starts_with(word) {
+            names.insert(name, description);
+        }
+    }
+    // and for a function's name, the function
+    names.extend(
+        functions
+            .iter()
+            .filter(|name| name.starts_with(word))
+            .map(|name| (name.as_str(), "Shell function")),
+    );
+    names
+        .into_iter()
+        .map(|(name, description)| Suggestion {
+            text: name.to_string(),
+            description: description.to_string(),
+            source: SuggestionSource::Command,
+            ..Default::default()
+        })
+        .collect()
+}
@@ -0,0 +1,294 @@
+//! Path completion.
+//!
+//! Directories are read on a blocking thread and their entries published in
+//! batches, so a directory with tens of thousands of entries doesn't hold up
+//! the response: a request waits for one page of matches (or the `files_ms`
+//! budget), answers with what has been read, and reports that more is coming.
+//! The client fetches the rest page by page while the popup is open.
+
+use super::{CompletionRequest, Suggestion, SuggestionSource};
+use crate::parser::Token;
+use crate::paths;
+use std::collections::HashMap;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Instant, SystemTime};
+
+/// Entries read before they are published to waiting requests
+const BATCH_SIZE: usize = 512;
+/// Directory listings remembered before the cache is reset
+const MAX_CACHED_LISTINGS: usize = 32;
+
+/// A directory entry
+#[derive(Debug, Clone)]
+struct Entry {
+    name: String,
+    is_dir: bool,
+}
+
+/// Entries read so far from one directory
+#[derive(Debug, Default)]
+struct Progress {
+    entries: Vec<Entry>,
+    /// Whether the whole directory has been read
+    done: bool,
+}
+
+/// A directory being read, with its modification time when reading started
+#[derive(Debug)]
+struct Listing {
+    modified: SystemTime,
+    progress: Mutex<Progress>,
+    /// Notified after every published batch
+    updated: Condvar,
+}
+
+impl Listing {
+    /// Read `dir`, publishing entries in batches.
+    ///
+    /// Small directories are sorted; larger ones keep the order the
+    /// filesystem returns, since earlier pages may already have been sent.
+    fn read(&self, dir: &Path) {
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        let mut first = true;
+        if let Ok(entries) = std::fs::read_dir(dir) {
+            for entry in entries.flatten() {
+                let is_dir = match entry.file_type() {
+                    // Follow symlinks so a link to a directory completes like one
+                    Ok(kind) if kind.is_symlink() => entry.path().is_dir(),
+                    Ok(kind) => kind.is_dir(),
+                    Err(_) => false,
+                };
+                batch.push(Entry {
+                    name: entry.file_name().to_string_lossy().into_owned(),
+                    is_dir,
+                });
+                if batch.len() >= BATCH_SIZE {
+                    self.publish(&mut batch, false);
+                    first = false;
+                }
+            }
+        }
+        if first {
+            batch.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+        self.publish(&mut batch, true);
+    }
+
+    fn publish(&self, batch: &mut Vec<Entry>, done: bool) {
+        let mut progress = self.progress.lock().unwrap_or_else(|e| e.into_inner());
+        progress.entries.append(batch);
+        progress.done = done;
+        self.updated.notify_all();
+    }
+}
+
+/// Directory listings shared between requests, invalidated when the
+/// directory changes
+#[derive(Debug, Default)]
+pub struct Listings {
+    dirs: Mutex<HashMap<PathBuf, Arc<Listing>>>,
+}
+
+impl Listings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The listing of `dir`, starting to read it if it changed since last
+    /// time, and whether it was already read or being read
+    fn get(&self, dir: &Path) -> Option<(Arc<Listing>, bool)> {
+        let meta = std::fs::metadata(dir).ok()?;
+        if !meta.is_dir() {
+            return None;
+        }
+        let modified = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+
+        let mut dirs = self.dirs.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(listing) = dirs.get(dir)
+            && listing.modified == modified
+        {
+            return Some((Arc::clone(listing), true));
+        }
+        let listing = Arc::new(Listing {
+            modified,
+            progress: Mutex::default(),
+            updated: Condvar::new(),
+        });
+        if dirs.len() >= MAX_CACHED_LISTINGS {
+            dirs.clear();
+        }
+        dirs.insert(dir.to_path_buf(), Arc::clone(&listing));
+
+        let reader = Arc::clone(&listing);
+        let dir = dir.to_path_buf();
+        tokio::task::spawn_blocking(move || reader.read(&dir));
+        Some((listing, false))
+    }
+}
+
+/// Which entries path completion offers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathKind {
+    /// Files and directories
+    All,
+    /// Directories only
+    Folders,
+    /// A path typed as the command: directories to change to, and
+    /// executables to run
+    Command,
+}
+
+/// Whether the first word of a command line is a path rather than a
+/// command name to look up
+pub fn looks_like_path(word: &str) -> bool {
+    word.contains('/') || matches!(word, "." | ".." | "~")
+}
+
+/// Whether `path`, typed as a command, names a directory to change to or
+/// an executable file
+pub fn is_runnable(request: &CompletionRequest, path: &str) -> bool {
+    let home = request
+        .env
+        .get("HOME")
+        .map(PathBuf::from)
+        .or_else(paths::home_dir);
+    let path = match (path.strip_prefix("~/"), home) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ if path == "~" => return true,
+        _ => match &request.cwd {
+            Some(cwd) => cwd.join(path),
+            None => PathBuf::from(path),
+        },
+    };
+    std::fs::metadata(request.local_path(&path))
+        .is_ok_and(|meta| meta.is_dir() || meta.permissions().mode() & 0o111 != 0)
+}
+
+/// Paths matching the word being typed
+#[derive(Debug, Default)]
+pub struct Matches {
+    pub suggestions: Vec<Suggestion>,
+    /// Whether the directory has been read in full
+    pub complete: bool,
+    /// Whether the directory's listing was cached from an earlier request
+    pub cached: bool,
+}
+
+/// Paths of `kind` starting with `word`, waiting until `wanted` of them are
+/// found, the directory is read, or `deadline` passes.
+///
+/// Matches come in the order the directory was read, so a request repeated
+/// later returns the same matches first and offsets into them stay valid.
+pub fn suggest(
+    request: &CompletionRequest,
+    token: &Token,
+    kind: PathKind,
+    wanted: usize,
+    listings: &Listings,
+    deadline: Instant,
+) -> Matches {
+    let Some(cwd) = &request.cwd else {
+        return Matches::default();
+    };
+    let word = token.text.as_str();
+    // `..` names a directory, not (only) the start of `..foo`
+    let named_dir = matches!(word, "." | ".." | "~").then(|| Suggestion {
+        text: format!("{word}/"),
+        source: if kind == PathKind::Command {
+            SuggestionSource::CdPath
+        } else {
+            SuggestionSource::Files
+        },
+        ..Default::default()
+    });
+    if kind == PathKind::Command
+        && let Some(dir) = named_dir
+    {
+        return Matches {
+            suggestions: vec![dir],
+            complete: true,
+            cached: true,
+        };
+    }
+    let (dir_part, prefix) = match word.rfind('/') {
+        Some(slash) => word.split_at(slash + 1),
+        None => ("", word),
+    };
+    // Suggestions keep `$HOME/` as typed, but the lookup needs its value
+    let expanded = token.expand(dir_part.len(), &request.env);
+    let dir = match expanded.strip_prefix("~/") {
+        Some(rest) => match request
+            .env
+            .get("HOME")
+            .map(PathBuf::from)
+            .or_else(paths::home_dir)
+        {
+            Some(home) => home.join(rest),
+            None => return Matches::default(),
+        },
+        None if expanded.is_empty() => cwd.clone(),
+        None => cwd.join(&expanded),
+    };
+    let Some((listing, cached)) = listings.get(&request.local_path(&dir)) else {
+        return Matches::default();
+    };
+
+    // Dotfiles only when asked for, like shells do
+    let matches = |entry: &Entry| {
+        entry.name.starts_with(prefix)
+            && (prefix.starts_with('.') || !entry.name.starts_with('.'))
+            && (entry.is_dir || kind != PathKind::Folders)
+    };
+    // Checked only for the final matches, as it takes a `stat` per file
+    let runnable = |entry: &Entry| {
+        kind != PathKind::Command
+            || entry.is_dir
+            || std::fs::metadata(request.local_path(&dir.join(&entry.name)))
+                .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+    };
+    let (mut checked, mut found) = (0, 0);
+    let progress = listing.progress.lock().unwrap_or_else(|e| e.into_inner());
+    let timeout = deadline.saturating_duration_since(Instant::now());
+    let (progress, _) = listing
+        .updated
+        .wait_timeout_while(progress, timeout, |progress| {
+            found += progress.entries[checked..]
+                .iter()
+                .filter(|entry| matches(entry))
+                .count();
+            checked = progress.entries.len();
+            !progress.done && found < wanted
+        })
+        .unwrap_or_else(|e| e.into_inner());
+
+    Matches {
+        suggestions: named_dir
+            .into_iter()
+            .chain(
+                progress
+                    .entries
+                    .iter()
+                    .filter(|entry| matches(entry) && runnable(entry))
+                    .map(|entry| Suggestion {
+                        text: format!(
+                            "{}{}{}",
+                            dir_part,
+                            entry.name,
+                            if entry.is_dir { "/" } else { "" }
+                        ),
+                        description: String::new(),
+                        source: if entry.is_dir && kind == PathKind::Command {
+                            SuggestionSource::CdPath
+                        } else {
+                            SuggestionSource::Files
+                        },
+                        ..Default::default()
+                    }),
+            )
+            .collect(),
+        complete: progress.done,
+        cached,
+    }
+}
@@ -0,0 +1,188 @@
+//! Serving recorded responses instead of completing (`daemon --fixture`).
+//!
+//! A fixture file is a JSON array of the responses to give for buffers:
+//!
+//! ```json
+//! [
+//!   {"buffer": "git ch", "response": {"suggestions": [{"text": "checkout"}]}},
+//!   {"buffer": "git ch", "cursor": 3, "delay_ms": 500, "response": {"suggestions": []}}
+//! ]
+//! ```
+//!
+//! `cursor` defaults to the end of the buffer and `offset` to the first page,
+//! and `delay_ms` holds the response back to mimic a slow completion. The
+//! `response` is what the daemon would send, so the output of
+//! `complete-batch` can be pasted in. Shell integrations and the popup can
+//! then be exercised end to end with no specs, index or history. The file is
+//! read again for every request, so edits apply right away.
+
+use super::{
+    BatchRequest, BatchResponse, CompletionRequest, CompletionResponse, ControlResponse, Endpoint,
+    ErrorResponse, MAX_REQUEST_BYTES, READ_TIMEOUT, VersionedResponse, listen, write_json,
+};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tracing::{error, info, warn};
+
+/// One recorded response
+#[derive(Debug, Deserialize)]
+struct Entry {
+    buffer: String,
+    cursor: Option<usize>,
+    #[serde(default)]
+    offset: usize,
+    #[serde(default)]
+    delay_ms: u64,
+    response: CompletionResponse,
+}
+
+/// Serve the responses in `fixture` on `socket_path` until interrupted
+pub async fn serve(socket_path: &Path, fixture: &Path) -> Result<()> {
+    // Fail at startup rather than on the first request
+    load(fixture)?;
+    let endpoint = Endpoint::Unix(socket_path.to_path_buf());
+    let listener = listen::Listener::bind(&endpoint).await?;
+    info!("Serving the fixture {} on {}", fixture.display(), endpoint);
+
+    let fixture = Arc::new(fixture.to_path_buf());
+    let accepting = async {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let fixture = Arc::clone(&fixture);
+                    tokio::spawn(async move {
+                        if let Err(e) = handle_connection(stream, &fixture).await {
+                            error!("Connection error: {}", e);
+                        }
+                    });
+                }
+                Err(e) => error!("Failed to accept connection: {}", e),
+            }
+        }
+    };
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result?,
+        () = accepting => {}
+    }
+    let _ = std::fs::remove_file(socket_path);
+    Ok(())
+}
+
+fn load(fixture: &Path) -> Result<Vec<Entry>> {
+    let text = std::fs::read_to_string(fixture)
+        .with_context(|| format!("Failed to read fixture {}", fixture.display()))?;
+    serde_json::from_str(&text)
+        .with_context(|| format!("Failed to parse fixture {}", fixture.display()))
+}
+
+async fn handle_connection(stream: Box<dyn listen::Stream>, fixture: &Path) -> Result<()> {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut reader = BufReader::new(reader).take(MAX_REQUEST_BYTES);
+    let mut line = String::new();
+    if tokio::time::timeout(READ_TIMEOUT, reader.read_line(&mut line))
+        .await
+        .is_err()
+    {
+        return Ok(());
+    }
+    let value: serde_json::Value = match serde_json::from_str(&line) {
+        Ok(value) => value,
+        Err(e) => {
+            let error_response = ErrorResponse {
+                error: format!("Invalid JSON: {}", e),
+            };
+            return write_json(&mut writer, &error_response).await;
+        }
+    };
+
+    // Control messages and reports are acknowledged and otherwise ignored
+    if value.get("control").is_some()
+        || value.get("accepted").is_some()
+        || value.get("visited").is_some()
+    {
+        let response = ControlResponse {
+            ok: true,
+            message: "Ignored in fixture mode".to_string(),
+        };
+        return write_json(&mut writer, &response).await;
+    }
+    if value.get("context").is_some() || value.get("events").is_some() {
+        let error_response = ErrorResponse {
+            error: "Not available in fixture mode".to_string(),
+        };
+        return write_json(&mut writer, &error_response).await;
+    }
+
+    let entries = match load(fixture) {
+        Ok(entries) => entries,
+        Err(e) => {
+            let error_response = ErrorResponse {
+                error: format!("{e:#}"),
+            };
+            return write_json(&mut writer, &error_response).await;
+        }
+    };
+    if value.get("batch").is_some() {
+        let batch = match serde_json::from_value::<BatchRequest>(value) {
+            Ok(batch) => batch.batch,
+            Err(e) => {
+                let error_response = ErrorResponse {
+                    error: format!("Invalid batch request: {}", e),
+                };
+                return write_json(&mut writer, &error_response).await;
+            }
+        };
+        let mut responses = Vec::with_capacity(batch.len());
+        for request in &batch {
+            let response = respond(request, &entries).await;
+            responses.push(VersionedResponse::new(response, request.version));
+        }
+        return write_json(&mut writer, &BatchResponse { responses }).await;
+    }
+
+    let request: CompletionRequest = match serde_json::from_value(value) {
+        Ok(request) => request,
+        Err(e) => {
+            let error_response = ErrorResponse {
+                error: format!("Invalid request: {}", e),
+            };
+            return write_json(&mut writer, &error_response).await;
+        }
+    };
+    let response = respond(&request, &entries).await;
+    write_json(
+        &mut writer,
+        &VersionedResponse::new(response, request.version),
+    )
+    .await
+}
+
+/// The recorded response to `request`, or no suggestions when there is none
+async fn respond(request: &CompletionRequest, entries: &[Entry]) -> CompletionResponse {
+    let entry = entries.iter().find(|entry| {
+        entry.buffer == request.buffer
+            && entry.cursor.unwrap_or(entry.buffer.len()) == request.cursor
+            && entry.offset == request.offset
+    });
+    let Some(entry) = entry else {
+        warn!(
+            "No fixture for {:?} at {} (offset {})",
+            request.buffer, request.cursor, request.offset
+        );
+        return CompletionResponse {
+            suggestions: Vec::new(),
+            next_offset: None,
+            standalone: false,
+            replace: None,
+            ranking: None,
+        };
+    };
+    if entry.delay_ms > 0 {
+        tokio::time::sleep(Duration::from_millis(entry.delay_ms)).await;
+    }
+    entry.response.clone()
+}
@@ -0,0 +1,143 @@
+use super::{CompletionRequest, Suggestion, SuggestionSource};
+use crate::index::CommandIndex;
+use crate::parser::{self, Token};
+
+/// Exit code of a command that was found but couldn't be executed
+const EXIT_NOT_EXECUTABLE: i32 = 126;
+/// Exit code of a command that wasn't found
+const EXIT_NOT_FOUND: i32 = 127;
+
+/// Commands that usually fail for lack of privileges rather than bad input
+const NEEDS_ROOT: &[&str] = &[
+    "apt",
+    "apt-get",
+    "dnf",
+    "yum",
+    "pacman",
+    "zypper",
+    "apk",
+    "systemctl",
+    "mount",
+    "umount",
+    "chown",
+    "useradd",
+    "usermod",
+];
+
+/// Corrective suggestions for the previous command when it failed.
+///
+/// Only offered while the buffer is empty: once the user starts typing, the
+/// regular completions take over.
+pub fn suggest(request: &CompletionRequest, index: &CommandIndex) -> Vec<Suggestion> {
+    let (Some(last), Some(code)) = (&request.last_command, request.last_exit_code) else {
+        return Vec::new();
+    };
+    if code == 0 || !request.buffer.trim().is_empty() {
+        return Vec::new();
+    }
+    // Only the first command of `make && ./run` is worth correcting
+    let commands = parser::commands(last);
+    let Some(words) = commands.first() else {
+        return Vec::new();
+    };
+    let first = &words[0];
+    let command = first.text.as_str();
+
+    let mut suggestions = Vec::new();
+    if code == EXIT_NOT_FOUND {
+        let known = index.executables.keys().chain(&request.functions);
+        if let Some(fixed) = closest(command, known.map(String::as_str)) {
+            suggestions.push(fix(last, first, fixed));
+        }
+    } else if let Some(sub) = words.get(1)
+        && !sub.text.starts_with('-')
+    {
+        // `git-push.1` man pages and `git-push` helpers name the subcommands
+        let prefix = format!("{command}-");
+        let known = index
+            .man_pages
+            .iter()
+            .chain(index.executables.keys())
+            .filter_map(|name| name.strip_prefix(&prefix));
+        if let Some(fixed) = closest(&sub.text, known) {
+            suggestions.push(fix(last, sub, fixed));
+        }
+    }
+
+    if command != "sudo" && (code == EXIT_NOT_EXECUTABLE || NEEDS_ROOT.contains(&command)) {
+        suggestions.push(Suggestion {
+            text: format!("sudo {last}"),
+            description: "Retry with sudo".to_string(),
+            source: SuggestionSource::History,
+            ..Default::default()
+        });
+    }
+    suggestions
+}
+
+/// `last` with the word `typo` replaced by `fixed`, the rest kept as typed
+fn fix(last: &str, typo: &Token, fixed: &str) -> Suggestion {
+    let span = typo.span();
+    let text = format!("{}{fixed}{}", &last[..span.start], &last[span.end..]);
+    Suggestion {
+        text,
+        description: format!("Did you mean `{fixed}`?"),
+        source: SuggestionSource::History,
+        ..Default::default()
+    }
+}
+
+/// The candidate nearest to `word`, if close enough to be a typo of it.
+///
+/// Returns `None` when `word` is itself a candidate: the command failed for
+/// some other reason.
+fn closest<'a>(word: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    let max = max_typo_distance(word);
+    let mut best: Option<(usize, &str)> = None;
+    for candidate in candidates {
+        if candidate == word {
+            return None;
+        }
+        let distance = edit_distance(word, candidate);
+        if distance <= max && best.is_none_or(|best| (distance, candidate) < best) {
+            best = Some((distance, candidate));
+        }
+    }
+    best.map(|(_, candidate)| candidate)
+}
+
+/// Largest edit distance at which a candidate still counts as a typo of `word`:
+/// one edit for short words, two for longer ones
+pub(super) fn max_typo_distance(word: &str) -> usize {
+    if word.chars().count() <= 4 { 1 } else { 2 }
+}
+
+/// Optimal string alignment distance: insertions, deletions, substitutions
+/// and transpositions of adjacent characters each cost one
+pub(super) fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    if a.len().abs_diff(b.len()) > 2 {
+        return usize::MAX;
+    }
+
+    // Three rolling rows: two back (for transpositions), previous, current
+    let mut before: Vec<usize> = vec![0; b.len() + 1];
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for i in 1..=a.len() {
+        current[0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            current[j] = (previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before[j - 2] + 1);
+            }
+        }
+        std::mem::swap(&mut before, &mut previous);
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
@@ -0,0 +1,617 @@
+//! Git completions read from the repository itself.
+//!
+//! The git spec lists branches, tags, remotes, stashes and files with
+//! generators that run `git`. Those scripts are recognized here and answered
+//! from the files under `.git` instead: loose and packed refs, the config,
+//! the stash's reflog and the index, which takes a fraction of the time of
+//! starting `git`. Anything not understood, like a reftable repository or an
+//! index in version 4, is left to the script. Untracked files depend on the
+//! ignore rules, so `git ls-files --others` still lists those.
+
+use super::{Suggestion, SuggestionSource};
+use crate::specs::Generator;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+/// What's left to run of the generator listing changed files
+const UNTRACKED: &[&str] = &["git", "ls-files", "--others", "--exclude-standard"];
+/// Kinds of refs, by the prefix of their full names
+const REF_KINDS: [(&str, &str); 3] = [
+    ("refs/heads/", "Branch"),
+    ("refs/remotes/", "Remote branch"),
+    ("refs/tags/", "Tag"),
+];
+/// Mode of a submodule's entry in the index
+const GITLINK_MODE: u32 = 0o160000;
+
+/// What a recognized generator lists
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Query {
+    /// `git for-each-ref --format=%(refname:short)` over these kinds
+    Refs([bool; 3]),
+    /// `git remote`
+    Remotes,
+    /// `git tag --list`
+    Tags,
+    /// `git stash list --format=%gd`
+    Stashes,
+    /// `git diff --name-only --relative` and the untracked files
+    Changed,
+    /// `git ls-files`
+    Tracked,
+}
+
+/// Suggestions read from the repository for a generator
+pub struct Answer {
+    pub suggestions: Vec<Suggestion>,
+    /// The part of the generator still to run, whose output lists more
+    pub rest: Option<&'static [&'static str]>,
+}
+
+/// Answer `generator`, run in `cwd`, without running it; `None` when its
+/// script isn't one of git's known here or the repository can't be read
+pub fn answer(generator: &Generator<'_>, cwd: &Path) -> Option<Answer> {
+    if generator.split_on.is_some() || generator.parse.is_some() {
+        return None;
+    }
+    let script: Vec<&str> = generator.script.iter().map(|part| part.as_ref()).collect();
+    let query = recognize(&script)?;
+    let repo = Repo::discover(cwd)?;
+    let (suggestions, rest) = match query {
+        Query::Refs(kinds) => (repo.refs(kinds, true)?, None),
+        Query::Tags => (repo.refs([false, false, true], false)?, None),
+        Query::Remotes => (repo.remotes(), None),
+        Query::Stashes => (repo.stashes(), None),
+        Query::Changed => (repo.files(cwd, true)?, Some(UNTRACKED)),
+        Query::Tracked => (repo.files(cwd, false)?, None),
+    };
+    Some(Answer { suggestions, rest })
+}
+
+fn recognize(script: &[&str]) -> Option<Query> {
+    match script {
+        [
+            "git",
+            "for-each-ref",
+            "--format=%(refname:short)",
+            patterns @ ..,
+        ] => {
+            let mut kinds = [false; 3];
+            for pattern in patterns {
+                let kind = REF_KINDS
+                    .iter()
+                    .position(|(prefix, _)| prefix.strip_suffix('/') == Some(pattern))?;
+                kinds[kind] = true;
+            }
+            kinds.contains(&true).then_some(Query::Refs(kinds))
+        }
+        ["git", "remote"] => Some(Query::Remotes),
+        ["git", "tag", "--list"] | ["git", "tag"] => Some(Query::Tags),
+        ["git", "stash", "list", "--format=%gd"] => Some(Query::Stashes),
+        ["git diff --name-only --relative; git ls-files --others --exclude-standard"] => {
+            Some(Query::Changed)
+        }
+        ["git", "ls-files"] => Some(Query::Tracked),
+        _ => None,
+    }
+}
+
+/// Where a repository's files are
+struct Repo {
+    /// The `.git` directory, or a linked worktree's own under it
+    git_dir: PathBuf,
+    /// Where the refs and config shared by all worktrees are
+    common_dir: PathBuf,
+    work_tree: PathBuf,
+}
+
+impl Repo {
+    /// The repository `cwd` is in, found like git does by looking for `.git`
+    /// in it and its parents
+    fn discover(cwd: &Path) -> Option<Self> {
+        let (work_tree, git_dir) = cwd.ancestors().find_map(|dir| {
+            let dot_git = dir.join(".git");
+            if dot_git.is_dir() {
+                return Some((dir, dot_git));
+            }
+            // A linked worktree or a submodule points at its directory
+            let link = fs::read_to_string(&dot_git).ok()?;
+            let target = link.strip_prefix("gitdir:")?.trim();
+            Some((dir, dir.join(target)))
+        })?;
+        let common_dir = match fs::read_to_string(git_dir.join("commondir")) {
+            Ok(common) => git_dir.join(common.trim()),
+            Err(_) => git_dir.clone(),
+        };
+        Some(Self {
+            git_dir,
+            common_dir,
+            work_tree: work_tree.to_path_buf(),
+        })
+    }
+
+    /// Short names of the refs of the chosen [`REF_KINDS`], in the order of
+    /// their full names, and with `qualify` longer ones where the short name
+    /// is ambiguous; `None` for refs kept in a reftable
+    fn refs(&self, kinds: [bool; 3], qualify: bool) -> Option<Vec<Suggestion>> {
+        if self.common_dir.join("reftable").exists() {
+            return None;
+        }
+        let packed = fs::read_to_string(self.common_dir.join("packed-refs")).unwrap_or_default();
+        let mut names = BTreeSet::new();
+        for line in packed.lines() {
+            // `^` lines are the commits annotated tags point to
+            if let Some((_, name)) = line.split_once(' ')
+                && !line.starts_with(['#', '^'])
+            {
+                names.insert(name.to_string());
+            }
+        }
+        for (prefix, _) in REF_KINDS {
+            loose_refs(&self.common_dir.join(prefix), prefix, &mut names);
+        }
+
+        let current = fs::read_to_string(self.git_dir.join("HEAD")).unwrap_or_default();
+        let current = current.strip_prefix("ref: ").map(str::trim);
+        let mut refs = Vec::new();
+        for (kind, (prefix, description)) in REF_KINDS.into_iter().enumerate() {
+            for name in names.iter().filter(|name| name.starts_with(prefix)) {
+                let short = &name[prefix.len()..];
+                // `origin/HEAD` only points at another remote branch
+                if kind == 1 && short.ends_with("/HEAD") {
+                    continue;
+                }
+                let description = match current {
+                    Some(head) if head == name => "Current branch",
+                    _ => description,
+                };
+                refs.push((kind, name.as_str(), short, description));
+            }
+        }
+        // A short name shared by a branch and a tag is spelled out, as git does
+        let mut uses: HashMap<&str, usize> = HashMap::new();
+        for (_, _, short, _) in &refs {
+            *uses.entry(short).or_default() += 1;
+        }
+        Some(
+            refs.iter()
+                .filter(|(kind, ..)| kinds[*kind])
+                .map(|&(_, name, short, description)| {
+                    let text = if qualify && uses[short] > 1 {
+                        &name["refs/".len()..]
+                    } else {
+                        short
+                    };
+                    suggestion(text, description)
+                })
+                .collect(),
+        )
+    }
+
+    /// The remotes in the config, with their URLs
+    fn remotes(&self) -> Vec<Suggestion> {
+        let config = fs::read_to_string(self.common_dir.join("config")).unwrap_or_default();
+        let mut remotes: BTreeMap<&str, &str> = BTreeMap::new();
+        let mut section = None;
+        for line in config.lines().map(str::trim) {
+            if let Some(header) = line.strip_prefix('[') {
+                section = header
+                    .strip_prefix("remote \"")
+                    .and_then(|rest| rest.split_once("\"]"))
+                    .map(|(name, _)| name);
+                if let Some(name) = section {
+                    remotes.entry(name).or_default();
+                }
+            } else if let Some(name) = section
+                && let Some((key, value)) = line.split_once('=')
+                && key.trim().eq_ignore_ascii_case("url")
+            {
+                remotes.insert(name, value.trim());
+            }
+        }
+        remotes
+            .into_iter()
+            .map(|(name, url)| suggestion(name, url))
+            .collect()
+    }
+
+    /// `stash@{0}` and on, newest first, with their messages
+    fn stashes(&self) -> Vec<Suggestion> {
+        let log = fs::read_to_string(self.common_dir.join("logs/refs/stash")).unwrap_or_default();
+        log.lines()
+            .rev()
+            .enumerate()
+            .map(|(number, line)| {
+                let message = line.split_once('\t').map_or("", |(_, message)| message);
+                suggestion(&format!("stash@{{{number}}}"), message)
+            })
+            .collect()
+    }
+
+    /// Files in the index under `cwd`, relative to it; with `changed`, only
+    /// the ones that were modified or deleted since they were staged
+    fn files(&self, cwd: &Path, changed: bool) -> Option<Vec<Suggestion>> {
+        let entries = read_index(&self.git_dir.join("index"))?;
+        let under = cwd.strip_prefix(&self.work_tree).ok()?;
+        let mut files = Vec::new();
+        let mut seen = BTreeSet::new();
+        for entry in &entries {
+            let Ok(relative) = Path::new(&entry.path).strip_prefix(under) else {
+                continue;
+            };
+            // Conflicted files have an entry per side
+            if !seen.insert(entry.path.as_str()) {
+                continue;
+            }
+            let description = if !changed {
+                ""
+            } else if entry.mode == GITLINK_MODE || entry.unchecked {
+                continue;
+            } else {
+                match fs::symlink_metadata(self.work_tree.join(&entry.path)) {
+                    Err(_) => "Deleted",
+                    Ok(meta) if entry.differs(&meta) => "Modified",
+                    Ok(_) => continue,
+                }
+            };
+            files.push(suggestion(&relative.to_string_lossy(), description));
+        }
+        Some(files)
+    }
+}
+
+/// Add the full names of the loose refs in `dir`, named `prefix` and on,
+/// to `names`
+fn loose_refs(dir: &Path, prefix: &str, names: &mut BTreeSet<String>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = format!("{prefix}{}", entry.file_name().to_string_lossy());
+        match entry.file_type() {
+            Ok(kind) if kind.is_dir() => loose_refs(&entry.path(), &format!("{name}/"), names),
+            // A ref being updated has a lock file next to it
+            Ok(_) if !name.ends_with(".lock") => {
+                names.insert(name);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A file as staged
+struct IndexEntry {
+    path: String,
+    mode: u32,
+    mtime: (u32, u32),
+    size: u32,
+    /// Marked assume-unchanged or skip-worktree, so git doesn't look
+    unchecked: bool,
+}
+
+impl IndexEntry {
+    /// Whether the file on disk looks changed since it was staged, going by
+    /// its size and modification time like git's first check
+    fn differs(&self, meta: &fs::Metadata) -> bool {
+        let (seconds, nanos) = self.mtime;
+        // The index keeps sizes and times truncated to 32 bits
+        meta.size() as u32 != self.size
+            || meta.mtime() as u32 != seconds
+            || (nanos != 0 && meta.mtime_nsec() as u32 != nanos)
+    }
+}
+
+/// The entries of the index at `path`, none when there is no index yet, or
+/// `None` for a format other than versions 2 and 3
+fn read_index(path: &Path) -> Option<Vec<IndexEntry>> {
+    let Ok(data) = fs::read(path) else {
+        return Some(Vec::new());
+    };
+    let u32_at = |at: usize| {
+        data.get(at..at + 4)
+            .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    };
+    let u16_at = |at: usize| {
+        data.get(at..at + 2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+    };
+    if data.get(..4)? != b"DIRC" || !(2..=3).contains(&u32_at(4)?) {
+        return None;
+    }
+    let count = u32_at(8)?;
+    let mut entries = Vec::with_capacity(count.min(1 << 20) as usize);
+    let mut at = 12;
+    for _ in 0..count {
+        let flags = u16_at(at + 60)?;
+        let mut name = at + 62;
+        let mut extended = 0;
+        if flags & 0x4000 != 0 {
+            extended = u16_at(name)?;
+            name += 2;
+        }
+        let length = data.get(name..)?.iter().position(|&byte| byte == 0)?;
+        entries.push(IndexEntry {
+            path: String::from_utf8_lossy(&data[name..name + length]).into_owned(),
+            mode: u32_at(at + 24)?,
+            mtime: (u32_at(at + 8)?, u32_at(at + 12)?),
+            size: u32_at(at + 36)?,
+            unchecked: flags & 0x8000 != 0 || extended & 0x4000 != 0,
+        });
+        // Entries are padded with NULs to a multiple of 8 bytes
+        at += (name - at + length + 8) & !7;
+    }
+    Some(entries)
+}
+
+fn suggestion(text: &str, description: &str) -> Suggestion {
+    Suggestion {
+        text: text.to_string(),
+        description: description.to_string(),
+        source: SuggestionSource::Spec,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    /// One index entry: `stage` goes in the flags, and `extended` makes it
+    /// a version 3 entry with those extended flags
+    fn entry(path: &str, mode: u32, stage: u16, extended: Option<u16>) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend([0; 8]); // ctime
+        bytes.extend(7u32.to_be_bytes()); // mtime seconds
+        bytes.extend(0u32.to_be_bytes()); // mtime nanoseconds
+        bytes.extend([0; 8]); // dev, ino
+        bytes.extend(mode.to_be_bytes());
+        bytes.extend([0; 8]); // uid, gid
+        bytes.extend(3u32.to_be_bytes()); // size
+        bytes.extend([0; 20]); // object id
+        let mut flags = (stage << 12) | path.len() as u16;
+        if extended.is_some() {
+            flags |= 0x4000;
+        }
+        bytes.extend(flags.to_be_bytes());
+        if let Some(extended) = extended {
+            bytes.extend(extended.to_be_bytes());
+        }
+        bytes.extend(path.as_bytes());
+        // At least one NUL, up to a multiple of 8
+        let padding = 8 - bytes.len() % 8;
+        bytes.extend(std::iter::repeat_n(0, padding));
+        bytes
+    }
+
+    fn index(version: u32, entries: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = b"DIRC".to_vec();
+        bytes.extend(version.to_be_bytes());
+        bytes.extend((entries.len() as u32).to_be_bytes());
+        for entry in entries {
+            bytes.extend(entry);
+        }
+        // The checksum git writes after the entries is never read
+        bytes.extend([0; 20]);
+        bytes
+    }
+
+    fn paths(entries: &[IndexEntry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.path.as_str()).collect()
+    }
+
+    fn texts(suggestions: &[Suggestion]) -> Vec<(&str, &str)> {
+        suggestions
+            .iter()
+            .map(|suggestion| (suggestion.text.as_str(), suggestion.description.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_read_index_version_2() {
+        let dir = TempDir::new();
+        let entries = [
+            entry("Cargo.toml", 0o100644, 0, None),
+            entry("src/a-much-longer-name.rs", 0o100755, 0, None),
+            entry("vendor/lib", GITLINK_MODE, 0, None),
+        ];
+        dir.write("index", index(2, &entries));
+        let read = read_index(&dir.path().join("index")).unwrap();
+        assert_eq!(
+            paths(&read),
+            ["Cargo.toml", "src/a-much-longer-name.rs", "vendor/lib"]
+        );
+        assert_eq!(read[1].mode, 0o100755);
+        assert_eq!((read[0].mtime, read[0].size), ((7, 0), 3));
+        assert!(read.iter().all(|entry| !entry.unchecked));
+    }
+
+    #[test]
+    fn test_read_index_version_3_extended_flags() {
+        let dir = TempDir::new();
+        let entries = [
+            entry("a.txt", 0o100644, 0, None),
+            // skip-worktree
+            entry("sparse/b.txt", 0o100644, 0, Some(0x4000)),
+            // intent-to-add only
+            entry("c.txt", 0o100644, 0, Some(0x2000)),
+        ];
+        dir.write("index", index(3, &entries));
+        let read = read_index(&dir.path().join("index")).unwrap();
+        assert_eq!(paths(&read), ["a.txt", "sparse/b.txt", "c.txt"]);
+        let unchecked: Vec<_> = read.iter().map(|entry| entry.unchecked).collect();
+        assert_eq!(unchecked, [false, true, false]);
+    }
+
+    #[test]
+    fn test_read_index_rejects_other_versions() {
+        let dir = TempDir::new();
+        dir.write("index", index(4, &[]));
+        assert!(read_index(&dir.path().join("index")).is_none());
+        dir.write("index", b"not an index");
+        assert!(read_index(&dir.path().join("index")).is_none());
+        // No index yet is an empty one
+        assert_eq!(read_index(&dir.path().join("missing")).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_read_index_truncated() {
+        let dir = TempDir::new();
+        let mut bytes = index(2, &[entry("a.txt", 0o100644, 0, None)]);
+        bytes.truncate(40);
+        dir.write("index", bytes);
+        assert!(read_index(&dir.path().join("index")).is_none());
+    }
+
+    #[test]
+    fn test_conflicted_files_are_listed_once() {
+        let dir = TempDir::new();
+        let entries = [
+            entry("clean.txt", 0o100644, 0, None),
+            entry("conflict.txt", 0o100644, 1, None),
+            entry("conflict.txt", 0o100644, 2, None),
+            entry("conflict.txt", 0o100644, 3, None),
+        ];
+        dir.write(".git/index", index(2, &entries));
+        dir.write(".git/HEAD", "ref: refs/heads/main\n");
+        let repo = Repo::discover(dir.path()).unwrap();
+        let files = repo.files(dir.path(), false).unwrap();
+        assert_eq!(texts(&files), [("clean.txt", ""), ("conflict.txt", "")]);
+        // Neither exists on disk, so both were deleted
+        let changed = repo.files(dir.path(), true).unwrap();
+        assert_eq!(
+            texts(&changed),
+            [("clean.txt", "Deleted"), ("conflict.txt", "Deleted")]
+        );
+    }
+
+    #[test]
+    fn test_files_are_relative_to_cwd() {
+        let dir = TempDir::new();
+        let entries = [
+            entry("README.md", 0o100644, 0, None),
+            entry("src/main.rs", 0o100644, 0, None),
+        ];
+        dir.write(".git/index", index(2, &entries));
+        dir.write("src/main.rs", "x");
+        let repo = Repo::discover(&dir.path().join("src")).unwrap();
+        let files = repo.files(&dir.path().join("src"), false).unwrap();
+        assert_eq!(texts(&files), [("main.rs", "")]);
+    }
+
+    #[test]
+    fn test_packed_refs_skip_peel_lines() {
+        let dir = TempDir::new();
+        dir.write(".git/HEAD", "ref: refs/heads/main\n");
+        dir.write(
+            ".git/packed-refs",
+            "# pack-refs with: peeled fully-peeled sorted \n\
+             1111111111111111111111111111111111111111 refs/heads/main\n\
+             2222222222222222222222222222222222222222 refs/remotes/origin/HEAD\n\
+             2222222222222222222222222222222222222222 refs/remotes/origin/main\n\
+             3333333333333333333333333333333333333333 refs/tags/v1.0\n\
+             ^4444444444444444444444444444444444444444\n",
+        );
+        dir.write(".git/refs/heads/feature/login", "5555\n");
+        dir.write(".git/refs/heads/wip.lock", "6666\n");
+        let repo = Repo::discover(dir.path()).unwrap();
+        let refs = repo.refs([true, true, true], true).unwrap();
+        assert_eq!(
+            texts(&refs),
+            [
+                ("feature/login", "Branch"),
+                ("main", "Current branch"),
+                ("origin/main", "Remote branch"),
+                ("v1.0", "Tag"),
+            ]
+        );
+        let tags = repo.refs([false, false, true], false).unwrap();
+        assert_eq!(texts(&tags), [("v1.0", "Tag")]);
+    }
+
+    #[test]
+    fn test_ambiguous_short_names_are_qualified() {
+        let dir = TempDir::new();
+        dir.write(".git/HEAD", "ref: refs/heads/main\n");
+        dir.write(".git/refs/heads/main", "1\n");
+        dir.write(".git/refs/heads/v2", "1\n");
+        dir.write(".git/refs/tags/v2", "2\n");
+        let repo = Repo::discover(dir.path()).unwrap();
+        let refs = repo.refs([true, false, true], true).unwrap();
+        assert_eq!(
+            texts(&refs),
+            [
+                ("main", "Current branch"),
+                ("heads/v2", "Branch"),
+                ("tags/v2", "Tag"),
+            ]
+        );
+        // `git tag` lists tags by their short names regardless
+        let tags = repo.refs([false, false, true], false).unwrap();
+        assert_eq!(texts(&tags), [("v2", "Tag")]);
+    }
+
+    #[test]
+    fn test_reftable_repositories_are_left_to_git() {
+        let dir = TempDir::new();
+        dir.write(".git/HEAD", "ref: refs/heads/.invalid\n");
+        dir.write(".git/reftable/tables.list", "");
+        let repo = Repo::discover(dir.path()).unwrap();
+        assert!(repo.refs([true, true, true], true).is_none());
+    }
+
+    #[test]
+    fn test_linked_worktree_shares_refs_through_commondir() {
+        let dir = TempDir::new();
+        dir.write("main/.git/HEAD", "ref: refs/heads/main\n");
+        dir.write("main/.git/refs/heads/main", "1\n");
+        dir.write("main/.git/refs/heads/feature", "2\n");
+        dir.write(
+            "main/.git/config",
+            "[remote \"origin\"]\n\turl = https://example.com/repo.git\n",
+        );
+        dir.write("main/.git/worktrees/wt/HEAD", "ref: refs/heads/feature\n");
+        dir.write("main/.git/worktrees/wt/commondir", "../..\n");
+        let git_dir = dir.path().join("main/.git/worktrees/wt");
+        dir.write("wt/.git", format!("gitdir: {}\n", git_dir.display()));
+        dir.write(
+            "main/.git/worktrees/wt/index",
+            index(2, &[entry("only-here.txt", 0o100644, 0, None)]),
+        );
+
+        let repo = Repo::discover(&dir.path().join("wt")).unwrap();
+        assert_eq!(repo.work_tree, dir.path().join("wt"));
+        let refs = repo.refs([true, false, false], true).unwrap();
+        // The worktree's own HEAD decides the current branch
+        assert_eq!(
+            texts(&refs),
+            [("feature", "Current branch"), ("main", "Branch")]
+        );
+        assert_eq!(
+            texts(&repo.remotes()),
+            [("origin", "https://example.com/repo.git")]
+        );
+        let files = repo.files(&dir.path().join("wt"), false).unwrap();
+        assert_eq!(texts(&files), [("only-here.txt", "")]);
+    }
+
+    #[test]
+    fn test_stashes_newest_first() {
+        let dir = TempDir::new();
+        dir.write(".git/HEAD", "ref: refs/heads/main\n");
+        dir.write(
+            ".git/logs/refs/stash",
+            "0 1 A <a> 1 +0000\tWIP on main: first\n0 2 A <a> 2 +0000\tOn main: second\n",
+        );
+        let repo = Repo::discover(dir.path()).unwrap();
+        assert_eq!(
+            texts(&repo.stashes()),
+            [
+                ("stash@{0}", "On main: second"),
+                ("stash@{1}", "WIP on main: first"),
+            ]
+        );
+    }
+}
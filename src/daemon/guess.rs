@@ -0,0 +1,364 @@
+//! Flag inference for commands nothing else describes.
+//!
+//! Local scripts rarely come with a spec or a man page, but they usually
+//! declare their options in a recognizable way: a `getopts` optstring or a
+//! `case` over `"$1"` in shell, `add_argument` calls in Python, clap
+//! attributes and builders in Rust. When the command is a readable text file,
+//! its source is scanned for those patterns and the flags found are offered
+//! with a "guessed" marker, since nothing guarantees they are accurate.
+
+use super::{CompletionRequest, Suggestion, SuggestionSource};
+use crate::index::CommandIndex;
+use crate::paths;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// Scripts larger than this are not scanned
+const MAX_SCRIPT_BYTES: u64 = 256 * 1024;
+/// Leading bytes checked for NULs to tell text from binaries
+const BINARY_SNIFF_BYTES: usize = 8 * 1024;
+/// Scanned scripts remembered before the cache is reset
+const MAX_CACHED_SCRIPTS: usize = 256;
+
+/// A flag found in a script's source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GuessedFlag {
+    /// `-v` or `--verbose`
+    pub name: String,
+    /// Help text declared next to the flag, if any
+    pub help: Option<String>,
+}
+
+/// Flags found in a script, with the script's modification time when scanned
+type Scanned = (SystemTime, Arc<Vec<GuessedFlag>>);
+
+/// Flags guessed per script, invalidated when the file changes
+#[derive(Debug, Default)]
+pub struct GuessCache {
+    scripts: Mutex<HashMap<PathBuf, Scanned>>,
+}
+
+impl GuessCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Flags guessed from the script at `path`, scanning it if it changed
+    fn flags(&self, path: &Path) -> Arc<Vec<GuessedFlag>> {
+        let Ok(meta) = std::fs::metadata(path) else {
+            return Arc::default();
+        };
+        if !meta.is_file() || meta.len() > MAX_SCRIPT_BYTES {
+            return Arc::default();
+        }
+        let modified = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+
+        let mut scripts = self.scripts.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((seen, flags)) = scripts.get(path)
+            && *seen == modified
+        {
+            return Arc::clone(flags);
+        }
+        let flags = Arc::new(
+            std::fs::read(path)
+                .map(|bytes| scan(&bytes))
+                .unwrap_or_default(),
+        );
+        if scripts.len() >= MAX_CACHED_SCRIPTS {
+            scripts.clear();
+        }
+        scripts.insert(path.to_path_buf(), (modified, Arc::clone(&flags)));
+        flags
+    }
+}
+
+/// Guessed flags for `command` matching the `-`-prefixed `word` being typed.
+///
+/// Only used when the command has neither a spec nor a man page.
+pub fn suggest(
+    request: &CompletionRequest,
+    command: &str,
+    word: &str,
+    index: &CommandIndex,
+    cache: &GuessCache,
+) -> Vec<Suggestion> {
+    if !word.starts_with('-') || index.specs.contains(command) || index.man_pages.contains(command)
+    {
+        return Vec::new();
+    }
+    let Some(path) = resolve(request, command, index) else {
+        return Vec::new();
+    };
+    // Executables in the index are already on the daemon's side
+    let path = if command.contains('/') {
+        request.local_path(&path)
+    } else {
+        path
+    };
+
+    cache
+        .flags(&path)
+        .iter()
+        .filter(|flag| flag.name.starts_with(word))
+        .map(|flag| Suggestion {
+            text: flag.name.clone(),
+            description: match &flag.help {
+                Some(help) => format!("(guessed) {help}"),
+                None => "(guessed)".to_string(),
+            },
+            source: SuggestionSource::Guessed,
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// File `command` runs: a path relative to the shell's directory, or a `PATH` lookup
+fn resolve(request: &CompletionRequest, command: &str, index: &CommandIndex) -> Option<PathBuf> {
+    if !command.contains('/') {
+        return index.executables.get(command).cloned();
+    }
+    if let Some(rest) = command.strip_prefix("~/") {
+        let home = request
+            .env
+            .get("HOME")
+            .map(PathBuf::from)
+            .or_else(paths::home_dir)?;
+        return Some(home.join(rest));
+    }
+    let path = Path::new(command);
+    if path.is_absolute() {
+        Some(path.to_path_buf())
+    } else {
+        Some(request.cwd.as_ref()?.join(path))
+    }
+}
+
+/// Flags declared in a script's source; empty for binaries
+fn scan(bytes: &[u8]) -> Vec<GuessedFlag> {
+    if bytes[..bytes.len().min(BINARY_SNIFF_BYTES)].contains(&0) {
+        return Vec::new();
+    }
+    let source = String::from_utf8_lossy(bytes);
+    let lines: Vec<&str> = source.lines().collect();
+    let shell = lines
+        .first()
+        .is_some_and(|line| line.starts_with("#!") && line.contains("sh"));
+
+    let mut flags = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        let line = line.trim();
+        getopts(line, &mut flags);
+        if shell {
+            case_pattern(line, &mut flags);
+        }
+        option_calls(line, &mut flags);
+        clap_builder(line, &mut flags);
+        clap_attribute(line, &lines[i + 1..], &mut flags);
+    }
+    dedup(flags)
+}
+
+/// `getopts "vo:" opt` declares `-v`, and `-o` taking a value
+fn getopts(line: &str, flags: &mut Vec<GuessedFlag>) {
+    let Some(rest) = after_word(line, "getopts") else {
+        return;
+    };
+    let optstring = rest
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .trim_matches(|c| c == '"' || c == '\'');
+    let mut chars = optstring.trim_start_matches(':').chars().peekable();
+    while let Some(c) = chars.next() {
+        if !c.is_ascii_alphanumeric() {
+            continue;
+        }
+        let takes_value = chars.next_if_eq(&':').is_some();
+        flags.push(GuessedFlag {
+            name: format!("-{c}"),
+            help: takes_value.then(|| "takes a value".to_string()),
+        });
+    }
+}
+
+/// `-v|--verbose)` or `--output=*)` branches of an argument-parsing `case`
+fn case_pattern(line: &str, flags: &mut Vec<GuessedFlag>) {
+    let Some((pattern, _)) = line.split_once(')') else {
+        return;
+    };
+    if !pattern.starts_with('-') {
+        return;
+    }
+    let alternatives: Vec<&str> = pattern
+        .split('|')
+        .map(|alt| alt.trim().trim_end_matches('*').trim_end_matches('='))
+        .collect();
+    if alternatives.iter().all(|alt| is_flag(alt)) {
+        flags.extend(alternatives.into_iter().map(|name| GuessedFlag {
+            name: name.to_string(),
+            help: None,
+        }));
+    }
+}
+
+/// `add_argument("-v", "--verbose", help="…")` in argparse and optparse,
+/// `@click.option("--name")` and `typer.Option("--name")`
+fn option_calls(line: &str, flags: &mut Vec<GuessedFlag>) {
+    for call in [
+        "add_argument(",
+        "add_option(",
+        "click.option(",
+        "typer.Option(",
+    ] {
+        let Some(start) = line.find(call) else {
+            continue;
+        };
+        let args = &line[start + call.len()..];
+        let literals = string_literals(args);
+        let help = keyword_literal(args, "help");
+        for literal in literals.into_iter().filter(|literal| is_flag(literal)) {
+            flags.push(GuessedFlag {
+                name: literal,
+                help: help.clone(),
+            });
+        }
+    }
+}
+
+/// `.long("verbose")` and `.short('v')` in clap's builder API
+fn clap_builder(line: &str, flags: &mut Vec<GuessedFlag>) {
+    let help = keyword_call(line, ".help(");
+    if let Some(long) = keyword_call(line, ".long(") {
+        flags.push(GuessedFlag {
+            name: format!("--{long}"),
+            help: help.clone(),
+        });
+    }
+    if let Some(short) = keyword_call(line, ".short(") {
+        flags.push(GuessedFlag {
+            name: format!("-{short}"),
+            help,
+        });
+    }
+}
+
+/// `#[arg(short, long)]` on a field of a clap derive struct; the field name
+/// (on a following line) supplies the names clap derives
+fn clap_attribute(line: &str, following: &[&str], flags: &mut Vec<GuessedFlag>) {
+    let Some(attribute) = line
+        .strip_prefix("#[arg(")
+        .or_else(|| line.strip_prefix("#[clap("))
+    else {
+        return;
+    };
+    let attribute = attribute.trim_end_matches(")]");
+    let field = following
+        .iter()
+        .map(|line| line.trim())
+        .find(|line| !line.starts_with("#[") && !line.starts_with("//"))
+        .and_then(|line| line.trim_start_matches("pub ").split_once(':'))
+        .map(|(name, _)| name.trim().to_string())
+        .filter(|name| {
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        });
+    let help = keyword_literal(attribute, "help");
+
+    for part in attribute.split(',').map(str::trim) {
+        let name = match part.split_once('=') {
+            Some((key, value)) => {
+                let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+                match key.trim() {
+                    "long" => format!("--{value}"),
+                    "short" => format!("-{value}"),
+                    _ => continue,
+                }
+            }
+            None => match (part, &field) {
+                ("long", Some(field)) => format!("--{}", field.replace('_', "-")),
+                ("short", Some(field)) => format!("-{}", &field[..1]),
+                _ => continue,
+            },
+        };
+        if is_flag(&name) {
+            flags.push(GuessedFlag {
+                name,
+                help: help.clone(),
+            });
+        }
+    }
+}
+
+/// Text after `word` when `line` invokes it as a command
+fn after_word<'a>(line: &'a str, word: &str) -> Option<&'a str> {
+    let start = line.find(word)?;
+    let before = line[..start].chars().next_back();
+    let rest = &line[start + word.len()..];
+    let boundary = before.is_none_or(|c| !c.is_alphanumeric() && c != '_');
+    (boundary && rest.starts_with(char::is_whitespace)).then_some(rest)
+}
+
+/// `-v`, `--dry-run`: a dash or two followed by a name
+fn is_flag(text: &str) -> bool {
+    let name = text
+        .strip_prefix("--")
+        .or_else(|| text.strip_prefix('-'))
+        .unwrap_or_default();
+    name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Contents of the quoted strings in `text`, up to the first unquoted `)`
+fn string_literals(text: &str) -> Vec<String> {
+    let mut literals = Vec::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            ')' => break,
+            '"' | '\'' => literals.push(chars.by_ref().take_while(|&q| q != c).collect()),
+            _ => {}
+        }
+    }
+    literals
+}
+
+/// The string literal assigned to `key = "…"` in `text`
+fn keyword_literal(text: &str, key: &str) -> Option<String> {
+    let mut rest = text;
+    while let Some(start) = rest.find(key) {
+        let before = rest[..start].chars().next_back();
+        let after = rest[start + key.len()..].trim_start();
+        rest = &rest[start + key.len()..];
+        if before.is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            continue;
+        }
+        if let Some(value) = after.strip_prefix('=') {
+            return string_literals(value.trim_start()).into_iter().next();
+        }
+    }
+    None
+}
+
+/// The literal argument of a method call like `.long("verbose")`
+fn keyword_call(line: &str, call: &str) -> Option<String> {
+    let start = line.find(call)?;
+    string_literals(&line[start + call.len()..])
+        .into_iter()
+        .next()
+}
+
+/// One entry per flag name, keeping the first one that has help text
+fn dedup(flags: Vec<GuessedFlag>) -> Vec<GuessedFlag> {
+    let mut unique: Vec<GuessedFlag> = Vec::new();
+    for flag in flags {
+        match unique.iter_mut().find(|seen| seen.name == flag.name) {
+            Some(seen) if seen.help.is_none() => seen.help = flag.help,
+            Some(_) => {}
+            None => unique.push(flag),
+        }
+    }
+    unique
+}
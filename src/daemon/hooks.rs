@@ -0,0 +1,207 @@
+//! Telling external tools what happened.
+//!
+//! Each [`Event`] is turned into one JSON line. It goes to every client
+//! subscribed with an [`EventsRequest`](super::EventsRequest), and to the
+//! `[hooks]` commands for the event, which run through `sh -c` with the line
+//! on stdin and the event's name in [`EVENT_VAR`]. Hooks run in the
+//! background and never hold up a completion; in safe mode they don't run.
+//! Like generators, each runs in its own process group counted among the
+//! generator pool's running ones, and the tasks running them are kept so the
+//! daemon can wait for them, or abort them, when it stops.
+
+use super::{Event, EventRecord};
+use crate::config::HooksConfig;
+use crate::generators::GeneratorPool;
+use std::collections::HashSet;
+use std::process::Stdio;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::broadcast;
+use tokio::task::JoinSet;
+use tracing::warn;
+
+/// Environment variable naming the event a hook runs for
+pub const EVENT_VAR: &str = "AUTOCOMPLETE_RS_EVENT";
+/// How long a hook may run before it is killed
+const HOOK_TIMEOUT: Duration = Duration::from_secs(10);
+/// Events kept for a subscriber that reads slower than they happen
+const BACKLOG: usize = 256;
+
+/// Where events go
+pub struct Events {
+    lines: broadcast::Sender<(&'static str, String)>,
+    /// Commands already reported as having no spec
+    unknown: Mutex<HashSet<String>>,
+    /// Runs the hooks' process groups are tracked by
+    pool: GeneratorPool,
+    /// Hooks running
+    running: Mutex<JoinSet<()>>,
+}
+
+impl Events {
+    pub fn new(pool: GeneratorPool) -> Self {
+        Self {
+            lines: broadcast::Sender::new(BACKLOG),
+            unknown: Mutex::default(),
+            pool,
+            running: Mutex::default(),
+        }
+    }
+
+    /// The hooks running, to wait for on shutdown; hooks started later are
+    /// kept apart
+    pub fn take_running(&self) -> JoinSet<()> {
+        std::mem::take(&mut *self.running.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Lines of the events from now on, with their names
+    pub fn subscribe(&self) -> broadcast::Receiver<(&'static str, String)> {
+        self.lines.subscribe()
+    }
+
+    /// Whether `command` is reported as unknown for the first time
+    pub fn first_unknown(&self, command: &str) -> bool {
+        self.unknown
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(command.to_string())
+    }
+
+    /// Send `event` to the subscribers, and run its hooks unless `safe`;
+    /// [private](Event::is_private) events go nowhere
+    pub fn emit(&self, event: Event, hooks: &HooksConfig, safe: bool) {
+        if event.is_private() {
+            return;
+        }
+        let name = event.name();
+        let commands = match &event {
+            Event::Accepted(_) => &hooks.accepted,
+            Event::UnknownCommand(_) => &hooks.unknown_command,
+        };
+        if self.lines.receiver_count() == 0 && (commands.is_empty() || safe) {
+            return;
+        }
+        let record = EventRecord {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+            event,
+        };
+        let line = match serde_json::to_string(&record) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("Failed to serialize {} event: {}", name, e);
+                return;
+            }
+        };
+        // Failing only means nobody is subscribed
+        let _ = self.lines.send((name, line.clone()));
+        let mut running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+        // Finished hooks are only forgotten here
+        while running.try_join_next().is_some() {}
+        for command in commands.iter().filter(|_| !safe) {
+            let pool = self.pool.clone();
+            running.spawn(run(command.clone(), name, format!("{line}\n"), pool));
+        }
+    }
+}
+
+/// Run one hook with `line` on its stdin, killing its process group if
+/// aborted or timed out
+async fn run(command: String, event: &'static str, line: String, pool: GeneratorPool) {
+    let mut sh = Command::new("sh");
+    sh.args(["-c", &command])
+        .env(EVENT_VAR, event)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    #[cfg(unix)]
+    sh.process_group(0);
+    let mut child = match sh.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            warn!("Failed to run {} hook `{}`: {}", event, command, e);
+            return;
+        }
+    };
+    let group = pool.track(child.id());
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that doesn't read its input closes the pipe early
+        let _ = stdin.write_all(line.as_bytes()).await;
+    }
+    let waited = tokio::time::timeout(HOOK_TIMEOUT, child.wait_with_output()).await;
+    if waited.is_ok() {
+        group.disarm();
+    }
+    match waited {
+        Ok(Ok(output)) if output.status.success() => {}
+        Ok(Ok(output)) => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            warn!(
+                "{} hook `{}` failed ({}) {}",
+                event,
+                command,
+                output.status,
+                stderr.trim()
+            )
+        }
+        Ok(Err(e)) => warn!("Failed to run {} hook `{}`: {}", event, command, e),
+        Err(_) => warn!(
+            "{} hook `{}` timed out after {:?}",
+            event, command, HOOK_TIMEOUT
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::daemon::{Accepted, UnknownCommand};
+
+    fn accepted(buffer: &str, text: &str) -> Event {
+        Event::Accepted(Accepted {
+            text: text.to_string(),
+            source: Default::default(),
+            buffer: buffer.to_string(),
+            cursor: buffer.len(),
+            command: None,
+            session_id: None,
+            cwd: None,
+        })
+    }
+
+    fn unknown(buffer: &str) -> Event {
+        Event::UnknownCommand(UnknownCommand {
+            command: buffer.split(' ').next().unwrap_or_default().to_string(),
+            buffer: buffer.to_string(),
+            session_id: None,
+            cwd: None,
+        })
+    }
+
+    #[test]
+    fn test_emit_private_events_reach_no_subscriber() {
+        let events = Events::new(GeneratorPool::new(1, Duration::from_secs(1)));
+        let mut lines = events.subscribe();
+        let hooks = HooksConfig::default();
+        events.emit(accepted("mysql --password=", "hunter2"), &hooks, true);
+        events.emit(
+            accepted("curl -H ", "'Authorization: Bearer x'"),
+            &hooks,
+            true,
+        );
+        events.emit(accepted(" git push", "origin"), &hooks, true);
+        events.emit(unknown("deploy --token abc"), &hooks, true);
+        events.emit(accepted("git ch", "checkout"), &hooks, true);
+        events.emit(unknown("frobnicate --"), &hooks, true);
+
+        let (name, line) = lines.try_recv().unwrap();
+        assert_eq!(name, "accepted");
+        assert!(line.contains("\"buffer\":\"git ch\""));
+        assert_eq!(lines.try_recv().unwrap().0, "unknown_command");
+        assert!(lines.try_recv().is_err());
+    }
+}
@@ -0,0 +1,36 @@
+//! Telling whether a client sees the same filesystem as the daemon.
+//!
+//! A shell in a container or devcontainer can reach the host's daemon through
+//! an abstract or TCP socket, but its paths and `PATH` are the container's.
+//! Clients identify their filesystem by the kernel's boot id and their mount
+//! namespace. A client on the same kernel can be served through
+//! `/proc/<pid>/root`, its root as the daemon sees it; one on another kernel
+//! (a VM, another machine) can't be served at all.
+
+use std::path::{Path, PathBuf};
+
+/// Identity of the filesystem the current process sees, `None` where mount
+/// namespaces don't exist
+pub fn filesystem_id() -> Option<String> {
+    identify(Path::new("/proc/self"))
+}
+
+/// Where the filesystem of process `pid` is visible to the daemon, if the
+/// process sees the filesystem identified by `id`
+pub fn root_of(pid: u32, id: &str) -> Option<PathBuf> {
+    let process = PathBuf::from(format!("/proc/{pid}"));
+    if identify(&process)? != id {
+        return None;
+    }
+    let root = process.join("root");
+    // Reading another user's root needs the same privileges as ptrace
+    std::fs::read_dir(&root).ok()?;
+    Some(root)
+}
+
+/// `<boot id>/<mount namespace>` of the process at `/proc/<pid>`
+fn identify(process: &Path) -> Option<String> {
+    let boot_id = std::fs::read_to_string("/proc/sys/kernel/random/boot_id").ok()?;
+    let namespace = std::fs::read_link(process.join("ns/mnt")).ok()?;
+    Some(format!("{}/{}", boot_id.trim(), namespace.display()))
+}
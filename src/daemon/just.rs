@@ -0,0 +1,534 @@
+//! Recipes of the project's `justfile`, for `just`.
+//!
+//! What recipes a project has is up to its `justfile`, which no spec can
+//! know. It is read from the working directory or the closest one above it,
+//! or the file `-f`/`--justfile` names, and its recipes and aliases are
+//! offered where `just` takes one, described like `just --list` does: with
+//! their parameters and the comment above them or their `[doc]` attribute.
+//! Private recipes, those starting with `_` or marked `[private]`, are left
+//! out. Where a word is an argument of the recipe before it, the parameter's
+//! default is offered instead.
+
+use super::{Suggestion, SuggestionSource};
+use crate::parser::Token;
+use std::path::{Path, PathBuf};
+
+/// Options of just taking a value, with how many
+const VALUE_OPTIONS: &[(&str, usize)] = &[
+    ("-f", 1),
+    ("--justfile", 1),
+    ("-d", 1),
+    ("--working-directory", 1),
+    ("--set", 2),
+    ("--shell", 1),
+    ("--shell-arg", 1),
+    ("--dotenv-filename", 1),
+    ("--dotenv-path", 1),
+    ("--chooser", 1),
+    ("--color", 1),
+    ("--command-color", 1),
+    ("--list-heading", 1),
+    ("--list-prefix", 1),
+];
+
+/// What is offered for the word being typed
+#[derive(Debug)]
+pub enum Completion {
+    /// The recipes and aliases, where just takes a recipe
+    Recipes(Vec<Suggestion>),
+    /// The default of the parameter the word is an argument for, if it has one
+    Parameter(Vec<Suggestion>),
+}
+
+#[derive(Debug, Default)]
+struct Recipe {
+    name: String,
+    parameters: Vec<Parameter>,
+    doc: Option<String>,
+    private: bool,
+}
+
+#[derive(Debug)]
+struct Parameter {
+    name: String,
+    /// As written, quotes included
+    default: Option<String>,
+    /// `+name` or `*name`, taking all the words after it
+    variadic: Option<char>,
+}
+
+impl Parameter {
+    /// The parameter as `just --list` shows it
+    fn signature(&self) -> String {
+        let variadic = self.variadic.map(String::from).unwrap_or_default();
+        match &self.default {
+            Some(default) => format!("{variadic}{}={default}", self.name),
+            None => format!("{variadic}{}", self.name),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Justfile {
+    recipes: Vec<Recipe>,
+    /// Alias and recipe names, and whether the alias is private
+    aliases: Vec<(String, String, bool)>,
+}
+
+impl Justfile {
+    fn recipe(&self, name: &str) -> Option<&Recipe> {
+        let name = self
+            .aliases
+            .iter()
+            .find(|(alias, _, _)| alias == name)
+            .map_or(name, |(_, recipe, _)| recipe.as_str());
+        self.recipes.iter().find(|recipe| recipe.name == name)
+    }
+}
+
+/// What to offer for `word` after the completed `tokens` of a `just`
+/// command run in `cwd`; `None` for other commands, options and projects
+/// without a justfile
+pub fn complete(tokens: &[Token], word: &str, cwd: &Path) -> Option<Completion> {
+    let (command, args) = tokens.split_first()?;
+    if command.text != "just" || word.starts_with('-') {
+        return None;
+    }
+    let mut explicit = None;
+    let mut positionals = Vec::new();
+    let mut words = args.iter().map(|token| token.text.as_str());
+    while let Some(word) = words.next() {
+        if let Some((option, value)) = word.split_once('=')
+            && option.starts_with("--")
+        {
+            if option == "--justfile" {
+                explicit = Some(value.to_string());
+            }
+            continue;
+        }
+        if let Some(&(option, values)) = VALUE_OPTIONS.iter().find(|(option, _)| *option == word) {
+            let value = words.next();
+            if matches!(option, "-f" | "--justfile") {
+                explicit = value.map(str::to_string);
+            }
+            words.by_ref().take(values - 1).for_each(drop);
+            continue;
+        }
+        if !word.starts_with('-') {
+            positionals.push(word);
+        }
+    }
+
+    let path = match explicit {
+        Some(path) => cwd.join(path),
+        None => find(cwd)?,
+    };
+    let mut justfile = Justfile::default();
+    read(&path, &mut justfile, 0);
+
+    // Arguments go to the recipe before them as long as it has parameters
+    // left; `NAME=value` before the first recipe sets a variable
+    let mut current: Option<&Recipe> = None;
+    let mut taken = 0;
+    for &positional in &positionals {
+        if current.is_none() && positional.contains('=') {
+            continue;
+        }
+        match current {
+            Some(recipe) if takes(recipe, taken).is_some() => taken += 1,
+            _ => {
+                current = justfile.recipe(positional);
+                taken = 0;
+            }
+        }
+    }
+    if let Some(recipe) = current
+        && let Some(parameter) = takes(recipe, taken)
+    {
+        let default = parameter
+            .default
+            .as_deref()
+            .map(unquote)
+            .filter(|default| !default.is_empty())
+            .map(|default| Suggestion {
+                text: default.to_string(),
+                description: format!("Default of {} of {}", parameter.name, recipe.name),
+                source: SuggestionSource::Spec,
+                ..Default::default()
+            });
+        return Some(Completion::Parameter(default.into_iter().collect()));
+    }
+
+    let mut suggestions: Vec<Suggestion> = justfile
+        .recipes
+        .iter()
+        .filter(|recipe| !recipe.private && !recipe.name.starts_with('_'))
+        .map(|recipe| Suggestion {
+            text: recipe.name.clone(),
+            description: describe(recipe),
+            source: SuggestionSource::Spec,
+            ..Default::default()
+        })
+        .collect();
+    for (alias, target, private) in &justfile.aliases {
+        if *private || alias.starts_with('_') {
+            continue;
+        }
+        suggestions.push(Suggestion {
+            text: alias.clone(),
+            description: format!("Alias for {target}"),
+            source: SuggestionSource::Spec,
+            ..Default::default()
+        });
+    }
+    suggestions.sort_by(|a, b| a.text.cmp(&b.text));
+    Some(Completion::Recipes(suggestions))
+}
+
+/// The parameter of `recipe` the next argument goes to, after `taken`
+fn takes(recipe: &Recipe, taken: usize) -> Option<&Parameter> {
+    recipe.parameters.get(taken).or_else(|| {
+        recipe
+            .parameters
+            .last()
+            .filter(|parameter| parameter.variadic.is_some())
+    })
+}
+
+/// The recipe's parameters and documentation, as `just --list` puts them
+fn describe(recipe: &Recipe) -> String {
+    let signature: Vec<String> = recipe.parameters.iter().map(Parameter::signature).collect();
+    match (signature.is_empty(), &recipe.doc) {
+        (true, None) => String::new(),
+        (true, Some(doc)) => doc.clone(),
+        (false, None) => signature.join(" "),
+        (false, Some(doc)) => format!("{} # {doc}", signature.join(" ")),
+    }
+}
+
+/// The justfile in `cwd` or the closest directory above it; just takes any
+/// capitalization of `justfile`, and `.justfile`
+fn find(cwd: &Path) -> Option<PathBuf> {
+    cwd.ancestors().find_map(|dir| {
+        let mut names: Vec<PathBuf> = std::fs::read_dir(dir)
+            .ok()?
+            .flatten()
+            .filter(|entry| {
+                let name = entry.file_name().to_string_lossy().to_ascii_lowercase();
+                name == "justfile" || name == ".justfile"
+            })
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .collect();
+        names.sort();
+        names.into_iter().next()
+    })
+}
+
+/// Add the recipes and aliases of the justfile at `path` and those it
+/// imports, up to a few files deep
+fn read(path: &Path, justfile: &mut Justfile, depth: usize) {
+    let Ok(text) = std::fs::read_to_string(path) else {
+        return;
+    };
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut comment: Option<String> = None;
+    let mut doc: Option<String> = None;
+    let mut private = false;
+    for line in text.lines() {
+        // Recipe bodies are indented
+        if line.starts_with([' ', '\t']) || line.trim().is_empty() {
+            comment = None;
+            doc = None;
+            private = false;
+            continue;
+        }
+        let line = line.trim_end();
+        if let Some(text) = line.strip_prefix('#') {
+            // `#!` starts a shebang body, not a comment for a recipe
+            comment = (!text.starts_with('!')).then(|| text.trim().to_string());
+            continue;
+        }
+        if let Some(attributes) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            for attribute in split(attributes, ',') {
+                let (name, argument) = match attribute.split_once(['(', ':']) {
+                    Some((name, argument)) => (name.trim(), Some(argument.trim_end_matches(')'))),
+                    None => (attribute.trim(), None),
+                };
+                match name {
+                    "private" => private = true,
+                    "doc" => doc = argument.map(|argument| unquote(argument.trim()).to_string()),
+                    _ => {}
+                }
+            }
+            continue;
+        }
+        let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        match keyword {
+            "alias" => {
+                if let Some((alias, target)) = rest.split_once(":=") {
+                    justfile.aliases.push((
+                        alias.trim().to_string(),
+                        target.trim().to_string(),
+                        private,
+                    ));
+                }
+            }
+            "import" | "import?" if depth < 4 => {
+                read(&dir.join(unquote(rest.trim())), justfile, depth + 1);
+            }
+            "set" | "export" | "import" | "import?" | "mod" | "mod?" | "unexport" => {}
+            _ => {
+                if let Some(recipe) = recipe(line) {
+                    let mut recipe = recipe;
+                    recipe.doc = doc
+                        .take()
+                        .or_else(|| comment.take())
+                        .filter(|doc| !doc.is_empty());
+                    recipe.private = private;
+                    // A later recipe of the same name replaces the earlier, as
+                    // with `set allow-duplicate-recipes`
+                    justfile.recipes.retain(|known| known.name != recipe.name);
+                    justfile.recipes.push(recipe);
+                }
+            }
+        }
+        comment = None;
+        doc = None;
+        private = false;
+    }
+}
+
+/// The recipe a header line declares, `None` for assignments and the like
+fn recipe(line: &str) -> Option<Recipe> {
+    let line = line.strip_prefix('@').unwrap_or(line);
+    let end = line
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+        .unwrap_or(line.len());
+    let name = &line[..end];
+    if name.is_empty() || name.starts_with('-') {
+        return None;
+    }
+    // The parameters run to the first `:` outside quotes and parentheses
+    let rest = &line[end..];
+    let colon = unquoted(rest, ':')?;
+    if rest[colon + 1..].starts_with('=') {
+        return None;
+    }
+    let parameters = split(&rest[..colon], ' ')
+        .into_iter()
+        .filter(|parameter| !parameter.is_empty())
+        .map(|parameter| {
+            let (variadic, parameter) = match parameter.strip_prefix(['+', '*']) {
+                Some(rest) => (parameter.chars().next(), rest),
+                None => (None, parameter),
+            };
+            let parameter = parameter.strip_prefix('$').unwrap_or(parameter);
+            let (name, default) = match parameter.split_once('=') {
+                Some((name, default)) => (name, Some(default.to_string())),
+                None => (parameter, None),
+            };
+            Parameter {
+                name: name.to_string(),
+                default,
+                variadic,
+            }
+        })
+        .collect();
+    Some(Recipe {
+        name: name.to_string(),
+        parameters,
+        ..Default::default()
+    })
+}
+
+/// Byte offset of the first `c` in `text` outside quotes and parentheses
+fn unquoted(text: &str, c: char) -> Option<usize> {
+    let mut quote = None;
+    let mut depth = 0usize;
+    for (at, ch) in text.char_indices() {
+        match (quote, ch) {
+            (Some(open), ch) if ch == open => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"' | '`') => quote = Some(ch),
+            (None, '(') => depth += 1,
+            (None, ')') => depth = depth.saturating_sub(1),
+            (None, ch) if ch == c && depth == 0 => return Some(at),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// `text` split at every `separator` outside quotes and parentheses
+fn split(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut rest = text;
+    while let Some(at) = unquoted(rest, separator) {
+        parts.push(rest[..at].trim());
+        rest = &rest[at + separator.len_utf8()..];
+    }
+    parts.push(rest.trim());
+    parts
+}
+
+/// A string literal's text, or `text` as is when it isn't one
+fn unquote(text: &str) -> &str {
+    ['\'', '"']
+        .into_iter()
+        .find_map(|quote| text.strip_prefix(quote)?.strip_suffix(quote))
+        .unwrap_or(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::ParseState;
+    use crate::testing::TempDir;
+
+    const JUSTFILE: &str = r#"set shell := ["bash", "-c"]
+version := "1.0"
+
+alias b := build
+[private]
+alias x := _hidden
+
+# Build the project
+build target="debug" *flags:
+    cargo build --profile {{target}} {{flags}}
+
+[doc("Run the tests")]
+test filter='':
+    cargo test {{filter}}
+
+[private]
+secret:
+    echo hidden
+
+_hidden:
+    echo hidden
+
+#!/usr/bin/env bash
+@deploy $env +hosts:
+    echo {{env}}
+
+import 'extra.just'
+"#;
+
+    fn texts(suggestions: &[Suggestion]) -> Vec<(&str, &str)> {
+        suggestions
+            .iter()
+            .map(|suggestion| (suggestion.text.as_str(), suggestion.description.as_str()))
+            .collect()
+    }
+
+    fn completion(dir: &TempDir, buffer: &str, word: &str) -> Option<Completion> {
+        let state = ParseState::new(buffer);
+        complete(state.tokens(), word, dir.path())
+    }
+
+    fn project() -> TempDir {
+        let dir = TempDir::new();
+        dir.write("justfile", JUSTFILE);
+        dir.write("extra.just", "# From the import\nlint:\n    cargo clippy\n");
+        dir
+    }
+
+    #[test]
+    fn test_recipe_headers() {
+        let build = recipe(r#"build target="a:b" *flags: dep"#).unwrap();
+        assert_eq!(build.name, "build");
+        let signatures: Vec<String> = build.parameters.iter().map(Parameter::signature).collect();
+        assert_eq!(signatures, [r#"target="a:b""#, "*flags"]);
+        assert_eq!(recipe("@quiet:").unwrap().name, "quiet");
+        // Assignments and settings declare no recipe
+        assert!(recipe("version := \"1.0\"").is_none());
+        assert!(recipe("export PATH := \"x\"").is_none());
+        assert!(recipe("-flag:").is_none());
+    }
+
+    #[test]
+    fn test_recipes_with_docs_and_aliases() {
+        let dir = project();
+        let Some(Completion::Recipes(recipes)) = completion(&dir, "just ", "") else {
+            panic!("expected recipes");
+        };
+        assert_eq!(
+            texts(&recipes),
+            [
+                ("b", "Alias for build"),
+                ("build", r#"target="debug" *flags # Build the project"#),
+                ("deploy", "env +hosts"),
+                ("lint", "From the import"),
+                ("test", "filter='' # Run the tests"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parameter_default_after_recipe() {
+        let dir = project();
+        let Some(Completion::Parameter(defaults)) = completion(&dir, "just build ", "") else {
+            panic!("expected a parameter");
+        };
+        assert_eq!(texts(&defaults), [("debug", "Default of target of build")]);
+        // The alias takes the recipe's parameters
+        assert!(matches!(
+            completion(&dir, "just b ", ""),
+            Some(Completion::Parameter(defaults)) if defaults.len() == 1
+        ));
+    }
+
+    #[test]
+    fn test_variadic_parameter_keeps_taking_words() {
+        let dir = project();
+        let Some(Completion::Parameter(defaults)) = completion(&dir, "just deploy prod a b ", "")
+        else {
+            panic!("expected a parameter");
+        };
+        assert!(defaults.is_empty());
+        // A recipe without parameters left is followed by another recipe
+        assert!(matches!(
+            completion(&dir, "just test x ", ""),
+            Some(Completion::Recipes(_))
+        ));
+    }
+
+    #[test]
+    fn test_justfile_option_and_parent_directories() {
+        let dir = project();
+        dir.write("other/tasks.just", "only:\n    true\n");
+        let Some(Completion::Recipes(recipes)) =
+            completion(&dir, "just --justfile other/tasks.just ", "")
+        else {
+            panic!("expected recipes");
+        };
+        assert_eq!(texts(&recipes), [("only", "")]);
+        dir.write("nested/deeper/file.txt", "");
+        let state = ParseState::new("just ");
+        let found = complete(state.tokens(), "", &dir.path().join("nested/deeper"));
+        assert!(matches!(found, Some(Completion::Recipes(recipes)) if recipes.len() == 5));
+    }
+
+    #[test]
+    fn test_other_commands_and_options_are_left_alone() {
+        let dir = project();
+        assert!(completion(&dir, "make ", "").is_none());
+        assert!(completion(&dir, "just ", "--li").is_none());
+        assert!(completion(&TempDir::new(), "just ", "").is_none());
+    }
+
+    #[test]
+    fn test_split_and_unquote() {
+        assert_eq!(
+            split(r#"doc("a, b"), private"#, ','),
+            [r#"doc("a, b")"#, "private"]
+        );
+        assert_eq!(unquote("'x'"), "x");
+        assert_eq!(unquote(r#""y""#), "y");
+        assert_eq!(unquote("z"), "z");
+    }
+}
@@ -0,0 +1,134 @@
+//! kubectl generators scoped to the cluster and namespace of the command.
+//!
+//! The kubectl spec's generators list the contexts, namespaces, kinds and
+//! resources of the current kubeconfig context. A command line naming
+//! another with `--context`, `-n`/`--namespace`, `--kubeconfig` and the like,
+//! or `-A`, is about other resources, so those options are passed on to the
+//! generators it runs, and listing resources by name (`kubectl get` with no
+//! kind, as in the spec) lists those of the kind typed before the name. The
+//! scoped script is what the output is cached under, so every namespace and
+//! context has its own. Asking a cluster takes a second or more, so the spec
+//! keeps outputs for long and gives expired ones while refreshing them.
+
+use crate::parser::Token;
+
+/// Options of kubectl taking a value, which is then not taken for a kind
+const VALUE_OPTIONS: &[&str] = &[
+    "-n",
+    "--namespace",
+    "--context",
+    "--kubeconfig",
+    "--cluster",
+    "--user",
+    "-c",
+    "--container",
+    "-f",
+    "--filename",
+    "-k",
+    "--kustomize",
+    "-l",
+    "--selector",
+    "-o",
+    "--output",
+    "-s",
+    "--server",
+    "--address",
+    "--dry-run",
+    "--field-selector",
+    "--grace-period",
+    "--port",
+    "--replicas",
+    "--since",
+    "--tail",
+    "--target-port",
+    "--to-revision",
+    "--type",
+];
+
+/// Options passed on to generators, with the long name of their short one
+const SCOPE_OPTIONS: &[(&str, &str)] = &[
+    ("-n", "--namespace"),
+    ("--namespace", "--namespace"),
+    ("--context", "--context"),
+    ("--kubeconfig", "--kubeconfig"),
+    ("--cluster", "--cluster"),
+    ("--user", "--user"),
+];
+
+/// Subcommands whose own subcommand comes before the kind, as in
+/// `kubectl rollout restart deployment`
+const GROUPS: &[&str] = &["rollout"];
+
+/// The options and words of a command line past `kubectl`
+#[derive(Debug, Default)]
+struct Typed {
+    /// Scoping options, as `--name=value`
+    scope: Vec<String>,
+    /// Words other than options and their values
+    positionals: Vec<String>,
+}
+
+/// The generator `script` scoped to the command line, of which `args` are
+/// the words after `kubectl`; `None` when it lists resources by name but no
+/// kind was typed
+pub fn scope(script: &[&str], args: &[Token]) -> Option<Vec<String>> {
+    let ["kubectl", rest @ ..] = script else {
+        return Some(script.iter().map(|part| part.to_string()).collect());
+    };
+    let typed = typed(args);
+    let mut scoped = vec!["kubectl".to_string()];
+    scoped.extend(typed.scope.iter().cloned());
+    match rest {
+        // The kind is left out of the spec, to be the one typed
+        ["get", option, ..] if option.starts_with('-') => {
+            let verbs = match typed.positionals.first() {
+                Some(verb) if GROUPS.contains(&verb.as_str()) => 2,
+                _ => 1,
+            };
+            let kind = typed.positionals.get(verbs)?;
+            // `pod/web` names its resource already
+            if kind.contains('/') {
+                return None;
+            }
+            scoped.push("get".to_string());
+            scoped.push(kind.clone());
+            scoped.extend(rest[1..].iter().map(|part| part.to_string()));
+        }
+        _ => scoped.extend(rest.iter().map(|part| part.to_string())),
+    }
+    Some(scoped)
+}
+
+fn typed(args: &[Token]) -> Typed {
+    let mut typed = Typed::default();
+    let mut words = args.iter().map(|token| token.text.as_str());
+    while let Some(word) = words.next() {
+        if word == "-A" || word == "--all-namespaces" {
+            typed.scope.push("--all-namespaces".to_string());
+            continue;
+        }
+        if word == "--" || !word.starts_with('-') || word == "-" {
+            typed.positionals.push(word.to_string());
+            continue;
+        }
+        // `--namespace=web`, `-n=web` and `-nweb` as well as `-n web`
+        let (name, attached) = match word.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None if !word.starts_with("--") && word.len() > 2 => {
+                let (name, value) = word.split_at(2);
+                (name, VALUE_OPTIONS.contains(&name).then_some(value))
+            }
+            None => (word, None),
+        };
+        if !VALUE_OPTIONS.contains(&name) {
+            continue;
+        }
+        let Some(value) = attached.or_else(|| words.next()) else {
+            break;
+        };
+        if let Some((_, long)) = SCOPE_OPTIONS.iter().find(|(option, _)| *option == name) {
+            typed.scope.push(format!("{long}={value}"));
+        }
+    }
+    typed
+}
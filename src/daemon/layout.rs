@@ -0,0 +1,52 @@
+use super::{Suggestion, TerminalInfo};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Columns used by the popup border
+const BORDER_WIDTH: usize = 2;
+/// Separator between text and description
+const SEPARATOR: &str = " - ";
+/// Descriptions narrower than this are dropped rather than truncated
+const MIN_DESCRIPTION_WIDTH: usize = 8;
+
+/// Truncate descriptions so every suggestion fits on one line of `terminal`.
+///
+/// Widths are measured in terminal columns, so wide (CJK, emoji) characters
+/// count double. Terminals without Unicode support get an ASCII ellipsis.
+pub fn fit(suggestions: Vec<Suggestion>, terminal: &TerminalInfo) -> Vec<Suggestion> {
+    let ellipsis = if terminal.unicode { "…" } else { "..." };
+    let available = usize::from(terminal.columns).saturating_sub(BORDER_WIDTH);
+
+    suggestions
+        .into_iter()
+        .map(|mut suggestion| {
+            let used = suggestion.text.width() + SEPARATOR.len();
+            let budget = available.saturating_sub(used);
+            if suggestion.description.width() > budget {
+                suggestion.description = if budget < MIN_DESCRIPTION_WIDTH {
+                    String::new()
+                } else {
+                    truncate(&suggestion.description, budget, ellipsis)
+                };
+            }
+            suggestion
+        })
+        .collect()
+}
+
+/// Cut `text` to at most `width` columns, including the ellipsis
+pub fn truncate(text: &str, width: usize, ellipsis: &str) -> String {
+    let budget = width.saturating_sub(ellipsis.width());
+    let mut used = 0;
+    let mut out = String::new();
+    for c in text.chars() {
+        let w = c.width().unwrap_or(0);
+        if used + w > budget {
+            break;
+        }
+        used += w;
+        out.push(c);
+    }
+    out.truncate(out.trim_end().len());
+    out.push_str(ellipsis);
+    out
+}
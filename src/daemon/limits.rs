@@ -0,0 +1,162 @@
+use super::{Suggestion, SuggestionSource};
+use crate::config::Config;
+use std::collections::HashMap;
+
+/// Enforce the per-source caps, then cut out the page of `max_suggestions`
+/// starting at `offset`.
+///
+/// Suggestions keep their original order; a suggestion is dropped once its
+/// source has already contributed its configured maximum. Also returns
+/// whether suggestions remain after the page.
+pub fn apply(
+    suggestions: Vec<Suggestion>,
+    offset: usize,
+    config: &Config,
+) -> (Vec<Suggestion>, bool) {
+    let mut taken: HashMap<SuggestionSource, usize> = HashMap::new();
+
+    let mut remaining = suggestions
+        .into_iter()
+        .filter(|suggestion| {
+            let count = taken.entry(suggestion.source).or_insert(0);
+            match config.source_limits.get(&suggestion.source) {
+                Some(&limit) if *count >= limit => false,
+                _ => {
+                    *count += 1;
+                    true
+                }
+            }
+        })
+        .skip(offset);
+    let page = remaining
+        .by_ref()
+        .take(config.general.max_suggestions)
+        .collect();
+    (page, remaining.next().is_some())
+}
+
+/// Whether `source` may still contribute after `count` suggestions
+pub fn has_room(source: SuggestionSource, count: usize, config: &Config) -> bool {
+    config
+        .source_limits
+        .get(&source)
+        .is_none_or(|&limit| count < limit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn suggestions(sources: &[(&str, SuggestionSource)]) -> Vec<Suggestion> {
+        sources
+            .iter()
+            .map(|&(text, source)| Suggestion {
+                text: text.to_string(),
+                source,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn config(max_suggestions: usize, limits: &[(SuggestionSource, usize)]) -> Config {
+        let mut config = Config::default();
+        config.general.max_suggestions = max_suggestions;
+        config.source_limits = limits.iter().copied().collect();
+        config
+    }
+
+    fn texts(page: &[Suggestion]) -> Vec<&str> {
+        page.iter()
+            .map(|suggestion| suggestion.text.as_str())
+            .collect()
+    }
+
+    fn mixed() -> Vec<Suggestion> {
+        use SuggestionSource::{Files, History, Spec};
+        suggestions(&[
+            ("add", Spec),
+            ("src/", Files),
+            ("git add .", History),
+            ("docs/", Files),
+            ("commit", Spec),
+            ("tests/", Files),
+        ])
+    }
+
+    #[test]
+    fn test_apply_cap_smaller_than_source_drops_its_later_suggestions() {
+        let config = config(10, &[(SuggestionSource::Files, 2)]);
+        let (page, more) = apply(mixed(), 0, &config);
+        assert_eq!(
+            texts(&page),
+            ["add", "src/", "git add .", "docs/", "commit"]
+        );
+        assert!(!more);
+    }
+
+    #[test]
+    fn test_apply_cap_larger_than_source_keeps_all() {
+        let config = config(10, &[(SuggestionSource::Spec, 5)]);
+        let (page, _) = apply(mixed(), 0, &config);
+        assert_eq!(page.len(), 6);
+    }
+
+    #[test]
+    fn test_apply_zero_limit_drops_the_source() {
+        let config = config(10, &[(SuggestionSource::Files, 0)]);
+        let (page, more) = apply(mixed(), 0, &config);
+        assert_eq!(texts(&page), ["add", "git add .", "commit"]);
+        assert!(!more);
+    }
+
+    #[test]
+    fn test_apply_zero_max_suggestions_returns_empty_page_with_more() {
+        let (page, more) = apply(mixed(), 0, &config(0, &[]));
+        assert!(page.is_empty());
+        assert!(more);
+        let (page, more) = apply(Vec::new(), 0, &config(0, &[]));
+        assert!(page.is_empty());
+        assert!(!more);
+    }
+
+    #[test]
+    fn test_apply_pages_end_at_the_last_suggestion() {
+        let config = config(2, &[]);
+        let (page, more) = apply(mixed(), 0, &config);
+        assert_eq!(texts(&page), ["add", "src/"]);
+        assert!(more);
+        let (page, more) = apply(mixed(), 4, &config);
+        assert_eq!(texts(&page), ["commit", "tests/"]);
+        assert!(!more);
+        let (page, more) = apply(mixed(), 5, &config);
+        assert_eq!(texts(&page), ["tests/"]);
+        assert!(!more);
+        let (page, more) = apply(mixed(), 6, &config);
+        assert!(page.is_empty());
+        assert!(!more);
+        let (page, more) = apply(mixed(), 60, &config);
+        assert!(page.is_empty());
+        assert!(!more);
+    }
+
+    #[test]
+    fn test_apply_offset_counts_suggestions_left_after_caps() {
+        let config = config(2, &[(SuggestionSource::Files, 1)]);
+        // add, src/, git add ., commit
+        let (page, more) = apply(mixed(), 2, &config);
+        assert_eq!(texts(&page), ["git add .", "commit"]);
+        assert!(!more);
+    }
+
+    #[test]
+    fn test_has_room_until_the_limit() {
+        let config = config(
+            10,
+            &[(SuggestionSource::Files, 2), (SuggestionSource::History, 0)],
+        );
+        assert!(has_room(SuggestionSource::Files, 1, &config));
+        assert!(!has_room(SuggestionSource::Files, 2, &config));
+        assert!(!has_room(SuggestionSource::History, 0, &config));
+        assert!(has_room(SuggestionSource::Spec, 1000, &config));
+    }
+}
@@ -0,0 +1,220 @@
+//! Endpoints the daemon listens on besides its main socket.
+//!
+//! One daemon can serve shells on the host and in containers: a devcontainer
+//! that shares the host's network namespace reaches an abstract socket, one
+//! with its own network a TCP port.
+
+use super::restart;
+use anyhow::{Context, Result, bail};
+use std::fmt;
+use std::net::SocketAddr;
+use std::os::fd::{AsRawFd, FromRawFd, RawFd};
+use std::path::PathBuf;
+use std::str::FromStr;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
+
+/// Where to listen or connect: `/path/to.sock` (or `unix:/path/to.sock`),
+/// `abstract:name` for a Linux abstract socket, or `tcp:host:port`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    Unix(PathBuf),
+    Abstract(String),
+    Tcp(String),
+}
+
+impl FromStr for Endpoint {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some(name) = s.strip_prefix("abstract:") {
+            if name.is_empty() {
+                bail!("Abstract socket name is empty");
+            }
+            return Ok(Endpoint::Abstract(name.to_string()));
+        }
+        if let Some(addr) = s.strip_prefix("tcp:") {
+            if !addr.contains(':') {
+                bail!("TCP endpoint needs a port: tcp:{addr}:<port>");
+            }
+            return Ok(Endpoint::Tcp(addr.to_string()));
+        }
+        let path = s.strip_prefix("unix:").unwrap_or(s);
+        if path.is_empty() {
+            bail!("Socket path is empty");
+        }
+        Ok(Endpoint::Unix(PathBuf::from(path)))
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Unix(path) => write!(f, "{}", path.display()),
+            Endpoint::Abstract(name) => write!(f, "abstract:{name}"),
+            Endpoint::Tcp(addr) => write!(f, "tcp:{addr}"),
+        }
+    }
+}
+
+/// A connection from either kind of socket
+pub trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+/// A bound endpoint accepting connections
+pub enum Listener {
+    Unix(UnixListener),
+    Tcp(TcpListener),
+}
+
+impl Listener {
+    /// Bind `endpoint`, replacing a leftover socket file
+    pub async fn bind(endpoint: &Endpoint) -> Result<Self> {
+        match endpoint {
+            Endpoint::Unix(path) => {
+                // The runtime directory may not exist yet (e.g. a custom socket location)
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent).with_context(|| {
+                        format!("Failed to create socket directory: {}", parent.display())
+                    })?;
+                }
+                let _ = std::fs::remove_file(path);
+                let listener = UnixListener::bind(path)
+                    .with_context(|| format!("Failed to bind to socket: {}", path.display()))?;
+                Ok(Listener::Unix(listener))
+            }
+            Endpoint::Abstract(name) => Ok(Listener::Unix(bind_abstract(name)?)),
+            Endpoint::Tcp(addr) => {
+                let listener = TcpListener::bind(addr)
+                    .await
+                    .with_context(|| format!("Failed to bind to tcp:{addr}"))?;
+                Ok(Listener::Tcp(listener))
+            }
+        }
+    }
+
+    /// Take over `fd`, a socket bound to `endpoint` by the daemon this one
+    /// replaced
+    pub fn adopt(endpoint: &Endpoint, fd: RawFd) -> Result<Self> {
+        restart::set_inheritable(fd, false);
+        // SAFETY: the previous daemon handed over `fd` open and is gone, so
+        // nothing else owns it
+        Ok(match endpoint {
+            Endpoint::Tcp(_) => {
+                let listener = unsafe { std::net::TcpListener::from_raw_fd(fd) };
+                listener.set_nonblocking(true)?;
+                Listener::Tcp(TcpListener::from_std(listener)?)
+            }
+            _ => {
+                let listener = unsafe { std::os::unix::net::UnixListener::from_raw_fd(fd) };
+                listener.set_nonblocking(true)?;
+                Listener::Unix(UnixListener::from_std(listener)?)
+            }
+        })
+    }
+
+    /// The listening socket, to hand over on restart
+    pub fn as_raw_fd(&self) -> RawFd {
+        match self {
+            Listener::Unix(listener) => listener.as_raw_fd(),
+            Listener::Tcp(listener) => listener.as_raw_fd(),
+        }
+    }
+
+    /// The next connection, and the client's process id when the socket
+    /// reports it (unix sockets, not TCP)
+    pub async fn accept(&self) -> std::io::Result<(Box<dyn Stream>, Option<u32>)> {
+        Ok(match self {
+            Listener::Unix(listener) => {
+                let (stream, _) = listener.accept().await?;
+                let pid = stream.peer_cred().ok().and_then(|cred| cred.pid());
+                (
+                    Box::new(stream),
+                    pid.and_then(|pid| u32::try_from(pid).ok()),
+                )
+            }
+            Listener::Tcp(listener) => (Box::new(listener.accept().await?.0), None),
+        })
+    }
+}
+
+/// Connect to a daemon listening on `endpoint`
+pub async fn connect(endpoint: &Endpoint) -> std::io::Result<Box<dyn Stream>> {
+    Ok(match endpoint {
+        Endpoint::Unix(path) => Box::new(UnixStream::connect(path).await?),
+        Endpoint::Abstract(name) => Box::new(connect_abstract(name)?),
+        Endpoint::Tcp(addr) => Box::new(TcpStream::connect(addr).await?),
+    })
+}
+
+/// Whether binding `addr` exposes the daemon beyond this machine
+pub fn is_public(endpoint: &Endpoint) -> bool {
+    match endpoint {
+        Endpoint::Tcp(addr) => !addr
+            .parse::<SocketAddr>()
+            .is_ok_and(|addr| addr.ip().is_loopback()),
+        _ => false,
+    }
+}
+
+/// Whether every local user can connect to `endpoint`: abstract and TCP
+/// sockets have no file permissions to keep them out
+pub fn is_shared(endpoint: &Endpoint) -> bool {
+    matches!(endpoint, Endpoint::Abstract(_) | Endpoint::Tcp(_))
+}
+
+#[cfg(target_os = "linux")]
+fn bind_abstract(name: &str) -> Result<UnixListener> {
+    use std::os::linux::net::SocketAddrExt;
+    let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+    let listener = std::os::unix::net::UnixListener::bind_addr(&addr)
+        .with_context(|| format!("Failed to bind to abstract:{name}"))?;
+    listener.set_nonblocking(true)?;
+    Ok(UnixListener::from_std(listener)?)
+}
+
+#[cfg(target_os = "linux")]
+fn connect_abstract(name: &str) -> std::io::Result<UnixStream> {
+    use std::os::linux::net::SocketAddrExt;
+    let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+    let stream = std::os::unix::net::UnixStream::connect_addr(&addr)?;
+    stream.set_nonblocking(true)?;
+    UnixStream::from_std(stream)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn bind_abstract(name: &str) -> Result<UnixListener> {
+    bail!("abstract:{name}: abstract sockets are only available on Linux")
+}
+
+#[cfg(not(target_os = "linux"))]
+fn connect_abstract(_name: &str) -> std::io::Result<UnixStream> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "abstract sockets are only available on Linux",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_socket_files_are_private() {
+        let shared = |endpoint: &str| is_shared(&endpoint.parse().unwrap());
+        assert!(!shared("/run/user/1000/autocomplete-rs.sock"));
+        assert!(!shared("unix:/workspaces/.autocomplete-rs.sock"));
+        assert!(shared("abstract:autocomplete-rs"));
+        assert!(shared("tcp:127.0.0.1:7777"));
+    }
+
+    #[test]
+    fn loopback_tcp_is_not_public() {
+        let public = |endpoint: &str| is_public(&endpoint.parse().unwrap());
+        assert!(!public("tcp:127.0.0.1:7777"));
+        assert!(!public("tcp:[::1]:7777"));
+        assert!(public("tcp:0.0.0.0:7777"));
+        assert!(!public("abstract:autocomplete-rs"));
+    }
+}
@@ -1,70 +1,546 @@
+use crate::config::Config;
+use crate::generators::{CacheKey, CachePolicy, GeneratorPool};
+use crate::i18n;
+use crate::index::CommandIndex;
+use crate::index::describe::Descriptions;
+use crate::parser::{self, SessionCache};
+use crate::specs::{self, CacheStrategy, Generator, SpecStore, Template, UserSpecs, resolve};
+use crate::stats::history::CommandHistory;
+use crate::stats::{self, Pruned, UsageStats};
 use anyhow::{Context, Result};
-use serde::{Deserialize, Serialize};
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::{UnixListener, UnixStream};
+use listen::Listener;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
 use tokio::signal;
-use tracing::{error, info};
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tracing::{debug, error, info, warn};
 
-/// Request from shell client containing command buffer and cursor position
-#[derive(Debug, Serialize, Deserialize)]
-pub struct CompletionRequest {
-    /// Current command buffer text
-    pub buffer: String,
-    /// Cursor position in the buffer
-    pub cursor: usize,
-    /// Protocol version for future compatibility
-    #[serde(default = "default_version")]
-    pub version: u8,
-}
+mod abbrev;
+mod cargo;
+mod coalesce;
+mod context;
+mod correct;
+mod docker;
+mod examples;
+mod executables;
+mod files;
+mod fixture;
+mod followup;
+mod git;
+mod guess;
+mod hooks;
+mod host;
+mod just;
+mod kubectl;
+mod layout;
+mod limits;
+mod listen;
+mod npm;
+mod pairs;
+mod protocol;
+mod rank;
+mod recall;
+mod restart;
+mod segment;
+mod values;
+mod visited;
+mod watcher;
 
-fn default_version() -> u8 {
-    1
-}
+pub use fixture::serve as serve_fixture;
+pub use host::filesystem_id;
+pub use layout::truncate;
+pub use listen::{Endpoint, connect};
+pub use protocol::*;
+
+/// How often usage statistics are flushed to disk
+const STATS_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+/// Minimum time between checks of the `PATH` directories for new executables
+const PATH_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// Command indexes of container filesystems kept before the cache is reset
+const MAX_FOREIGN_INDEXES: usize = 8;
+/// How often the usage stats and command history are pruned
+const COMPACT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// How long a restart waits for the requests in flight
+const RESTART_GRACE: Duration = Duration::from_secs(2);
+/// How long requests in flight get to finish when the daemon shuts down
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(2);
+/// Longest request line read; a buffer this large is completed around its
+/// cursor anyway, see [`segment`]
+const MAX_REQUEST_BYTES: u64 = 16 * 1024 * 1024;
+/// How long a client has to send its request line after connecting
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a client has to take each response line; one that stops reading
+/// is hung up on instead of holding the connection's task and buffers
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
 
-/// Individual completion suggestion
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Suggestion {
-    /// Text to insert
-    pub text: String,
-    /// Description of what this completion does
-    pub description: String,
+/// A client that stopped reading before its response was written
+#[derive(Debug, thiserror::Error)]
+#[error("Client took no response within {WRITE_TIMEOUT:?}")]
+struct WriteTimeout;
+
+/// How a connection went wrong, counted in [`ConnectionErrors`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Failure {
+    Malformed,
+    Protocol,
+    Timeout,
+    Dropped,
 }
 
-/// Response sent back to shell client
-#[derive(Debug, Serialize, Deserialize)]
-pub struct CompletionResponse {
-    /// List of suggestions
-    pub suggestions: Vec<Suggestion>,
+/// State shared by all connections
+struct DaemonState {
+    config: Config,
+    specs: SpecStore,
+    stats: Mutex<UsageStats>,
+    stats_path: Option<PathBuf>,
+    /// Executed commands, recorded only when predictions are enabled
+    history: Mutex<CommandHistory>,
+    history_path: Option<PathBuf>,
+    index: RwLock<Arc<CommandIndex>>,
+    index_path: Option<PathBuf>,
+    /// Summaries filled in for suggestions without a description
+    descriptions: Arc<Descriptions>,
+    descriptions_path: Option<PathBuf>,
+    /// Runs spec generators
+    generators: GeneratorPool,
+    /// Previous parse of each session's buffer
+    sessions: SessionCache,
+    /// Responses shared by identical requests from different sessions
+    coalescer: coalesce::Coalescer,
+    /// Flags inferred from scripts without a spec or man page
+    guesses: guess::GuessCache,
+    /// Directory listings for path completion, read in the background
+    listings: files::Listings,
+    /// The latest directories each session changed to
+    recent_dirs: visited::RecentDirs,
+    /// Docker objects listed by the Docker daemon, for the docker spec
+    docker: docker::Answers,
+    /// Commands read from the shell's history file
+    shell_history: recall::ShellHistory,
+    /// When the `PATH` directories were last checked for changes
+    path_checked: Mutex<Option<Instant>>,
+    /// Identity of the filesystem the daemon sees
+    filesystem_id: Option<String>,
+    /// Indexes of the `PATH` of clients in containers, by their root
+    foreign_indexes: Mutex<HashMap<PathBuf, Arc<CommandIndex>>>,
+    /// Run no external commands and write nothing to disk (`daemon --safe`)
+    safe: bool,
+    /// Set to replace the daemon with the executable on disk
+    restart: watch::Sender<bool>,
+    /// Hooks and subscribers to tell what happened
+    events: hooks::Events,
+    /// When the daemon started
+    started: Instant,
+    /// Connections that went wrong, for `status`
+    errors: Mutex<ConnectionErrors>,
 }
 
-/// Error response sent when request fails
-#[derive(Debug, Serialize)]
-pub struct ErrorResponse {
-    /// Error message
-    pub error: String,
+impl DaemonState {
+    /// Count a connection that went wrong, and why
+    fn failed(&self, failure: Failure, message: String) {
+        warn!("Bad connection: {}", message);
+        let mut errors = self.errors.lock().unwrap_or_else(|e| e.into_inner());
+        let count = match failure {
+            Failure::Malformed => &mut errors.malformed,
+            Failure::Protocol => &mut errors.protocol,
+            Failure::Timeout => &mut errors.timeouts,
+            Failure::Dropped => &mut errors.dropped,
+        };
+        *count += 1;
+        errors.last = Some(message);
+    }
+
+    /// Current command index snapshot
+    fn index(&self) -> Arc<CommandIndex> {
+        Arc::clone(&self.index.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Rebuild the command index and persist it, for the `PATH` of the
+    /// latest client or, before any, the daemon's own
+    fn rebuild_index(&self) -> Arc<CommandIndex> {
+        let started = Instant::now();
+        let path_var = match self.index().path_var.as_str() {
+            "" => std::env::var("PATH").unwrap_or_default(),
+            path_var => path_var.to_string(),
+        };
+        let index = CommandIndex::build(&path_var);
+        info!(
+            "Indexed {} executables, {} specs, {} man pages in {:?}",
+            index.executables.len(),
+            index.specs.len(),
+            index.man_pages.len(),
+            started.elapsed()
+        );
+        self.replace_index(index)
+    }
+
+    /// Bring the executables up to date with a client's `PATH`: directories
+    /// added to it are scanned, removed ones dropped, and ones whose contents
+    /// changed (`cargo install`, `brew install`) rescanned. An unchanged
+    /// `PATH` is checked at most once per [`PATH_CHECK_INTERVAL`].
+    fn refresh_path(&self, path_var: &str) {
+        let index = self.index();
+        if index.path_var == path_var {
+            let mut checked = self.path_checked.lock().unwrap_or_else(|e| e.into_inner());
+            if checked.is_some_and(|at| at.elapsed() < PATH_CHECK_INTERVAL) {
+                return;
+            }
+            *checked = Some(Instant::now());
+        }
+        let started = Instant::now();
+        let Some(updated) = index.with_path(path_var) else {
+            return;
+        };
+        info!(
+            "Updated PATH index: {} → {} executables in {:?}",
+            index.executables.len(),
+            updated.executables.len(),
+            started.elapsed()
+        );
+        self.replace_index(updated);
+    }
+
+    /// Index of a container client's `PATH`, scanned through `root` and
+    /// kept up to date like the daemon's own
+    fn foreign_index(&self, root: &Path, path_var: &str) -> Arc<CommandIndex> {
+        let dirs = std::env::split_paths(path_var)
+            .map(|dir| root.join(dir.strip_prefix("/").unwrap_or(&dir)));
+        let local_path = std::env::join_paths(dirs)
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let mut indexes = self
+            .foreign_indexes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let index = match indexes.get(root) {
+            Some(index) => match index.with_path(&local_path) {
+                Some(updated) => Arc::new(updated),
+                None => return Arc::clone(index),
+            },
+            None => {
+                if indexes.len() >= MAX_FOREIGN_INDEXES {
+                    indexes.clear();
+                }
+                info!("Indexing PATH of container at {}", root.display());
+                Arc::new(CommandIndex::build(&local_path))
+            }
+        };
+        indexes.insert(root.to_path_buf(), Arc::clone(&index));
+        index
+    }
+
+    /// Reload the user specs and patches that changed since `previous`,
+    /// returning the directory's current state
+    fn reload_user_specs(&self, previous: UserSpecs) -> UserSpecs {
+        let current = UserSpecs::scan();
+        let (commands, added_or_removed) = current.changes_since(&previous);
+        for command in &commands {
+            self.specs.invalidate(command);
+            // Loading now reports a broken edit right away
+            if self.specs.get(command).is_some() {
+                info!("Reloaded spec for {}", command);
+            } else {
+                info!("No spec for {} anymore", command);
+            }
+        }
+        if added_or_removed {
+            self.replace_index(self.index().with_specs());
+        }
+        current
+    }
+
+    /// Persist `index` and serve it from now on
+    fn replace_index(&self, index: CommandIndex) -> Arc<CommandIndex> {
+        if !self.safe
+            && let Some(path) = &self.index_path
+            && let Err(e) = index.save(path)
+        {
+            warn!("Failed to save command index: {:#}", e);
+        }
+        let index = Arc::new(index);
+        *self.index.write().unwrap_or_else(|e| e.into_inner()) = Arc::clone(&index);
+        index
+    }
+
+    /// Drop usage data beyond the `[retention]` limits, and save what's left
+    /// when anything was dropped
+    fn prune(&self) -> Pruned {
+        let pruned = {
+            let mut stats = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+            let mut history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+            stats::prune(&mut stats, &mut history, &self.config.retention)
+        };
+        if pruned.commands > 0 || pruned.history_entries > 0 {
+            info!("{}", pruned);
+            self.save_stats();
+        }
+        pruned
+    }
+
+    /// Persist usage stats, the description cache, and the command history
+    /// when it is being recorded; in safe mode they stay in memory
+    fn save_stats(&self) {
+        if self.safe {
+            return;
+        }
+        if let Some(path) = &self.descriptions_path
+            && let Err(e) = self.descriptions.save(path)
+        {
+            warn!("Failed to save description cache: {:#}", e);
+        }
+        if let Some(path) = &self.stats_path {
+            let stats = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+            if let Err(e) = stats.save(path) {
+                warn!("Failed to save usage stats: {:#}", e);
+            }
+        }
+        if self.config.predictions.enabled
+            && let Some(path) = &self.history_path
+        {
+            let history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+            if let Err(e) = history.save(path) {
+                warn!("Failed to save command history: {:#}", e);
+            }
+        }
+    }
 }
 
-pub async fn start(socket_path: &str) -> Result<()> {
-    // Remove existing socket if it exists
-    let _ = std::fs::remove_file(socket_path);
+/// Serve completions on `socket_path` and the configured endpoints until
+/// interrupted. In `safe` mode specs, caches, and usage data are only read:
+/// generators, man page lookups, and network endpoints are off, and nothing
+/// is written besides the socket.
+pub async fn start(socket_path: &Path, config: Config, safe: bool) -> Result<()> {
+    let stats_path = UsageStats::default_path();
+    let stats = stats_path
+        .as_deref()
+        .map(UsageStats::load)
+        .unwrap_or_default();
+    let history_path = CommandHistory::default_path();
+    let history = match &history_path {
+        Some(path) if config.predictions.enabled => CommandHistory::load(path),
+        _ => CommandHistory::default(),
+    }
+    .with_max_entries(config.retention.max_history_entries);
+    let descriptions_path = Descriptions::default_path();
+    let descriptions = descriptions_path
+        .as_deref()
+        .map(Descriptions::load)
+        .unwrap_or_default();
+    let index_path = CommandIndex::default_path();
+    let persisted_index = index_path.as_deref().and_then(CommandIndex::load);
+    let refresh_interval = Duration::from_secs(config.daemon.index_refresh_secs);
+    let index_is_fresh = persisted_index
+        .as_ref()
+        .is_some_and(|index| !index.is_stale(refresh_interval));
+    let generators = generator_pool(&config);
+    let events = hooks::Events::new(generators.clone());
+    let state = Arc::new(DaemonState {
+        config,
+        specs: SpecStore::new(),
+        stats: Mutex::new(stats),
+        stats_path,
+        history: Mutex::new(history),
+        history_path,
+        index: RwLock::new(Arc::new(persisted_index.unwrap_or_default())),
+        index_path,
+        descriptions: Arc::new(descriptions),
+        descriptions_path,
+        generators,
+        sessions: SessionCache::new(),
+        coalescer: coalesce::Coalescer::new(),
+        guesses: guess::GuessCache::new(),
+        listings: files::Listings::new(),
+        recent_dirs: visited::RecentDirs::new(),
+        docker: docker::Answers::new(),
+        shell_history: recall::ShellHistory::new(),
+        path_checked: Mutex::new(None),
+        filesystem_id: host::filesystem_id(),
+        foreign_indexes: Mutex::default(),
+        safe,
+        restart: watch::Sender::new(false),
+        events,
+        started: Instant::now(),
+        errors: Mutex::default(),
+    });
 
-    let listener = UnixListener::bind(socket_path)
-        .context(format!("Failed to bind to socket: {}", socket_path))?;
-    info!("Daemon listening on {}", socket_path);
+    let mut endpoints = vec![Endpoint::Unix(socket_path.to_path_buf())];
+    if safe {
+        info!("Safe mode: no generators, man page lookups, network endpoints, or writes");
+        if !state.config.daemon.listen.is_empty() {
+            warn!("Safe mode: not listening on the endpoints in daemon.listen");
+        }
+    }
+    for endpoint in state.config.daemon.listen.iter().filter(|_| !safe) {
+        endpoints.push(
+            endpoint
+                .parse()
+                .with_context(|| format!("Invalid endpoint in daemon.listen: {endpoint}"))?,
+        );
+    }
+    // Sockets still open from the daemon this one replaced
+    let inherited = restart::inherited();
+    let mut listeners = Vec::new();
+    for endpoint in &endpoints {
+        match inherited.get(&endpoint.to_string()) {
+            Some(&fd) => {
+                listeners.push(Listener::adopt(endpoint, fd)?);
+                info!("Daemon listening on {} (taken over)", endpoint);
+            }
+            None => {
+                listeners.push(Listener::bind(endpoint).await?);
+                info!("Daemon listening on {}", endpoint);
+            }
+        }
+        if listen::is_public(endpoint) {
+            warn!(
+                "{} is reachable from other machines; anyone who can connect can read completions for your files and history",
+                endpoint
+            );
+        }
+    }
 
-    // Set up graceful shutdown
-    let shutdown = signal::ctrl_c();
-    tokio::pin!(shutdown);
+    // Warm up specs in the background so the first request doesn't pay for loading
+    let user_specs = UserSpecs::scan();
+    let warm_state = Arc::clone(&state);
+    tokio::task::spawn_blocking(move || warm_up(&warm_state));
 
-    loop {
-        tokio::select! {
-            // Handle new connections
-            result = listener.accept() => {
-                match result {
-                    Ok((stream, _addr)) => {
+    // Reload user specs as they are edited, and restart when the executable,
+    // and with it the bundled specs, is replaced
+    let executable = Arc::new(restart::Executable::current());
+    let watch_state = Arc::clone(&state);
+    let watched = Arc::clone(&executable);
+    match watcher::Watch::start(specs::user_dir(), executable.path().map(Path::to_path_buf)) {
+        Ok((mut watch, mut changes)) => {
+            tokio::spawn(async move {
+                let mut user_specs = user_specs;
+                while let Some(change) = changes.recv().await {
+                    tokio::time::sleep(watcher::DEBOUNCE).await;
+                    let (mut specs_changed, mut exe_changed) = (false, false);
+                    for change in std::iter::once(change)
+                        .chain(std::iter::from_fn(|| changes.try_recv().ok()))
+                    {
+                        specs_changed |= change == watcher::Change::Specs;
+                        exe_changed |= change == watcher::Change::Executable;
+                    }
+                    if exe_changed {
+                        // Only restart once the new file has been left alone
+                        let (state, watched) = (Arc::clone(&watch_state), Arc::clone(&watched));
                         tokio::spawn(async move {
-                            if let Err(e) = handle_connection(stream).await {
-                                error!("Connection error: {}", e);
+                            tokio::time::sleep(restart::SETTLE_TIME).await;
+                            if watched.replaced() && !*state.restart.borrow() {
+                                info!("The executable was replaced, restarting");
+                                state.restart.send_replace(true);
+                            }
+                        });
+                    }
+                    if specs_changed {
+                        watch.refresh();
+                        let state = Arc::clone(&watch_state);
+                        match tokio::task::spawn_blocking(move || {
+                            state.reload_user_specs(user_specs)
+                        })
+                        .await
+                        {
+                            Ok(current) => user_specs = current,
+                            Err(_) => return,
+                        }
+                    }
+                }
+            });
+        }
+        Err(e) => warn!(
+            "Failed to watch the spec directory: {}; edited specs load after a restart",
+            e
+        ),
+    }
+
+    // Build the command index in the background, then keep it fresh
+    let index_state = Arc::clone(&state);
+    tokio::spawn(async move {
+        if !index_is_fresh {
+            let state = Arc::clone(&index_state);
+            let _ = tokio::task::spawn_blocking(move || state.rebuild_index()).await;
+        }
+        if refresh_interval.is_zero() {
+            return;
+        }
+        let mut interval = tokio::time::interval(refresh_interval);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let state = Arc::clone(&index_state);
+            let _ = tokio::task::spawn_blocking(move || state.rebuild_index()).await;
+        }
+    });
+
+    // Periodically persist usage stats
+    let flush_state = Arc::clone(&state);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(STATS_FLUSH_INTERVAL);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let state = Arc::clone(&flush_state);
+            let _ = tokio::task::spawn_blocking(move || state.save_stats()).await;
+        }
+    });
+
+    // Keep usage data within the retention limits, starting with what was loaded
+    let compact_state = Arc::clone(&state);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(COMPACT_INTERVAL);
+        loop {
+            interval.tick().await;
+            let state = Arc::clone(&compact_state);
+            let _ = tokio::task::spawn_blocking(move || state.prune()).await;
+        }
+    });
+
+    // Set up graceful shutdown, on Ctrl+C and on what service managers send
+    let mut terminate = signal::unix::signal(signal::unix::SignalKind::terminate())?;
+    let shutdown = async {
+        tokio::select! {
+            result = signal::ctrl_c() => result,
+            _ = terminate.recv() => Ok(()),
+        }
+    };
+
+    // Accepting stops on restart or shutdown, handing back the listener and
+    // the connections still being served
+    let (stop, stopped) = watch::channel(false);
+    let mut accepting = Vec::new();
+    for (listener, endpoint) in listeners.into_iter().zip(endpoints.clone()) {
+        let state = Arc::clone(&state);
+        let mut stopped = stopped.clone();
+        let shared = listen::is_shared(&endpoint);
+        accepting.push(tokio::spawn(async move {
+            let mut restart = state.restart.subscribe();
+            let mut connections = JoinSet::new();
+            loop {
+                let accepted = tokio::select! {
+                    accepted = listener.accept() => accepted,
+                    _ = restart.wait_for(|restart| *restart) => break,
+                    _ = stopped.wait_for(|stopped| *stopped) => break,
+                };
+                // Finished connections are only forgotten here
+                while connections.try_join_next().is_some() {}
+                match accepted {
+                    Ok((stream, pid)) => {
+                        let state = Arc::clone(&state);
+                        connections.spawn(async move {
+                            if let Err(e) = handle_connection(stream, pid, shared, &state).await {
+                                let failure = if e.is::<WriteTimeout>() {
+                                    Failure::Timeout
+                                } else {
+                                    Failure::Dropped
+                                };
+                                state.failed(failure, e.to_string());
                             }
                         });
                     }
@@ -73,70 +549,1244 @@ pub async fn start(socket_path: &str) -> Result<()> {
                     }
                 }
             }
-            // Handle shutdown signal
-            _ = &mut shutdown => {
-                info!("Received shutdown signal, cleaning up...");
-                break;
+            Accepting {
+                endpoint,
+                listener,
+                connections,
             }
+        }));
+    }
+
+    let mut restart = state.restart.subscribe();
+    tokio::select! {
+        result = shutdown => {
+            result?;
+            info!("Received shutdown signal, finishing requests...");
+            stop.send_replace(true);
+            let mut connections = Vec::new();
+            for task in accepting {
+                // New connections are refused from here on
+                let Accepting { connections: served, .. } = task.await?;
+                connections.push(served);
+            }
+            let aborted = drain(&mut connections, SHUTDOWN_GRACE).await;
+            if aborted > 0 {
+                warn!("Aborted {} requests still running after {:?}", aborted, SHUTDOWN_GRACE);
+            }
+            // Hooks of those requests get the same grace, then are killed
+            let aborted = drain(&mut [state.events.take_running()], SHUTDOWN_GRACE).await;
+            if aborted > 0 {
+                warn!("Aborted {} hooks still running after {:?}", aborted, SHUTDOWN_GRACE);
+            }
+            state.generators.shut_down();
+        }
+        _ = restart.wait_for(|restart| *restart) => {
+            let mut listeners = Vec::new();
+            let mut connections = Vec::new();
+            for task in accepting {
+                let accepting = task.await?;
+                listeners.push((accepting.endpoint, accepting.listener));
+                connections.push(accepting.connections);
+            }
+            // The new daemon takes the sockets over, so nothing is refused
+            drain(&mut connections, RESTART_GRACE).await;
+            // Nothing is left to reap a hook once the process is replaced
+            drain(&mut [state.events.take_running()], RESTART_GRACE).await;
+            state.generators.shut_down();
+            state.save_stats();
+            let sockets: Vec<_> = listeners
+                .iter()
+                .map(|(endpoint, listener)| (endpoint.clone(), listener.as_raw_fd()))
+                .collect();
+            let error = match executable.path() {
+                Some(path) => restart::exec(path, &sockets),
+                None => anyhow::anyhow!("Failed to locate the executable"),
+            };
+            error!("Restart failed, shutting down: {:#}", error);
         }
     }
 
-    // Cleanup socket file
-    let _ = std::fs::remove_file(socket_path);
+    // Cleanup socket files
+    for endpoint in &endpoints {
+        if let Endpoint::Unix(path) = endpoint {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+    state.save_stats();
     info!("Daemon shut down gracefully");
 
     Ok(())
 }
 
-async fn handle_connection(stream: UnixStream) -> Result<()> {
-    let (reader, mut writer) = stream.into_split();
+/// A listener and the connections accepted on it, once accepting stopped
+struct Accepting {
+    endpoint: Endpoint,
+    listener: Listener,
+    connections: JoinSet<()>,
+}
+
+/// Wait up to `grace` for the `connections` to finish, then abort the rest;
+/// returns how many were aborted
+async fn drain(connections: &mut [JoinSet<()>], grace: Duration) -> usize {
+    let finished = tokio::time::timeout(grace, async {
+        for set in connections.iter_mut() {
+            while set.join_next().await.is_some() {}
+        }
+    })
+    .await;
+    if finished.is_ok() {
+        return 0;
+    }
+    let mut aborted = 0;
+    for set in connections.iter_mut() {
+        aborted += set.len();
+        // Dropping a request's future kills the generators it runs
+        set.abort_all();
+        while set.join_next().await.is_some() {}
+    }
+    aborted
+}
+
+fn generator_pool(config: &Config) -> GeneratorPool {
+    let family_timeouts = config
+        .timeouts
+        .generators
+        .iter()
+        .map(|(&family, &ms)| (family, Duration::from_millis(ms)))
+        .collect();
+    GeneratorPool::new(
+        config.daemon.generator_concurrency,
+        Duration::from_millis(config.daemon.generator_timeout_ms),
+    )
+    .with_family_timeouts(family_timeouts)
+}
+
+/// Complete `request` in this process, for a client whose filesystem the
+/// daemon can't see. Nothing is loaded from or saved to disk besides specs,
+/// and paths get the same `files_ms` budget but no later pages.
+pub async fn complete_standalone(
+    request: &CompletionRequest,
+    config: Config,
+) -> CompletionResponse {
+    let path_var = request.env.get("PATH").cloned().unwrap_or_default();
+    let index = tokio::task::spawn_blocking(move || CommandIndex::build(&path_var))
+        .await
+        .unwrap_or_default();
+    let generators = generator_pool(&config);
+    let state = DaemonState {
+        events: hooks::Events::new(generators.clone()),
+        generators,
+        config,
+        specs: SpecStore::new(),
+        stats: Mutex::default(),
+        stats_path: None,
+        history: Mutex::default(),
+        history_path: None,
+        index: RwLock::new(Arc::new(index)),
+        index_path: None,
+        descriptions: Arc::default(),
+        descriptions_path: None,
+        sessions: SessionCache::new(),
+        coalescer: coalesce::Coalescer::new(),
+        guesses: guess::GuessCache::new(),
+        listings: files::Listings::new(),
+        recent_dirs: visited::RecentDirs::new(),
+        docker: docker::Answers::new(),
+        shell_history: recall::ShellHistory::new(),
+        path_checked: Mutex::new(None),
+        filesystem_id: None,
+        foreign_indexes: Mutex::default(),
+        safe: false,
+        restart: watch::Sender::new(false),
+        started: Instant::now(),
+        errors: Mutex::default(),
+    };
+    let mut response = handle_completion(request, state.index(), &state).await;
+    response.next_offset = None;
+    response
+}
+
+/// Load the user's specs, then the specs for the configured and most
+/// frequently used commands
+fn warm_up(state: &DaemonState) {
+    let started = Instant::now();
+    // Loading them all up front reports broken ones right away rather than
+    // on first use
+    let user = crate::specs::user_commands();
+    if !user.is_empty() {
+        let loaded = state.specs.preload(user.iter().map(String::as_str));
+        info!(
+            "Loaded {} of {} user specs and patches in {:?}",
+            loaded,
+            user.len(),
+            started.elapsed()
+        );
+    }
+
+    let mut commands: Vec<String> = state.config.daemon.warm_up.clone();
+    {
+        let stats = state.stats.lock().unwrap_or_else(|e| e.into_inner());
+        for command in stats.top_commands(state.config.daemon.warm_up_from_usage) {
+            if !commands.iter().any(|c| c == command) {
+                commands.push(command.to_string());
+            }
+        }
+    }
+    if commands.is_empty() {
+        return;
+    }
+    let loaded = state.specs.preload(commands.iter().map(String::as_str));
+    info!(
+        "Warmed up {} of {} specs in {:?}",
+        loaded,
+        commands.len(),
+        started.elapsed()
+    );
+}
+
+/// Requests refused on endpoints every local user can reach: they manage
+/// the daemon, reveal accepted command lines or write usage data
+const OWNER_ONLY: &[&str] = &["control", "status", "accepted", "visited", "events"];
+
+/// Serve one request from a client, process `pid` if the socket tells;
+/// a client of a `shared` endpoint only gets completions and context
+async fn handle_connection(
+    stream: Box<dyn listen::Stream>,
+    pid: Option<u32>,
+    shared: bool,
+    state: &Arc<DaemonState>,
+) -> Result<()> {
+    let (reader, mut writer) = tokio::io::split(stream);
     let mut reader = BufReader::new(reader);
     let mut line = String::new();
 
-    // Read request (one line of JSON)
-    reader
-        .read_line(&mut line)
-        .await
-        .context("Failed to read request")?;
+    // Read request (one line of JSON), which a client that connects and
+    // never finishes it must not put off forever
+    let mut limited = (&mut reader).take(MAX_REQUEST_BYTES);
+    match tokio::time::timeout(READ_TIMEOUT, limited.read_line(&mut line)).await {
+        Ok(read) => {
+            if read.context("Failed to read request")? == 0 {
+                state.failed(
+                    Failure::Dropped,
+                    "Connection closed before a request".to_string(),
+                );
+                return Ok(());
+            }
+        }
+        Err(_) => {
+            let error = format!("No request received within {READ_TIMEOUT:?}");
+            state.failed(Failure::Timeout, error.clone());
+            return write_json(&mut writer, &ErrorResponse { error }).await;
+        }
+    }
+    if line.len() as u64 >= MAX_REQUEST_BYTES && !line.ends_with('\n') {
+        let error = format!("Request longer than {MAX_REQUEST_BYTES} bytes");
+        state.failed(Failure::Protocol, error.clone());
+        return write_json(&mut writer, &ErrorResponse { error }).await;
+    }
 
     // Parse request
-    let request: CompletionRequest = match serde_json::from_str(&line) {
-        Ok(req) => req,
+    let value: serde_json::Value = match serde_json::from_str(&line) {
+        Ok(value) => value,
         Err(e) => {
             // Send error response for malformed JSON
-            let error_response = ErrorResponse {
-                error: format!("Invalid JSON: {}", e),
-            };
-            let response = serde_json::to_string(&error_response)?;
-            writer.write_all(response.as_bytes()).await?;
-            writer.write_all(b"\n").await?;
+            let error = format!("Invalid JSON: {}", e);
+            state.failed(Failure::Malformed, error.clone());
+            return write_json(&mut writer, &ErrorResponse { error }).await;
+        }
+    };
+
+    if shared && let Some(kind) = OWNER_ONLY.iter().find(|&&kind| value.get(kind).is_some()) {
+        let error = format!("`{kind}` requests are not served on TCP or abstract sockets");
+        state.failed(Failure::Protocol, error.clone());
+        return write_json(&mut writer, &ErrorResponse { error }).await;
+    }
+
+    // Control messages are distinguished by their `control` field
+    if value.get("control").is_some() {
+        let response = match serde_json::from_value::<ControlRequest>(value) {
+            Ok(request) => handle_control(request.control, state).await,
+            Err(e) => {
+                let message = format!("Invalid control request: {}", e);
+                state.failed(Failure::Protocol, message.clone());
+                ControlResponse { ok: false, message }
+            }
+        };
+        return write_json(&mut writer, &response).await;
+    }
+
+    // Status questions are answered by the daemon's own state
+    if value.get("status").is_some() {
+        let response = match serde_json::from_value::<StatusRequest>(value) {
+            Ok(_) => serde_json::to_value(status(state))?,
+            Err(e) => {
+                let error = format!("Invalid status request: {}", e);
+                state.failed(Failure::Protocol, error.clone());
+                serde_json::to_value(ErrorResponse { error })?
+            }
+        };
+        return write_json(&mut writer, &response).await;
+    }
+
+    // Context questions are answered without generating suggestions
+    if value.get("context").is_some() {
+        let request = match serde_json::from_value::<ContextRequest>(value) {
+            Ok(request) => request.context,
+            Err(e) => {
+                let error = format!("Invalid context request: {}", e);
+                state.failed(Failure::Protocol, error.clone());
+                return write_json(&mut writer, &ErrorResponse { error }).await;
+            }
+        };
+        let response = context(request, pid, state).await?;
+        return write_json(&mut writer, &response).await;
+    }
+
+    // Accepted suggestions are passed on to hooks and subscribers
+    if value.get("accepted").is_some() {
+        let response = match serde_json::from_value::<AcceptedRequest>(value) {
+            Ok(request) => {
+                accepted(request.accepted, state);
+                ControlResponse {
+                    ok: true,
+                    message: "Reported".to_string(),
+                }
+            }
+            Err(e) => {
+                let message = format!("Invalid accepted report: {}", e);
+                state.failed(Failure::Protocol, message.clone());
+                ControlResponse { ok: false, message }
+            }
+        };
+        return write_json(&mut writer, &response).await;
+    }
+
+    // Changes of directory are counted for completing `cd`
+    if value.get("visited").is_some() {
+        let response = match serde_json::from_value::<VisitedRequest>(value) {
+            Ok(request) => {
+                visited::record(&request.visited, &state.recent_dirs, &state.stats);
+                ControlResponse {
+                    ok: true,
+                    message: "Recorded".to_string(),
+                }
+            }
+            Err(e) => {
+                let message = format!("Invalid visited report: {}", e);
+                state.failed(Failure::Protocol, message.clone());
+                ControlResponse { ok: false, message }
+            }
+        };
+        return write_json(&mut writer, &response).await;
+    }
+
+    // Subscribers get events until they hang up
+    if value.get("events").is_some() {
+        let wanted = match serde_json::from_value::<EventsRequest>(value) {
+            Ok(request) => request.events,
+            Err(e) => {
+                let error = format!("Invalid events request: {}", e);
+                state.failed(Failure::Protocol, error.clone());
+                return write_json(&mut writer, &ErrorResponse { error }).await;
+            }
+        };
+        return stream_events(&mut writer, &wanted, state).await;
+    }
+
+    // Batches answer many requests in one round trip, in order
+    if value.get("batch").is_some() {
+        let batch = match serde_json::from_value::<BatchRequest>(value) {
+            Ok(batch) => batch.batch,
+            Err(e) => {
+                let error = format!("Invalid batch request: {}", e);
+                state.failed(Failure::Protocol, error.clone());
+                return write_json(&mut writer, &ErrorResponse { error }).await;
+            }
+        };
+        let mut responses = Vec::with_capacity(batch.len());
+        for request in batch {
+            let version = request.version;
+            let response = complete(request, pid, state).await?;
+            responses.push(VersionedResponse::new(response, version));
+        }
+        return write_json(&mut writer, &BatchResponse { responses }).await;
+    }
+
+    let request: CompletionRequest = match serde_json::from_value(value) {
+        Ok(req) => req,
+        Err(e) => {
+            let error = format!("Invalid request: {}", e);
+            state.failed(Failure::Protocol, error.clone());
+            return write_json(&mut writer, &ErrorResponse { error }).await;
+        }
+    };
+    let version = request.version;
+    let response = complete(request, pid, state).await?;
+    write_json(&mut writer, &VersionedResponse::new(response, version)).await
+}
+
+/// Remember an accepted suggestion for ranking, and pass it on, naming the
+/// command it was accepted for
+fn accepted(mut accepted: Accepted, state: &DaemonState) {
+    let before_cursor = accepted
+        .buffer
+        .get(..accepted.cursor)
+        .unwrap_or(&accepted.buffer);
+    if accepted.command.is_none() {
+        accepted.command = parser::commands(before_cursor)
+            .last()
+            .and_then(|words| words.first())
+            .map(|word| word.text.clone());
+    }
+    let words = executables::skip_keywords(
+        parser::ParseState::new(before_cursor).tokens().to_vec(),
+        None,
+    );
+    let tokens =
+        parser::expand_abbreviation(&segment::trim_words(words), &state.config.abbreviations);
+    // Credentials are neither stored nor handed to hooks and subscribers
+    if stats::is_private(&accepted.buffer) || stats::is_private(&accepted.text) {
+        return;
+    }
+    if let Some(context) = rank::context(&tokens) {
+        let mut stats = state.stats.lock().unwrap_or_else(|e| e.into_inner());
+        stats.record_accepted(&context, &accepted.text);
+    }
+    state
+        .events
+        .emit(Event::Accepted(accepted), &state.config.hooks, state.safe);
+}
+
+/// Write the events named in `wanted`, or all of them, as they happen,
+/// until the client hangs up or the daemon restarts
+async fn stream_events<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    wanted: &[String],
+    state: &DaemonState,
+) -> Result<()> {
+    let mut events = state.events.subscribe();
+    let mut restart = state.restart.subscribe();
+    loop {
+        let event = tokio::select! {
+            event = events.recv() => event,
+            _ = restart.wait_for(|restart| *restart) => return Ok(()),
+        };
+        let line = match event {
+            Ok((name, line)) if wanted.is_empty() || wanted.iter().any(|w| w == name) => line,
+            Ok(_) => continue,
+            Err(tokio::sync::broadcast::error::RecvError::Lagged(missed)) => {
+                warn!("An event subscriber fell behind, dropped {} events", missed);
+                continue;
+            }
+            Err(tokio::sync::broadcast::error::RecvError::Closed) => return Ok(()),
+        };
+        // A subscriber that went away has hung up
+        if write_line(writer, &line).await.is_err() {
             return Ok(());
         }
+    }
+}
+
+/// The daemon's [`StatusResponse`]
+fn status(state: &DaemonState) -> StatusResponse {
+    StatusResponse {
+        pid: std::process::id(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        uptime_secs: state.started.elapsed().as_secs(),
+        errors: state
+            .errors
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone(),
+    }
+}
+
+/// Complete one request from process `pid`, or wait for the response to an
+/// identical one from another session
+async fn complete(
+    request: CompletionRequest,
+    pid: Option<u32>,
+    state: &Arc<DaemonState>,
+) -> Result<CompletionResponse> {
+    let (flight, joined) = state.coalescer.join(&request);
+    if joined {
+        info!("Sharing the response to an identical request");
+    }
+    flight
+        .get_or_try_init(|| compute(request, pid, state))
+        .await
+        .cloned()
+}
+
+/// Complete one request from process `pid`, through the filesystem it sees
+async fn compute(
+    mut request: CompletionRequest,
+    pid: Option<u32>,
+    state: &Arc<DaemonState>,
+) -> Result<CompletionResponse> {
+    let Some(index) = client_index(&mut request, pid, state).await? else {
+        return Ok(CompletionResponse {
+            suggestions: Vec::new(),
+            next_offset: None,
+            standalone: true,
+            replace: None,
+            ranking: None,
+        });
     };
+    Ok(handle_completion(&request, index, state).await)
+}
+
+/// The executables process `pid` sees, pointing `request` at its root if
+/// it is in a container; `None` if the daemon can't see its filesystem
+async fn client_index(
+    request: &mut CompletionRequest,
+    pid: Option<u32>,
+    state: &Arc<DaemonState>,
+) -> Result<Option<Arc<CommandIndex>>> {
+    // A shell in a container sees other files and executables than the daemon
+    if let Some(id) = &request.filesystem_id
+        && state.filesystem_id.as_ref().is_some_and(|own| own != id)
+    {
+        match pid.and_then(|pid| host::root_of(pid, id)) {
+            Some(root) => request.root = Some(root),
+            None => return Ok(None),
+        }
+    }
 
+    let path_var = request.env.get("PATH").cloned();
+    let index = {
+        let state = Arc::clone(state);
+        let root = request.root.clone();
+        tokio::task::spawn_blocking(move || match (root, path_var) {
+            (Some(root), path_var) => {
+                state.foreign_index(&root, path_var.as_deref().unwrap_or_default())
+            }
+            (None, Some(path_var)) => {
+                state.refresh_path(&path_var);
+                state.index()
+            }
+            (None, None) => state.index(),
+        })
+        .await?
+    };
+    Ok(Some(index))
+}
+
+/// What the command line of `request` expects at the cursor, from the
+/// parser and the command's spec alone
+async fn context(
+    mut request: CompletionRequest,
+    pid: Option<u32>,
+    state: &Arc<DaemonState>,
+) -> Result<ContextResponse> {
+    let index = client_index(&mut request, pid, state).await?;
+    if let Some((narrowed, _)) = segment::narrow(&request) {
+        request = narrowed;
+    }
+    let before_cursor = request
+        .buffer
+        .get(..request.cursor)
+        .unwrap_or(&request.buffer);
+    let session = request.session_id.as_deref().unwrap_or_default();
+    let (tokens, current) =
+        state
+            .sessions
+            .parse(session, before_cursor, request.syntax(), |parsed| {
+                let words = executables::skip_keywords(parsed.tokens().to_vec(), request.dialect());
+                (
+                    parser::expand_abbreviation(
+                        &segment::trim_words(words),
+                        &state.config.abbreviations,
+                    ),
+                    parsed.current_token().cloned().unwrap_or_default(),
+                )
+            });
+    let word = current.text.as_str();
+    let (command, args) = match tokens.split_first() {
+        Some((command, args)) => (command.text.as_str(), args),
+        None => (word, &[][..]),
+    };
+    let spec = (!command.is_empty())
+        .then(|| state.specs.get(command))
+        .flatten();
+    let (expects, arg_name) = match &spec {
+        _ if tokens.is_empty() => (ArgKind::Command, None),
+        Some(spec) => {
+            let (kind, name) = resolve::expects(spec, args, word);
+            (kind, name.map(str::to_string))
+        }
+        None if word.starts_with('-') => (ArgKind::Flag, None),
+        // Without a spec, any argument may be a path
+        None => (ArgKind::Path, None),
+    };
+    let valid = index.filter(|_| !command.is_empty()).map(|index| {
+        if files::looks_like_path(command) {
+            files::is_runnable(&request, command)
+        } else {
+            index.executables.contains_key(command)
+                || request.functions.iter().any(|function| function == command)
+                || executables::builtin_names(request.dialect()).any(|name| name == command)
+        }
+    });
+
+    Ok(ContextResponse {
+        command: Some(command.to_string()).filter(|command| !command.is_empty()),
+        valid,
+        spec: spec.is_some(),
+        expects,
+        arg_name,
+    })
+}
+
+/// Write one line of JSON to the client
+async fn write_json<W, T>(writer: &mut W, value: &T) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+    T: Serialize,
+{
+    let json = serde_json::to_string(value)?;
+    write_line(writer, &json).await
+}
+
+/// Write `line` and a newline, giving up on a client that doesn't take it
+/// within [`WRITE_TIMEOUT`]
+async fn write_line<W: AsyncWrite + Unpin>(writer: &mut W, line: &str) -> Result<()> {
+    let write = async {
+        writer.write_all(line.as_bytes()).await?;
+        writer.write_all(b"\n").await?;
+        writer.flush().await
+    };
+    match tokio::time::timeout(WRITE_TIMEOUT, write).await {
+        Ok(written) => Ok(written?),
+        Err(_) => Err(WriteTimeout.into()),
+    }
+}
+
+async fn handle_control(command: ControlCommand, state: &Arc<DaemonState>) -> ControlResponse {
+    info!("Received control request: {:?}", command);
+    match command {
+        ControlCommand::Reindex => {
+            state.specs.clear();
+            let state = Arc::clone(state);
+            match tokio::task::spawn_blocking(move || state.rebuild_index()).await {
+                Ok(index) => ControlResponse {
+                    ok: true,
+                    message: format!(
+                        "Indexed {} executables, {} specs, {} man pages",
+                        index.executables.len(),
+                        index.specs.len(),
+                        index.man_pages.len()
+                    ),
+                },
+                Err(e) => ControlResponse {
+                    ok: false,
+                    message: format!("Reindex failed: {}", e),
+                },
+            }
+        }
+        ControlCommand::SaveStats if state.safe => ControlResponse {
+            ok: false,
+            message: "Safe mode: nothing is written".to_string(),
+        },
+        ControlCommand::SaveStats => {
+            state.save_stats();
+            ControlResponse {
+                ok: true,
+                message: "Saved usage stats".to_string(),
+            }
+        }
+        ControlCommand::ReloadStats => {
+            let Some(path) = &state.stats_path else {
+                return ControlResponse {
+                    ok: false,
+                    message: "No stats file location".to_string(),
+                };
+            };
+            let stats = UsageStats::load(path);
+            let message = format!("Loaded stats for {} commands", stats.commands.len());
+            *state.stats.lock().unwrap_or_else(|e| e.into_inner()) = stats;
+            ControlResponse { ok: true, message }
+        }
+        ControlCommand::ReloadSpecs => {
+            state.specs.clear();
+            ControlResponse {
+                ok: true,
+                message: "Specs will be read again".to_string(),
+            }
+        }
+        ControlCommand::Restart => {
+            state.restart.send_replace(true);
+            ControlResponse {
+                ok: true,
+                message: "Restarting".to_string(),
+            }
+        }
+        ControlCommand::Prune => {
+            let state = Arc::clone(state);
+            match tokio::task::spawn_blocking(move || state.prune()).await {
+                Ok(pruned) => ControlResponse {
+                    ok: true,
+                    message: pruned.to_string(),
+                },
+                Err(e) => ControlResponse {
+                    ok: false,
+                    message: format!("Pruning failed: {}", e),
+                },
+            }
+        }
+    }
+}
+
+/// Complete `request`, finding executables in `index`
+async fn handle_completion(
+    request: &CompletionRequest,
+    index: Arc<CommandIndex>,
+    state: &DaemonState,
+) -> CompletionResponse {
+    let config = &state.config;
+    let started = Instant::now();
+    // A huge buffer is completed around the cursor only
+    let narrowed = segment::narrow(request);
+    let (request, shift) = match &narrowed {
+        Some((narrowed, start)) => (narrowed, *start),
+        None => (request, 0),
+    };
+    // The buffer may hold credentials, so it only goes to the debug log
     info!(
-        "Received request: buffer='{}', cursor={}",
-        request.buffer, request.cursor
+        "Received request: cursor={}, length={}{}",
+        request.cursor,
+        request.buffer.len(),
+        if shift > 0 { " (window)" } else { "" }
     );
+    debug!("Request buffer: '{}'", stats::loggable(&request.buffer));
 
-    // Generate suggestions (hardcoded for now, will be implemented in MVP parser phase)
-    let suggestions = generate_suggestions(&request);
+    // Later pages repeat the first request; count it once
+    if request.offset == 0
+        && let Some(command) = parser::commands_in(&request.buffer, request.syntax())
+            .first()
+            .and_then(|words| words.first())
+    {
+        let mut stats = state.stats.lock().unwrap_or_else(|e| e.into_inner());
+        stats.record_command(&command.text);
+    }
+    if request.offset == 0 && config.daemon.remember_flag_values {
+        values::record(request, &state.specs, &state.stats);
+    }
 
-    // Send response
-    let response = CompletionResponse { suggestions };
-    let response_json = serde_json::to_string(&response)?;
-    writer.write_all(response_json.as_bytes()).await?;
-    writer.write_all(b"\n").await?;
-    writer.flush().await?;
+    // Config locale wins over the client's environment, English is the fallback
+    let locale = config
+        .general
+        .locale
+        .as_deref()
+        .or(request.locale.as_deref())
+        .map(i18n::normalize)
+        .unwrap_or_else(|| i18n::DEFAULT_LOCALE.to_string());
 
-    Ok(())
+    // Only the text before the cursor matters for what is being completed
+    let before_cursor = request
+        .buffer
+        .get(..request.cursor)
+        .unwrap_or(&request.buffer);
+    let after_cursor = &request.buffer[before_cursor.len()..];
+    let session = request.session_id.as_deref().unwrap_or_default();
+    let (tokens, mut current, mut whole) =
+        state
+            .sessions
+            .parse(session, before_cursor, request.syntax(), |parsed| {
+                let words = executables::skip_keywords(parsed.tokens().to_vec(), request.dialect());
+                let tokens =
+                    parser::expand_abbreviation(&segment::trim_words(words), &config.abbreviations);
+                (
+                    tokens,
+                    parsed.current_token().cloned().unwrap_or_default(),
+                    parsed.whole_word(after_cursor),
+                )
+            });
+    // `--name=va`: the spec is given the whole word, the rest only the value,
+    // which is all that gets replaced
+    let typed = current.text.clone();
+    let attached = current.attached_value(before_cursor, request.syntax());
+    let value_attached = attached.is_some();
+    if let Some(value) = attached {
+        whole = whole.and_then(|whole| whole.attached_value(&request.buffer, request.syntax()));
+        current = value;
+    }
+    let word = current.text.as_str();
+    // Matching goes by the part before the cursor; the part after it is
+    // replaced along with it
+    let replace = whole.as_ref().map_or(
+        before_cursor.len()..before_cursor.len(),
+        parser::Token::span,
+    );
+    let suffix = whole
+        .as_ref()
+        .and_then(|whole| whole.text.get(word.len()..))
+        .unwrap_or_default();
+    let command = tokens.first().map(|token| token.text.clone());
+    let mut cache_hit = command
+        .as_deref()
+        .is_none_or(|command| state.specs.is_cached(command));
+
+    // Corrections for a failed command come first, then the regular suggestions
+    let mut suggestions = followup::suggest(request, &index);
+    if config.predictions.enabled {
+        suggestions.extend(predict(request, session, state));
+    }
+    let from_spec = generate_suggestions(request, &tokens, &typed, &locale, &index, state).await;
+    // A path where the command goes runs a script or, with zsh's `AUTO_CD`,
+    // changes to the directory
+    let command_path = command.is_none() && files::looks_like_path(word);
+    let package_script = npm::wants_script(&tokens, word);
+    let recipes = request
+        .cwd
+        .as_ref()
+        .and_then(|cwd| just::complete(&tokens, word, &request.local_path(cwd)));
+    let recipe = matches!(recipes, Some(just::Completion::Recipes(_)));
+    let paths = match &from_spec {
+        Some(spec) => spec.paths.map(|template| match template {
+            Template::Filepaths => files::PathKind::All,
+            Template::Folders => files::PathKind::Folders,
+        }),
+        None if command_path => Some(files::PathKind::Command),
+        // Without a spec, any argument may be a path
+        None if command.is_some() && !word.starts_with('-') && !package_script && !recipe => {
+            Some(files::PathKind::All)
+        }
+        None => None,
+    };
+    if from_spec.is_none()
+        && request.offset == 0
+        && let Some(command) = &command
+        && !files::looks_like_path(command)
+        && !stats::is_private(&request.buffer)
+        && state.events.first_unknown(command)
+    {
+        state.events.emit(
+            Event::UnknownCommand(UnknownCommand {
+                command: command.clone(),
+                buffer: request.buffer.clone(),
+                session_id: request.session_id.clone(),
+                cwd: request.cwd.clone(),
+            }),
+            &config.hooks,
+            state.safe,
+        );
+    }
+    suggestions.extend(from_spec.map(|spec| spec.suggestions).unwrap_or_default());
+    // Directories visited before, wherever they are, for `cd`
+    if config.directories.enabled
+        && paths == Some(files::PathKind::Folders)
+        && tokens.len() == 1
+        && command
+            .as_deref()
+            .is_some_and(|command| visited::COMMANDS.contains(&command))
+    {
+        suggestions.extend(visited::suggest(
+            request,
+            word,
+            config.directories.max_directories,
+            &state.recent_dirs,
+            &state.stats,
+        ));
+    }
+    // The package's scripts, which no spec knows
+    if package_script && let Some(cwd) = &request.cwd {
+        for script in npm::suggest(&request.local_path(cwd)) {
+            if !suggestions.iter().any(|s| s.text == script.text) {
+                suggestions.push(script);
+            }
+        }
+    }
+    // The justfile's recipes, or the default of the recipe's parameter
+    if let Some(just::Completion::Recipes(found) | just::Completion::Parameter(found)) = recipes {
+        suggestions.extend(found);
+    }
+    // Examples once the command is typed, before any of its arguments
+    if config.examples.enabled && !tokens.is_empty() && typed.is_empty() {
+        let words: Vec<&str> = tokens.iter().map(|token| token.text.as_str()).collect();
+        suggestions.extend(examples::suggest(&words, config.examples.max_examples));
+    }
+    if let Some(command) = &command
+        && !value_attached
+    {
+        suggestions.extend(guess::suggest(
+            request,
+            command,
+            word,
+            &index,
+            &state.guesses,
+        ));
+    }
+    if command.is_none() && !command_path {
+        suggestions.extend(executables::suggest(
+            word,
+            &index,
+            &request.functions,
+            request.dialect(),
+        ));
+    }
+    context::retain_valid(&mut suggestions, tokens.get(1..).unwrap_or_default());
+    // The spec's long flags, to correct a misspelled one with
+    let flags: Vec<Suggestion> = if word.starts_with("--") {
+        suggestions
+            .iter()
+            .filter(|suggestion| {
+                suggestion.source == SuggestionSource::Spec && suggestion.text.starts_with("--")
+            })
+            .cloned()
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let mut suggestions = {
+        let stats = state.stats.lock().unwrap_or_else(|e| e.into_inner());
+        // How much the command named is used tells when naming one; after
+        // it, how often each suggestion was picked there
+        let context = rank::context(&tokens);
+        let frecency = |suggestion: &Suggestion| match &context {
+            None => suggestion
+                .text
+                .split_whitespace()
+                .next()
+                .map_or(0, |name| stats.frecency(name)),
+            Some(context) => stats.accepted_frecency(context, &suggestion.text),
+        };
+        rank::rank(
+            suggestions,
+            word,
+            &config.ranking,
+            frecency,
+            request.explain,
+        )
+    };
+    // A flag nothing matches may be misspelled
+    if !flags.is_empty()
+        && !suggestions
+            .iter()
+            .any(|suggestion| suggestion.text.starts_with('-'))
+    {
+        suggestions.extend(correct::flags(word, &flags));
+    }
+    // With the cursor inside a word, what ends like the rest of it comes first
+    if !suffix.is_empty() {
+        suggestions.sort_by_key(|suggestion| !suggestion.text.ends_with(suffix));
+    }
+    // Neither expansions nor corrections contain the typed word, so fuzzy
+    // ranking would drop them
+    if command.is_none() {
+        let mut expansions = abbrev::suggest(word, &config.abbreviations);
+        expansions.append(&mut suggestions);
+        suggestions = expansions;
+        if !command_path {
+            let stats = state.stats.lock().unwrap_or_else(|e| e.into_inner());
+            suggestions.extend(correct::suggest(
+                word,
+                &index,
+                &request.functions,
+                request.dialect(),
+                &stats,
+            ));
+        }
+    }
+    // Whole commands run before that start like this one, below the rest
+    // but above paths, which must stay last
+    if config.history.enabled {
+        let start = tokens.first().map_or(replace.start, |token| token.start);
+        suggestions.extend(recall::suggest(
+            &state.shell_history,
+            config.history.file.as_deref(),
+            &before_cursor[start..],
+            replace.start - start,
+            config.history.max_commands,
+        ));
+    }
+    // Paths keep the order they were read in, so offsets into them stay
+    // valid while a large directory is still being read
+    let mut files_complete = true;
+    if let Some(paths) = paths {
+        let wanted = request.offset + config.general.max_suggestions;
+        let files = files::suggest(
+            request,
+            &current,
+            paths,
+            wanted.saturating_sub(suggestions.len()),
+            &state.listings,
+            Instant::now() + Duration::from_millis(config.timeouts.files_ms),
+        );
+        cache_hit &= files.cached;
+        files_complete = files.complete
+            || !limits::has_room(SuggestionSource::Files, files.suggestions.len(), config);
+        suggestions.extend(files.suggestions);
+    }
+    let (mut suggestions, more) = limits::apply(suggestions, request.offset, config);
+    let next_offset = (more || !files_complete).then(|| request.offset + suggestions.len());
+    describe(&mut suggestions, command.as_deref(), state);
+    pairs::close(&mut suggestions, request.syntax());
+    if let Some(terminal) = &request.terminal {
+        suggestions = layout::fit(suggestions, terminal);
+    }
+    // Later pages only slice what the first one gathered
+    if request.offset == 0
+        && let Some(command) = &command
+    {
+        let mut stats = state.stats.lock().unwrap_or_else(|e| e.into_inner());
+        stats.record_completion(command, started.elapsed(), cache_hit);
+    }
+
+    CompletionResponse {
+        suggestions,
+        next_offset,
+        standalone: false,
+        replace: Some(replace.start + shift..replace.end + shift),
+        ranking: request.explain.then(|| config.ranking.clone()),
+    }
 }
 
-/// Generate completion suggestions for a request
-/// TODO: This is a placeholder - real implementation in Phase 1B (MVP Parser)
-fn generate_suggestions(request: &CompletionRequest) -> Vec<Suggestion> {
-    // For now, return empty suggestions
-    // This will be replaced with actual parser logic in implement-mvp-parser
-    let _ = request;
-    Vec::new()
+/// Fill in blank descriptions with command summaries, looking up unknown
+/// ones in the background so they are there next time
+fn describe(suggestions: &mut [Suggestion], command: Option<&str>, state: &DaemonState) {
+    for suggestion in suggestions {
+        if !suggestion.description.is_empty()
+            || matches!(
+                suggestion.source,
+                SuggestionSource::Files | SuggestionSource::CdPath
+            )
+            || suggestion.text.starts_with('-')
+        {
+            continue;
+        }
+        // Subcommands are named like their man pages: `git-push`
+        let name = match command {
+            Some(command) => format!("{}-{}", command, suggestion.text),
+            None => suggestion.text.clone(),
+        };
+        match state.descriptions.cached(&name) {
+            Some(summary) => suggestion.description = summary.unwrap_or_default(),
+            // Lookups may run `gzip` on compressed man pages
+            None if !state.safe && state.descriptions.start_lookup(&name) => {
+                let descriptions = Arc::clone(&state.descriptions);
+                tokio::task::spawn_blocking(move || descriptions.lookup(&name));
+            }
+            None => {}
+        }
+    }
+}
+
+/// Record the shell's last command, then predict whole commands if the buffer is empty
+fn predict(request: &CompletionRequest, session: &str, state: &DaemonState) -> Vec<Suggestion> {
+    let Some(cwd) = &request.cwd else {
+        return Vec::new();
+    };
+    let mut history = state.history.lock().unwrap_or_else(|e| e.into_inner());
+    if request.offset == 0
+        && let (Some(command), Some(0)) = (&request.last_command, request.last_exit_code)
+    {
+        history.record(session, command, cwd);
+    }
+    if !request.buffer.trim().is_empty() {
+        return Vec::new();
+    }
+
+    history
+        .predict(
+            cwd,
+            state.config.predictions.max_predictions,
+            Instant::now() + Duration::from_millis(state.config.timeouts.history_ms),
+        )
+        .into_iter()
+        .map(|prediction| Suggestion {
+            description: if prediction.same_dir {
+                "Often run here".to_string()
+            } else {
+                "Often run in similar projects".to_string()
+            },
+            text: prediction.command,
+            source: SuggestionSource::Prediction,
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// Suggestions from the command's spec, and the paths it wants completed
+struct SpecSuggestions {
+    suggestions: Vec<Suggestion>,
+    paths: Option<Template>,
+}
+
+/// Walk the buffer through the spec of its command and run the generators
+/// of the argument being completed; `None` if the command has no spec.
+///
+/// Commands on `PATH` without a Fig or user spec fall back to one read from
+/// their man page. In safe mode neither generators nor the man page fallback
+/// run.
+async fn generate_suggestions(
+    request: &CompletionRequest,
+    tokens: &[parser::Token],
+    word: &str,
+    locale: &str,
+    index: &CommandIndex,
+    state: &DaemonState,
+) -> Option<SpecSuggestions> {
+    let (command, args) = tokens.split_first()?;
+    let name = command.text.as_str();
+    let spec = state.specs.get(name).or_else(|| {
+        (state.config.daemon.man_page_specs
+            && !state.safe
+            && index.executables.contains_key(name)
+            && index.man_pages.contains(name))
+        .then(|| state.specs.man_page_spec(name))
+        .flatten()
+    })?;
+    let resolution = resolve::resolve(&spec, args, word, locale);
+
+    let mut suggestions = resolution.suggestions;
+    // Reading the manifests runs nothing, so this is done in safe mode too
+    if let Some(option) = resolution.value_of
+        && name == "cargo"
+        && let Some(cwd) = &request.cwd
+    {
+        suggestions.extend(cargo::suggest(option, &request.local_path(cwd)));
+    }
+    if let Some(option) = resolution.value_of
+        && state.config.daemon.remember_flag_values
+    {
+        let stats = state.stats.lock().unwrap_or_else(|e| e.into_inner());
+        for remembered in values::suggest(name, option, &stats) {
+            if !suggestions.iter().any(|s| s.text == remembered.text) {
+                suggestions.push(remembered);
+            }
+        }
+    }
+    for generator in resolution.generators {
+        let mut script: Vec<&str> = generator.script.iter().map(|part| part.as_ref()).collect();
+        let cwd = request.cwd.as_deref().map(|cwd| request.local_path(cwd));
+        // Reading `.git` runs nothing, so it is done in safe mode too
+        if state.config.daemon.native_git
+            && let Some(cwd) = cwd.as_deref()
+            && let Some(answer) = git::answer(generator, cwd)
+        {
+            for suggestion in answer.suggestions {
+                if !suggestions.iter().any(|s| s.text == suggestion.text) {
+                    suggestions.push(suggestion);
+                }
+            }
+            match answer.rest {
+                Some(rest) => script = rest.to_vec(),
+                None => continue,
+            }
+        }
+        // So is asking the Docker daemon, which answers in milliseconds what
+        // the docker CLI takes most of a second to
+        if state.config.daemon.native_docker {
+            let (_, policy) = cache_policy(name, generator, &script, cwd.as_deref(), &state.config);
+            let answer = docker::answer(
+                generator,
+                request,
+                cwd.as_deref(),
+                word,
+                policy.ttl,
+                &state.docker,
+            );
+            if let Some(found) = answer.await {
+                for suggestion in found {
+                    if !suggestions.iter().any(|s| s.text == suggestion.text) {
+                        suggestions.push(suggestion);
+                    }
+                }
+                continue;
+            }
+        }
+        if state.safe {
+            continue;
+        }
+        // Cluster listings are of the context and namespace on the command line
+        let scoped;
+        if name == "kubectl" {
+            let Some(kubectl) = kubectl::scope(&script, args) else {
+                continue;
+            };
+            scoped = kubectl;
+            script = scoped.iter().map(String::as_str).collect();
+        }
+        // A single string is a shell command line, as in Fig
+        let argv = match script.as_slice() {
+            [line] => vec!["sh", "-c", line],
+            _ => script,
+        };
+        let (key, policy) = cache_policy(name, generator, &argv, cwd.as_deref(), &state.config);
+        let started = Instant::now();
+        let own_timeout = generator.script_timeout.map(Duration::from_millis);
+        let output = state
+            .generators
+            .run_cached(key, policy, &argv, cwd.as_deref(), own_timeout)
+            .await;
+        if !matches!(output, Ok((_, true))) {
+            state
+                .stats
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .record_generator(&script_line(&argv), started.elapsed());
+        }
+        let output = match output {
+            Ok((output, _)) => output,
+            Err(e) => {
+                warn!("Generator `{}` failed: {}", script_line(&argv), e);
+                continue;
+            }
+        };
+        for (value, description) in generator.items(&output) {
+            if !suggestions.iter().any(|s| s.text == value) {
+                suggestions.push(Suggestion {
+                    text: value.to_string(),
+                    description: description.unwrap_or_default().to_string(),
+                    source: SuggestionSource::Spec,
+                    ..Default::default()
+                });
+            }
+        }
+    }
+    Some(SpecSuggestions {
+        suggestions,
+        paths: resolution.paths,
+    })
+}
+
+/// Where the output of `generator`, run for `command` as `argv` in `cwd`, is
+/// kept and for how long: per directory for the configured time, unless the
+/// spec gives the generator a `cache` of its own
+fn cache_policy(
+    command: &str,
+    generator: &Generator<'_>,
+    argv: &[&str],
+    cwd: Option<&Path>,
+    config: &Config,
+) -> (CacheKey, CachePolicy) {
+    let configured = Duration::from_millis(config.daemon.generator_cache_ttl_ms);
+    let cache = generator.cache.as_ref();
+    let key = CacheKey {
+        command: command.to_string(),
+        cwd: cache
+            .is_none_or(|cache| cache.cache_by_directory)
+            .then(|| cwd.map(Path::to_path_buf))
+            .flatten(),
+        generator: cache
+            .and_then(|cache| cache.cache_key.as_deref())
+            .map_or_else(|| script_line(argv), str::to_string),
+    };
+    let policy = CachePolicy {
+        ttl: cache
+            .and_then(|cache| cache.ttl)
+            .map_or(configured, Duration::from_millis),
+        stale_while_revalidate: cache
+            .is_some_and(|cache| cache.strategy == CacheStrategy::StaleWhileRevalidate),
+    };
+    (key, policy)
+}
+
+/// A generator's command line as the user would type it
+fn script_line(argv: &[&str]) -> String {
+    match argv {
+        ["sh", "-c", line] => line.to_string(),
+        argv => argv.join(" "),
+    }
 }
@@ -0,0 +1,73 @@
+//! Scripts of the nearest `package.json`, for `npm run` and the like.
+//!
+//! What a package's scripts are is up to its `package.json`, which no spec
+//! can know. For `npm run`, `pnpm run`, `yarn run` and `bun run`, and plain
+//! `yarn` and `pnpm`, which run a script named in place of a command, they
+//! are read from the `package.json` in the working directory or the closest
+//! one above it, with what they run as the description. Reading the file
+//! runs nothing, so this works in safe mode too.
+
+use super::{Suggestion, SuggestionSource};
+use crate::parser::Token;
+use serde_json::Value;
+use std::path::Path;
+
+/// Package managers, and whether a script's name may follow theirs directly
+const MANAGERS: &[(&str, bool)] = &[
+    ("npm", false),
+    ("pnpm", true),
+    ("yarn", true),
+    ("bun", false),
+];
+
+/// Whether `word`, after the completed `tokens`, names a script, as in
+/// `npm run <word>`
+pub fn wants_script(tokens: &[Token], word: &str) -> bool {
+    if word.starts_with('-') {
+        return false;
+    }
+    let Some((command, args)) = tokens.split_first() else {
+        return false;
+    };
+    let Some(&(_, direct)) = MANAGERS.iter().find(|(name, _)| *name == command.text) else {
+        return false;
+    };
+    let mut positionals = args.iter().filter(|token| !token.text.starts_with('-'));
+    match positionals.next() {
+        None => direct,
+        Some(verb) => {
+            matches!(verb.text.as_str(), "run" | "run-script") && positionals.next().is_none()
+        }
+    }
+}
+
+/// The scripts of the `package.json` nearest to `cwd`, in alphabetical order
+pub fn suggest(cwd: &Path) -> Vec<Suggestion> {
+    let Some(text) = cwd
+        .ancestors()
+        .map(|dir| dir.join("package.json"))
+        .find(|manifest| manifest.is_file())
+        .and_then(|manifest| std::fs::read_to_string(manifest).ok())
+    else {
+        return Vec::new();
+    };
+    let Ok(package) = serde_json::from_str::<Value>(&text) else {
+        return Vec::new();
+    };
+    let Some(scripts) = package.get("scripts").and_then(Value::as_object) else {
+        return Vec::new();
+    };
+    scripts
+        .iter()
+        .filter_map(|(name, script)| {
+            let script = script.as_str()?;
+            Some(Suggestion {
+                text: name.clone(),
+                // A script spanning lines is shown on one
+                description: script.split_whitespace().collect::<Vec<_>>().join(" "),
+                source: SuggestionSource::Spec,
+                ..Default::default()
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,187 @@
+//! Closing the quotes and brackets an inserted suggestion leaves open.
+//!
+//! A template like `--filter '` or `--query '.[` opens something for the
+//! user to type into. Inserted as is, it would leave a buffer the shell
+//! can't run until they close it themselves, so the closing characters are
+//! inserted after it and the cursor is left before them. Paths are names,
+//! not shell text, and are left as they are.
+
+use super::{Suggestion, SuggestionSource};
+use crate::parser::Syntax;
+
+/// Close what each of `suggestions` opens, the way `syntax` quotes
+pub fn close(suggestions: &mut [Suggestion], syntax: Syntax) {
+    for suggestion in suggestions {
+        if matches!(
+            suggestion.source,
+            SuggestionSource::Files | SuggestionSource::CdPath | SuggestionSource::Visited
+        ) {
+            continue;
+        }
+        let insertion = suggestion.insertion().to_string();
+        let closing = closing(&insertion, syntax);
+        if closing.is_empty() {
+            continue;
+        }
+        // Snippets move the cursor through their placeholders instead
+        if suggestion.cursor_offset.is_none() && !suggestion.source.is_snippet() {
+            suggestion.cursor_offset = Some(insertion.len());
+        }
+        suggestion.insert_text = Some(format!("{insertion}{closing}"));
+    }
+}
+
+/// What closes the quotes and brackets `text` leaves open, innermost first
+fn closing(text: &str, syntax: Syntax) -> String {
+    let quotes: &[char] = match syntax {
+        Syntax::Posix => &['\'', '"', '`'],
+        Syntax::PowerShell => &['\'', '"'],
+        Syntax::Cmd => &['"'],
+    };
+    let mut open: Vec<char> = Vec::new();
+    let mut escaped = false;
+    let mut previous = None;
+    for c in text.chars() {
+        let dollar = previous.replace(c) == Some('$');
+        if std::mem::take(&mut escaped) {
+            previous = None;
+            continue;
+        }
+        let quote = open.last().copied().filter(|open| quotes.contains(open));
+        match quote {
+            Some(quote) if c == quote => {
+                open.pop();
+            }
+            // Escapes work in double quotes, except cmd.exe's
+            Some('"') if c == syntax.escape() && syntax != Syntax::Cmd => escaped = true,
+            // `"$(date` leaves the command substitution open too
+            Some('"') if c == '(' && dollar && syntax != Syntax::Cmd => open.push(c),
+            Some(_) => {}
+            None if c == syntax.escape() => escaped = true,
+            None if quotes.contains(&c) || matches!(c, '(' | '[' | '{') => open.push(c),
+            None => {
+                if let Some(opening) = opening(c)
+                    && open.last() == Some(&opening)
+                {
+                    open.pop();
+                }
+            }
+        }
+    }
+    open.into_iter().rev().map(closer).collect()
+}
+
+fn opening(c: char) -> Option<char> {
+    match c {
+        ')' => Some('('),
+        ']' => Some('['),
+        '}' => Some('{'),
+        _ => None,
+    }
+}
+
+fn closer(c: char) -> char {
+    match c {
+        '(' => ')',
+        '[' => ']',
+        '{' => '}',
+        quote => quote,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn suggestion(text: &str, source: SuggestionSource) -> Suggestion {
+        Suggestion {
+            text: text.to_string(),
+            source,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_closing_nested_pairs_close_innermost_first() {
+        assert_eq!(closing(".[{", Syntax::Posix), "}]");
+        assert_eq!(closing("\"$(date", Syntax::Posix), ")\"");
+        assert_eq!(closing("$(echo \"a", Syntax::Posix), "\")");
+        // Brackets inside quotes are text
+        assert_eq!(closing("--query '.[", Syntax::Posix), "'");
+        assert_eq!(closing("'$(", Syntax::Posix), "'");
+    }
+
+    #[test]
+    fn test_closing_escaped_quotes_stay_open() {
+        assert_eq!(closing("\"a\\\"b", Syntax::Posix), "\"");
+        assert_eq!(closing("it\\'s", Syntax::Posix), "");
+        assert_eq!(closing("\"a`\"b", Syntax::PowerShell), "\"");
+        // Single quotes don't escape in POSIX shells
+        assert_eq!(closing("'a\\'", Syntax::Posix), "");
+        // cmd.exe has no escapes inside double quotes
+        assert_eq!(closing("\"a^\"", Syntax::Cmd), "");
+        assert_eq!(closing("a^\"", Syntax::Cmd), "");
+    }
+
+    #[test]
+    fn test_closing_closed_pairs_add_nothing() {
+        assert_eq!(closing("'x'", Syntax::Posix), "");
+        assert_eq!(closing("\"$(date)\"", Syntax::Posix), "");
+        assert_eq!(closing("[a]{b}(c)", Syntax::Posix), "");
+        assert_eq!(closing("--since=", Syntax::Posix), "");
+        // A stray closer doesn't close what it doesn't match
+        assert_eq!(closing("[a)", Syntax::Posix), "]");
+    }
+
+    #[test]
+    fn test_closing_quotes_follow_the_syntax() {
+        assert_eq!(closing("`date", Syntax::Posix), "`");
+        assert_eq!(closing("'a", Syntax::Cmd), "");
+        assert_eq!(closing("\"a", Syntax::Cmd), "\"");
+    }
+
+    #[test]
+    fn test_close_leaves_cursor_before_the_closers() {
+        let mut suggestions = vec![
+            suggestion("--filter '", SuggestionSource::Spec),
+            suggestion("--all", SuggestionSource::Spec),
+        ];
+        close(&mut suggestions, Syntax::Posix);
+        assert_eq!(suggestions[0].insert_text.as_deref(), Some("--filter ''"));
+        assert_eq!(suggestions[0].cursor_offset, Some("--filter '".len()));
+        assert_eq!(suggestions[0].text, "--filter '");
+        assert_eq!(suggestions[1].insert_text, None);
+        assert_eq!(suggestions[1].cursor_offset, None);
+    }
+
+    #[test]
+    fn test_close_extends_insert_text_and_keeps_cursor_offset() {
+        let mut template = suggestion("--message", SuggestionSource::Spec);
+        template.insert_text = Some("--message \"".to_string());
+        template.cursor_offset = Some(2);
+        let mut suggestions = vec![template];
+        close(&mut suggestions, Syntax::Posix);
+        assert_eq!(
+            suggestions[0].insert_text.as_deref(),
+            Some("--message \"\"")
+        );
+        assert_eq!(suggestions[0].cursor_offset, Some(2));
+    }
+
+    #[test]
+    fn test_close_skips_paths_and_leaves_snippet_cursor() {
+        let mut suggestions = vec![
+            suggestion("it's (1)/", SuggestionSource::Files),
+            suggestion("[a/", SuggestionSource::Visited),
+            suggestion("git commit -m \"", SuggestionSource::Example),
+        ];
+        close(&mut suggestions, Syntax::Posix);
+        assert_eq!(suggestions[0].insert_text, None);
+        assert_eq!(suggestions[1].insert_text, None);
+        assert_eq!(
+            suggestions[2].insert_text.as_deref(),
+            Some("git commit -m \"\"")
+        );
+        assert_eq!(suggestions[2].cursor_offset, None);
+    }
+}
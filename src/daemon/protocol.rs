@@ -0,0 +1,784 @@
+use crate::config::RankingConfig;
+use crate::install::Shell;
+use crate::parser::Syntax;
+use crate::parser::snippet::Snippet;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Newest protocol version, which this build's client speaks.
+///
+/// 1. Suggestions of `text`, `description` and `source`, each inserted as
+///    `text` in place of the word at the cursor
+/// 2. Responses add `replace`, `next_offset` and `standalone`, suggestions
+///    `insert_text`, `cursor_offset` and `score`, and sources beyond `spec`,
+///    `files` and `history`
+///
+/// Requests have kept their shape: every field added since version 1 is
+/// optional, so any request is read as is and only its `version` decides
+/// the shape of the response, see [`VersionedResponse`].
+pub const PROTOCOL_VERSION: u8 = 2;
+
+/// Request from shell client containing command buffer and cursor position
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompletionRequest {
+    /// Current command buffer text
+    pub buffer: String,
+    /// Cursor position in the buffer, as a byte offset; clients convert
+    /// the character count shells report
+    pub cursor: usize,
+    /// Protocol version the client reads responses in; 1 when missing, as
+    /// from scripts written before there were versions
+    #[serde(default = "default_version")]
+    pub version: u8,
+    /// Locale of the client (`de_DE`, `fr`), used to pick translated descriptions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    /// Identifies the shell session (e.g. `$$` and tty), keying per-session daemon state
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// Working directory of the shell, for file and generator completion
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<PathBuf>,
+    /// Completion-relevant environment variables of the shell (filtered by the client)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Last command run in the shell
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_command: Option<String>,
+    /// Exit code of the last command run in the shell
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_exit_code: Option<i32>,
+    /// Size and capabilities of the client's terminal, used to fit suggestions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub terminal: Option<TerminalInfo>,
+    /// Suggestions to skip, to fetch the page after those already received;
+    /// see [`CompletionResponse::next_offset`]
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub offset: usize,
+    /// Identifies the filesystem the shell sees, so a daemon on the host can
+    /// tell a shell in a container apart
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filesystem_id: Option<String>,
+    /// Attach each suggestion's [`Score`] and the weights behind it
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub explain: bool,
+    /// Names of the functions defined in the shell, offered where the
+    /// command goes
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub functions: Vec<String>,
+    /// The client's shell (`zsh`, `bash`, `fish`, `pwsh`, `cmd`), choosing
+    /// how the buffer is split into words and the builtins and keywords
+    /// offered where the command goes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell: Option<String>,
+    /// Where the daemon sees the client's filesystem, when it's a container's;
+    /// set by the daemon, never sent
+    #[serde(skip)]
+    pub root: Option<PathBuf>,
+}
+
+/// What the client's terminal can display
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TerminalInfo {
+    /// Width in columns
+    pub columns: u16,
+    /// Height in rows
+    pub rows: u16,
+    /// Supports 24-bit color
+    #[serde(default)]
+    pub truecolor: bool,
+    /// Can render non-ASCII symbols (box drawing, `…`)
+    #[serde(default)]
+    pub unicode: bool,
+}
+
+impl CompletionRequest {
+    /// Request with no client context
+    pub fn new(buffer: impl Into<String>, cursor: usize) -> Self {
+        Self {
+            buffer: buffer.into(),
+            cursor,
+            version: PROTOCOL_VERSION,
+            locale: None,
+            session_id: None,
+            cwd: None,
+            env: BTreeMap::new(),
+            last_command: None,
+            last_exit_code: None,
+            terminal: None,
+            offset: 0,
+            filesystem_id: None,
+            explain: false,
+            functions: Vec::new(),
+            shell: None,
+            root: None,
+        }
+    }
+
+    /// The client's shell, when it named one this daemon knows
+    pub fn dialect(&self) -> Option<Shell> {
+        self.shell.as_deref().and_then(Shell::from_name)
+    }
+
+    /// How the client's shell splits the buffer into words; POSIX unless it
+    /// is PowerShell or cmd.exe
+    pub fn syntax(&self) -> Syntax {
+        self.shell
+            .as_deref()
+            .map(Syntax::of_shell)
+            .unwrap_or_default()
+    }
+
+    /// `path` on the client's filesystem as the daemon sees it
+    pub fn local_path(&self, path: &Path) -> PathBuf {
+        match &self.root {
+            Some(root) => root.join(path.strip_prefix("/").unwrap_or(path)),
+            None => path.to_path_buf(),
+        }
+    }
+}
+
+fn default_version() -> u8 {
+    1
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+fn is_zero(offset: &usize) -> bool {
+    *offset == 0
+}
+
+/// Individual completion suggestion
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Suggestion {
+    /// Text to insert
+    pub text: String,
+    /// Description of what this completion does
+    #[serde(default)]
+    pub description: String,
+    /// Where this suggestion came from
+    #[serde(default)]
+    pub source: SuggestionSource,
+    /// What to insert instead of `text`, for templates spanning several
+    /// tokens (`--message ""`); `text` is still what is shown and matched
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub insert_text: Option<String>,
+    /// Byte offset in the inserted text to leave the cursor at (inside the
+    /// quotes of `--message ""`) instead of after it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor_offset: Option<usize>,
+    /// How the suggestion was ranked, for requests that ask; `None` for
+    /// those kept in place, such as paths and corrections
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<Score>,
+}
+
+impl Suggestion {
+    /// Text to put into the buffer
+    pub fn insertion(&self) -> &str {
+        self.insert_text.as_deref().unwrap_or(&self.text)
+    }
+
+    /// Where the cursor goes in [`insertion`](Self::insertion), in characters
+    /// as shells count them; `None` for the end. Offsets past the end or
+    /// inside a character are ignored.
+    pub fn cursor_chars(&self) -> Option<usize> {
+        let insertion = self.insertion();
+        let offset = self.cursor_offset?;
+        insertion
+            .get(..offset)
+            .filter(|_| offset < insertion.len())
+            .map(|before| before.chars().count())
+    }
+}
+
+/// The signals a suggestion was ranked by, each between 0 and 1, and their
+/// sum weighted by `[ranking]`
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+pub struct Score {
+    pub total: f64,
+    /// Position among the suggestions gathered
+    pub order: f64,
+    /// Match of the typed word
+    pub fuzzy: f64,
+    /// Weight of the source
+    pub source: f64,
+    /// Use of the command
+    pub frecency: f64,
+}
+
+/// Origin of a suggestion, used for per-source limits
+#[derive(
+    Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum SuggestionSource {
+    /// Subcommands, options, and arguments from a completion spec
+    #[default]
+    Spec,
+    /// Filesystem paths
+    Files,
+    /// Directories to change to, for a path typed where the command goes
+    /// (zsh's `AUTO_CD`)
+    #[serde(rename = "cd-path", alias = "cd_path")]
+    CdPath,
+    /// Executables on `PATH` and shell builtins, for the command name
+    Command,
+    /// Directories the shell changed to before, for `cd` and `pushd`
+    Visited,
+    /// Previously executed commands
+    History,
+    /// Flags inferred from a script's source; may be inaccurate
+    Guessed,
+    /// Replacements for a mistyped command name or long flag
+    Correction,
+    /// Whole commands predicted from history on an empty buffer
+    Prediction,
+    /// Expansions of user-defined abbreviations; the text may contain
+    /// `$1` / `${1:default}` placeholders
+    Abbreviation,
+    /// Example invocations of the command just typed, with placeholders
+    /// like abbreviations
+    Example,
+}
+
+impl SuggestionSource {
+    /// Whether the text is a snippet whose placeholders the user fills in
+    pub fn is_snippet(self) -> bool {
+        matches!(
+            self,
+            SuggestionSource::Abbreviation | SuggestionSource::Example
+        )
+    }
+}
+
+/// Response sent back to shell client
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompletionResponse {
+    /// List of suggestions
+    pub suggestions: Vec<Suggestion>,
+    /// Set when more suggestions follow, either later pages or paths still
+    /// being read: repeat the request with this `offset` to get them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_offset: Option<usize>,
+    /// The daemon can't see the client's filesystem (another kernel, or a
+    /// container it has no access to); the client completes on its own
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub standalone: bool,
+    /// Byte range of the buffer a selected suggestion replaces: the word
+    /// around the cursor, including any part of it after the cursor
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replace: Option<Range<usize>>,
+    /// The weights suggestions were ranked with, for requests that
+    /// [`explain`](CompletionRequest::explain)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ranking: Option<RankingConfig>,
+}
+
+/// A completion response in the shape of the protocol version the request
+/// asked for. Reading one back gives the newest shape, which parses any
+/// older one since every field it added is optional.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum VersionedResponse {
+    V2(CompletionResponse),
+    V1(CompletionResponseV1),
+}
+
+impl VersionedResponse {
+    /// `response` downgraded as far as `version` needs; versions newer than
+    /// this daemon's get its newest
+    pub fn new(response: CompletionResponse, version: u8) -> Self {
+        match version {
+            0 | 1 => Self::V1(response.into()),
+            _ => Self::V2(response),
+        }
+    }
+
+    /// The response upgraded to the newest shape
+    pub fn into_latest(self) -> CompletionResponse {
+        match self {
+            Self::V2(response) => response,
+            Self::V1(response) => response.into(),
+        }
+    }
+}
+
+/// A completion response as version 1 clients read it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompletionResponseV1 {
+    pub suggestions: Vec<SuggestionV1>,
+}
+
+/// A suggestion as version 1 clients read it: `text` is what they insert
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SuggestionV1 {
+    pub text: String,
+    pub description: String,
+    #[serde(default)]
+    pub source: SuggestionSource,
+}
+
+impl From<CompletionResponse> for CompletionResponseV1 {
+    fn from(response: CompletionResponse) -> Self {
+        Self {
+            suggestions: response
+                .suggestions
+                .into_iter()
+                .map(SuggestionV1::from)
+                .collect(),
+        }
+    }
+}
+
+impl From<CompletionResponseV1> for CompletionResponse {
+    fn from(response: CompletionResponseV1) -> Self {
+        Self {
+            suggestions: response
+                .suggestions
+                .into_iter()
+                .map(Suggestion::from)
+                .collect(),
+            next_offset: None,
+            standalone: false,
+            replace: None,
+            ranking: None,
+        }
+    }
+}
+
+impl From<Suggestion> for SuggestionV1 {
+    /// Version 1 clients insert `text` and know nothing of placeholders, so
+    /// they get what to insert with the placeholders' defaults filled in
+    fn from(suggestion: Suggestion) -> Self {
+        let text = if suggestion.source.is_snippet() {
+            Snippet::parse(suggestion.insertion()).text
+        } else {
+            suggestion.insertion().to_string()
+        };
+        let source = match suggestion.source {
+            SuggestionSource::Files | SuggestionSource::CdPath | SuggestionSource::Visited => {
+                SuggestionSource::Files
+            }
+            SuggestionSource::History | SuggestionSource::Prediction => SuggestionSource::History,
+            _ => SuggestionSource::Spec,
+        };
+        Self {
+            text,
+            description: suggestion.description,
+            source,
+        }
+    }
+}
+
+impl From<SuggestionV1> for Suggestion {
+    fn from(suggestion: SuggestionV1) -> Self {
+        Self {
+            text: suggestion.text,
+            description: suggestion.description,
+            source: suggestion.source,
+            ..Default::default()
+        }
+    }
+}
+
+/// Error response sent when request fails
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    /// Error message
+    pub error: String,
+}
+
+/// Several completion requests answered in one round trip, sent instead of
+/// a completion request: `{"batch": [{"buffer": "git ", "cursor": 4}, ...]}`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchRequest {
+    pub batch: Vec<CompletionRequest>,
+}
+
+/// Responses to a [`BatchRequest`], in the order of its requests
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchResponse {
+    /// Each in the version its request asked for
+    pub responses: Vec<VersionedResponse>,
+}
+
+/// What the command line at the cursor expects, asked instead of a
+/// completion request: `{"context": {"buffer": "git ch", "cursor": 6}}`.
+///
+/// Answered from the parser and specs alone, without generators, path
+/// listings or history, so prompts can ask on every keystroke.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContextRequest {
+    pub context: CompletionRequest,
+}
+
+/// Reply to a [`ContextRequest`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContextResponse {
+    /// First word of the command at the cursor, as typed so far
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// Whether `command` can be run: it is on `PATH`, or is a path to an
+    /// executable or a directory, or is one of the request's shell functions;
+    /// unset when the daemon can't see the client's filesystem. Shell builtins
+    /// and aliases are unknown to it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub valid: Option<bool>,
+    /// Whether `command` has a spec
+    #[serde(default)]
+    pub spec: bool,
+    /// What the word at the cursor is
+    pub expects: ArgKind,
+    /// Name the spec gives the expected value, like `branch`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arg_name: Option<String>,
+}
+
+/// Kinds of word a command line may expect next
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArgKind {
+    /// The command name
+    Command,
+    /// A subcommand, or a positional argument where the spec allows either
+    Subcommand,
+    /// A flag
+    Flag,
+    /// A value from a fixed list, a generator, or free-form text
+    Value,
+    /// A path
+    Path,
+    /// Nothing: the spec takes no further arguments
+    Nothing,
+}
+
+/// A suggestion the user picked, reported by the client once it is inserted:
+/// `{"accepted": {"text": "checkout", "buffer": "git ch", "cursor": 6}}`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AcceptedRequest {
+    pub accepted: Accepted,
+}
+
+/// What was accepted, and into which command line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Accepted {
+    /// Text of the suggestion
+    pub text: String,
+    /// Where the suggestion came from
+    #[serde(default)]
+    pub source: SuggestionSource,
+    /// The buffer before the suggestion was inserted
+    pub buffer: String,
+    /// Cursor position in that buffer, as a byte offset
+    pub cursor: usize,
+    /// First word of the command at the cursor; filled in by the daemon
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<PathBuf>,
+}
+
+/// The shell changed to a directory, reported by its integration:
+/// `{"visited": {"dir": "/home/me/src", "session_id": "zsh-4242-pts1"}}`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VisitedRequest {
+    pub visited: Visited,
+}
+
+/// The directory changed to, and by which session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Visited {
+    pub dir: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+}
+
+/// A command without a spec was completed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnknownCommand {
+    pub command: String,
+    /// The buffer being completed
+    pub buffer: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<PathBuf>,
+}
+
+/// Something that happened in the daemon, given to `[hooks]` commands and
+/// to [`EventsRequest`] subscribers: `{"event": "accepted", "time": ..., ...}`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// A suggestion was inserted into the buffer
+    Accepted(Accepted),
+    /// A command without a spec was completed, reported once per command
+    /// while the daemon runs
+    UnknownCommand(UnknownCommand),
+}
+
+impl Event {
+    /// Name of the event, as in its `event` field
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::Accepted(_) => "accepted",
+            Event::UnknownCommand(_) => "unknown_command",
+        }
+    }
+
+    /// Whether the event carries a command line that must not leave the
+    /// daemon, by the rule usage stats follow
+    pub fn is_private(&self) -> bool {
+        match self {
+            Event::Accepted(accepted) => {
+                crate::stats::is_private(&accepted.buffer)
+                    || crate::stats::is_private(&accepted.text)
+            }
+            Event::UnknownCommand(unknown) => crate::stats::is_private(&unknown.buffer),
+        }
+    }
+}
+
+/// An [`Event`] and when it happened, one JSON line each
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventRecord {
+    /// Unix time in seconds
+    pub time: u64,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+/// Subscribe to events, sent instead of a completion request:
+/// `{"events": ["accepted"]}`. The daemon answers with one [`EventRecord`]
+/// per line until the connection is closed.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EventsRequest {
+    /// Names of the events wanted; all of them when empty
+    pub events: Vec<String>,
+}
+
+/// Control message for managing a running daemon, sent instead of a
+/// completion request: `{"control": "reindex"}`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ControlRequest {
+    pub control: ControlCommand,
+}
+
+/// Operations available through [`ControlRequest`]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlCommand {
+    /// Rebuild the command index now
+    Reindex,
+    /// Write the in-memory usage stats to disk
+    SaveStats,
+    /// Replace the in-memory usage stats with the file on disk
+    ReloadStats,
+    /// Apply the `[retention]` limits to the usage stats and command history,
+    /// then save them
+    Prune,
+    /// Replace the daemon with the executable on disk, keeping its sockets
+    Restart,
+    /// Forget the loaded specs, so they are read again, e.g. after
+    /// `spec prefer`
+    ReloadSpecs,
+}
+
+/// Question about the daemon's health, sent instead of a completion
+/// request: `{"status": true}`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StatusRequest {
+    pub status: bool,
+}
+
+/// Reply to a [`StatusRequest`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusResponse {
+    /// Process id of the daemon
+    pub pid: u32,
+    /// Version of the daemon's executable
+    pub version: String,
+    /// Seconds since the daemon started or last restarted
+    pub uptime_secs: u64,
+    /// Connections that went wrong in that time
+    pub errors: ConnectionErrors,
+}
+
+/// Counts of connections that went wrong, so a shell integration sending
+/// garbage gets noticed
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConnectionErrors {
+    /// Request lines that weren't JSON
+    pub malformed: u64,
+    /// JSON that wasn't a message the daemon knows, and lines too long
+    pub protocol: u64,
+    /// Clients that sent no request in time or didn't read the response
+    pub timeouts: u64,
+    /// Connections closed before a request, or failing before the response
+    /// was written
+    pub dropped: u64,
+    /// What went wrong last
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last: Option<String>,
+}
+
+impl ConnectionErrors {
+    pub fn total(&self) -> u64 {
+        self.malformed + self.protocol + self.timeouts + self.dropped
+    }
+}
+
+/// Reply to a [`ControlRequest`]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ControlResponse {
+    /// Whether the operation succeeded
+    pub ok: bool,
+    /// Human readable outcome
+    pub message: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+
+    fn response() -> CompletionResponse {
+        CompletionResponse {
+            suggestions: vec![
+                Suggestion {
+                    text: "--message".to_string(),
+                    description: "Commit message".to_string(),
+                    insert_text: Some("--message \"\"".to_string()),
+                    cursor_offset: Some(11),
+                    ..Default::default()
+                },
+                Suggestion {
+                    text: "gcm".to_string(),
+                    description: "git commit -m".to_string(),
+                    source: SuggestionSource::Abbreviation,
+                    insert_text: Some("git commit -m \"${1:message}\"".to_string()),
+                    ..Default::default()
+                },
+                Suggestion {
+                    text: "src/".to_string(),
+                    source: SuggestionSource::CdPath,
+                    ..Default::default()
+                },
+            ],
+            next_offset: Some(3),
+            standalone: false,
+            replace: Some(11..13),
+            ranking: None,
+        }
+    }
+
+    #[test]
+    fn test_request_without_version_is_version_1() {
+        let request: CompletionRequest =
+            serde_json::from_str(r#"{"buffer": "git ", "cursor": 4}"#).unwrap();
+        assert_eq!(request.version, 1);
+        assert_eq!((request.buffer.as_str(), request.cursor), ("git ", 4));
+        assert_eq!(CompletionRequest::new("git ", 4).version, PROTOCOL_VERSION);
+    }
+
+    #[test]
+    fn test_request_with_unknown_fields_ignores_them() {
+        let request: CompletionRequest = serde_json::from_str(
+            r#"{"buffer": "ls", "cursor": 2, "version": 99, "not_yet_invented": [1, 2]}"#,
+        )
+        .unwrap();
+        assert_eq!(request.version, 99);
+    }
+
+    #[test]
+    fn test_request_serialize_round_trips() {
+        let request = CompletionRequest {
+            session_id: Some("zsh-1-pts0".to_string()),
+            cwd: Some(PathBuf::from("/home/me")),
+            env: BTreeMap::from([("HOME".to_string(), "/home/me".to_string())]),
+            last_exit_code: Some(1),
+            terminal: Some(TerminalInfo {
+                columns: 80,
+                rows: 24,
+                truecolor: true,
+                unicode: false,
+            }),
+            offset: 10,
+            functions: vec!["mkcd".to_string()],
+            shell: Some("zsh".to_string()),
+            ..CompletionRequest::new("git ch", 6)
+        };
+        let sent = serde_json::to_value(&request).unwrap();
+        let read: CompletionRequest = serde_json::from_value(sent.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&read).unwrap(), sent);
+    }
+
+    #[test]
+    fn test_version_1_response_puts_insertion_in_text() {
+        let sent = serde_json::to_value(VersionedResponse::new(response(), 1)).unwrap();
+        assert_eq!(
+            sent,
+            json!({"suggestions": [
+                {"text": "--message \"\"", "description": "Commit message", "source": "spec"},
+                {"text": "git commit -m \"message\"", "description": "git commit -m", "source": "spec"},
+                {"text": "src/", "description": "", "source": "files"},
+            ]})
+        );
+    }
+
+    #[test]
+    fn test_version_2_response_is_unchanged() {
+        let latest = serde_json::to_value(response()).unwrap();
+        for version in [2, PROTOCOL_VERSION, u8::MAX] {
+            let sent = serde_json::to_value(VersionedResponse::new(response(), version)).unwrap();
+            assert_eq!(sent, latest);
+        }
+    }
+
+    #[test]
+    fn test_version_1_response_upgrades_to_newest_shape() {
+        let v1 = json!({"suggestions": [
+            {"text": "checkout", "description": "Switch branches"},
+            {"text": "main", "description": "", "source": "history"},
+        ]});
+        let upgraded: CompletionResponse =
+            serde_json::from_value::<CompletionResponseV1>(v1.clone())
+                .unwrap()
+                .into();
+        let read: CompletionResponse = serde_json::from_value(v1).unwrap();
+        assert_eq!(
+            serde_json::to_value(upgraded).unwrap(),
+            serde_json::to_value(read).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_batch_response_reads_back_in_newest_shape() {
+        let batch = BatchResponse {
+            responses: vec![
+                VersionedResponse::new(response(), 1),
+                VersionedResponse::new(response(), 2),
+            ],
+        };
+        let sent = serde_json::to_string(&batch).unwrap();
+        let read: BatchResponse = serde_json::from_str(&sent).unwrap();
+        let responses: Vec<CompletionResponse> = read
+            .responses
+            .into_iter()
+            .map(VersionedResponse::into_latest)
+            .collect();
+        assert_eq!(responses[0].replace, None);
+        assert_eq!(responses[0].suggestions[0].text, "--message \"\"");
+        assert_eq!(responses[1].replace, Some(11..13));
+        assert_eq!(responses[1].suggestions[0].insertion(), "--message \"\"");
+        let v1: Value = serde_json::from_str(&sent).unwrap();
+        assert!(v1["responses"][0].get("replace").is_none());
+    }
+}
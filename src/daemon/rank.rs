@@ -0,0 +1,277 @@
+//! The order suggestions are offered in.
+//!
+//! A spec lists its subcommands and options in the order that matters most,
+//! while the word typed so far says which of them are meant. Neither wins
+//! outright: every suggestion the word matches gets a score per signal,
+//! each between 0 and 1, and the sum weighted by `[ranking]` decides its
+//! place.
+//!
+//! - order: its position among the suggestions gathered, first is 1
+//! - fuzzy: its match score over the best match's, 0 when nothing is typed
+//! - source: the weight of where it came from, from `[ranking.sources]`
+//! - frecency: when completing the command name, the frecency of the command
+//!   it starts with over the highest one among the suggestions; after it,
+//!   how often and lately the suggestion was picked after the same words
+//!   (see [`context`]), over the most picked one's
+
+use super::{Score, Suggestion};
+use crate::config::RankingConfig;
+use crate::fuzzy;
+use crate::parser::Token;
+
+/// Words of the context a pick is remembered for, at most
+const CONTEXT_WORDS: usize = 3;
+
+/// What picks are remembered after: the command and the subcommands up to
+/// its first option, so `main` picked for `git checkout -b x ma` counts for
+/// `git checkout`; `None` while the command name is typed
+pub fn context(tokens: &[Token]) -> Option<String> {
+    let words: Vec<&str> = tokens
+        .iter()
+        .map(|token| token.text.as_str())
+        .take_while(|word| !word.starts_with('-'))
+        .take(CONTEXT_WORDS)
+        .collect();
+    (!words.is_empty()).then(|| words.join(" "))
+}
+
+/// The suggestions `word` matches, best first, with their scores attached
+/// when `explain` is set; `frecency` gives a suggestion's raw frecency
+pub fn rank(
+    suggestions: Vec<Suggestion>,
+    word: &str,
+    weights: &RankingConfig,
+    frecency: impl Fn(&Suggestion) -> u64,
+    explain: bool,
+) -> Vec<Suggestion> {
+    let matched = fuzzy::filter(word, &suggestions, |suggestion| suggestion.text.as_str());
+    let count = suggestions.len() as f64;
+    let best_match = matched.iter().map(|ranked| ranked.score).max().unwrap_or(0);
+    let uses: Vec<u64> = matched
+        .iter()
+        .map(|ranked| frecency(&suggestions[ranked.index]))
+        .collect();
+    let most_used = uses.iter().copied().max().unwrap_or(0);
+
+    let mut slots: Vec<Option<Suggestion>> = suggestions.into_iter().map(Some).collect();
+    let mut ranked: Vec<(Suggestion, Score)> = matched
+        .iter()
+        .zip(uses)
+        .filter_map(|(ranked, uses)| {
+            let suggestion = slots[ranked.index].take()?;
+            let mut score = Score {
+                total: 0.0,
+                order: 1.0 - ranked.index as f64 / count,
+                fuzzy: ratio(u64::from(ranked.score), u64::from(best_match)),
+                source: weights
+                    .sources
+                    .get(&suggestion.source)
+                    .copied()
+                    .unwrap_or(1.0),
+                frecency: ratio(uses, most_used),
+            };
+            score.total = weights.order * score.order
+                + weights.fuzzy * score.fuzzy
+                + weights.source * score.source
+                + weights.frecency * score.frecency;
+            Some((suggestion, score))
+        })
+        .collect();
+    // Stable, so equal scores keep the order they were gathered in
+    ranked.sort_by(|(_, a), (_, b)| b.total.total_cmp(&a.total));
+    ranked
+        .into_iter()
+        .map(|(suggestion, score)| Suggestion {
+            score: explain.then_some(score),
+            ..suggestion
+        })
+        .collect()
+}
+
+fn ratio(value: u64, max: u64) -> f64 {
+    if max == 0 {
+        0.0
+    } else {
+        value as f64 / max as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::daemon::SuggestionSource;
+    use crate::parser::ParseState;
+
+    fn suggestions(texts: &[&str]) -> Vec<Suggestion> {
+        texts
+            .iter()
+            .map(|text| Suggestion {
+                text: text.to_string(),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    /// Weights that count nothing, to set the one a test is about
+    fn none() -> RankingConfig {
+        RankingConfig {
+            order: 0.0,
+            fuzzy: 0.0,
+            source: 0.0,
+            frecency: 0.0,
+            ..Default::default()
+        }
+    }
+
+    fn texts(ranked: &[Suggestion]) -> Vec<&str> {
+        ranked
+            .iter()
+            .map(|suggestion| suggestion.text.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_rank_order_weight_keeps_gathered_order() {
+        let weights = RankingConfig {
+            order: 1.0,
+            ..none()
+        };
+        let ranked = rank(
+            suggestions(&["status", "add", "commit"]),
+            "",
+            &weights,
+            |_| 0,
+            false,
+        );
+        assert_eq!(texts(&ranked), ["status", "add", "commit"]);
+    }
+
+    #[test]
+    fn test_rank_fuzzy_weight_puts_best_match_first() {
+        let weights = RankingConfig {
+            fuzzy: 1.0,
+            ..none()
+        };
+        let ranked = rank(
+            suggestions(&["cherry-pick", "checkout"]),
+            "checkout",
+            &weights,
+            |_| 0,
+            false,
+        );
+        assert_eq!(texts(&ranked), ["checkout"]);
+        let ranked = rank(
+            suggestions(&["cache-check-k", "check"]),
+            "check",
+            &weights,
+            |_| 0,
+            false,
+        );
+        assert_eq!(texts(&ranked), ["check", "cache-check-k"]);
+    }
+
+    #[test]
+    fn test_rank_source_weight_puts_heavier_source_first() {
+        let mut weights = RankingConfig {
+            source: 1.0,
+            ..none()
+        };
+        weights.sources.insert(SuggestionSource::History, 2.0);
+        weights.sources.insert(SuggestionSource::Files, 0.5);
+        let mut gathered = suggestions(&["src", "main", "HEAD"]);
+        gathered[0].source = SuggestionSource::Files;
+        gathered[2].source = SuggestionSource::History;
+        let ranked = rank(gathered, "", &weights, |_| 0, false);
+        // The spec's `main` has no entry and counts 1
+        assert_eq!(texts(&ranked), ["HEAD", "main", "src"]);
+    }
+
+    #[test]
+    fn test_rank_frecency_weight_puts_most_used_first() {
+        let weights = RankingConfig {
+            frecency: 1.0,
+            ..none()
+        };
+        let uses = |suggestion: &Suggestion| match suggestion.text.as_str() {
+            "push" => 10,
+            "pull" => 40,
+            _ => 0,
+        };
+        let ranked = rank(
+            suggestions(&["prune", "push", "pull"]),
+            "p",
+            &weights,
+            uses,
+            false,
+        );
+        assert_eq!(texts(&ranked), ["pull", "push", "prune"]);
+    }
+
+    #[test]
+    fn test_rank_heavier_weight_wins_between_signals() {
+        let uses = |suggestion: &Suggestion| u64::from(suggestion.text == "commit");
+        let gathered = || suggestions(&["add", "commit"]);
+        let by_order = RankingConfig {
+            order: 1.0,
+            frecency: 0.1,
+            ..none()
+        };
+        let ranked = rank(gathered(), "", &by_order, uses, false);
+        assert_eq!(texts(&ranked), ["add", "commit"]);
+        let by_frecency = RankingConfig {
+            order: 0.1,
+            frecency: 1.0,
+            ..none()
+        };
+        let ranked = rank(gathered(), "", &by_frecency, uses, false);
+        assert_eq!(texts(&ranked), ["commit", "add"]);
+    }
+
+    #[test]
+    fn test_rank_ties_keep_gathered_order() {
+        let weights = RankingConfig {
+            frecency: 1.0,
+            ..none()
+        };
+        let ranked = rank(suggestions(&["b", "c", "a"]), "", &weights, |_| 5, false);
+        assert_eq!(texts(&ranked), ["b", "c", "a"]);
+        let ranked = rank(suggestions(&["b", "c", "a"]), "", &none(), |_| 0, false);
+        assert_eq!(texts(&ranked), ["b", "c", "a"]);
+    }
+
+    #[test]
+    fn test_rank_unmatched_suggestions_are_dropped() {
+        let ranked = rank(
+            suggestions(&["build", "test", "bench"]),
+            "b",
+            &RankingConfig::default(),
+            |_| 0,
+            false,
+        );
+        assert_eq!(texts(&ranked), ["build", "bench"]);
+    }
+
+    #[test]
+    fn test_rank_explain_attaches_scores() {
+        let weights = RankingConfig::default();
+        let ranked = rank(suggestions(&["add", "commit"]), "", &weights, |_| 0, true);
+        let score = ranked[0].score.as_ref().unwrap();
+        assert_eq!((score.order, score.fuzzy, score.source), (1.0, 0.0, 1.0));
+        assert_eq!(score.total, weights.order + weights.source);
+        assert_eq!(ranked[1].score.as_ref().unwrap().order, 0.5);
+        let ranked = rank(suggestions(&["add"]), "", &weights, |_| 0, false);
+        assert!(ranked[0].score.is_none());
+    }
+
+    #[test]
+    fn test_context_stops_at_first_option() {
+        let words = |line: &str| context(ParseState::new(line).tokens());
+        assert_eq!(words("git checkout -b x ma"), Some("git checkout".into()));
+        assert_eq!(
+            words("kubectl get pods -n x "),
+            Some("kubectl get pods".into())
+        );
+        assert_eq!(words("a b c d e "), Some("a b c".into()));
+        assert_eq!(words("gi"), None);
+    }
+}
@@ -0,0 +1,115 @@
+//! Whole commands from the user's zsh history that start like the buffer.
+//!
+//! The history file is read the first time it is needed and again whenever
+//! it changes, which with `INC_APPEND_HISTORY` or `SHARE_HISTORY` is after
+//! every command. Commands are offered most recent first, each once, and
+//! never those the privacy filter keeps out of the stats. They come after
+//! what the spec offers, inserted from the start of the word being typed so
+//! picking one completes the rest of the command line.
+
+use super::{Suggestion, SuggestionSource};
+use crate::stats::import::{self, HistorySource};
+use crate::stats::is_private;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tracing::warn;
+
+/// The history as last read, and what the file looked like then
+#[derive(Debug)]
+struct Snapshot {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    len: u64,
+    /// Distinct commands, the most recent first
+    commands: Arc<Vec<String>>,
+}
+
+/// The zsh history, read again when the file changes
+#[derive(Debug, Default)]
+pub struct ShellHistory {
+    snapshot: Mutex<Option<Snapshot>>,
+}
+
+impl ShellHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The commands in the history at `path`, reading it if it changed
+    fn commands(&self, path: &Path) -> Arc<Vec<String>> {
+        let metadata = std::fs::metadata(path).ok();
+        let modified = metadata
+            .as_ref()
+            .and_then(|metadata| metadata.modified().ok());
+        let len = metadata.as_ref().map_or(0, |metadata| metadata.len());
+        let mut snapshot = self.snapshot.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(snapshot) = snapshot.as_ref()
+            && snapshot.path == path
+            && snapshot.modified == modified
+            && snapshot.len == len
+        {
+            return Arc::clone(&snapshot.commands);
+        }
+
+        let entries = match metadata {
+            Some(_) => import::read(HistorySource::Zsh, path, &mut |_| {}).unwrap_or_else(|e| {
+                warn!("Failed to read shell history: {:#}", e);
+                Vec::new()
+            }),
+            None => Vec::new(),
+        };
+        let mut seen = HashSet::new();
+        let commands: Vec<String> = entries
+            .into_iter()
+            .rev()
+            .map(|entry| entry.command)
+            // Multi-line commands don't fit on a line of the menu
+            .filter(|command| !command.contains('\n') && !is_private(command))
+            .filter(|command| seen.insert(command.clone()))
+            .collect();
+        let commands = Arc::new(commands);
+        *snapshot = Some(Snapshot {
+            path: path.to_path_buf(),
+            modified,
+            len,
+            commands: Arc::clone(&commands),
+        });
+        commands
+    }
+}
+
+/// Up to `limit` commands from the history at `file`, or zsh's own, that
+/// start with `typed` and go on past it; `word` is where in `typed` the
+/// word being replaced starts
+pub fn suggest(
+    history: &ShellHistory,
+    file: Option<&Path>,
+    typed: &str,
+    word: usize,
+    limit: usize,
+) -> Vec<Suggestion> {
+    if typed.trim().is_empty() || limit == 0 {
+        return Vec::new();
+    }
+    let Some(path) = file
+        .map(Path::to_path_buf)
+        .or_else(|| HistorySource::Zsh.default_file())
+    else {
+        return Vec::new();
+    };
+    history
+        .commands(&path)
+        .iter()
+        .filter(|command| command.len() > typed.len() && command.starts_with(typed))
+        .take(limit)
+        .map(|command| Suggestion {
+            text: command.clone(),
+            description: "Run before".to_string(),
+            source: SuggestionSource::History,
+            insert_text: Some(command[word..].to_string()),
+            ..Default::default()
+        })
+        .collect()
+}
@@ -0,0 +1,110 @@
+//! Replacing a running daemon with the executable on disk.
+//!
+//! The bundled specs are compiled into the binary, so a new catalog arrives
+//! with a new executable. When the file the daemon was started from changes,
+//! or a client sends the `restart` control message, the daemon stops
+//! accepting, lets the requests in flight finish, saves its state and
+//! `exec`s the executable with its own arguments. The listening sockets are
+//! handed over open: their descriptors survive the `exec`, listed in
+//! [`LISTEN_FDS_VAR`], so connections made meanwhile wait in the socket's
+//! backlog instead of failing.
+
+use super::listen::Endpoint;
+use std::collections::HashMap;
+use std::os::fd::RawFd;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Environment variable carrying the inherited sockets: one `fd endpoint`
+/// per line
+pub const LISTEN_FDS_VAR: &str = "AUTOCOMPLETE_RS_LISTEN_FDS";
+
+/// How long after the executable changed it is taken to be fully written
+pub const SETTLE_TIME: Duration = Duration::from_secs(1);
+
+/// Sockets handed over by the daemon this one replaced, by endpoint
+pub fn inherited() -> HashMap<String, RawFd> {
+    let Ok(value) = std::env::var(LISTEN_FDS_VAR) else {
+        return HashMap::new();
+    };
+    value
+        .lines()
+        .filter_map(|line| {
+            let (fd, endpoint) = line.split_once(' ')?;
+            Some((endpoint.to_string(), fd.parse().ok()?))
+        })
+        .collect()
+}
+
+/// Run `exe` in place of this process, handing it `sockets`. Only returns
+/// if that fails.
+pub fn exec(exe: &Path, sockets: &[(Endpoint, RawFd)]) -> anyhow::Error {
+    let mut listen_fds = String::new();
+    for (endpoint, fd) in sockets {
+        set_inheritable(*fd, true);
+        listen_fds.push_str(&format!("{fd} {endpoint}\n"));
+    }
+    let error = std::process::Command::new(exe)
+        .args(std::env::args_os().skip(1))
+        .env(LISTEN_FDS_VAR, listen_fds)
+        .exec();
+    for (_, fd) in sockets {
+        set_inheritable(*fd, false);
+    }
+    anyhow::Error::new(error).context(format!("Failed to run {}", exe.display()))
+}
+
+/// Let `fd` survive `exec`, or close it there again, as sockets shouldn't
+/// leak into generators
+pub fn set_inheritable(fd: RawFd, inheritable: bool) {
+    // SAFETY: fcntl on a descriptor only reads and sets its flags
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFD);
+        if flags < 0 {
+            return;
+        }
+        let flags = if inheritable {
+            flags & !libc::FD_CLOEXEC
+        } else {
+            flags | libc::FD_CLOEXEC
+        };
+        libc::fcntl(fd, libc::F_SETFD, flags);
+    }
+}
+
+/// The executable the daemon was started from, to notice when it is
+/// replaced
+#[derive(Debug)]
+pub struct Executable {
+    path: Option<PathBuf>,
+    modified: Option<SystemTime>,
+}
+
+impl Executable {
+    pub fn current() -> Self {
+        let path = std::env::current_exe().ok();
+        let modified = path.as_deref().and_then(modified);
+        Self { path, modified }
+    }
+
+    /// Where the executable is; once replaced, the running process's own
+    /// file is gone, but this names the new one
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Whether the file was replaced since the daemon started, and has
+    /// stayed untouched long enough to be complete
+    pub fn replaced(&self) -> bool {
+        let Some(now) = self.path.as_deref().and_then(modified) else {
+            return false;
+        };
+        self.modified.is_some_and(|started| started != now)
+            && now.elapsed().is_ok_and(|age| age >= SETTLE_TIME)
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).ok()?.modified().ok()
+}
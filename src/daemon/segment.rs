@@ -0,0 +1,197 @@
+//! Completing around the cursor of a huge buffer.
+//!
+//! A pasted log or generated script can leave megabytes and tens of
+//! thousands of words in the buffer, and parsing all of it on every keystroke
+//! would blow the latency budget. Once the buffer is longer than
+//! [`MAX_BUFFER`], only a window around the cursor is completed: from the
+//! start of the cursor's line, at most [`MAX_BEFORE`] bytes back, to the end
+//! of it, at most [`MAX_AFTER`] bytes on. A command reaching back further is
+//! read as if it began at the window, where a word cut in half or a quote
+//! opened before it may be misread; that beats timing out.
+//!
+//! Within the window, a command of more than [`MAX_WORDS`] words keeps its
+//! name and the words closest to the cursor.
+
+use super::CompletionRequest;
+use crate::parser::Token;
+
+/// Buffers up to this many bytes are completed whole
+pub const MAX_BUFFER: usize = 16 * 1024;
+/// Bytes of a huge buffer kept before the cursor
+const MAX_BEFORE: usize = 8 * 1024;
+/// Bytes of a huge buffer kept after the cursor
+const MAX_AFTER: usize = 1024;
+/// Words of the cursor's command given to its spec
+pub const MAX_WORDS: usize = 256;
+
+/// `request` narrowed to the window around its cursor, and the byte offset
+/// the window starts at in the original buffer; `None` when the buffer is
+/// short enough to complete whole
+pub fn narrow(request: &CompletionRequest) -> Option<(CompletionRequest, usize)> {
+    let buffer = request.buffer.as_str();
+    if buffer.len() <= MAX_BUFFER {
+        return None;
+    }
+    let cursor = floor_boundary(buffer, request.cursor.min(buffer.len()));
+
+    let earliest = ceil_boundary(buffer, cursor.saturating_sub(MAX_BEFORE));
+    let before = &buffer[earliest..cursor];
+    // A line start if there is one, else the first whole word
+    let start = match before.rfind('\n') {
+        Some(newline) => earliest + newline + 1,
+        None if earliest == 0 => 0,
+        None => before
+            .find(char::is_whitespace)
+            .map_or(earliest, |space| earliest + space + 1),
+    };
+
+    let latest = floor_boundary(buffer, (cursor + MAX_AFTER).min(buffer.len()));
+    let end = buffer[cursor..latest]
+        .find('\n')
+        .map_or(latest, |newline| cursor + newline);
+
+    Some((
+        CompletionRequest {
+            buffer: buffer[start..end].to_string(),
+            cursor: cursor - start,
+            ..request.clone()
+        },
+        start,
+    ))
+}
+
+/// The words of a command, at most [`MAX_WORDS`] of them: its name and the
+/// last ones before the cursor
+pub fn trim_words(mut tokens: Vec<Token>) -> Vec<Token> {
+    if tokens.len() > MAX_WORDS {
+        tokens.drain(1..tokens.len() - (MAX_WORDS - 1));
+    }
+    tokens
+}
+
+fn floor_boundary(text: &str, mut at: usize) -> usize {
+    while !text.is_char_boundary(at) {
+        at -= 1;
+    }
+    at
+}
+
+fn ceil_boundary(text: &str, mut at: usize) -> usize {
+    while !text.is_char_boundary(at) {
+        at += 1;
+    }
+    at
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::ParseState;
+    use std::time::{Duration, Instant};
+
+    fn request(buffer: String, cursor: usize) -> CompletionRequest {
+        CompletionRequest::new(buffer, cursor)
+    }
+
+    #[test]
+    fn test_narrow_short_buffer_is_left_whole() {
+        assert!(narrow(&request("git checkout ma".to_string(), 15)).is_none());
+        let limit = "x".repeat(MAX_BUFFER);
+        assert!(narrow(&request(limit, MAX_BUFFER)).is_none());
+    }
+
+    #[test]
+    fn test_narrow_window_starts_at_cursor_line() {
+        let pasted = "echo some pasted output\n".repeat(100_000);
+        let buffer = format!("{pasted}git ch");
+        let cursor = buffer.len();
+        let (narrowed, start) = narrow(&request(buffer.clone(), cursor)).unwrap();
+        assert_eq!(narrowed.buffer, "git ch");
+        assert_eq!(narrowed.cursor, 6);
+        assert_eq!(&buffer[start..start + narrowed.cursor], "git ch");
+    }
+
+    #[test]
+    fn test_narrow_window_ends_at_end_of_cursor_line() {
+        let buffer = format!("git ch\n{}", "after\n".repeat(100_000));
+        let (narrowed, start) = narrow(&request(buffer, 6)).unwrap();
+        assert_eq!(
+            (narrowed.buffer.as_str(), narrowed.cursor, start),
+            ("git ch", 6, 0)
+        );
+    }
+
+    #[test]
+    fn test_narrow_huge_line_is_cut_at_a_word() {
+        let words = "word ".repeat(1_000_000);
+        let buffer = format!("grep {words}--colo");
+        let cursor = buffer.len();
+        let (narrowed, start) = narrow(&request(buffer.clone(), cursor)).unwrap();
+        assert!(narrowed.buffer.len() <= MAX_BEFORE);
+        assert!(narrowed.buffer.starts_with("word "));
+        assert!(narrowed.buffer.ends_with("--colo"));
+        assert_eq!(narrowed.cursor, narrowed.buffer.len());
+        assert_eq!(&buffer[start..start + narrowed.cursor], narrowed.buffer);
+    }
+
+    #[test]
+    fn test_narrow_cursor_in_middle_of_huge_buffer() {
+        let half = "a ".repeat(1_000_000);
+        let buffer = format!("{half}ls --al{half}");
+        let cursor = half.len() + "ls --al".len();
+        let (narrowed, start) = narrow(&request(buffer.clone(), cursor)).unwrap();
+        assert!(narrowed.buffer.len() <= MAX_BEFORE + MAX_AFTER);
+        assert_eq!(start + narrowed.cursor, cursor);
+        assert!(narrowed.buffer[..narrowed.cursor].ends_with("ls --al"));
+    }
+
+    #[test]
+    fn test_narrow_multibyte_edges_fall_on_char_boundaries() {
+        // Three-byte characters put the byte limits inside them
+        let buffer = "€".repeat(1_000_000);
+        for cursor in [
+            0,
+            1,
+            2,
+            buffer.len() / 2 + 1,
+            buffer.len() - 1,
+            buffer.len(),
+        ] {
+            let (narrowed, start) = narrow(&request(buffer.clone(), cursor)).unwrap();
+            assert!(buffer.is_char_boundary(start));
+            assert!(narrowed.buffer.is_char_boundary(narrowed.cursor));
+            assert!(narrowed.buffer.chars().all(|c| c == '€'));
+        }
+    }
+
+    #[test]
+    fn test_narrow_cursor_past_end_is_clamped() {
+        let buffer = "x\n".repeat(MAX_BUFFER);
+        let (narrowed, start) = narrow(&request(buffer.clone(), usize::MAX)).unwrap();
+        assert_eq!(start + narrowed.cursor, buffer.len());
+    }
+
+    #[test]
+    fn test_narrow_open_quote_parses_only_the_window() {
+        let buffer = format!("echo '{}", "quoted ".repeat(500_000));
+        let cursor = buffer.len();
+        let started = Instant::now();
+        let (narrowed, _) = narrow(&request(buffer, cursor)).unwrap();
+        let parsed = ParseState::new(&narrowed.buffer[..narrowed.cursor]);
+        assert!(parsed.tokens().len() <= MAX_BEFORE);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_trim_words_long_command_keeps_name_and_last_words() {
+        let buffer = format!("rm {}last", "file ".repeat(10_000));
+        let tokens = ParseState::new(&buffer).tokens().to_vec();
+        let trimmed = trim_words(tokens.clone());
+        assert_eq!(trimmed.len(), MAX_WORDS);
+        assert_eq!(trimmed[0].text, "rm");
+        assert_eq!(trimmed.last(), tokens.last());
+
+        let short = ParseState::new("git commit -m ").tokens().to_vec();
+        assert_eq!(trim_words(short.clone()), short);
+    }
+}
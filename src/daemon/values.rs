@@ -0,0 +1,59 @@
+//! Values of free-form flags remembered from earlier commands.
+//!
+//! Specs can list the values of `--output json|yaml`, but not of `--profile`,
+//! `--namespace` or `-t`: those are whatever the user has been passing. After
+//! a command succeeds, the values it gave such flags are stored per command
+//! and flag, and offered again, most recent first, when that flag's value is
+//! being completed.
+
+use super::{CompletionRequest, Suggestion, SuggestionSource};
+use crate::parser;
+use crate::specs::{SpecOption, SpecStore, resolve};
+use crate::stats::{UsageStats, is_private};
+use std::sync::Mutex;
+
+/// Remember the flag values in the shell's last command, if it succeeded.
+/// The shell reports it with every request until the next command runs,
+/// which only refreshes when the values were last used.
+pub fn record(request: &CompletionRequest, specs: &SpecStore, stats: &Mutex<UsageStats>) {
+    let (Some(line), Some(0)) = (&request.last_command, request.last_exit_code) else {
+        return;
+    };
+    if is_private(line) {
+        return;
+    }
+    // Every command of `kubectl get pods -n web | grep api` ran
+    for words in parser::commands(line) {
+        let Some((command, args)) = words.split_first() else {
+            continue;
+        };
+        let Some(spec) = specs.get(&command.text) else {
+            continue;
+        };
+        let values = resolve::flag_values(&spec, args);
+        if values.is_empty() {
+            continue;
+        }
+        let mut stats = stats.lock().unwrap_or_else(|e| e.into_inner());
+        for (option, value) in values {
+            // Suggestions are inserted as typed, with no quoting
+            if !value.contains(char::is_whitespace) {
+                stats.record_flag_value(&command.text, option.key(), &value);
+            }
+        }
+    }
+}
+
+/// Values given to `option` of `command` before
+pub fn suggest(command: &str, option: &SpecOption<'_>, stats: &UsageStats) -> Vec<Suggestion> {
+    stats
+        .flag_values(command, option.key())
+        .into_iter()
+        .map(|value| Suggestion {
+            text: value.to_string(),
+            description: "Used before".to_string(),
+            source: SuggestionSource::History,
+            ..Default::default()
+        })
+        .collect()
+}
@@ -0,0 +1,144 @@
+//! Directories the shell changed to, for completing `cd` and `pushd`.
+//!
+//! The shell integration reports every change of directory. Each visit is
+//! counted in the usage stats, which rank directories by frecency across
+//! sessions like zoxide does, and the session remembers its own latest ones
+//! in memory, the way `cd -` and `dirs` do. Completing the directory of `cd`
+//! offers the session's recent directories first, then the frecent ones,
+//! those the typed word matches anywhere in their path.
+
+use super::{CompletionRequest, Suggestion, SuggestionSource, Visited};
+use crate::fuzzy::Matcher;
+use crate::paths;
+use crate::stats::UsageStats;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Directories remembered per session, the oldest forgotten first
+const MAX_RECENT: usize = 20;
+/// Sessions remembered before the oldest are all forgotten
+const MAX_SESSIONS: usize = 256;
+
+/// Commands whose first argument is a directory to change to
+pub const COMMANDS: &[&str] = &["cd", "pushd"];
+
+/// The latest directories of every session, the most recent first
+#[derive(Debug, Default)]
+pub struct RecentDirs {
+    sessions: Mutex<HashMap<String, VecDeque<PathBuf>>>,
+}
+
+impl RecentDirs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remember that `session` changed to `dir`
+    pub fn record(&self, session: &str, dir: &Path) {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        if sessions.len() >= MAX_SESSIONS && !sessions.contains_key(session) {
+            sessions.clear();
+        }
+        let recent = sessions.entry(session.to_string()).or_default();
+        recent.retain(|recent| recent != dir);
+        recent.push_front(dir.to_path_buf());
+        recent.truncate(MAX_RECENT);
+    }
+
+    fn of(&self, session: &str) -> Vec<PathBuf> {
+        let sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        sessions
+            .get(session)
+            .map(|recent| recent.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Count a reported visit, unless it names no absolute directory
+pub fn record(visited: &Visited, recent: &RecentDirs, stats: &Mutex<UsageStats>) {
+    let Some(dir) = visited.dir.to_str() else {
+        return;
+    };
+    if !visited.dir.is_absolute() {
+        return;
+    }
+    recent.record(
+        visited.session_id.as_deref().unwrap_or_default(),
+        &visited.dir,
+    );
+    let mut stats = stats.lock().unwrap_or_else(|e| e.into_inner());
+    stats.record_visit(dir);
+}
+
+/// Up to `max` visited directories `word` matches, other than the current
+/// one and those that are gone: the session's recent ones, then the most
+/// frecent
+pub fn suggest(
+    request: &CompletionRequest,
+    word: &str,
+    max: usize,
+    recent: &RecentDirs,
+    stats: &Mutex<UsageStats>,
+) -> Vec<Suggestion> {
+    let session = request.session_id.as_deref().unwrap_or_default();
+    let mut candidates: Vec<(PathBuf, String)> = recent
+        .of(session)
+        .into_iter()
+        .map(|dir| (dir, "Recently visited".to_string()))
+        .collect();
+    {
+        let stats = stats.lock().unwrap_or_else(|e| e.into_inner());
+        candidates.extend(
+            stats
+                .frecent_directories()
+                .into_iter()
+                .map(|(dir, visits)| (PathBuf::from(dir), visited_times(visits.count))),
+        );
+    }
+
+    let home = request
+        .env
+        .get("HOME")
+        .map(PathBuf::from)
+        .or_else(paths::home_dir);
+    let matcher = Matcher::new(word);
+    let mut seen = Vec::new();
+    let mut suggestions = Vec::new();
+    for (dir, description) in candidates {
+        if suggestions.len() >= max {
+            break;
+        }
+        if seen.contains(&dir) || request.cwd.as_ref() == Some(&dir) {
+            continue;
+        }
+        let text = display(&dir, home.as_deref());
+        if matcher.score(&text).is_none() || !request.local_path(&dir).is_dir() {
+            continue;
+        }
+        seen.push(dir);
+        suggestions.push(Suggestion {
+            text,
+            description,
+            source: SuggestionSource::Visited,
+            ..Default::default()
+        });
+    }
+    suggestions
+}
+
+/// `dir` as typed: under `~` when in the home directory, and ending in `/`
+fn display(dir: &Path, home: Option<&Path>) -> String {
+    match home.and_then(|home| dir.strip_prefix(home).ok()) {
+        Some(rest) if rest.as_os_str().is_empty() => "~/".to_string(),
+        Some(rest) => format!("~/{}/", rest.display()),
+        None => format!("{}/", dir.display().to_string().trim_end_matches('/')),
+    }
+}
+
+fn visited_times(count: u64) -> String {
+    match count {
+        1 => "Visited once".to_string(),
+        count => format!("Visited {count} times"),
+    }
+}
@@ -0,0 +1,144 @@
+//! Watching the user's spec directory and the daemon's executable.
+//!
+//! Both are watched with `notify`, so an idle daemon sleeps until one of
+//! them changes. The spec directory may not exist yet: its parent is
+//! watched too, and the directory is watched once it appears. The
+//! executable is watched through its directory, as an install usually
+//! replaces the file rather than writing to it.
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::mpsc::{UnboundedReceiver, unbounded_channel};
+
+/// How long after a change further ones are waited for, as editors and
+/// installers write a file in several steps
+pub const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// What a file event touched
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    /// A spec or patch, or the spec directory itself
+    Specs,
+    /// The file the daemon was started from
+    Executable,
+}
+
+/// The watches on the spec directory and the executable; dropping it stops
+/// them
+pub struct Watch {
+    watcher: RecommendedWatcher,
+    specs: Option<PathBuf>,
+}
+
+impl Watch {
+    /// Watch the spec directory `specs` and the file `executable`, sending
+    /// what each event touched to the returned receiver
+    pub fn start(
+        specs: Option<PathBuf>,
+        executable: Option<PathBuf>,
+    ) -> notify::Result<(Self, UnboundedReceiver<Change>)> {
+        let (changed, changes) = unbounded_channel();
+        let (spec_dir, exe) = (specs.clone(), executable.clone());
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            let Ok(event) = event else {
+                return;
+            };
+            if matches!(event.kind, EventKind::Access(_)) {
+                return;
+            }
+            for path in &event.paths {
+                let change = if exe.as_deref() == Some(path.as_path()) {
+                    Change::Executable
+                } else if spec_dir.as_deref().is_some_and(|dir| path.starts_with(dir)) {
+                    Change::Specs
+                } else {
+                    continue;
+                };
+                let _ = changed.send(change);
+            }
+        })?;
+        let parents = specs
+            .iter()
+            .chain(&executable)
+            .filter_map(|path| path.parent());
+        for dir in parents {
+            if dir.is_dir() {
+                watcher.watch(dir, RecursiveMode::NonRecursive)?;
+            }
+        }
+        let mut watch = Self { watcher, specs };
+        watch.refresh();
+        Ok((watch, changes))
+    }
+
+    /// Watch the spec directory if it exists; called again after it
+    /// changed, as it may have been created or replaced since
+    pub fn refresh(&mut self) {
+        if let Some(dir) = self.specs.as_deref().filter(|dir| dir.is_dir()) {
+            let _ = self.watcher.watch(dir, RecursiveMode::NonRecursive);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    /// The next change, or `None` if there is none within a few seconds
+    fn next(changes: &mut UnboundedReceiver<Change>) -> Option<Change> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            tokio::time::timeout(Duration::from_secs(5), changes.recv())
+                .await
+                .ok()
+                .flatten()
+        })
+    }
+
+    fn drain(changes: &mut UnboundedReceiver<Change>) {
+        std::thread::sleep(DEBOUNCE);
+        while changes.try_recv().is_ok() {}
+    }
+
+    #[test]
+    fn test_watch_spec_edits_report_specs() {
+        let dir = TempDir::new();
+        dir.write("specs/git.json", "{}");
+        let specs = dir.path().join("specs");
+        let (_watch, mut changes) = Watch::start(Some(specs), None).unwrap();
+        dir.write("specs/git.json", r#"{"name": "git"}"#);
+        assert_eq!(next(&mut changes), Some(Change::Specs));
+    }
+
+    #[test]
+    fn test_watch_missing_spec_dir_is_watched_once_created() {
+        let dir = TempDir::new();
+        let specs = dir.path().join("specs");
+        let (mut watch, mut changes) = Watch::start(Some(specs.clone()), None).unwrap();
+        std::fs::create_dir(&specs).unwrap();
+        assert_eq!(next(&mut changes), Some(Change::Specs));
+        watch.refresh();
+        drain(&mut changes);
+        dir.write("specs/cargo.json", "{}");
+        assert_eq!(next(&mut changes), Some(Change::Specs));
+    }
+
+    #[test]
+    fn test_watch_replaced_executable_reports_executable() {
+        let dir = TempDir::new();
+        dir.write("bin/autocomplete-rs", "old");
+        dir.write("bin/other", "");
+        let exe = dir.path().join("bin/autocomplete-rs");
+        let (_watch, mut changes) = Watch::start(None, Some(exe.clone())).unwrap();
+        // Neighbours of the executable are no change
+        dir.write("bin/other", "changed");
+        dir.write("bin/autocomplete-rs.new", "new");
+        std::fs::rename(dir.path().join("bin/autocomplete-rs.new"), &exe).unwrap();
+        assert_eq!(next(&mut changes), Some(Change::Executable));
+    }
+}
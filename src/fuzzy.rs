@@ -0,0 +1,376 @@
+//! Fuzzy matching for large candidate sets.
+//!
+//! File and history generators can produce tens of thousands of candidates,
+//! and filtering has to finish within a keystroke. Three things keep it fast:
+//!
+//! - A 64-bit character mask per query rejects most candidates before any
+//!   scoring: a candidate can only match if it contains every character
+//!   class the query does.
+//! - ASCII queries (the common case) are matched on bytes, with no
+//!   allocation per candidate.
+//! - Above [`PARALLEL_THRESHOLD`] candidates, scoring is split across
+//!   scoped threads.
+//!
+//! Matching is case-insensitive for ASCII letters. Run `cargo bench --bench
+//! fuzzy` to check the timings.
+
+use std::cmp::Reverse;
+
+/// Candidate count above which filtering is split across threads
+pub const PARALLEL_THRESHOLD: usize = 8192;
+
+const SCORE_MATCH: u32 = 16;
+const BONUS_CONSECUTIVE: u32 = 8;
+const BONUS_BOUNDARY: u32 = 10;
+const BONUS_PREFIX: u32 = 12;
+/// Gaps longer than this stop costing more
+const MAX_GAP_PENALTY: u32 = 10;
+
+/// A candidate that matched, by position in the input slice
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ranked {
+    pub index: usize,
+    pub score: u32,
+}
+
+/// A compiled query
+#[derive(Debug, Clone)]
+pub struct Matcher {
+    /// Folded query bytes, used when the query is ASCII
+    bytes: Vec<u8>,
+    /// Folded query characters, used otherwise
+    chars: Vec<char>,
+    ascii: bool,
+    mask: u64,
+}
+
+impl Matcher {
+    pub fn new(query: &str) -> Self {
+        let ascii = query.is_ascii();
+        Self {
+            bytes: query.bytes().map(|b| b.to_ascii_lowercase()).collect(),
+            chars: query.chars().map(|c| c.to_ascii_lowercase()).collect(),
+            ascii,
+            mask: char_mask(query),
+        }
+    }
+
+    /// Whether the query matches everything
+    pub fn is_empty(&self) -> bool {
+        self.chars.is_empty()
+    }
+
+    /// Score `candidate`, or `None` if the query isn't a subsequence of it
+    pub fn score(&self, candidate: &str) -> Option<u32> {
+        if self.is_empty() {
+            return Some(0);
+        }
+        if candidate.len() < self.bytes.len() || self.mask & !char_mask(candidate) != 0 {
+            return None;
+        }
+        if self.ascii {
+            score_units(&self.bytes, candidate.as_bytes())
+        } else {
+            let candidate: Vec<char> = candidate.chars().collect();
+            score_units(&self.chars, &candidate)
+        }
+    }
+}
+
+/// Indices and scores of the candidates matching `query`, best first.
+///
+/// Ties are broken by shorter key, then by original order.
+pub fn filter<T, F>(query: &str, candidates: &[T], key: F) -> Vec<Ranked>
+where
+    T: Sync,
+    F: Fn(&T) -> &str + Sync,
+{
+    filter_top(query, candidates, usize::MAX, key)
+}
+
+/// Like [`filter`], but only the best `limit` matches are kept.
+///
+/// With tens of thousands of matches, fully sorting them costs more than
+/// scoring; selecting the top `limit` first keeps this linear.
+pub fn filter_top<T, F>(query: &str, candidates: &[T], limit: usize, key: F) -> Vec<Ranked>
+where
+    T: Sync,
+    F: Fn(&T) -> &str + Sync,
+{
+    let matcher = Matcher::new(query);
+    let threads = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(candidates.len() / (PARALLEL_THRESHOLD / 2))
+        .max(1);
+
+    let mut ranked = if candidates.len() < PARALLEL_THRESHOLD || threads == 1 {
+        score_chunk(&matcher, candidates, 0, &key)
+    } else {
+        let chunk_size = candidates.len().div_ceil(threads);
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = candidates
+                .chunks(chunk_size)
+                .enumerate()
+                .map(|(i, chunk)| {
+                    let (matcher, key) = (&matcher, &key);
+                    scope.spawn(move || score_chunk(matcher, chunk, i * chunk_size, key))
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap_or_default())
+                .collect()
+        })
+    };
+
+    // Keys are unique thanks to the index, so unstable sorting is deterministic
+    let order = |r: &Ranked| (Reverse(r.score), key(&candidates[r.index]).len(), r.index);
+    if limit < ranked.len() {
+        if limit == 0 {
+            return Vec::new();
+        }
+        ranked.select_nth_unstable_by_key(limit - 1, order);
+        ranked.truncate(limit);
+    }
+    ranked.sort_unstable_by_key(order);
+    ranked
+}
+
+fn score_chunk<T, F>(matcher: &Matcher, chunk: &[T], offset: usize, key: &F) -> Vec<Ranked>
+where
+    F: Fn(&T) -> &str,
+{
+    chunk
+        .iter()
+        .enumerate()
+        .filter_map(|(i, item)| {
+            matcher.score(key(item)).map(|score| Ranked {
+                index: offset + i,
+                score,
+            })
+        })
+        .collect()
+}
+
+/// Bit per character class present in `text`: `a-z`, `0-9`, common
+/// separators, and one bucket for everything else
+fn char_mask(text: &str) -> u64 {
+    text.bytes()
+        .fold(0, |mask, b| mask | 1 << MASK_BITS[usize::from(b)])
+}
+
+/// Mask bit for every byte, built at compile time so the prefilter is a table lookup
+const MASK_BITS: [u8; 256] = {
+    let mut bits = [63u8; 256];
+    let mut b = 0;
+    while b < 256 {
+        let lower = (b as u8).to_ascii_lowercase();
+        bits[b] = match lower {
+            b'a'..=b'z' => lower - b'a',
+            b'0'..=b'9' => 26 + lower - b'0',
+            b'-' => 36,
+            b'_' => 37,
+            b'.' => 38,
+            b'/' => 39,
+            b' ' => 40,
+            // Non-ASCII lead and continuation bytes, punctuation
+            _ => 63,
+        };
+        b += 1;
+    }
+    bits
+};
+
+/// A code unit the scorer can work on: bytes for ASCII queries, chars otherwise
+trait Unit: Copy + Eq {
+    fn fold(self) -> Self;
+    fn is_separator(self) -> bool;
+    fn is_upper(self) -> bool;
+}
+
+impl Unit for u8 {
+    fn fold(self) -> Self {
+        self.to_ascii_lowercase()
+    }
+    fn is_separator(self) -> bool {
+        matches!(self, b'-' | b'_' | b'.' | b'/' | b' ' | b':' | b'=')
+    }
+    fn is_upper(self) -> bool {
+        self.is_ascii_uppercase()
+    }
+}
+
+impl Unit for char {
+    fn fold(self) -> Self {
+        self.to_ascii_lowercase()
+    }
+    fn is_separator(self) -> bool {
+        matches!(self, '-' | '_' | '.' | '/' | ' ' | ':' | '=')
+    }
+    fn is_upper(self) -> bool {
+        self.is_uppercase()
+    }
+}
+
+/// Find the tightest window containing `query` as a subsequence and score it.
+///
+/// A forward pass finds where the first match ends; a backward pass from
+/// there finds the latest start, so `git` in `gadget-git` scores the
+/// contiguous `git` rather than the scattered `g…i…t`.
+fn score_units<U: Unit>(query: &[U], candidate: &[U]) -> Option<u32> {
+    let mut qi = 0;
+    let mut end = 0;
+    for (ci, &unit) in candidate.iter().enumerate() {
+        if unit.fold() == query[qi] {
+            qi += 1;
+            if qi == query.len() {
+                end = ci;
+                break;
+            }
+        }
+    }
+    if qi < query.len() {
+        return None;
+    }
+
+    let mut start = end;
+    let mut qi = query.len();
+    for ci in (0..=end).rev() {
+        if candidate[ci].fold() == query[qi - 1] {
+            qi -= 1;
+            if qi == 0 {
+                start = ci;
+                break;
+            }
+        }
+    }
+
+    let mut score = 0u32;
+    let mut penalty = 0u32;
+    let mut qi = 0;
+    let mut last: Option<usize> = None;
+    for ci in start..=end {
+        if qi == query.len() {
+            break;
+        }
+        let unit = candidate[ci];
+        if unit.fold() != query[qi] {
+            continue;
+        }
+        score += SCORE_MATCH;
+        if ci == 0 {
+            score += BONUS_PREFIX;
+        } else if candidate[ci - 1].is_separator()
+            || (unit.is_upper() && !candidate[ci - 1].is_upper())
+        {
+            score += BONUS_BOUNDARY;
+        }
+        match last {
+            Some(prev) if prev + 1 == ci => score += BONUS_CONSECUTIVE,
+            Some(prev) => penalty += ((ci - prev - 1) as u32).min(MAX_GAP_PENALTY),
+            None => {}
+        }
+        last = Some(ci);
+        qi += 1;
+    }
+    // Prefer matches that cover more of the candidate
+    penalty += ((candidate.len() - (end - start + 1)) as u32).min(MAX_GAP_PENALTY) / 2;
+    Some(score.saturating_sub(penalty))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranked<'a>(query: &str, candidates: &[&'a str]) -> Vec<&'a str> {
+        filter(query, candidates, |candidate| candidate)
+            .into_iter()
+            .map(|ranked| candidates[ranked.index])
+            .collect()
+    }
+
+    #[test]
+    fn test_exact_before_prefix_before_subsequence() {
+        let candidates = ["gxixt", "legit", "git-lfs-extension", "github", "git"];
+        assert_eq!(
+            ranked("git", &candidates),
+            ["git", "github", "git-lfs-extension", "legit", "gxixt"]
+        );
+    }
+
+    #[test]
+    fn test_contiguous_match_wins_over_scattered() {
+        let matcher = Matcher::new("git");
+        assert!(matcher.score("gadget-git") > matcher.score("gadget-int"));
+    }
+
+    #[test]
+    fn test_word_boundaries_score_higher() {
+        let matcher = Matcher::new("fb");
+        assert!(matcher.score("foo-bar") > matcher.score("foobar"));
+        assert!(matcher.score("fooBar") > matcher.score("foobar"));
+    }
+
+    #[test]
+    fn test_case_folding() {
+        assert_eq!(
+            Matcher::new("readme").score("README.md"),
+            Matcher::new("README").score("readme.md")
+        );
+        assert!(Matcher::new("CaRgO").score("cargo.toml").is_some());
+        // Non-ASCII queries fold their ASCII letters only
+        assert!(Matcher::new("naïve").score("Naïve.txt").is_some());
+        assert!(Matcher::new("É").score("é").is_none());
+    }
+
+    #[test]
+    fn test_char_mask_prefilter() {
+        assert_eq!(char_mask("A"), char_mask("a"));
+        assert_eq!(char_mask("abc"), char_mask("cba"));
+        assert_eq!(char_mask("é"), char_mask("~"));
+        // A digit or separator the candidate lacks rules it out unscored
+        let matcher = Matcher::new("a1");
+        assert_ne!(matcher.mask & !char_mask("aaa"), 0);
+        assert_eq!(matcher.score("aaa"), None);
+        assert_eq!(Matcher::new("a/b").score("ab"), None);
+        // Every class present isn't enough: the order has to match too
+        assert_eq!(Matcher::new("ba").mask & !char_mask("ab"), 0);
+        assert_eq!(Matcher::new("ba").score("ab"), None);
+    }
+
+    #[test]
+    fn test_empty_query_matches_everything_in_order() {
+        assert_eq!(ranked("", &["bb", "a", "cc"]), ["a", "bb", "cc"]);
+        assert!(Matcher::new("").is_empty());
+    }
+
+    #[test]
+    fn test_filter_top_keeps_the_best() {
+        let candidates = ["gxixt", "legit", "github", "git"];
+        let top = filter_top("git", &candidates, 2, |candidate| candidate);
+        assert_eq!(top, filter("git", &candidates, |candidate| candidate)[..2]);
+        assert!(filter_top("git", &candidates, 0, |candidate| candidate).is_empty());
+    }
+
+    #[test]
+    fn test_parallel_filtering_matches_serial() {
+        let candidates: Vec<String> = (0..PARALLEL_THRESHOLD * 2)
+            .map(|i| format!("dir{}/file_{i}.rs", i % 7))
+            .collect();
+        let parallel = filter("d3f1", &candidates, String::as_str);
+        let matcher = Matcher::new("d3f1");
+        let mut serial: Vec<Ranked> = candidates
+            .iter()
+            .enumerate()
+            .filter_map(|(index, candidate)| {
+                matcher
+                    .score(candidate)
+                    .map(|score| Ranked { index, score })
+            })
+            .collect();
+        serial.sort_by_key(|r| (Reverse(r.score), candidates[r.index].len(), r.index));
+        assert!(!parallel.is_empty());
+        assert_eq!(parallel, serial);
+    }
+}
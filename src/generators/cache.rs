@@ -0,0 +1,111 @@
+//! Generator outputs kept for reuse.
+//!
+//! Completing `kubectl logs` asks for pods on every keystroke, and listing
+//! them can take a second. A successful run's output is kept for the
+//! generator's time to live and given to the requests that follow instead of
+//! running the command again. Failed runs aren't kept, so the next request
+//! tries again.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Outputs kept before the cache is reset
+const MAX_CACHED_OUTPUTS: usize = 256;
+
+/// What an output is kept under
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    /// The command being completed
+    pub command: String,
+    /// The working directory, for generators whose output depends on it
+    pub cwd: Option<PathBuf>,
+    /// The generator's cache key, or its script
+    pub generator: String,
+}
+
+/// How long an output is reused, and what happens once it expired
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CachePolicy {
+    pub ttl: Duration,
+    /// Give the expired output while the generator runs again, rather than
+    /// waiting for the run
+    pub stale_while_revalidate: bool,
+}
+
+/// A kept output, as found in the cache
+#[derive(Debug, PartialEq, Eq)]
+pub enum Lookup {
+    /// Usable as it is
+    Hit(String),
+    /// Expired, for a stale-while-revalidate generator; usable while the
+    /// caller runs it again
+    Stale(String),
+    /// Not kept, or expired and to be waited for
+    Missing,
+}
+
+#[derive(Debug)]
+struct Cached {
+    output: String,
+    stored: Instant,
+    /// A run to replace the output has started
+    refreshing: bool,
+}
+
+/// Outputs of successful generator runs
+#[derive(Debug, Default)]
+pub struct OutputCache {
+    outputs: Mutex<HashMap<CacheKey, Cached>>,
+}
+
+impl OutputCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The output kept under `key`, if `policy` still allows using it
+    pub fn lookup(&self, key: &CacheKey, policy: CachePolicy) -> Lookup {
+        let mut outputs = self.outputs.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(cached) = outputs.get_mut(key) else {
+            return Lookup::Missing;
+        };
+        if cached.stored.elapsed() < policy.ttl {
+            return Lookup::Hit(cached.output.clone());
+        }
+        if !policy.stale_while_revalidate {
+            outputs.remove(key);
+            return Lookup::Missing;
+        }
+        if cached.refreshing {
+            // Someone is already running it again
+            return Lookup::Hit(cached.output.clone());
+        }
+        cached.refreshing = true;
+        Lookup::Stale(cached.output.clone())
+    }
+
+    /// Keep `output` under `key`
+    pub fn store(&self, key: CacheKey, output: String) {
+        let mut outputs = self.outputs.lock().unwrap_or_else(|e| e.into_inner());
+        if outputs.len() >= MAX_CACHED_OUTPUTS && !outputs.contains_key(&key) {
+            outputs.clear();
+        }
+        let cached = Cached {
+            output,
+            stored: Instant::now(),
+            refreshing: false,
+        };
+        outputs.insert(key, cached);
+    }
+
+    /// Let the next request run the generator of `key` again after a
+    /// background run failed
+    pub fn refresh_failed(&self, key: &CacheKey) {
+        let mut outputs = self.outputs.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(cached) = outputs.get_mut(key) {
+            cached.refreshing = false;
+        }
+    }
+}
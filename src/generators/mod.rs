@@ -0,0 +1,311 @@
+//! Execution of spec generators (external commands that produce suggestions).
+//!
+//! Generators run on a bounded pool: at most `generator_concurrency` run at
+//! once and the rest wait for a slot. Each run gets its own process group
+//! and a deadline covering both the wait and the run itself. When the
+//! deadline passes, the whole group is killed, so a hung command (or
+//! anything it spawned) can't outlive the request. The leader is reaped by
+//! tokio, so timed-out runs don't leave zombies behind in the daemon.
+//!
+//! The deadline defaults to `generator_timeout_ms`, but can be set per
+//! [`GeneratorFamily`], since listing git branches should take milliseconds
+//! while asking a remote Kubernetes cluster can take seconds. A spec may
+//! give one generator its own, which only a family's setting overrides.
+//!
+//! Outputs are kept in an [`OutputCache`] shared by the pool's clones, so a
+//! generator run again within its time to live isn't run at all.
+//!
+//! When the daemon shuts down, [`GeneratorPool::shut_down`] kills the groups
+//! still running, background refreshes included, and starts no more.
+
+mod cache;
+
+pub use cache::{CacheKey, CachePolicy, OutputCache};
+
+use cache::Lookup;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::process::Command;
+use tokio::sync::Semaphore;
+use tracing::warn;
+
+/// Why a generator produced no output
+#[derive(Debug, Error)]
+pub enum GeneratorError {
+    #[error("generator has no command")]
+    Empty,
+    #[error("the daemon is shutting down")]
+    ShuttingDown,
+    #[error("generator timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("failed to spawn `{program}`: {source}")]
+    Spawn {
+        program: String,
+        source: std::io::Error,
+    },
+    #[error("`{program}` exited with {status}: {stderr}")]
+    Failed {
+        program: String,
+        status: std::process::ExitStatus,
+        stderr: String,
+    },
+}
+
+/// Groups of generators with their own timeout, keyed by the tool they run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GeneratorFamily {
+    /// `git`
+    Git,
+    /// `docker`, `docker-compose`, `podman`
+    Docker,
+    /// `kubectl`, `helm`, `oc`
+    Kubernetes,
+}
+
+impl GeneratorFamily {
+    /// Family of a generator running `program`
+    pub fn of(program: &str) -> Option<Self> {
+        let name = program.rsplit('/').next().unwrap_or(program);
+        match name {
+            "git" => Some(Self::Git),
+            "docker" | "docker-compose" | "podman" | "podman-compose" => Some(Self::Docker),
+            "kubectl" | "helm" | "oc" | "kubectx" | "kubens" => Some(Self::Kubernetes),
+            _ => None,
+        }
+    }
+}
+
+/// Bounded pool that runs generator commands
+#[derive(Debug, Clone)]
+pub struct GeneratorPool {
+    permits: Arc<Semaphore>,
+    /// Timeout of generators outside any family with its own
+    timeout: Duration,
+    family_timeouts: BTreeMap<GeneratorFamily, Duration>,
+    outputs: Arc<OutputCache>,
+    /// Process groups of the generators running
+    running: Arc<Mutex<HashSet<u32>>>,
+}
+
+impl GeneratorPool {
+    pub fn new(concurrency: usize, timeout: Duration) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(concurrency.max(1))),
+            timeout,
+            family_timeouts: BTreeMap::new(),
+            outputs: Arc::new(OutputCache::new()),
+            running: Arc::default(),
+        }
+    }
+
+    /// Kill the generators running and refuse to start any more
+    pub fn shut_down(&self) {
+        self.permits.close();
+        let running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+        for &pid in running.iter() {
+            kill_group(pid);
+        }
+    }
+
+    /// Count the process group led by `pid` among the running ones, so it
+    /// is killed on shutdown or when the returned guard is dropped
+    pub fn track(&self, pid: Option<u32>) -> ProcessGroup {
+        ProcessGroup::new(pid, &self.running)
+    }
+
+    /// Use `timeouts` instead of the default for generators of those families
+    pub fn with_family_timeouts(mut self, timeouts: BTreeMap<GeneratorFamily, Duration>) -> Self {
+        self.family_timeouts = timeouts;
+        self
+    }
+
+    /// Deadline for a generator running `argv`, `own` being the one its
+    /// spec gives it
+    pub fn timeout_for(&self, argv: &[&str], own: Option<Duration>) -> Duration {
+        // `sh -c "git branch"` belongs to the family of the command it runs
+        let program = match argv {
+            ["sh", "-c", script, ..] => script.split_whitespace().next().unwrap_or_default(),
+            [program, ..] => program,
+            [] => "",
+        };
+        GeneratorFamily::of(program)
+            .and_then(|family| self.family_timeouts.get(&family).copied())
+            .or(own)
+            .unwrap_or(self.timeout)
+    }
+
+    /// Run `argv` in `cwd` and return its standard output, within `own`
+    /// unless its family has a timeout of its own
+    pub async fn run_within(
+        &self,
+        argv: &[&str],
+        cwd: Option<&Path>,
+        own: Option<Duration>,
+    ) -> Result<String, GeneratorError> {
+        let timeout = self.timeout_for(argv, own);
+        let (program, args) = argv.split_first().ok_or(GeneratorError::Empty)?;
+        match tokio::time::timeout(timeout, self.run_unbounded(program, args, cwd)).await {
+            Ok(result) => result,
+            Err(_) => Err(GeneratorError::Timeout(timeout)),
+        }
+    }
+
+    /// Run `argv` like [`run_within`](Self::run_within), unless its output
+    /// kept under `key` may still be used under `policy`; also tells whether
+    /// the output was reused
+    pub async fn run_cached(
+        &self,
+        key: CacheKey,
+        policy: CachePolicy,
+        argv: &[&str],
+        cwd: Option<&Path>,
+        own: Option<Duration>,
+    ) -> Result<(String, bool), GeneratorError> {
+        if policy.ttl.is_zero() {
+            return Ok((self.run_within(argv, cwd, own).await?, false));
+        }
+        match self.outputs.lookup(&key, policy) {
+            Lookup::Hit(output) => return Ok((output, true)),
+            Lookup::Stale(output) => {
+                self.refresh(key, argv, cwd, own);
+                return Ok((output, true));
+            }
+            Lookup::Missing => {}
+        }
+        let output = self.run_within(argv, cwd, own).await?;
+        self.outputs.store(key, output.clone());
+        Ok((output, false))
+    }
+
+    /// Run `argv` in the background and keep its output under `key`
+    fn refresh(&self, key: CacheKey, argv: &[&str], cwd: Option<&Path>, own: Option<Duration>) {
+        let pool = self.clone();
+        let argv: Vec<String> = argv.iter().map(|arg| arg.to_string()).collect();
+        let cwd = cwd.map(Path::to_path_buf);
+        tokio::spawn(async move {
+            let args: Vec<&str> = argv.iter().map(String::as_str).collect();
+            match pool.run_within(&args, cwd.as_deref(), own).await {
+                Ok(output) => pool.outputs.store(key, output),
+                Err(e) => {
+                    warn!("Generator `{}` failed: {}", argv.join(" "), e);
+                    pool.outputs.refresh_failed(&key);
+                }
+            }
+        });
+    }
+
+    async fn run_unbounded(
+        &self,
+        program: &str,
+        args: &[&str],
+        cwd: Option<&Path>,
+    ) -> Result<String, GeneratorError> {
+        // The semaphore is only closed on shutdown
+        let _permit = self
+            .permits
+            .acquire()
+            .await
+            .map_err(|_| GeneratorError::ShuttingDown)?;
+
+        let mut command = Command::new(program);
+        command
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(cwd) = cwd {
+            command.current_dir(cwd);
+        }
+        #[cfg(unix)]
+        command.process_group(0);
+
+        let child = command.spawn().map_err(|source| GeneratorError::Spawn {
+            program: program.to_string(),
+            source,
+        })?;
+        let group = self.track(child.id());
+
+        let output = child
+            .wait_with_output()
+            .await
+            .map_err(|source| GeneratorError::Spawn {
+                program: program.to_string(),
+                source,
+            })?;
+        group.disarm();
+
+        if !output.status.success() {
+            return Err(GeneratorError::Failed {
+                program: program.to_string(),
+                status: output.status,
+                stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            });
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+/// Kills the generator's process group when dropped before the run finished,
+/// which is what happens when the timeout cancels the future, and keeps it
+/// among the pool's running ones until then
+pub struct ProcessGroup {
+    pid: Option<u32>,
+    running: Arc<Mutex<HashSet<u32>>>,
+}
+
+impl ProcessGroup {
+    fn new(pid: Option<u32>, running: &Arc<Mutex<HashSet<u32>>>) -> Self {
+        if let Some(pid) = pid {
+            running
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(pid);
+        }
+        Self {
+            pid,
+            running: Arc::clone(running),
+        }
+    }
+
+    /// Forget the group once its leader exited, without killing it
+    pub fn disarm(mut self) {
+        if let Some(pid) = self.pid.take() {
+            self.forget(pid);
+        }
+    }
+
+    fn forget(&self, pid: u32) {
+        self.running
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&pid);
+    }
+}
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        if let Some(pid) = self.pid {
+            self.forget(pid);
+            kill_group(pid);
+        }
+    }
+}
+
+/// Kill the process group led by `pid`
+fn kill_group(pid: u32) {
+    #[cfg(unix)]
+    if let Ok(pgid) = libc::pid_t::try_from(pid) {
+        // SAFETY: killpg has no memory-safety preconditions; the group was
+        // created for a generator, so nothing else is signalled
+        unsafe {
+            libc::killpg(pgid, libc::SIGKILL);
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Localized description text.
+//!
+//! Spec descriptions may be written either as a plain string (English) or as a
+//! table keyed by locale:
+//!
+//! ```json
+//! { "en": "Switch branches", "de": "Branches wechseln", "pt_BR": "Trocar de branch" }
+//! ```
+//!
+//! Lookups try the exact locale (`pt_BR`), then its language (`pt`), then
+//! English.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+
+/// Locale used when nothing more specific is available
+pub const DEFAULT_LOCALE: &str = "en";
+
+/// Text with optional per-locale translations
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LocalizedText {
+    /// English text, used as the final fallback
+    pub default: String,
+    /// Translations keyed by locale (`de`, `pt_BR`, ...)
+    pub translations: BTreeMap<String, String>,
+}
+
+impl LocalizedText {
+    /// Resolve the text for `locale`, falling back to the language and then English
+    pub fn get(&self, locale: &str) -> &str {
+        let locale = normalize(locale);
+        if let Some(text) = self.translations.get(&locale) {
+            return text;
+        }
+        locale
+            .split_once('_')
+            .and_then(|(language, _)| self.translations.get(language))
+            .unwrap_or(&self.default)
+    }
+}
+
+impl From<&str> for LocalizedText {
+    fn from(text: &str) -> Self {
+        Self {
+            default: text.to_string(),
+            translations: BTreeMap::new(),
+        }
+    }
+}
+
+impl From<String> for LocalizedText {
+    fn from(text: String) -> Self {
+        Self {
+            default: text,
+            translations: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum RawText {
+    Plain(String),
+    Localized(BTreeMap<String, String>),
+}
+
+impl Serialize for LocalizedText {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.translations.is_empty() {
+            return serializer.serialize_str(&self.default);
+        }
+        let mut map = self.translations.clone();
+        map.insert(DEFAULT_LOCALE.to_string(), self.default.clone());
+        RawText::Localized(map).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for LocalizedText {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match RawText::deserialize(deserializer)? {
+            RawText::Plain(text) => text.into(),
+            RawText::Localized(map) => {
+                let mut translations: BTreeMap<String, String> = map
+                    .into_iter()
+                    .map(|(locale, text)| (normalize(&locale), text))
+                    .collect();
+                let default = translations.remove(DEFAULT_LOCALE).unwrap_or_default();
+                Self {
+                    default,
+                    translations,
+                }
+            }
+        })
+    }
+}
+
+/// Normalize a locale identifier: `de-DE.UTF-8` → `de_DE`
+pub fn normalize(locale: &str) -> String {
+    let locale = locale.split(['.', '@']).next().unwrap_or_default();
+    locale.replace('-', "_")
+}
+
+/// Locale of the current process from `LC_ALL`, `LC_MESSAGES`, or `LANG`
+pub fn env_locale() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .map(|value| normalize(&value))
+        .filter(|locale| locale != "C" && locale != "POSIX")
+}
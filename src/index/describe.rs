@@ -0,0 +1,286 @@
+//! One-line summaries of commands, for suggestions that come without a
+//! description.
+//!
+//! Summaries come from, in order: a small dataset bundled with the binary,
+//! the local tldr-pages cache of a tldr client, and the NAME section of the
+//! command's man page. Reading pages (and decompressing man pages) is too
+//! slow for the request path, so lookups happen in the background and their
+//! results, misses included, are cached on disk. A cache an administrator
+//! provisioned in a system data directory is consulted before looking up,
+//! and never written.
+
+use super::man_dirs;
+use crate::paths;
+use anyhow::{Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock, RwLock};
+
+/// `name<TAB>summary` lines for common commands
+const BUNDLED: &str = include_str!("../../data/tldr.tsv");
+
+/// Man pages larger than this are not read
+const MAX_MAN_PAGE_BYTES: u64 = 1024 * 1024;
+
+/// Summaries by command name (`git-push` for subcommands); `None` records a miss
+#[derive(Debug, Default)]
+pub struct Descriptions {
+    cache: RwLock<HashMap<String, Option<String>>>,
+    /// Summaries from the system caches, below the user's
+    shared: HashMap<String, Option<String>>,
+    /// Names with a lookup in flight
+    pending: Mutex<HashSet<String>>,
+    /// Whether the cache changed since it was last saved
+    dirty: AtomicBool,
+}
+
+impl Descriptions {
+    /// Default location of the persisted cache
+    pub fn default_path() -> Option<PathBuf> {
+        paths::cache_dir().map(|dir| dir.join("descriptions.json"))
+    }
+
+    /// Load a persisted cache, starting empty if it is missing or unreadable,
+    /// along with the system ones
+    pub fn load(path: &Path) -> Self {
+        let mut shared = HashMap::new();
+        // The most important directory comes first, so it is read last
+        for dir in paths::system_data_dirs().iter().rev() {
+            shared.extend(read_cache(&dir.join("descriptions.json")));
+        }
+        Self {
+            cache: RwLock::new(read_cache(path)),
+            shared,
+            ..Self::default()
+        }
+    }
+
+    /// Write the cache to `path` if anything was looked up since the last save
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_vec(&*self.cache.read().unwrap_or_else(|e| e.into_inner()))?;
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, json)
+            .with_context(|| format!("Failed to write descriptions: {}", tmp.display()))?;
+        std::fs::rename(&tmp, path)
+            .with_context(|| format!("Failed to write descriptions: {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Summary of `name` if it is known without reading any files.
+    ///
+    /// The outer `None` means a [`lookup`](Self::lookup) is needed; the inner
+    /// one that there is no summary to be found.
+    pub fn cached(&self, name: &str) -> Option<Option<String>> {
+        if let Some(summary) = bundled().get(name) {
+            return Some(Some(summary.to_string()));
+        }
+        if let Some(summary) = self
+            .cache
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(name)
+        {
+            return Some(summary.clone());
+        }
+        self.shared.get(name).cloned()
+    }
+
+    /// Claim the lookup for `name`; false if another one is already running
+    pub fn start_lookup(&self, name: &str) -> bool {
+        self.pending
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(name.to_string())
+    }
+
+    /// Find the summary of `name` in tldr pages or man pages and cache it
+    pub fn lookup(&self, name: &str) {
+        let summary = tldr_summary(name).or_else(|| man_summary(name));
+        self.cache
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(name.to_string(), summary);
+        self.dirty.store(true, Ordering::Relaxed);
+        self.pending
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(name);
+    }
+}
+
+/// Summaries saved at `path`, none if it is missing or unreadable
+fn read_cache(path: &Path) -> HashMap<String, Option<String>> {
+    std::fs::read(path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+fn bundled() -> &'static HashMap<&'static str, &'static str> {
+    static TABLE: OnceLock<HashMap<&'static str, &'static str>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        BUNDLED
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .filter_map(|line| line.split_once('\t'))
+            .collect()
+    })
+}
+
+/// Directories where tldr clients keep their copy of tldr-pages
+fn tldr_roots() -> Vec<PathBuf> {
+    let mut roots: Vec<PathBuf> = std::env::var_os("TLDR_CACHE_DIR")
+        .map(|dir| PathBuf::from(dir).join("pages"))
+        .into_iter()
+        .collect();
+    if let Some(cache) = paths::cache_dir().and_then(|dir| Some(dir.parent()?.to_path_buf())) {
+        // tealdeer, tldr-c / tlrc
+        roots.push(cache.join("tealdeer/tldr-pages/pages.en"));
+        roots.push(cache.join("tealdeer/tldr-master/pages"));
+        roots.push(cache.join("tldr/pages"));
+        roots.push(cache.join("tlrc/pages.en"));
+    }
+    if let Some(home) = paths::home_dir() {
+        // The Node.js client
+        roots.push(home.join(".tldr/cache/pages"));
+        roots.push(home.join(".tldrc/tldr/pages"));
+    }
+    roots
+}
+
+/// The `> Summary.` line of a tldr page
+fn tldr_summary(name: &str) -> Option<String> {
+    let platform = if cfg!(target_os = "macos") {
+        "osx"
+    } else {
+        "linux"
+    };
+    let file = format!("{name}.md");
+    let page = tldr_roots().into_iter().find_map(|root| {
+        ["common", platform]
+            .iter()
+            .find_map(|dir| std::fs::read_to_string(root.join(dir).join(&file)).ok())
+    })?;
+    let line = page.lines().find_map(|line| line.strip_prefix("> "))?;
+    Some(tidy(line))
+}
+
+/// The description from the NAME section of `name`'s man page
+fn man_summary(name: &str) -> Option<String> {
+    parse_name_section(&man_page(name)?)
+}
+
+/// Roff source of `name`'s section 1 or 8 man page, decompressed
+pub fn man_page(name: &str) -> Option<String> {
+    let path = man_dirs().into_iter().find_map(|dir| {
+        ["1", "8"].iter().find_map(|section| {
+            let base = dir
+                .join(format!("man{section}"))
+                .join(format!("{name}.{section}"));
+            [base.with_extension(format!("{section}.gz")), base]
+                .into_iter()
+                .find(|path| path.is_file())
+        })
+    })?;
+    if std::fs::metadata(&path).ok()?.len() > MAX_MAN_PAGE_BYTES {
+        return None;
+    }
+    if path.extension().is_some_and(|ext| ext == "gz") {
+        let output = std::process::Command::new("gzip")
+            .arg("-dc")
+            .arg(&path)
+            .output()
+            .ok()?;
+        Some(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        std::fs::read_to_string(&path).ok()
+    }
+}
+
+/// Summary from roff source: mdoc's `.Nd` line, or the text after `\-` in
+/// the man macros' NAME section
+pub fn parse_name_section(source: &str) -> Option<String> {
+    if let Some(line) = source.lines().find_map(|line| line.strip_prefix(".Nd ")) {
+        return Some(tidy(&unroff(line)));
+    }
+
+    let mut lines = source.lines().skip_while(|line| !is_name_heading(line));
+    lines.next()?;
+    let mut text = String::new();
+    for line in lines {
+        if line.starts_with(".SH") || line.starts_with(".Sh") {
+            break;
+        }
+        // Macro lines contribute their arguments: `.B git-push`, `.IX ...` aside
+        let words = match line.strip_prefix('.') {
+            Some(line) if line.starts_with("IX") || line.starts_with('\\') => continue,
+            Some(line) => line.split_once(' ').map_or("", |(_, args)| args),
+            None => line,
+        };
+        text.push(' ');
+        text.push_str(words);
+    }
+    let text = unroff(&text);
+    let (_, summary) = text.split_once(" - ")?;
+    Some(tidy(summary))
+}
+
+fn is_name_heading(line: &str) -> bool {
+    line.strip_prefix(".SH")
+        .or_else(|| line.strip_prefix(".Sh"))
+        .is_some_and(|title| title.trim().trim_matches('"').eq_ignore_ascii_case("name"))
+}
+
+/// Plain text from roff: escapes and font changes removed, whitespace collapsed
+pub fn unroff(text: &str) -> String {
+    let mut out = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('-') => out.push('-'),
+            Some('e') | Some('\\') => out.push('\\'),
+            // Font changes: `\fB`, `\f(CW`, `\f[I]`
+            Some('f') => match chars.next() {
+                Some('(') => {
+                    chars.nth(1);
+                }
+                Some('[') => while chars.next().is_some_and(|c| c != ']') {},
+                _ => {}
+            },
+            // Named characters: `\(em`, `\(aq`
+            Some('(') => {
+                let name: String = chars.by_ref().take(2).collect();
+                out.push_str(match name.as_str() {
+                    "aq" => "'",
+                    "dq" => "\"",
+                    "em" | "en" | "hy" => "-",
+                    _ => "",
+                });
+            }
+            // `\&`, `\/`, `\,` and other zero-width escapes
+            _ => {}
+        }
+    }
+    out.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Capitalized, without a trailing period, like the bundled summaries
+fn tidy(summary: &str) -> String {
+    let summary = summary.trim().trim_end_matches('.');
+    let mut chars = summary.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
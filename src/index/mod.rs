@@ -0,0 +1,228 @@
+//! Command index: executables on `PATH`, available specs, and man pages.
+//!
+//! Building the index means walking every `PATH` and man directory, which is
+//! too slow to do on the request path. The daemon builds it in the
+//! background, persists it to the cache directory, and loads the persisted
+//! copy on startup so cold starts don't touch the filesystem.
+
+use crate::paths;
+use crate::specs;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub mod describe;
+
+/// Man page directories searched when `MANPATH` is unset
+const DEFAULT_MAN_DIRS: &[&str] = &[
+    "/usr/share/man",
+    "/usr/local/share/man",
+    "/opt/homebrew/share/man",
+];
+
+/// Snapshot of the commands available on this machine.
+///
+/// The executable index follows the `PATH` of the latest client, since
+/// shells often extend the daemon's.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CommandIndex {
+    /// Unix timestamp (seconds) when the index was built
+    pub built_at: u64,
+    /// `PATH` value the executable index was built from
+    pub path_var: String,
+    /// Executable name → first matching path on `PATH`
+    pub executables: BTreeMap<String, PathBuf>,
+    /// Executables per `PATH` directory, to rescan only directories that changed
+    #[serde(default)]
+    pub path_dirs: BTreeMap<PathBuf, DirScan>,
+    /// Commands with a bundled or user spec
+    pub specs: BTreeSet<String>,
+    /// Commands with a section 1 or 8 man page
+    pub man_pages: BTreeSet<String>,
+}
+
+/// Executables found in one `PATH` directory
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DirScan {
+    /// Modification time of the directory when scanned, which changes when
+    /// an executable is installed or removed
+    pub modified: Option<SystemTime>,
+    pub names: BTreeSet<String>,
+}
+
+impl CommandIndex {
+    /// Scan the filesystem and build a fresh index
+    pub fn build(path_var: &str) -> Self {
+        let mut man_pages = BTreeSet::new();
+        for dir in man_dirs() {
+            for section in ["man1", "man8"] {
+                scan_man_pages(&dir.join(section), &mut man_pages);
+            }
+        }
+
+        let mut index = Self {
+            built_at: unix_now(),
+            specs: specs::available().into_iter().collect(),
+            man_pages,
+            ..Self::default()
+        };
+        index.scan_path(path_var);
+        index
+    }
+
+    /// Copy of the index for `path_var`, rescanning only the directories
+    /// that are new to it or changed since they were scanned; `None` if
+    /// nothing changed
+    pub fn with_path(&self, path_var: &str) -> Option<Self> {
+        let changed = path_var != self.path_var
+            || self
+                .path_dirs
+                .iter()
+                .any(|(dir, scan)| dir_modified(dir) != scan.modified);
+        if !changed {
+            return None;
+        }
+        let mut index = Self {
+            built_at: self.built_at,
+            path_dirs: self.path_dirs.clone(),
+            specs: self.specs.clone(),
+            man_pages: self.man_pages.clone(),
+            ..Self::default()
+        };
+        index.scan_path(path_var);
+        Some(index)
+    }
+
+    /// Copy of the index with the available specs listed afresh, after
+    /// user specs were added or removed
+    pub fn with_specs(&self) -> Self {
+        Self {
+            built_at: self.built_at,
+            path_var: self.path_var.clone(),
+            executables: self.executables.clone(),
+            path_dirs: self.path_dirs.clone(),
+            specs: specs::available().into_iter().collect(),
+            man_pages: self.man_pages.clone(),
+        }
+    }
+
+    /// Scan the `PATH` directories not already scanned at their current
+    /// modification time, dropping those no longer on it
+    fn scan_path(&mut self, path_var: &str) {
+        let dirs: Vec<PathBuf> = std::env::split_paths(path_var).collect();
+        let mut scanned = std::mem::take(&mut self.path_dirs);
+        for dir in &dirs {
+            let modified = dir_modified(dir);
+            let scan = match scanned.remove(dir) {
+                Some(scan) if scan.modified == modified => scan,
+                _ => DirScan {
+                    modified,
+                    names: scan_executables(dir),
+                },
+            };
+            self.path_dirs.insert(dir.clone(), scan);
+        }
+
+        // Earlier PATH entries win, like the shell's own lookup
+        self.executables.clear();
+        for dir in &dirs {
+            for name in &self.path_dirs[dir].names {
+                self.executables
+                    .entry(name.clone())
+                    .or_insert_with(|| dir.join(name));
+            }
+        }
+        self.path_var = path_var.to_string();
+    }
+
+    /// Default location of the persisted index
+    pub fn default_path() -> Option<PathBuf> {
+        paths::cache_dir().map(|dir| dir.join("index.msgpack"))
+    }
+
+    /// Load a persisted index, returning `None` when missing or unreadable
+    pub fn load(path: &Path) -> Option<Self> {
+        let bytes = std::fs::read(path).ok()?;
+        rmp_serde::from_slice(&bytes).ok()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let bytes = rmp_serde::to_vec(self)?;
+        let tmp = path.with_extension("msgpack.tmp");
+        std::fs::write(&tmp, bytes)
+            .with_context(|| format!("Failed to write index: {}", tmp.display()))?;
+        std::fs::rename(&tmp, path)
+            .with_context(|| format!("Failed to write index: {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Whether the index is older than `max_age`
+    pub fn is_stale(&self, max_age: Duration) -> bool {
+        unix_now().saturating_sub(self.built_at) >= max_age.as_secs()
+    }
+}
+
+/// Directories holding `man1`, `man8`, … section directories
+pub fn man_dirs() -> Vec<PathBuf> {
+    match std::env::var_os("MANPATH") {
+        Some(manpath) if !manpath.is_empty() => std::env::split_paths(&manpath).collect(),
+        _ => DEFAULT_MAN_DIRS.iter().map(PathBuf::from).collect(),
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+fn dir_modified(dir: &Path) -> Option<SystemTime> {
+    std::fs::metadata(dir).and_then(|meta| meta.modified()).ok()
+}
+
+/// Names of the executables in `dir`
+fn scan_executables(dir: &Path) -> BTreeSet<String> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return BTreeSet::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| is_executable(&entry.path()))
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect()
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    // Follows symlinks, so links into e.g. Homebrew's Cellar count
+    std::fs::metadata(path)
+        .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+fn scan_man_pages(dir: &Path, pages: &mut BTreeSet<String>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        // `git.1`, `git.1.gz`, `tar.1p` → command name before the section suffix
+        let name = name.strip_suffix(".gz").unwrap_or(&name);
+        if let Some((command, _section)) = name.rsplit_once('.') {
+            pages.insert(command.to_string());
+        }
+    }
+}
@@ -0,0 +1,336 @@
+//! Shell integration setup: rc-file snippets, shell detection, and services.
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub mod service;
+pub mod wizard;
+
+/// zsh integration script, served by `autocomplete-rs shell-init zsh`
+const ZSH_SCRIPT: &str = include_str!("../../shell-integration/zsh.zsh");
+
+/// Markers around the snippet we add to rc files
+pub const BLOCK_START: &str = "# >>> autocomplete-rs >>>";
+pub const BLOCK_END: &str = "# <<< autocomplete-rs <<<";
+
+/// Shells autocomplete-rs knows about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Zsh,
+    Bash,
+    Fish,
+}
+
+impl Shell {
+    pub const ALL: [Shell; 3] = [Shell::Zsh, Shell::Bash, Shell::Fish];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Shell::Zsh => "zsh",
+            Shell::Bash => "bash",
+            Shell::Fish => "fish",
+        }
+    }
+
+    /// Parse a shell name or path (`zsh`, `/bin/zsh`, `-zsh`)
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.rsplit('/').next().unwrap_or(name);
+        let name = name.trim_start_matches('-');
+        Shell::ALL.into_iter().find(|shell| shell.name() == name)
+    }
+
+    /// Whether an integration script exists for this shell yet
+    pub fn is_supported(self) -> bool {
+        self.integration_script().is_some()
+    }
+
+    /// Integration script sourced by the rc-file snippet
+    pub fn integration_script(self) -> Option<&'static str> {
+        match self {
+            Shell::Zsh => Some(ZSH_SCRIPT),
+            Shell::Bash | Shell::Fish => None,
+        }
+    }
+
+    /// Startup file the snippet is written to
+    pub fn rc_file(self) -> Option<PathBuf> {
+        let home = std::env::var_os("HOME").map(PathBuf::from)?;
+        Some(match self {
+            Shell::Zsh => std::env::var_os("ZDOTDIR")
+                .map(PathBuf::from)
+                .unwrap_or(home)
+                .join(".zshrc"),
+            Shell::Bash => home.join(".bashrc"),
+            Shell::Fish => std::env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| home.join(".config"))
+                .join("fish")
+                .join("config.fish"),
+        })
+    }
+
+    /// Marked block appended to the rc file
+    pub fn snippet(self) -> String {
+        let line = match self {
+            Shell::Fish => "autocomplete-rs shell-init fish | source".to_string(),
+            _ => format!("eval \"$(autocomplete-rs shell-init {})\"", self.name()),
+        };
+        format!("{}\n{}\n{}\n", BLOCK_START, line, BLOCK_END)
+    }
+}
+
+impl std::fmt::Display for Shell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The shell this process was started from.
+///
+/// Checks the parent process first (the shell running `autocomplete-rs`),
+/// then `$SHELL`, then the platform default (zsh on macOS, bash elsewhere).
+pub fn invoking_shell() -> Shell {
+    parent_process_name()
+        .and_then(|name| Shell::from_name(&name))
+        .or_else(|| {
+            std::env::var("SHELL")
+                .ok()
+                .and_then(|shell| Shell::from_name(&shell))
+        })
+        .unwrap_or(if cfg!(target_os = "macos") {
+            Shell::Zsh
+        } else {
+            Shell::Bash
+        })
+}
+
+#[cfg(unix)]
+fn parent_process_name() -> Option<String> {
+    let ppid = std::os::unix::process::parent_id();
+    // Linux exposes it directly; elsewhere ask `ps`
+    if let Ok(comm) = std::fs::read_to_string(format!("/proc/{}/comm", ppid)) {
+        return Some(comm.trim().to_string());
+    }
+    let output = std::process::Command::new("ps")
+        .args(["-o", "comm=", "-p", &ppid.to_string()])
+        .output()
+        .ok()?;
+    let name = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!name.is_empty()).then_some(name)
+}
+
+#[cfg(not(unix))]
+fn parent_process_name() -> Option<String> {
+    None
+}
+
+/// Shells the user appears to use: the login shell first, then any shell
+/// whose rc file exists
+pub fn detect_shells() -> Vec<Shell> {
+    let mut shells: Vec<Shell> = std::env::var("SHELL")
+        .ok()
+        .and_then(|shell| Shell::from_name(&shell))
+        .into_iter()
+        .collect();
+    for shell in Shell::ALL {
+        let has_rc = shell.rc_file().is_some_and(|rc| rc.exists());
+        if has_rc && !shells.contains(&shell) {
+            shells.push(shell);
+        }
+    }
+    shells
+}
+
+/// Result of adding the snippet to an rc file
+#[derive(Debug)]
+pub enum RcChange {
+    /// The marked block was already present
+    AlreadyInstalled,
+    /// The block was appended; `backup` holds the previous contents, if any
+    Installed { backup: Option<PathBuf> },
+}
+
+/// Whether `contents` already contains our marked block
+pub fn has_block(contents: &str) -> bool {
+    contents.lines().any(|line| line.trim() == BLOCK_START)
+}
+
+/// Append the shell's marked block to `rc`, backing up the existing file first
+pub fn add_block(shell: Shell, rc: &Path) -> Result<RcChange> {
+    let existing = match std::fs::read_to_string(rc) {
+        Ok(contents) => Some(contents),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to read {}", rc.display()));
+        }
+    };
+
+    if existing.as_deref().is_some_and(has_block) {
+        return Ok(RcChange::AlreadyInstalled);
+    }
+
+    let backup = match &existing {
+        Some(_) => Some(backup_file(rc)?),
+        None => None,
+    };
+
+    let mut contents = existing.unwrap_or_default();
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    if !contents.is_empty() {
+        contents.push('\n');
+    }
+    contents.push_str(&shell.snippet());
+
+    if let Some(parent) = rc.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(rc, contents).with_context(|| format!("Failed to write {}", rc.display()))?;
+
+    Ok(RcChange::Installed { backup })
+}
+
+/// Remove our marked block from `rc`, leaving the rest of the file untouched.
+///
+/// Returns the backup of the previous contents, or `None` if there was no
+/// block to remove.
+pub fn remove_block(rc: &Path) -> Result<Option<PathBuf>> {
+    let contents = match std::fs::read_to_string(rc) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to read {}", rc.display()));
+        }
+    };
+    if !has_block(&contents) {
+        return Ok(None);
+    }
+
+    let mut kept: Vec<&str> = Vec::new();
+    let mut in_block = false;
+    for line in contents.lines() {
+        match line.trim() {
+            BLOCK_START => {
+                in_block = true;
+                // Drop the blank separator line `add_block` put before the block
+                if kept.last().is_some_and(|last| last.trim().is_empty()) {
+                    kept.pop();
+                }
+            }
+            BLOCK_END if in_block => in_block = false,
+            _ if in_block => {}
+            _ => kept.push(line),
+        }
+    }
+    let mut updated = kept.join("\n");
+    if !updated.is_empty() {
+        updated.push('\n');
+    }
+
+    let backup = backup_file(rc)?;
+    std::fs::write(rc, updated).with_context(|| format!("Failed to write {}", rc.display()))?;
+    Ok(Some(backup))
+}
+
+/// Start an interactive `shell` that reads its rc file and check that the
+/// integration loaded
+pub fn verify(shell: Shell) -> Result<bool> {
+    let check = match shell {
+        Shell::Zsh => "(( $+functions[_autocomplete_rs_widget] ))",
+        Shell::Bash | Shell::Fish => anyhow::bail!("Cannot verify {} yet", shell),
+    };
+    let status = std::process::Command::new(shell.name())
+        .args(["-i", "-c", check])
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .with_context(|| format!("Failed to launch {}", shell))?;
+    Ok(status.success())
+}
+
+/// Copy `path` to a timestamped sibling (`.zshrc.autocomplete-rs-backup-<unix time>`),
+/// adding `-1`, `-2`... when a backup of the same second exists, so an
+/// earlier one is never overwritten
+pub fn backup_file(path: &Path) -> Result<PathBuf> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    let mut source = std::fs::File::open(path)
+        .with_context(|| format!("Failed to back up {}", path.display()))?;
+    for attempt in 0u32.. {
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(".autocomplete-rs-backup-{}", timestamp));
+        if attempt > 0 {
+            name.push(format!("-{}", attempt));
+        }
+        let backup = path.with_file_name(name);
+        let mut file = match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&backup)
+        {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to create {}", backup.display()));
+            }
+        };
+        std::io::copy(&mut source, &mut file)
+            .with_context(|| format!("Failed to back up {}", path.display()))?;
+        // Keep the rc file's permissions, as a copy would
+        if let Ok(metadata) = source.metadata() {
+            let _ = file.set_permissions(metadata.permissions());
+        }
+        return Ok(backup);
+    }
+    unreachable!("ran out of backup names for {}", path.display())
+}
+
+/// Script printed by `shell-init`, with the configured trigger key applied
+pub fn shell_init(shell: Shell, trigger: &str) -> Result<String> {
+    let script = shell.integration_script().with_context(|| {
+        format!(
+            "Unsupported shell: {}. Currently only 'zsh' is supported.",
+            shell
+        )
+    })?;
+    let trigger = trigger.replace('\'', r"'\''");
+    Ok(format!(
+        "[[ -z \"$AUTOCOMPLETE_RS_TRIGGER\" ]] && AUTOCOMPLETE_RS_TRIGGER='{}'\n{}",
+        trigger, script
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn test_backup_file_never_overwrites_an_earlier_backup() {
+        let dir = TempDir::new();
+        let rc = dir.path().join(".zshrc");
+        std::fs::write(&rc, "before install\n").unwrap();
+        let first = backup_file(&rc).unwrap();
+        std::fs::write(&rc, "after install\n").unwrap();
+        let second = backup_file(&rc).unwrap();
+        let third = backup_file(&rc).unwrap();
+
+        assert_ne!(first, second);
+        assert_ne!(second, third);
+        assert_eq!(std::fs::read_to_string(&first).unwrap(), "before install\n");
+        assert_eq!(std::fs::read_to_string(&second).unwrap(), "after install\n");
+        assert_eq!(std::fs::read_to_string(&third).unwrap(), "after install\n");
+    }
+
+    #[test]
+    fn test_backup_file_of_missing_file_fails() {
+        let dir = TempDir::new();
+        assert!(backup_file(&dir.path().join(".zshrc")).is_err());
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+}
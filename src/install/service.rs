@@ -0,0 +1,114 @@
+//! Login services that keep the daemon running: a systemd user unit on Linux
+//! and a launchd agent on macOS.
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const SYSTEMD_UNIT: &str = "autocomplete-rs.service";
+const LAUNCHD_LABEL: &str = "dev.autocomplete-rs.daemon";
+
+/// Location of the service definition for this platform
+pub fn unit_path() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from)?;
+    if cfg!(target_os = "macos") {
+        Some(
+            home.join("Library/LaunchAgents")
+                .join(format!("{}.plist", LAUNCHD_LABEL)),
+        )
+    } else {
+        let config = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| home.join(".config"));
+        Some(config.join("systemd/user").join(SYSTEMD_UNIT))
+    }
+}
+
+/// Write the service definition for `exe` and enable it
+pub fn install(exe: &Path) -> Result<PathBuf> {
+    let path = unit_path().context("Could not determine the home directory")?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    if cfg!(target_os = "macos") {
+        std::fs::write(&path, launchd_plist(exe))?;
+        run("launchctl", &["load", "-w", &path.to_string_lossy()])?;
+    } else {
+        std::fs::write(&path, systemd_unit(exe))?;
+        run("systemctl", &["--user", "daemon-reload"])?;
+        run("systemctl", &["--user", "enable", "--now", SYSTEMD_UNIT])?;
+    }
+
+    Ok(path)
+}
+
+/// Stop and disable the service and delete its definition, returning the
+/// removed file, or `None` if no service was installed
+pub fn uninstall() -> Result<Option<PathBuf>> {
+    let Some(path) = unit_path().filter(|path| path.exists()) else {
+        return Ok(None);
+    };
+
+    if cfg!(target_os = "macos") {
+        run("launchctl", &["unload", "-w", &path.to_string_lossy()])?;
+        std::fs::remove_file(&path)?;
+    } else {
+        run("systemctl", &["--user", "disable", "--now", SYSTEMD_UNIT])?;
+        std::fs::remove_file(&path)?;
+        run("systemctl", &["--user", "daemon-reload"])?;
+    }
+
+    Ok(Some(path))
+}
+
+fn systemd_unit(exe: &Path) -> String {
+    format!(
+        "[Unit]\n\
+         Description=autocomplete-rs completion daemon\n\
+         \n\
+         [Service]\n\
+         ExecStart={} daemon\n\
+         Restart=on-failure\n\
+         \n\
+         [Install]\n\
+         WantedBy=default.target\n",
+        exe.display()
+    )
+}
+
+fn launchd_plist(exe: &Path) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{}</string>
+    <key>ProgramArguments</key>
+    <array>
+        <string>{}</string>
+        <string>daemon</string>
+    </array>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <true/>
+</dict>
+</plist>
+"#,
+        LAUNCHD_LABEL,
+        exe.display()
+    )
+}
+
+fn run(program: &str, args: &[&str]) -> Result<()> {
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .with_context(|| format!("Failed to run {}", program))?;
+    if !status.success() {
+        anyhow::bail!("`{} {}` exited with {}", program, args.join(" "), status);
+    }
+    Ok(())
+}
@@ -0,0 +1,177 @@
+//! `autocomplete-rs init`: interactive first-run setup.
+
+use super::{RcChange, service};
+use crate::config::toml;
+use crate::daemon::CompletionRequest;
+use crate::tui::THEMES;
+use crate::{client, i18n, paths};
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Trigger keys offered by the wizard, as (label, zsh bindkey sequence)
+pub const TRIGGERS: [(&str, &str); 3] = [
+    ("Alt+Space", "^[ "),
+    ("Ctrl+Space", "^@"),
+    ("Tab (replaces zsh's default completion)", "^I"),
+];
+
+/// Run the setup wizard
+pub async fn run() -> Result<()> {
+    println!("autocomplete-rs setup");
+    println!();
+
+    // 1. Shell integration
+    let shells = super::detect_shells();
+    if shells.is_empty() {
+        println!("No supported shell detected (checked $SHELL and rc files).");
+    }
+    for shell in shells {
+        if !shell.is_supported() {
+            println!("Detected {}, which is not supported yet; skipping.", shell);
+            continue;
+        }
+        let Some(rc) = shell.rc_file() else {
+            continue;
+        };
+        let question = format!("Add autocomplete-rs to {}?", rc.display());
+        if !confirm(&question, true)? {
+            continue;
+        }
+        match super::add_block(shell, &rc)? {
+            RcChange::AlreadyInstalled => println!("  Already installed in {}", rc.display()),
+            RcChange::Installed { backup } => {
+                println!("  Updated {}", rc.display());
+                if let Some(backup) = backup {
+                    println!("  Backup saved to {}", backup.display());
+                }
+            }
+        }
+    }
+    println!();
+
+    // 2. Theme and trigger key
+    let theme = choose("Pick a theme:", &THEMES)?;
+    let trigger_labels: Vec<&str> = TRIGGERS.iter().map(|(label, _)| *label).collect();
+    let trigger = TRIGGERS[choose("Pick a trigger key:", &trigger_labels)?].1;
+    write_settings(THEMES[theme], trigger)?;
+    println!();
+
+    // 3. Daemon
+    let socket = paths::socket_path();
+    let exe = std::env::current_exe().context("Failed to locate the autocomplete-rs binary")?;
+    if confirm("Start the daemon automatically at login?", false)? {
+        match service::install(&exe) {
+            Ok(unit) => println!("  Installed service {}", unit.display()),
+            Err(e) => println!("  Could not install the service: {:#}", e),
+        }
+    }
+    if !client_can_connect(&socket).await {
+        println!("Starting the daemon...");
+        start_daemon(&exe, &socket).await?;
+    }
+
+    // 4. Live test
+    let request = CompletionRequest {
+        locale: i18n::env_locale(),
+        ..CompletionRequest::new("git ", 4)
+    };
+    let started = Instant::now();
+    let response = client::request(&socket, &request).await?;
+    println!(
+        "Test completion for `git ` returned {} suggestion(s) in {:.1?}",
+        response.suggestions.len(),
+        started.elapsed()
+    );
+    println!();
+    println!("All set! Open a new shell to start using autocomplete-rs.");
+
+    Ok(())
+}
+
+/// Persist theme and trigger choices into the config file
+pub fn write_settings(theme: &str, trigger: &str) -> Result<()> {
+    let path = paths::config_file().context("Could not determine the config directory")?;
+    let mut contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            "# autocomplete-rs configuration\n".to_string()
+        }
+        Err(e) => return Err(e).context(format!("Failed to read {}", path.display())),
+    };
+    contents = toml::set_value(&contents, "theme", "name", &Value::from(theme));
+    contents = toml::set_value(&contents, "keybindings", "trigger", &Value::from(trigger));
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, contents)?;
+    println!("  Saved settings to {}", path.display());
+    Ok(())
+}
+
+async fn client_can_connect(socket: &Path) -> bool {
+    tokio::net::UnixStream::connect(socket).await.is_ok()
+}
+
+/// Launch a detached daemon and wait for its socket to accept connections
+async fn start_daemon(exe: &Path, socket: &Path) -> Result<()> {
+    Command::new(exe)
+        .arg("daemon")
+        .arg("--socket")
+        .arg(socket)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("Failed to start the daemon")?;
+
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while Instant::now() < deadline {
+        if client_can_connect(socket).await {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    anyhow::bail!("Daemon did not start listening on {}", socket.display())
+}
+
+/// Ask a yes/no question on stdin
+pub fn confirm(question: &str, default: bool) -> Result<bool> {
+    let hint = if default { "[Y/n]" } else { "[y/N]" };
+    let answer = prompt(&format!("{} {} ", question, hint))?;
+    Ok(match answer.to_ascii_lowercase().as_str() {
+        "" => default,
+        "y" | "yes" => true,
+        _ => false,
+    })
+}
+
+/// Ask the user to pick one of `options`, returning its index (default: first)
+pub fn choose(question: &str, options: &[&str]) -> Result<usize> {
+    println!("{}", question);
+    for (i, option) in options.iter().enumerate() {
+        println!("  {}) {}", i + 1, option);
+    }
+    loop {
+        let answer = prompt(&format!("Choice [1-{}, default 1]: ", options.len()))?;
+        if answer.is_empty() {
+            return Ok(0);
+        }
+        match answer.parse::<usize>() {
+            Ok(n) if (1..=options.len()).contains(&n) => return Ok(n - 1),
+            _ => println!("Please enter a number between 1 and {}", options.len()),
+        }
+    }
+}
+
+fn prompt(text: &str) -> Result<String> {
+    print!("{}", text);
+    io::stdout().flush()?;
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim().to_string())
+}
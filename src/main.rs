@@ -1,10 +1,26 @@
 use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use tokio::net::UnixStream;
 
+mod client;
+mod config;
 mod daemon;
+mod fuzzy;
+mod generators;
+mod i18n;
+mod index;
+mod install;
 mod parser;
+mod paths;
+mod porcelain;
+mod report;
+mod specs;
+mod stats;
+#[cfg(test)]
+mod testing;
 mod tui;
 
 #[derive(Parser)]
@@ -19,69 +35,557 @@ struct Cli {
 enum Commands {
     /// Start the autocomplete daemon
     Daemon {
-        /// Unix socket path
-        #[arg(short, long, default_value = "/tmp/autocomplete-rs.sock")]
-        socket: String,
+        /// Unix socket path (defaults to $XDG_RUNTIME_DIR/autocomplete-rs.sock)
+        #[arg(short, long)]
+        socket: Option<PathBuf>,
+        /// Run no external commands, listen on no network endpoints, and
+        /// write nothing to disk; completions come from specs and history
+        #[arg(long)]
+        safe: bool,
+        /// Answer with the responses recorded in this JSON file instead of
+        /// completing, for testing shell integrations and the popup
+        #[arg(long, value_name = "FILE", conflicts_with = "safe")]
+        fixture: Option<PathBuf>,
     },
     /// Stop the running daemon
     Stop {
-        /// Unix socket path
-        #[arg(short, long, default_value = "/tmp/autocomplete-rs.sock")]
-        socket: String,
+        /// Unix socket path (defaults to $XDG_RUNTIME_DIR/autocomplete-rs.sock)
+        #[arg(short, long)]
+        socket: Option<PathBuf>,
     },
     /// Check daemon status
     Status {
-        /// Unix socket path
-        #[arg(short, long, default_value = "/tmp/autocomplete-rs.sock")]
-        socket: String,
+        /// Unix socket path (defaults to $XDG_RUNTIME_DIR/autocomplete-rs.sock)
+        #[arg(short, long)]
+        socket: Option<PathBuf>,
+        /// Print the status as JSON, with counts of bad requests and connections
+        #[arg(long)]
+        json: bool,
     },
     /// Get completion suggestions for a command buffer
     Complete {
         /// Command buffer to complete
         buffer: String,
-        /// Cursor position in the buffer
+        /// Cursor position in the buffer, in characters as shells count them
         #[arg(short, long)]
         cursor: usize,
-        /// Unix socket path
-        #[arg(short, long, default_value = "/tmp/autocomplete-rs.sock")]
-        socket: String,
+        /// Unix socket path (defaults to $XDG_RUNTIME_DIR/autocomplete-rs.sock)
+        #[arg(short, long)]
+        socket: Option<PathBuf>,
+        /// Identifier of the calling shell session
+        #[arg(long)]
+        session: Option<String>,
+        /// Last command run in the shell
+        #[arg(long)]
+        last_command: Option<String>,
+        /// Exit code of the last command run in the shell
+        #[arg(long, allow_negative_numbers = true)]
+        exit_code: Option<i32>,
+        /// Functions defined in the shell, separated by spaces
+        #[arg(long, value_delimiter = ' ')]
+        functions: Vec<String>,
+        /// The calling shell (zsh, bash, fish, pwsh, cmd), for its words, builtins and keywords
+        #[arg(long)]
+        shell: Option<String>,
+        /// Draw the popup on /dev/tty, keeping stdout for the selection only
+        #[arg(long)]
+        tty: bool,
+        /// List the suggestions as numbered plain text and read the number of
+        /// the choice from stdin, for screen readers and dumb terminals
+        #[arg(long, conflicts_with = "format")]
+        plain: bool,
+        /// Print every suggestion through this template instead of showing
+        /// the popup, e.g. 'text\tdesc' (fields: text, desc, source, insert)
+        #[arg(long, value_name = "TEMPLATE")]
+        format: Option<String>,
+        /// Pad or cut a field of --format to this many columns, e.g. text=30
+        #[arg(long, value_name = "FIELD=COLUMNS", requires = "format")]
+        width: Vec<String>,
+        /// End --format records with NUL instead of a newline
+        #[arg(short = 'z', long, requires = "format")]
+        null: bool,
+    },
+    /// Complete many command buffers in one round trip, JSON in and out
+    ///
+    /// Reads a JSON array of `[buffer, cursor]` pairs or request objects
+    /// (`{"buffer": "git ", "cursor": 4, ...}`) and prints the responses as
+    /// a JSON array in the same order.
+    CompleteBatch {
+        /// File with the requests (standard input when omitted)
+        file: Option<PathBuf>,
+        /// Unix socket path (defaults to $XDG_RUNTIME_DIR/autocomplete-rs.sock)
+        #[arg(short, long)]
+        socket: Option<PathBuf>,
+    },
+    /// Describe what the command line expects at the cursor, as JSON
+    ///
+    /// For prompts that show hints: whether the command exists, whether it
+    /// has a spec, and the kind of word expected next (`subcommand`, `flag`,
+    /// `value`, `path`, ...). No suggestions are generated.
+    Context {
+        /// Command buffer to describe
+        buffer: String,
+        /// Cursor position in the buffer (the end when omitted)
+        #[arg(short, long)]
+        cursor: Option<usize>,
+        /// Unix socket path (defaults to $XDG_RUNTIME_DIR/autocomplete-rs.sock)
+        #[arg(short, long)]
+        socket: Option<PathBuf>,
+        /// Identifier of the calling shell session
+        #[arg(long)]
+        session: Option<String>,
+        /// Functions defined in the shell, separated by spaces
+        #[arg(long, value_delimiter = ' ')]
+        functions: Vec<String>,
+        /// The calling shell (zsh, bash, fish, pwsh, cmd), for its words, builtins and keywords
+        #[arg(long)]
+        shell: Option<String>,
+    },
+    /// Show how the suggestions for a command line were ranked
+    ///
+    /// Lists the first page of suggestions with the signals each was ranked
+    /// by (spec order, fuzzy match, source, frecency) and the `[ranking]`
+    /// weights the daemon sums them with.
+    Explain {
+        /// Command buffer to complete
+        buffer: String,
+        /// Cursor position in the buffer (the end when omitted)
+        #[arg(short, long)]
+        cursor: Option<usize>,
+        /// Unix socket path (defaults to $XDG_RUNTIME_DIR/autocomplete-rs.sock)
+        #[arg(short, long)]
+        socket: Option<PathBuf>,
+        /// Identifier of the calling shell session
+        #[arg(long)]
+        session: Option<String>,
     },
     /// Install shell integration
     Install {
-        /// Shell to install for (zsh, bash, fish)
+        /// Shell to install for (zsh, bash, fish; detected when omitted)
+        shell: Option<String>,
+        /// Show what would change without touching any file
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Remove shell integration and the login service
+    Uninstall {
+        /// Also delete the config, cache and data directories
+        #[arg(long)]
+        purge: bool,
+    },
+    /// Print the integration script for a shell (used from rc files)
+    ShellInit {
+        /// Shell to print the script for (zsh; detected when omitted)
+        shell: Option<String>,
+    },
+    /// Interactive first-run setup
+    Init,
+    /// Preview the popup with sample suggestions, trying out themes, trigger
+    /// keys and layouts, and save the chosen settings
+    TuiTest,
+    /// Rebuild the command index (executables, specs, man pages)
+    Reindex {
+        /// Unix socket path (defaults to $XDG_RUNTIME_DIR/autocomplete-rs.sock)
+        #[arg(short, long)]
+        socket: Option<PathBuf>,
+    },
+    /// Restart the running daemon from its executable on disk, keeping its
+    /// socket, e.g. to pick up the specs of an upgrade
+    Restart {
+        /// Unix socket path (defaults to $XDG_RUNTIME_DIR/autocomplete-rs.sock)
+        #[arg(short, long)]
+        socket: Option<PathBuf>,
+    },
+    /// Print the daemon's events as JSON lines as they happen
+    Events {
+        /// Only these events (`accepted`, `unknown_command`); all by default
+        events: Vec<String>,
+        /// Unix socket path (defaults to $XDG_RUNTIME_DIR/autocomplete-rs.sock)
+        #[arg(short, long)]
+        socket: Option<PathBuf>,
+    },
+    /// Tell the daemon the shell changed to a directory, so `cd` can offer
+    /// it later (used from the shell integration's hook)
+    Visited {
+        /// The directory changed to (the current one when omitted)
+        dir: Option<PathBuf>,
+        /// Unix socket path (defaults to $XDG_RUNTIME_DIR/autocomplete-rs.sock)
+        #[arg(short, long)]
+        socket: Option<PathBuf>,
+        /// Identifier of the calling shell session
+        #[arg(long)]
+        session: Option<String>,
+    },
+    /// Collect a redacted diagnostic bundle to attach to bug reports
+    Report {
+        /// Where to write the archive (defaults to ./autocomplete-rs-report-<time>.tar)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Also record the daemon's response for this command buffer
+        #[arg(long)]
+        trace: Option<String>,
+        /// Unix socket path (defaults to $XDG_RUNTIME_DIR/autocomplete-rs.sock)
+        #[arg(short, long)]
+        socket: Option<PathBuf>,
+    },
+    /// Upgrade config and caches written by an older version
+    Migrate {
+        /// Show what would change without touching any file
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Inspect and validate the configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Manage the command usage data used for ranking
+    History {
+        #[command(subcommand)]
+        action: HistoryAction,
+    },
+    /// Report on locally collected usage statistics
+    Stats {
+        #[command(subcommand)]
+        action: StatsAction,
+    },
+    /// Inspect and validate completion specs
+    Spec {
+        #[command(subcommand)]
+        action: SpecAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum SpecAction {
+    /// List the commands with a spec and where each comes from
+    List {
+        /// Only list the user's specs and patches
+        #[arg(long)]
+        user: bool,
+    },
+    /// Print a command's spec as the daemon sees it, patches applied, or the
+    /// one read from its man page
+    Show {
+        /// Command whose spec to print
+        command: String,
+    },
+    /// Use the spec from SOURCE for a command that has several
+    ///
+    /// By default the user's own spec wins over an imported carapace one, a
+    /// carapace spec over a system one and a system spec over the bundled
+    /// one. `default` forgets the choice.
+    Prefer {
+        /// user, carapace, system, bundled, or default
+        source: String,
+        /// Command the choice is for
+        command: String,
+        /// Unix socket of the daemon to tell (defaults to $XDG_RUNTIME_DIR/autocomplete-rs.sock)
+        #[arg(short, long)]
+        socket: Option<PathBuf>,
+    },
+    /// Check a spec or patch file for errors and ignored fields
+    Validate {
+        /// Spec (<command>.json, .toml or .yaml) or patch (<command>.patch.json)
+        file: PathBuf,
+    },
+    /// Draft a spec for a command from its --help output
+    Generate {
+        /// Command to run with --help
+        command: String,
+        /// Levels of subcommands to ask for their own help
+        #[arg(short, long, default_value_t = 1)]
+        depth: usize,
+        /// Save it as the command's user spec instead of printing it
+        #[arg(short, long)]
+        write: bool,
+        /// Overwrite an existing user spec when writing
+        #[arg(short, long, requires = "write")]
+        force: bool,
+    },
+    /// Convert Fig specs (compiled .js, or .ts with Node 22.6+) into JSON
+    /// specs, such as private ones kept outside withfig/autocomplete
+    Convert {
+        /// Directory of Fig specs, `<command>.js` or `.ts` each, or a
+        /// checkout whose `build/` directory holds them
+        dir: PathBuf,
+        /// Where to write the specs (defaults to the user spec directory)
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+        /// Overwrite specs already there
+        #[arg(long)]
+        force: bool,
+    },
+    /// Compile a command's spec into a standalone shell completion script
+    Export {
+        /// Command whose spec to export
+        command: String,
+        /// Shell to write the script for (zsh, bash)
+        #[arg(short, long, default_value = "zsh")]
         shell: String,
     },
 }
 
+#[derive(Subcommand)]
+enum StatsAction {
+    /// List the commands and generators with the slowest completions
+    Slow {
+        /// How many commands and generators to list
+        #[arg(short = 'n', long, default_value_t = 10)]
+        limit: usize,
+        /// Unix socket path (defaults to $XDG_RUNTIME_DIR/autocomplete-rs.sock)
+        #[arg(short, long)]
+        socket: Option<PathBuf>,
+    },
+    /// Forget usage data beyond the [retention] limits of the config
+    Prune {
+        /// Report what would be forgotten without changing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Unix socket path (defaults to $XDG_RUNTIME_DIR/autocomplete-rs.sock)
+        #[arg(short, long)]
+        socket: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum HistoryAction {
+    /// Import existing shell history so ranking is good from the start
+    Import {
+        /// History to import (every one found when omitted)
+        #[arg(long = "from", value_enum)]
+        sources: Vec<stats::import::HistorySource>,
+        /// Read this file instead of the default location (needs exactly one --from)
+        #[arg(long)]
+        file: Option<PathBuf>,
+        /// Unix socket path (defaults to $XDG_RUNTIME_DIR/autocomplete-rs.sock)
+        #[arg(short, long)]
+        socket: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Validate the config file, reporting unknown keys and type errors
+    Check {
+        /// Config file to check (defaults to $XDG_CONFIG_HOME/autocomplete-rs/config.toml)
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+    },
+    /// Print the effective configuration and where each value comes from
+    Default {
+        /// Config file to layer over the defaults
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+        /// Show the configuration as `complete --plain` sees it
+        #[arg(long)]
+        plain: bool,
+    },
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Initialize logging (only for daemon, suppress for complete command)
     if std::env::args().any(|arg| arg == "daemon") {
-        tracing_subscriber::fmt::init();
+        // Safe mode writes nothing, including the log file
+        init_daemon_logging(!std::env::args().any(|arg| arg == "--safe"));
     }
 
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::Daemon { socket } => {
-            tracing::info!("Starting autocomplete daemon on {}", socket);
-            daemon::start(&socket).await?;
+        Commands::Daemon {
+            socket,
+            safe,
+            fixture,
+        } => {
+            let socket = socket.unwrap_or_else(paths::socket_path);
+            if let Some(fixture) = fixture {
+                return daemon::serve_fixture(&socket, &fixture).await;
+            }
+            tracing::info!("Starting autocomplete daemon on {}", socket.display());
+            if !safe {
+                for action in config::migrate::run()? {
+                    tracing::info!("Migrated: {}", action);
+                }
+            }
+            let config = config::Config::load()?;
+            daemon::start(&socket, config, safe).await?;
         }
         Commands::Stop { socket } => {
-            stop_daemon(&socket).await?;
+            stop_daemon(&socket.unwrap_or_else(paths::socket_path)).await?;
         }
-        Commands::Status { socket } => {
-            status_command(&socket).await?;
+        Commands::Status { socket, json } => {
+            status_command(&socket.unwrap_or_else(paths::socket_path), json).await?;
         }
         Commands::Complete {
             buffer,
             cursor,
             socket,
+            session,
+            last_command,
+            exit_code,
+            functions,
+            shell,
+            tty,
+            plain,
+            format,
+            width,
+            null,
+        } => {
+            let socket = socket.unwrap_or_else(paths::socket_path);
+            // Shells count characters, the daemon bytes
+            let cursor = byte_offset(&buffer, cursor);
+            let request = daemon::CompletionRequest {
+                session_id: session,
+                last_command,
+                last_exit_code: exit_code,
+                functions: shell_functions(functions),
+                shell,
+                ..daemon::CompletionRequest::new(buffer, cursor)
+            };
+            match format {
+                Some(template) => {
+                    let format = porcelain::Format::parse(&template, &width, null)?;
+                    porcelain_command(request, &socket, &format).await?;
+                }
+                None => complete_command(request, &socket, tty, plain).await?,
+            }
+        }
+        Commands::CompleteBatch { file, socket } => {
+            complete_batch_command(file, &socket.unwrap_or_else(paths::socket_path)).await?;
+        }
+        Commands::Context {
+            buffer,
+            cursor,
+            socket,
+            session,
+            functions,
+            shell,
+        } => {
+            let socket = socket.unwrap_or_else(paths::socket_path);
+            let cursor = cursor.map_or(buffer.len(), |cursor| byte_offset(&buffer, cursor));
+            let request = daemon::CompletionRequest {
+                session_id: session,
+                functions: shell_functions(functions),
+                shell,
+                cwd: std::env::current_dir().ok(),
+                env: client::context_env(std::env::vars()),
+                filesystem_id: daemon::filesystem_id(),
+                ..daemon::CompletionRequest::new(buffer, cursor)
+            };
+            let context = client::context(&socket, request).await?;
+            println!("{}", serde_json::to_string(&context)?);
+        }
+        Commands::Explain {
+            buffer,
+            cursor,
+            socket,
+            session,
         } => {
-            complete_command(&buffer, cursor, &socket).await?;
+            let cursor = cursor.map_or(buffer.len(), |cursor| byte_offset(&buffer, cursor));
+            let request = daemon::CompletionRequest {
+                session_id: session,
+                explain: true,
+                ..daemon::CompletionRequest::new(buffer, cursor)
+            };
+            explain_command(request, &socket.unwrap_or_else(paths::socket_path)).await?;
         }
-        Commands::Install { shell } => {
-            install_command(&shell)?;
+        Commands::Install { shell, dry_run } => {
+            install_command(shell.as_deref(), dry_run)?;
+        }
+        Commands::Uninstall { purge } => {
+            uninstall_command(purge)?;
+        }
+        Commands::ShellInit { shell } => {
+            shell_init_command(shell.as_deref())?;
+        }
+        Commands::Init => {
+            install::wizard::run().await?;
+        }
+        Commands::TuiTest => {
+            tui_test_command()?;
+        }
+        Commands::Reindex { socket } => {
+            reindex_command(&socket.unwrap_or_else(paths::socket_path)).await?;
+        }
+        Commands::Events { events, socket } => {
+            let socket = socket.unwrap_or_else(paths::socket_path);
+            client::events(&socket, events, |line| {
+                use std::io::Write;
+                let mut out = std::io::stdout().lock();
+                writeln!(out, "{line}")?;
+                // Piped into another tool, each event should arrive right away
+                out.flush()?;
+                Ok(())
+            })
+            .await?;
+        }
+        Commands::Visited {
+            dir,
+            socket,
+            session,
+        } => {
+            let socket = socket.unwrap_or_else(paths::socket_path);
+            let dir = match dir {
+                Some(dir) => dir,
+                None => std::env::current_dir().context("No current directory")?,
+            };
+            let visited = daemon::Visited {
+                dir,
+                session_id: session,
+            };
+            client::visited(&socket, visited).await?;
+        }
+        Commands::Restart { socket } => {
+            let socket = socket.unwrap_or_else(paths::socket_path);
+            let response = client::control(&socket, daemon::ControlCommand::Restart)
+                .await
+                .context("No daemon to restart")?;
+            if !response.ok {
+                anyhow::bail!(response.message);
+            }
+            println!("{}", response.message);
+        }
+        Commands::Report {
+            output,
+            trace,
+            socket,
+        } => {
+            let socket = socket.unwrap_or_else(paths::socket_path);
+            let (path, files) = report::create(output, &socket, trace.as_deref()).await?;
+            println!("Wrote {} ({})", path.display(), files.join(", "));
+            println!("Home directory, user and host names are redacted; review it before sharing");
+        }
+        Commands::Migrate { dry_run } => {
+            migrate_command(dry_run)?;
+        }
+        Commands::Config { action } => {
+            config_command(action)?;
+        }
+        Commands::History {
+            action:
+                HistoryAction::Import {
+                    sources,
+                    file,
+                    socket,
+                },
+        } => {
+            history_import_command(sources, file, &socket.unwrap_or_else(paths::socket_path))
+                .await?;
+        }
+        Commands::Stats {
+            action: StatsAction::Slow { limit, socket },
+        } => {
+            stats_slow_command(limit, &socket.unwrap_or_else(paths::socket_path)).await?;
+        }
+        Commands::Stats {
+            action: StatsAction::Prune { dry_run, socket },
+        } => {
+            stats_prune_command(dry_run, &socket.unwrap_or_else(paths::socket_path)).await?;
+        }
+        Commands::Spec { action } => {
+            spec_command(action).await?;
         }
     }
 
@@ -89,51 +593,329 @@ async fn main() -> Result<()> {
 }
 
 /// Handle the complete command: connect to daemon, get suggestions, show TUI
-async fn complete_command(buffer: &str, cursor: usize, socket_path: &str) -> Result<()> {
-    // Connect to daemon
-    let stream = UnixStream::connect(socket_path)
-        .await
-        .context("Failed to connect to daemon. Is it running?")?;
+async fn complete_command(
+    request: daemon::CompletionRequest,
+    socket_path: &Path,
+    tty: bool,
+    plain: bool,
+) -> Result<()> {
+    let terminal = tui::detect_terminal();
+    // A broken config shouldn't cost the completion, only its settings
+    let flags = config::flag_overlay(
+        plain.then_some(("accessibility.plain", serde_json::Value::Bool(true))),
+    );
+    let loaded = config::Config::load_layered(None, flags).map(|loaded| loaded.config);
+    let plain = plain
+        || std::env::var("TERM").is_ok_and(|term| term == "dumb")
+        || loaded
+            .as_ref()
+            .is_ok_and(|config| config.accessibility.plain);
+    let request = daemon::CompletionRequest {
+        locale: i18n::env_locale(),
+        // The client runs in the shell's directory and inherits its exported environment
+        cwd: std::env::current_dir().ok(),
+        env: client::context_env(std::env::vars()),
+        // A plain list has no popup to fit, so descriptions stay whole
+        terminal: (!plain).then_some(terminal),
+        filesystem_id: daemon::filesystem_id(),
+        ..request
+    };
+    let mut response = client::request(socket_path, &request).await?;
+    // The daemon runs outside this container and can't see its files
+    if response.standalone {
+        response = daemon::complete_standalone(&request, config::Config::load()?).await;
+    }
+    // A huge directory may not have produced a match within the first request
+    while response.suggestions.is_empty()
+        && let Some(offset) = response.next_offset
+    {
+        tokio::time::sleep(PAGE_RETRY_INTERVAL).await;
+        let next = daemon::CompletionRequest {
+            offset,
+            ..request.clone()
+        };
+        response = client::request(socket_path, &next).await?;
+    }
 
-    let (reader, mut writer) = stream.into_split();
-    let mut reader = BufReader::new(reader);
+    // Show TUI with suggestions
+    if !response.suggestions.is_empty() {
+        let replace = response
+            .replace
+            .clone()
+            .unwrap_or_else(|| word_at(&request.buffer, request.cursor));
+        // Shells count characters, not bytes
+        let chars = |at: usize| {
+            request
+                .buffer
+                .get(..at)
+                .map_or(0, |text| text.chars().count())
+        };
+        let (start, end) = (chars(replace.start), chars(replace.end));
+        let theme = loaded.as_ref().map(|config| config.theme.name.as_str());
+        let theme = tui::Theme::named(theme.unwrap_or_default(), terminal.truecolor);
+        // Reported to the daemon once a suggestion is picked
+        let accepted = daemon::Accepted {
+            text: String::new(),
+            source: daemon::SuggestionSource::default(),
+            buffer: request.buffer.clone(),
+            cursor: request.cursor,
+            command: None,
+            session_id: request.session_id.clone(),
+            cwd: request.cwd.clone(),
+        };
+        let mut ui = tui::CompletionUI::new(response.suggestions, terminal)
+            .on_tty(tty)
+            .plain(plain)
+            .with_theme(theme);
+        if let Some(offset) = response.next_offset {
+            ui = ui.with_feed(page_feed(socket_path.to_path_buf(), request, offset));
+        }
+        // The popup blocks; pages are fetched on the runtime meanwhile
+        if let Some(selected) = tokio::task::spawn_blocking(move || ui.run()).await?? {
+            // Print selected completion to stdout for zsh to capture, then,
+            // after unit separators, the range of the buffer it replaces and
+            // the cursor position when not at the end
+            match selected.cursor_chars() {
+                Some(cursor) => {
+                    println!("{}\x1f{start}\x1f{end}\x1f{cursor}", selected.insertion())
+                }
+                None => println!("{}\x1f{start}\x1f{end}", selected.insertion()),
+            }
+            // Only for hooks; the shell waits for this process to exit
+            let accepted = daemon::Accepted {
+                text: selected.text,
+                source: selected.source,
+                ..accepted
+            };
+            let _ = tokio::time::timeout(
+                ACCEPTED_REPORT_TIMEOUT,
+                client::accepted(socket_path, accepted),
+            )
+            .await;
+        }
+    }
 
-    // Send request
-    let request = daemon::CompletionRequest {
-        buffer: buffer.to_string(),
-        cursor,
-        version: 1,
+    Ok(())
+}
+
+/// Print every suggestion for `request` as a record of `format`
+async fn porcelain_command(
+    request: daemon::CompletionRequest,
+    socket_path: &Path,
+    format: &porcelain::Format,
+) -> Result<()> {
+    use std::io::Write;
+
+    let mut request = daemon::CompletionRequest {
+        locale: i18n::env_locale(),
+        cwd: std::env::current_dir().ok(),
+        env: client::context_env(std::env::vars()),
+        // No popup to fit, so descriptions stay whole
+        terminal: None,
+        filesystem_id: daemon::filesystem_id(),
+        ..request
     };
-    let request_json = serde_json::to_string(&request)?;
-    writer.write_all(request_json.as_bytes()).await?;
-    writer.write_all(b"\n").await?;
-    writer.flush().await?;
+    let mut response = client::request(socket_path, &request).await?;
+    if response.standalone {
+        response = daemon::complete_standalone(&request, config::Config::load()?).await;
+    }
+    let mut out = std::io::stdout().lock();
+    loop {
+        for suggestion in &response.suggestions {
+            match out.write_all(format.record(suggestion).as_bytes()) {
+                // A picker that has what it needs closes the pipe early
+                Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => return Ok(()),
+                result => result?,
+            }
+        }
+        let Some(offset) = response.next_offset else {
+            break;
+        };
+        if response.suggestions.is_empty() {
+            tokio::time::sleep(PAGE_RETRY_INTERVAL).await;
+        }
+        request = daemon::CompletionRequest {
+            offset,
+            // Already recorded by the first request
+            last_command: None,
+            last_exit_code: None,
+            ..request
+        };
+        response = client::request(socket_path, &request).await?;
+    }
+    match out.flush() {
+        Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// The names given to `--functions`, without the empty ones a doubled or
+/// trailing space leaves
+fn shell_functions(functions: Vec<String>) -> Vec<String> {
+    functions
+        .into_iter()
+        .filter(|name| !name.is_empty())
+        .collect()
+}
 
-    // Read response
-    let mut response_line = String::new();
-    reader.read_line(&mut response_line).await?;
+/// Byte offset of the character at `chars` in `buffer`, the end when past it
+fn byte_offset(buffer: &str, chars: usize) -> usize {
+    buffer
+        .char_indices()
+        .nth(chars)
+        .map_or(buffer.len(), |(at, _)| at)
+}
 
-    // Parse response
-    let response: daemon::CompletionResponse =
-        serde_json::from_str(&response_line).context("Failed to parse daemon response")?;
+/// Byte range of the word around `cursor` in `buffer`, for daemons that
+/// don't say which range to replace
+fn word_at(buffer: &str, cursor: usize) -> std::ops::Range<usize> {
+    let before = buffer.get(..cursor).unwrap_or(buffer);
+    parser::ParseState::new(before)
+        .whole_word(&buffer[before.len()..])
+        .map_or(before.len()..before.len(), |word| word.span())
+}
 
-    // Show TUI with suggestions
-    if !response.suggestions.is_empty() {
-        let mut ui = tui::CompletionUI::new(response.suggestions);
-        if let Some(selected) = ui.run()? {
-            // Print selected completion to stdout for zsh to capture
-            println!("{}", selected.text);
+/// One entry of `complete-batch` input
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum BatchItem {
+    /// `["git ", 4]`
+    Pair(String, usize),
+    Request(Box<daemon::CompletionRequest>),
+}
+
+impl BatchItem {
+    /// The request to send; a pair's cursor counts characters, like
+    /// `complete`'s, while a request object's is already a byte offset
+    fn into_request(self) -> daemon::CompletionRequest {
+        match self {
+            BatchItem::Pair(buffer, cursor) => {
+                let cursor = byte_offset(&buffer, cursor);
+                daemon::CompletionRequest::new(buffer, cursor)
+            }
+            BatchItem::Request(request) => *request,
         }
     }
+}
+
+/// Handle `complete-batch`: send every request in the input to the daemon at
+/// once and print what it answered
+async fn complete_batch_command(file: Option<PathBuf>, socket_path: &Path) -> Result<()> {
+    let input = match &file {
+        Some(file) => std::fs::read_to_string(file)
+            .with_context(|| format!("Failed to read {}", file.display()))?,
+        None => std::io::read_to_string(std::io::stdin()).context("Failed to read stdin")?,
+    };
+    let items: Vec<BatchItem> = serde_json::from_str(&input)
+        .context("Expected a JSON array of [buffer, cursor] pairs or request objects")?;
 
+    // Like `complete`, but without a terminal to fit the suggestions to
+    let cwd = std::env::current_dir().ok();
+    let env = client::context_env(std::env::vars());
+    let requests: Vec<daemon::CompletionRequest> = items
+        .into_iter()
+        .map(|item| {
+            let mut request = item.into_request();
+            request.cwd = request.cwd.or_else(|| cwd.clone());
+            if request.env.is_empty() {
+                request.env = env.clone();
+            }
+            request.locale = request.locale.or_else(i18n::env_locale);
+            request.filesystem_id = request.filesystem_id.or_else(daemon::filesystem_id);
+            request
+        })
+        .collect();
+
+    let mut responses = client::batch(socket_path, requests.clone()).await?;
+    // The daemon runs outside this container and can't see its files
+    if responses.iter().any(|response| response.standalone) {
+        let config = config::Config::load()?;
+        for (response, request) in responses.iter_mut().zip(&requests) {
+            if response.standalone {
+                *response = daemon::complete_standalone(request, config.clone()).await;
+            }
+        }
+    }
+    println!("{}", serde_json::to_string_pretty(&responses)?);
     Ok(())
 }
 
-/// Stop the running daemon
-async fn stop_daemon(socket_path: &str) -> Result<()> {
-    use std::path::Path;
+/// Pause before asking again for a page the daemon is still reading
+const PAGE_RETRY_INTERVAL: Duration = Duration::from_millis(25);
+/// How long the daemon gets to take note of an accepted suggestion
+const ACCEPTED_REPORT_TIMEOUT: Duration = Duration::from_millis(100);
 
-    if !Path::new(socket_path).exists() {
+/// Fetch pages of `request` from `offset` on whenever the popup asks for one
+fn page_feed(socket: PathBuf, request: daemon::CompletionRequest, offset: usize) -> tui::Feed {
+    let (pages_tx, pages) = std::sync::mpsc::channel();
+    let (wants, mut wants_rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut request = daemon::CompletionRequest {
+        offset,
+        // Already recorded by the first request
+        last_command: None,
+        last_exit_code: None,
+        ..request
+    };
+    tokio::spawn(async move {
+        while wants_rx.recv().await.is_some() {
+            loop {
+                let Ok(response) = client::request(&socket, &request).await else {
+                    return;
+                };
+                let Some(next) = response.next_offset else {
+                    let _ = pages_tx.send(response.suggestions);
+                    return;
+                };
+                request.offset = next;
+                if !response.suggestions.is_empty() {
+                    if pages_tx.send(response.suggestions).is_err() {
+                        return;
+                    }
+                    break;
+                }
+                tokio::time::sleep(PAGE_RETRY_INTERVAL).await;
+            }
+        }
+    });
+    tui::Feed { pages, wants }
+}
+
+/// Logs rotate once they grow past this size
+const MAX_LOG_BYTES: u64 = 1024 * 1024;
+
+/// Log to stderr and, unless `to_file` is false, to the log file, which
+/// `report` collects
+fn init_daemon_logging(to_file: bool) {
+    use tracing_subscriber::prelude::*;
+
+    let file = paths::log_file().filter(|_| to_file).and_then(|path| {
+        std::fs::create_dir_all(path.parent()?).ok()?;
+        // Keep one previous log around
+        if std::fs::metadata(&path).is_ok_and(|meta| meta.len() > MAX_LOG_BYTES) {
+            let _ = std::fs::rename(&path, path.with_extension("log.1"));
+        }
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .ok()
+    });
+    let file_layer = file.map(|file| {
+        tracing_subscriber::fmt::layer()
+            .with_ansi(false)
+            .with_writer(std::sync::Mutex::new(file))
+    });
+
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::filter::LevelFilter::INFO)
+        .with(tracing_subscriber::fmt::layer())
+        .with(file_layer)
+        .init();
+}
+
+/// Stop the running daemon
+async fn stop_daemon(socket_path: &Path) -> Result<()> {
+    if !socket_path.exists() {
         println!("Daemon is not running (socket not found)");
         return Ok(());
     }
@@ -158,45 +940,881 @@ async fn stop_daemon(socket_path: &str) -> Result<()> {
 }
 
 /// Check daemon status
-async fn status_command(socket_path: &str) -> Result<()> {
-    use std::path::Path;
+async fn status_command(socket_path: &Path, json: bool) -> Result<()> {
+    let status = if socket_path.exists() {
+        client::status(socket_path).await.ok()
+    } else {
+        None
+    };
+    // Daemons from before status requests only take the connection
+    let running = if !socket_path.exists() {
+        Err("Daemon is not running (socket not found)")
+    } else if status.is_none() && UnixStream::connect(socket_path).await.is_err() {
+        Err("Socket exists but daemon is not responding (stale socket)")
+    } else {
+        Ok(())
+    };
 
-    if !Path::new(socket_path).exists() {
-        println!("Daemon is not running (socket not found)");
+    if json {
+        let mut value = serde_json::json!({
+            "running": running.is_ok(),
+            "socket": socket_path,
+        });
+        if let Some(status) = &status
+            && let (Some(value), serde_json::Value::Object(status)) =
+                (value.as_object_mut(), serde_json::to_value(status)?)
+        {
+            value.extend(status);
+        }
+        println!("{}", serde_json::to_string_pretty(&value)?);
+        return Ok(());
+    }
+    if let Err(message) = running {
+        println!("{message}");
+        return Ok(());
+    }
+    println!("Daemon is running on {}", socket_path.display());
+    let Some(status) = status else {
+        return Ok(());
+    };
+    println!(
+        "pid {}, version {}, up {}",
+        status.pid,
+        status.version,
+        uptime(status.uptime_secs)
+    );
+    let errors = &status.errors;
+    if errors.total() > 0 {
+        println!(
+            "Bad connections: {} malformed, {} protocol errors, {} timeouts, {} dropped",
+            errors.malformed, errors.protocol, errors.timeouts, errors.dropped
+        );
+        if let Some(last) = &errors.last {
+            println!("Last: {last}");
+        }
+    }
+    Ok(())
+}
+
+/// `secs` as its two largest units, as in `3h 12m`
+fn uptime(secs: u64) -> String {
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m {}s", secs / 60, secs % 60),
+        3600..86400 => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+        _ => format!("{}d {}h", secs / 86400, secs % 86400 / 3600),
+    }
+}
+
+/// Install shell integration
+fn install_command(shell: Option<&str>, dry_run: bool) -> Result<()> {
+    let shell = match shell {
+        Some(name) => parse_shell(name)?,
+        None => {
+            let detected = install::invoking_shell();
+            // Only ask when someone can answer
+            if std::io::stdin().is_terminal()
+                && !install::wizard::confirm(
+                    &format!("Detected {}. Install for it?", detected),
+                    true,
+                )?
+            {
+                anyhow::bail!(
+                    "Aborted; pass the shell explicitly, e.g. `autocomplete-rs install zsh`"
+                );
+            }
+            detected
+        }
+    };
+    if !shell.is_supported() {
+        anyhow::bail!(
+            "Unsupported shell: {}. Currently only 'zsh' is supported.",
+            shell
+        );
+    }
+    let rc = shell
+        .rc_file()
+        .context("Could not determine the home directory")?;
+
+    if dry_run {
+        let installed =
+            std::fs::read_to_string(&rc).is_ok_and(|contents| install::has_block(&contents));
+        if installed {
+            println!(
+                "{} already contains the autocomplete-rs block",
+                rc.display()
+            );
+        } else {
+            if rc.exists() {
+                println!("Would back up {} and append:", rc.display());
+            } else {
+                println!("Would create {} with:", rc.display());
+            }
+            println!();
+            print!("{}", shell.snippet());
+        }
         return Ok(());
     }
 
-    // Try to connect to verify daemon is responsive
-    match UnixStream::connect(socket_path).await {
-        Ok(_stream) => {
-            println!("Daemon is running on {}", socket_path);
+    match install::add_block(shell, &rc)? {
+        install::RcChange::AlreadyInstalled => {
+            println!("Already installed in {}", rc.display());
         }
-        Err(_) => {
-            println!("Socket exists but daemon is not responding (stale socket)");
+        install::RcChange::Installed { backup } => {
+            println!("Added autocomplete-rs to {}", rc.display());
+            if let Some(backup) = backup {
+                println!("Backup saved to {}", backup.display());
+            }
         }
     }
 
+    match install::verify(shell) {
+        Ok(true) => println!("Verified: a new {} session loads the integration", shell),
+        Ok(false) => println!(
+            "Warning: a new {} session did not load the integration; is autocomplete-rs on PATH?",
+            shell
+        ),
+        Err(e) => println!("Warning: could not verify the installation: {:#}", e),
+    }
+    println!(
+        "Restart your shell or run `exec {}` to start using it",
+        shell
+    );
     Ok(())
 }
 
-/// Install shell integration
-fn install_command(shell: &str) -> Result<()> {
-    match shell {
-        "zsh" => {
-            println!("To install autocomplete-rs for zsh, add this to your ~/.zshrc:");
-            println!();
-            println!("# autocomplete-rs");
-            println!("source <(autocomplete-rs shell-init zsh)");
+/// Undo `init`/`install`: rc-file blocks, the service, and with `purge` all stored files
+fn uninstall_command(purge: bool) -> Result<()> {
+    let mut removed = 0;
+
+    for shell in install::Shell::ALL {
+        let Some(rc) = shell.rc_file() else {
+            continue;
+        };
+        if let Some(backup) = install::remove_block(&rc)? {
+            println!(
+                "Removed autocomplete-rs block from {} (backup: {})",
+                rc.display(),
+                backup.display()
+            );
+            removed += 1;
+        }
+    }
+
+    if let Some(unit) = install::service::uninstall()? {
+        println!("Stopped and removed service {}", unit.display());
+        removed += 1;
+    }
+
+    if purge {
+        let dirs = [paths::config_dir(), paths::cache_dir(), paths::data_dir()];
+        for dir in dirs.into_iter().flatten() {
+            if dir.exists() {
+                std::fs::remove_dir_all(&dir)
+                    .with_context(|| format!("Failed to remove {}", dir.display()))?;
+                println!("Deleted {}", dir.display());
+                removed += 1;
+            }
+        }
+    }
+
+    if removed == 0 {
+        println!("Nothing to remove");
+    } else {
+        println!("Restart your shell to finish uninstalling");
+    }
+    Ok(())
+}
+
+/// Rebuild the command index, through the daemon when it is running
+async fn reindex_command(socket_path: &Path) -> Result<()> {
+    if let Ok(response) = client::control(socket_path, daemon::ControlCommand::Reindex).await {
+        if !response.ok {
+            anyhow::bail!(response.message);
+        }
+        println!("{}", response.message);
+        return Ok(());
+    }
+
+    // No daemon: build the index here so the next daemon start picks it up
+    let path_var = std::env::var("PATH").unwrap_or_default();
+    let index = index::CommandIndex::build(&path_var);
+    let path =
+        index::CommandIndex::default_path().context("Could not determine the cache directory")?;
+    index.save(&path)?;
+    println!(
+        "Indexed {} executables, {} specs, {} man pages into {}",
+        index.executables.len(),
+        index.specs.len(),
+        index.man_pages.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// Show the popup with sample suggestions and save the settings picked in it
+fn tui_test_command() -> Result<()> {
+    let config = config::Config::load()?;
+    let mut triggers: Vec<(String, String)> = install::wizard::TRIGGERS
+        .iter()
+        .map(|(label, sequence)| (label.to_string(), sequence.to_string()))
+        .collect();
+    let current = &config.keybindings.trigger;
+    let trigger = match triggers
+        .iter()
+        .position(|(_, sequence)| sequence == current)
+    {
+        Some(at) => at,
+        None => {
+            triggers.insert(0, (format!("{current} (current)"), current.clone()));
+            0
+        }
+    };
+    let preview = tui::Preview {
+        theme: tui::THEMES
+            .iter()
+            .position(|name| *name == config.theme.name)
+            .unwrap_or(0),
+        triggers,
+        trigger,
+        compact: false,
+    };
+
+    let sample = [
+        (
+            "checkout",
+            "Switch branches or restore files",
+            daemon::SuggestionSource::Spec,
+        ),
+        (
+            "commit",
+            "Record changes to the repository",
+            daemon::SuggestionSource::Spec,
+        ),
+        (
+            "--verbose",
+            "Be more verbose",
+            daemon::SuggestionSource::Spec,
+        ),
+        ("src/", "", daemon::SuggestionSource::Files),
+        ("README.md", "", daemon::SuggestionSource::Files),
+        (
+            "git push origin main",
+            "Ran 12 times",
+            daemon::SuggestionSource::History,
+        ),
+        (
+            "gco ${1:branch}",
+            "git checkout",
+            daemon::SuggestionSource::Abbreviation,
+        ),
+    ];
+    let suggestions = sample
+        .into_iter()
+        .map(|(text, description, source)| daemon::Suggestion {
+            text: text.to_string(),
+            description: description.to_string(),
+            source,
+            ..Default::default()
+        })
+        .collect();
+    let mut ui = tui::CompletionUI::new(suggestions, tui::detect_terminal()).with_preview(preview);
+    if ui.run()?.is_none() {
+        return Ok(());
+    }
+    let Some(preview) = ui.preview() else {
+        return Ok(());
+    };
+    let (_, trigger) = &preview.triggers[preview.trigger];
+    install::wizard::write_settings(tui::THEMES[preview.theme], trigger)?;
+    if trigger != current {
+        println!("Open a new shell for the new trigger key to take effect.");
+    }
+    Ok(())
+}
+
+/// Print the shell integration script with the configured trigger key
+fn shell_init_command(shell: Option<&str>) -> Result<()> {
+    // Runs inside `eval "$(...)"`, so detection must not prompt
+    let shell = match shell {
+        Some(name) => parse_shell(name)?,
+        None => install::invoking_shell(),
+    };
+    let config = config::Config::load()?;
+    print!(
+        "{}",
+        install::shell_init(shell, &config.keybindings.trigger)?
+    );
+    Ok(())
+}
+
+fn parse_shell(name: &str) -> Result<install::Shell> {
+    install::Shell::from_name(name).with_context(|| format!("Unknown shell: {}", name))
+}
+
+fn migrate_command(dry_run: bool) -> Result<()> {
+    if dry_run {
+        let actions = config::migrate::plan()?;
+        if actions.is_empty() {
+            println!("Everything is up to date");
+        }
+        for action in actions {
+            println!("Would {}", action);
+        }
+        return Ok(());
+    }
+    let actions = config::migrate::run()?;
+    if actions.is_empty() {
+        println!("Everything is up to date");
+    }
+    for action in actions {
+        println!("Migrated: {}", action);
+    }
+    Ok(())
+}
+
+/// Handle `config check` and `config default`
+fn config_command(action: ConfigAction) -> Result<()> {
+    match action {
+        ConfigAction::Check { file } => {
+            for name in config::unknown_env() {
+                println!("warning: {} matches no config key and is ignored", name);
+            }
+            let Some(path) = file.or_else(paths::config_file) else {
+                anyhow::bail!("Could not determine the config file location");
+            };
+            if !path.exists() {
+                println!("No config file at {} (defaults apply)", path.display());
+                return Ok(());
+            }
+            let contents = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+            let issues = config::inspect::check(&contents);
+            if issues.is_empty() {
+                println!("{}: OK", path.display());
+                return Ok(());
+            }
+            for issue in &issues {
+                println!("{}: {}", path.display(), issue);
+            }
+            anyhow::bail!("Found {} problem(s) in {}", issues.len(), path.display());
+        }
+        ConfigAction::Default { file, plain } => {
+            let flags = config::flag_overlay(
+                plain.then_some(("accessibility.plain", serde_json::Value::Bool(true))),
+            );
+            let loaded = config::Config::load_layered(file.as_deref(), flags)?;
+            print!("{}", config::inspect::render(&loaded));
+        }
+    }
+    Ok(())
+}
+
+/// Handle `spec list`, `spec show`, `spec prefer`, `spec validate` and
+/// `spec generate`
+async fn spec_command(action: SpecAction) -> Result<()> {
+    match action {
+        SpecAction::List { user } => {
+            let commands: Vec<String> = if user {
+                specs::user_commands().into_iter().collect()
+            } else {
+                let mut commands = specs::available();
+                // A patch without a spec to apply to is worth seeing too
+                for command in specs::user_commands() {
+                    if let Err(at) = commands.binary_search(&command) {
+                        commands.insert(at, command);
+                    }
+                }
+                commands
+            };
+            let width = commands.iter().map(String::len).max().unwrap_or(0);
+            for command in &commands {
+                let origin = specs::origin(command);
+                let mut from = match origin.sources.split_first() {
+                    Some(((_, used), others)) => {
+                        let mut from = match used {
+                            Some(path) => path.display().to_string(),
+                            None => "bundled".to_string(),
+                        };
+                        if !others.is_empty() {
+                            let others: Vec<&str> =
+                                others.iter().map(|(source, _)| source.name()).collect();
+                            let over = if origin.preferred.is_some() {
+                                "preferred over"
+                            } else {
+                                "overrides"
+                            };
+                            from.push_str(&format!(" ({over} {})", others.join(", ")));
+                        }
+                        from
+                    }
+                    None => "no spec".to_string(),
+                };
+                if let Some(patch) = &origin.patch {
+                    from.push_str(&format!(", patched by {}", patch.display()));
+                }
+                // Problems in the user's files are what the list is most often for
+                let broken = origin
+                    .sources
+                    .iter()
+                    .filter_map(|(_, path)| path.as_ref())
+                    .chain(&origin.patch)
+                    .any(|path| specs::validate::check(path).is_err());
+                let note = if broken {
+                    "  [invalid, see spec validate]"
+                } else {
+                    ""
+                };
+                println!("{:width$}  {}{}", command, from, note);
+            }
+        }
+        SpecAction::Show { command } => {
+            let store = specs::SpecStore::new();
+            let spec = match store.get(&command) {
+                Some(spec) => spec,
+                None => {
+                    let Some(spec) = store.man_page_spec(&command) else {
+                        anyhow::bail!("No spec for `{}`", command);
+                    };
+                    eprintln!(
+                        "No spec for `{}`; this one is read from its man page",
+                        command
+                    );
+                    spec
+                }
+            };
+            println!("{}", serde_json::to_string_pretty(&*spec)?);
+        }
+        SpecAction::Prefer {
+            source,
+            command,
+            socket,
+        } => {
+            let source = match source.as_str() {
+                "default" => None,
+                name => Some(name.parse::<specs::SpecSource>()?),
+            };
+            let sources = specs::sources(&command);
+            if let Some(source) = source
+                && !sources.iter().any(|(found, _)| *found == source)
+            {
+                anyhow::bail!("No {} spec for `{}`", source, command);
+            }
+            specs::prefer::set_preference(&command, source)?;
+            let used = match source {
+                Some(source) => source,
+                None => match sources.first() {
+                    Some((source, _)) => *source,
+                    None => {
+                        println!("`{}` has no spec", command);
+                        return Ok(());
+                    }
+                },
+            };
+            println!("`{}` uses the {} spec", command, used);
+            // Not running is fine: the next daemon reads the choice anyway
+            let socket = socket.unwrap_or_else(paths::socket_path);
+            let _ = client::control(&socket, daemon::ControlCommand::ReloadSpecs).await;
+        }
+        SpecAction::Export { command, shell } => {
+            let shell = parse_shell(&shell)?;
+            let store = specs::SpecStore::new();
+            let spec = store
+                .get(&command)
+                .or_else(|| store.man_page_spec(&command))
+                .with_context(|| format!("No spec for `{}`", command))?;
+            let script = match shell {
+                install::Shell::Zsh => specs::export::zsh(&command, &spec),
+                install::Shell::Bash => specs::export::bash(&command, &spec),
+                install::Shell::Fish => {
+                    anyhow::bail!("Exporting specs for {} isn't supported", shell.name())
+                }
+            };
+            print!("{}", script);
+        }
+        SpecAction::Validate { file } => {
+            let issues = specs::validate::check(&file)
+                .with_context(|| format!("{} is invalid", file.display()))?;
+            if issues.is_empty() {
+                println!("{}: OK", file.display());
+                return Ok(());
+            }
+            for issue in &issues {
+                println!("{}: {}", file.display(), issue);
+            }
+            anyhow::bail!("Found {} problem(s) in {}", issues.len(), file.display());
+        }
+        SpecAction::Convert { dir, out, force } => {
+            let out = match out {
+                Some(out) => out,
+                None => specs::user_dir().context("Could not determine the user spec directory")?,
+            };
+            let converted = specs::convert::convert(&dir, &out, force)?;
+            for path in &converted.existing {
+                println!(
+                    "Kept {}, which already exists; pass --force to overwrite it",
+                    path.display()
+                );
+            }
+            println!(
+                "Wrote {} spec(s) to {}; check them with spec validate",
+                converted.written.len(),
+                out.display()
+            );
+        }
+        SpecAction::Generate {
+            command,
+            depth,
+            write,
+            force,
+        } => {
+            let spec = specs::generate::generate(&command, depth)?;
+            let json = serde_json::to_string_pretty(&spec)?;
+            if !write {
+                println!("{}", json);
+                return Ok(());
+            }
+            let dir = specs::user_dir().context("Could not determine the user spec directory")?;
+            let path = dir.join(format!("{}.json", command));
+            if path.exists() && !force {
+                anyhow::bail!(
+                    "{} already exists; pass --force to overwrite it",
+                    path.display()
+                );
+            }
+            std::fs::create_dir_all(&dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+            std::fs::write(&path, json + "\n")
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            println!(
+                "Wrote {}; review it, then run spec validate",
+                path.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Import shell history into the usage stats, through the daemon's copy when
+/// it is running so its in-memory counts aren't lost or overwritten
+async fn history_import_command(
+    sources: Vec<stats::import::HistorySource>,
+    file: Option<PathBuf>,
+    socket_path: &Path,
+) -> Result<()> {
+    use stats::import::{self, HistorySource};
+
+    let files: Vec<(HistorySource, PathBuf)> = match (file, sources.as_slice()) {
+        (Some(file), [source]) => vec![(*source, file)],
+        (Some(_), _) => anyhow::bail!("--file needs exactly one --from"),
+        (None, sources) => {
+            let sources = if sources.is_empty() {
+                &HistorySource::ALL[..]
+            } else {
+                sources
+            };
+            sources
+                .iter()
+                .filter_map(|&source| Some((source, source.default_file()?)))
+                .filter(|(_, path)| path.exists())
+                .collect()
+        }
+    };
+    if files.is_empty() {
+        anyhow::bail!("No shell history found; pass --from and --file to point at one");
+    }
+
+    let daemon_running = client::control(socket_path, daemon::ControlCommand::SaveStats)
+        .await
+        .is_ok();
+    let stats_path =
+        stats::UsageStats::default_path().context("Could not determine the data directory")?;
+    let mut usage = stats::UsageStats::load(&stats_path);
+    let known_before = usage.commands.len();
+
+    let show_progress = std::io::stderr().is_terminal();
+    let mut total = import::ImportSummary::default();
+    for (source, path) in &files {
+        let label = format!("{} ({})", source, path.display());
+        let mut progress = |done: f64| {
+            if show_progress {
+                eprint!("\r\x1b[K{}", progress_line(&label, done));
+            }
+        };
+        let entries = match import::read(*source, path, &mut progress) {
+            Ok(entries) => entries,
+            Err(e) => {
+                if show_progress {
+                    eprintln!();
+                }
+                eprintln!("Skipping {}: {:#}", label, e);
+                continue;
+            }
+        };
+        let summary = import::ingest(&mut usage, &entries);
+        if show_progress {
+            eprintln!();
+        }
+        println!(
+            "{}: {} commands, {} imported, {} skipped as private",
+            label, summary.entries, summary.imported, summary.private
+        );
+        total.entries += summary.entries;
+        total.imported += summary.imported;
+        total.private += summary.private;
+    }
+
+    usage.save(&stats_path)?;
+    if daemon_running {
+        client::control(socket_path, daemon::ControlCommand::ReloadStats).await?;
+    }
+    println!(
+        "Imported {} of {} commands ({} new command names); top: {}",
+        total.imported,
+        total.entries,
+        usage.commands.len() - known_before,
+        usage.top_commands(5).join(", ")
+    );
+    Ok(())
+}
+
+/// Print the commands and generators with the worst p95 latency, with the
+/// daemon's latest timings when it is running
+/// Prune the usage stats and command history, in the daemon when it is
+/// running so it doesn't write the forgotten data back
+async fn stats_prune_command(dry_run: bool, socket_path: &Path) -> Result<()> {
+    if !dry_run
+        && let Ok(response) = client::control(socket_path, daemon::ControlCommand::Prune).await
+    {
+        if !response.ok {
+            anyhow::bail!("{}", response.message);
+        }
+        println!("{}", response.message);
+        return Ok(());
+    }
+    if dry_run {
+        let _ = client::control(socket_path, daemon::ControlCommand::SaveStats).await;
+    }
+    let retention = config::Config::load()?.retention;
+    let stats_path =
+        stats::UsageStats::default_path().context("Could not determine the data directory")?;
+    let history_path = stats::history::CommandHistory::default_path()
+        .context("Could not determine the data directory")?;
+    let mut usage = stats::UsageStats::load(&stats_path);
+    let mut history = stats::history::CommandHistory::load(&history_path);
+    let pruned = stats::prune(&mut usage, &mut history, &retention);
+    if dry_run {
+        println!(
+            "Would forget {} command(s) and {} history entries",
+            pruned.commands, pruned.history_entries
+        );
+        return Ok(());
+    }
+    if pruned.commands > 0 {
+        usage.save(&stats_path)?;
+    }
+    if pruned.history_entries > 0 {
+        history.save(&history_path)?;
+    }
+    println!("{}", pruned);
+    Ok(())
+}
+
+async fn stats_slow_command(limit: usize, socket_path: &Path) -> Result<()> {
+    // Not running is fine: the file has the timings up to its last flush
+    let _ = client::control(socket_path, daemon::ControlCommand::SaveStats).await;
+    let stats_path =
+        stats::UsageStats::default_path().context("Could not determine the data directory")?;
+    let usage = stats::UsageStats::load(&stats_path);
+
+    let completions = stats::slowest(&usage.completions, limit);
+    let generators = stats::slowest(&usage.generators, limit);
+    if completions.is_empty() && generators.is_empty() {
+        println!("No completion timings recorded yet");
+        return Ok(());
+    }
+    if !completions.is_empty() {
+        println!(
+            "Completions (latest {} per command)",
+            stats::latency::MAX_SAMPLES
+        );
+        println!(
+            "{:>9} {:>9} {:>8} {:>7}  COMMAND",
+            "P95", "P50", "REQUESTS", "CACHED"
+        );
+        for (command, latency) in &completions {
+            let cached = latency
+                .cache_hit_rate()
+                .map(|rate| format!("{:.0}%", rate * 100.0))
+                .unwrap_or_else(|| "-".to_string());
+            println!(
+                "{:>9} {:>9} {:>8} {:>7}  {}",
+                format_latency(latency.p95()),
+                format_latency(latency.p50()),
+                latency.count(),
+                cached,
+                command
+            );
+        }
+    }
+    if !generators.is_empty() {
+        if !completions.is_empty() {
             println!();
-            println!("Or manually source the integration script:");
-            println!("source /path/to/autocomplete-rs/shell-integration/zsh.zsh");
         }
-        _ => {
-            anyhow::bail!(
-                "Unsupported shell: {}. Currently only 'zsh' is supported.",
-                shell
+        println!(
+            "Generators (latest {} runs each, never cached)",
+            stats::latency::MAX_SAMPLES
+        );
+        println!("{:>9} {:>9} {:>8}  GENERATOR", "P95", "P50", "RUNS");
+        for (script, latency) in generators {
+            println!(
+                "{:>9} {:>9} {:>8}  {}",
+                format_latency(latency.p95()),
+                format_latency(latency.p50()),
+                latency.count(),
+                script
             );
         }
     }
     Ok(())
 }
+
+async fn explain_command(request: daemon::CompletionRequest, socket_path: &Path) -> Result<()> {
+    let request = daemon::CompletionRequest {
+        locale: i18n::env_locale(),
+        cwd: std::env::current_dir().ok(),
+        env: client::context_env(std::env::vars()),
+        filesystem_id: daemon::filesystem_id(),
+        ..request
+    };
+    let response = client::request(socket_path, &request).await?;
+    if response.standalone {
+        anyhow::bail!("The daemon can't see this filesystem, so it doesn't rank for this shell");
+    }
+    if let Some(weights) = &response.ranking {
+        println!(
+            "Weights: order {}, fuzzy {}, source {}, frecency {}",
+            weights.order, weights.fuzzy, weights.source, weights.frecency
+        );
+    }
+    if response.suggestions.is_empty() {
+        println!("No suggestions");
+        return Ok(());
+    }
+    println!(
+        "{:>6} {:>6} {:>6} {:>6} {:>8}  SUGGESTION",
+        "TOTAL", "ORDER", "FUZZY", "SOURCE", "FRECENCY"
+    );
+    for suggestion in &response.suggestions {
+        let source = serde_json::to_value(suggestion.source)?;
+        let source = source.as_str().unwrap_or_default();
+        match &suggestion.score {
+            Some(score) => println!(
+                "{:>6.2} {:>6.2} {:>6.2} {:>6.2} {:>8.2}  {} ({source})",
+                score.total,
+                score.order,
+                score.fuzzy,
+                score.source,
+                score.frecency,
+                suggestion.text
+            ),
+            // Paths, corrections and expansions keep their place
+            None => println!(
+                "{:>6} {:>6} {:>6} {:>6} {:>8}  {} ({source}, not ranked)",
+                "-", "-", "-", "-", "-", suggestion.text
+            ),
+        }
+    }
+    if response.next_offset.is_some() {
+        println!("(more on later pages)");
+    }
+    Ok(())
+}
+
+/// `850µs`, `12.3ms`, `2.05s`
+fn format_latency(latency: Option<Duration>) -> String {
+    match latency {
+        None => "-".to_string(),
+        Some(latency) if latency < Duration::from_millis(1) => {
+            format!("{}µs", latency.as_micros())
+        }
+        Some(latency) if latency < Duration::from_secs(1) => {
+            format!("{:.1}ms", latency.as_secs_f64() * 1000.0)
+        }
+        Some(latency) => format!("{:.2}s", latency.as_secs_f64()),
+    }
+}
+
+/// `label [#####-----]  42%`
+fn progress_line(label: &str, done: f64) -> String {
+    const WIDTH: usize = 30;
+    let filled = ((done.clamp(0.0, 1.0) * WIDTH as f64) as usize).min(WIDTH);
+    format!(
+        "{} [{}{}] {:>3.0}%",
+        label,
+        "#".repeat(filled),
+        "-".repeat(WIDTH - filled),
+        done * 100.0
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_counts_characters_before_multibyte_text() {
+        let buffer = "echo é src/ma";
+        let cursor = byte_offset(buffer, buffer.chars().count());
+        assert_eq!(cursor, buffer.len());
+        let replace = word_at(buffer, cursor);
+        assert_eq!(&buffer[replace.clone()], "src/ma");
+        // The range goes back to the shell in characters again
+        let chars = |at: usize| buffer[..at].chars().count();
+        assert_eq!((chars(replace.start), chars(replace.end)), (7, 13));
+    }
+
+    #[test]
+    fn context_cursor_after_multibyte_text_sees_the_last_command() {
+        let buffer = "echo é; git ";
+        let cursor = byte_offset(buffer, 12);
+        assert_eq!(cursor, buffer.len());
+        let commands = parser::commands(&buffer[..cursor]);
+        let words: Vec<_> = commands
+            .last()
+            .unwrap()
+            .iter()
+            .map(|token| token.text.as_str())
+            .collect();
+        // `git` is complete, so a subcommand comes next rather than a command
+        assert_eq!(words, ["git"]);
+        // Taken as bytes, the same count stops inside `git`
+        assert_eq!(&buffer[..12], "echo é; git");
+    }
+
+    #[test]
+    fn cursor_inside_and_past_the_buffer() {
+        assert_eq!(byte_offset("é é", 2), 3);
+        assert_eq!(byte_offset("é é", 99), 5);
+        assert_eq!(byte_offset("", 0), 0);
+    }
+
+    #[test]
+    fn test_batch_pair_after_multibyte_text_counts_characters() {
+        let items: Vec<BatchItem> =
+            serde_json::from_str(r#"[["echo é; git ", 12], ["echo é", 99]]"#).unwrap();
+        let cursors: Vec<_> = items
+            .into_iter()
+            .map(|item| item.into_request().cursor)
+            .collect();
+        assert_eq!(cursors, ["echo é; git ".len(), "echo é".len()]);
+    }
+
+    #[test]
+    fn test_batch_request_object_keeps_byte_cursor() {
+        let items: Vec<BatchItem> =
+            serde_json::from_str(r#"[{"buffer": "echo é; git ", "cursor": 5}]"#).unwrap();
+        let request = items.into_iter().next().unwrap().into_request();
+        assert_eq!(request.cursor, 5);
+    }
+}
@@ -1,19 +1,904 @@
-use anyhow::Result;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
 
-/// Parse a command buffer and return completion suggestions
-#[allow(dead_code)]
-pub fn parse_buffer(_buffer: &str, _cursor: usize) -> Result<Vec<String>> {
-    // TODO: Implement buffer parsing logic
-    // 1. Tokenize the command buffer
-    // 2. Determine what we're completing (command, subcommand, option, arg)
-    // 3. Load appropriate spec
-    // 4. Generate suggestions
+pub mod snippet;
 
-    Ok(vec![])
+/// Parse states kept per session before the least recently used is dropped
+const MAX_SESSIONS: usize = 64;
+
+/// The commands of a buffer, each as its words, in the order they start.
+///
+/// Commands are separated by `|`, `&&`, `||`, `;`, `&` and newlines, and
+/// those in `$(...)`, backticks or a `( ... )` subshell are commands of their
+/// own; the word a substitution is part of stays in the enclosing command.
+pub fn commands(buffer: &str) -> Vec<Vec<Token>> {
+    commands_in(buffer, Syntax::Posix)
+}
+
+/// The commands of a buffer in the words of `syntax`
+pub fn commands_in(buffer: &str, syntax: Syntax) -> Vec<Vec<Token>> {
+    let mut state = ParseState::with_syntax(buffer, syntax);
+    // The buffer is complete, so an expansion, substitution or subshell
+    // still open at its end ends there too
+    state.lexer.close_expansion();
+    while !state.frames.is_empty() {
+        state.close();
+    }
+    state.end_command();
+    let mut commands = state.commands;
+    commands.sort_by_key(|words| words[0].start);
+    commands
+}
+
+/// How a shell splits its command line into words.
+///
+/// POSIX shells escape with a backslash, which on Windows separates the
+/// parts of a path. PowerShell escapes with a backtick instead, takes a
+/// doubled quote inside quotes for the quote itself, reads `$env:NAME` and
+/// passes values as `-Name:value`; cmd.exe escapes with `^` outside double
+/// quotes, has no single quotes and expands `%NAME%`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Syntax {
+    #[default]
+    Posix,
+    PowerShell,
+    Cmd,
+}
+
+impl Syntax {
+    /// The syntax of the shell named `name`, a path or executable name such
+    /// as `pwsh.exe` included; POSIX for shells it isn't known for
+    pub fn of_shell(name: &str) -> Self {
+        let name = name.rsplit(['/', '\\']).next().unwrap_or(name);
+        let name = name.to_ascii_lowercase();
+        match name.strip_suffix(".exe").unwrap_or(&name) {
+            "pwsh" | "powershell" => Syntax::PowerShell,
+            "cmd" => Syntax::Cmd,
+            _ => Syntax::Posix,
+        }
+    }
+
+    /// The character escaping the next one
+    pub fn escape(self) -> char {
+        match self {
+            Syntax::Posix => '\\',
+            Syntax::PowerShell => '`',
+            Syntax::Cmd => '^',
+        }
+    }
+
+    /// What separates an option's name from a value attached to it, and the
+    /// prefix the name starts with
+    fn attached(self) -> (char, &'static str) {
+        match self {
+            Syntax::Posix => ('=', "--"),
+            Syntax::PowerShell => (':', "-"),
+            Syntax::Cmd => (':', "/"),
+        }
+    }
+}
+
+/// A shell word with quotes and escapes removed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Token {
+    /// Unquoted text
+    pub text: String,
+    /// Byte offset of the first character in the buffer
+    pub start: usize,
+    /// Byte offset just past the last character in the buffer, closing
+    /// quote included
+    pub end: usize,
+    /// `$NAME` and `${NAME}` expansions (`$env:NAME` in PowerShell, `%NAME%`
+    /// in cmd.exe), as byte ranges of `text`; a `$` in single quotes or after
+    /// an escape is literal and not included
+    pub expansions: Vec<Range<usize>>,
+}
+
+impl Token {
+    /// Byte range of the word in the buffer
+    pub fn span(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    /// `text` up to byte `end`, with the expansions entirely before it
+    /// replaced by their values in `env`; unknown variables are kept as typed
+    pub fn expand(&self, end: usize, env: &BTreeMap<String, String>) -> String {
+        let mut expanded = String::new();
+        let mut at = 0;
+        for range in self.expansions.iter().filter(|range| range.end <= end) {
+            let name = self.text[range.start + 1..range.end]
+                .trim_start_matches('{')
+                .trim_end_matches(['}', '%']);
+            let name = match name.get(..4) {
+                Some(scope) if scope.eq_ignore_ascii_case("env:") => &name[4..],
+                _ => name,
+            };
+            if let Some(value) = env.get(name) {
+                expanded.push_str(&self.text[at..range.start]);
+                expanded.push_str(value);
+                at = range.end;
+            }
+        }
+        expanded.push_str(&self.text[at..end]);
+        expanded
+    }
+
+    /// The value of a `--name=value` word as a token of its own, `buffer`
+    /// being what the spans index; `None` for other words and for names
+    /// that aren't typed as is (`"--name"=value`). PowerShell attaches values
+    /// as `-Name:value` and cmd.exe as `/name:value`, and native programs
+    /// run from PowerShell still take `--name=value`.
+    pub fn attached_value(&self, buffer: &str, syntax: Syntax) -> Option<Token> {
+        let (separator, prefix) = syntax.attached();
+        let name = match self.text.split_once(separator) {
+            Some((name, _))
+                if name.len() > prefix.len() && name.starts_with(prefix) && !name.contains('=') =>
+            {
+                name
+            }
+            _ => {
+                let (name, _) = self.text.split_once('=')?;
+                if name.len() < 3 || !name.starts_with("--") {
+                    return None;
+                }
+                name
+            }
+        };
+        let at = name.len() + 1;
+        if buffer.get(self.start..self.start + at)? != &self.text[..at] {
+            return None;
+        }
+        Some(Token {
+            text: self.text[at..].to_string(),
+            start: self.start + at,
+            end: self.end,
+            expansions: self
+                .expansions
+                .iter()
+                .filter(|range| range.start >= at)
+                .map(|range| range.start - at..range.end - at)
+                .collect(),
+        })
+    }
+}
+
+/// Lexer state at the end of the buffer seen so far
+#[derive(Debug, Clone, Default)]
+struct Lexer {
+    /// Open quote character, if inside a quoted section
+    quote: Option<char>,
+    /// Whether the previous character was an escape outside single quotes
+    escape: bool,
+    /// Word being built, if the buffer doesn't end in unquoted whitespace
+    current: Option<Token>,
+    /// Start in the current word's text of the expansion being read, and
+    /// whether it is braced
+    expansion: Option<(usize, bool)>,
+    /// Where a quote just closed would reopen with a literal quote, when
+    /// doubled as in PowerShell's `'it''s'`
+    reopen: Option<(usize, char)>,
+    /// Whether the previous character was an unquoted `&` in PowerShell
+    ampersand: bool,
+}
+
+impl Lexer {
+    /// End the expansion being read, keeping it if it names something
+    fn close_expansion(&mut self) {
+        if let Some((start, _)) = self.expansion.take()
+            && let Some(token) = &mut self.current
+            && token.text.len() > start + 1
+        {
+            token.expansions.push(start..token.text.len());
+        }
+    }
+}
+
+/// A command substitution or subshell being read, and what it interrupted
+#[derive(Debug, Clone)]
+struct Frame {
+    /// `)` or `` ` ``
+    closer: char,
+    /// Quote the substitution is in
+    quote: Option<char>,
+    /// Completed words of the enclosing command
+    tokens: Vec<Token>,
+    /// Word of the enclosing command the substitution is part of; none for
+    /// a subshell
+    current: Option<Token>,
+}
+
+/// Tokenization of a buffer that can be resumed when the buffer grows.
+///
+/// Typing only ever appends to the buffer, so instead of re-lexing the whole
+/// command line on every keystroke the lexer keeps its state and only
+/// consumes the new suffix.
+#[derive(Debug, Clone, Default)]
+pub struct ParseState {
+    buffer: String,
+    /// Completed words of the command being typed
+    tokens: Vec<Token>,
+    /// Commands that ended before it
+    commands: Vec<Vec<Token>>,
+    /// Substitutions and subshells the command being typed is nested in,
+    /// innermost last
+    frames: Vec<Frame>,
+    lexer: Lexer,
+    syntax: Syntax,
+}
+
+impl ParseState {
+    pub fn new(buffer: &str) -> Self {
+        Self::with_syntax(buffer, Syntax::Posix)
+    }
+
+    pub fn with_syntax(buffer: &str, syntax: Syntax) -> Self {
+        let mut state = Self {
+            syntax,
+            ..Self::default()
+        };
+        state.feed(buffer);
+        state
+    }
+
+    /// Move to `buffer`, resuming from the current state when it extends the
+    /// previous buffer; returns whether parsing resumed
+    pub fn advance(&mut self, buffer: &str) -> bool {
+        match buffer.strip_prefix(self.buffer.as_str()) {
+            Some(suffix) => {
+                self.feed(suffix);
+                true
+            }
+            None => {
+                *self = Self::with_syntax(buffer, self.syntax);
+                false
+            }
+        }
+    }
+
+    /// Completed words before the one under the cursor, of the command it
+    /// is in: after `git log | grep `, those of `grep`
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    /// The word being typed, if the buffer doesn't end in whitespace. It
+    /// ends at the cursor, possibly inside open quotes or an expansion.
+    pub fn current_token(&self) -> Option<&Token> {
+        self.lexer.current.as_ref()
+    }
+
+    /// The word being typed, continued through the part of it in `rest`, the
+    /// buffer after the cursor: `chec` and `kout` make `checkout`. `None`
+    /// when the cursor isn't touching a word, or only the start of one.
+    pub fn whole_word(&self, rest: &str) -> Option<Token> {
+        let start = self.current_token()?.start;
+        let mut state = self.clone();
+        for (i, c) in rest.char_indices() {
+            state.feed(&rest[i..i + c.len_utf8()]);
+            if state
+                .current_token()
+                .is_none_or(|token| token.start != start)
+            {
+                break;
+            }
+        }
+        // Ended by whitespace or a separator after the cursor, or still open
+        state
+            .tokens
+            .iter()
+            .chain(state.commands.iter().flatten())
+            .chain(state.lexer.current.as_ref())
+            .find(|token| token.start == start)
+            .or(self.current_token())
+            .cloned()
+    }
+
+    fn feed(&mut self, input: &str) {
+        let offset = self.buffer.len();
+        self.buffer.push_str(input);
+        let syntax = self.syntax;
+
+        for (i, c) in input.char_indices() {
+            let at = offset + i;
+            let next = at + c.len_utf8();
+            let lexer = &mut self.lexer;
+            if lexer.escape {
+                lexer.escape = false;
+                // A POSIX backslash-newline continues the line and is removed,
+                // along with the word the backslash alone would have started
+                if syntax == Syntax::Posix && c == '\n' {
+                    match &mut lexer.current {
+                        Some(token) if token.start + 1 == at && token.text.is_empty() => {
+                            lexer.current = None;
+                        }
+                        Some(token) => token.end = next,
+                        None => {}
+                    }
+                    continue;
+                }
+                let token = word(&mut lexer.current, at);
+                // In POSIX double quotes a backslash only escapes `$`, a
+                // backtick, `"`, itself and a newline, and is kept otherwise
+                if syntax == Syntax::Posix
+                    && lexer.quote == Some('"')
+                    && !matches!(c, '$' | '`' | '"' | '\\')
+                {
+                    token.text.push('\\');
+                }
+                token.text.push(c);
+                token.end = next;
+                continue;
+            }
+            // A doubled quote stands for itself in PowerShell
+            if let Some((reopen, quote)) = lexer.reopen.take()
+                && reopen == at
+                && c == quote
+            {
+                lexer.quote = Some(c);
+                let token = word(&mut lexer.current, at);
+                token.text.push(c);
+                token.end = next;
+                continue;
+            }
+            // PowerShell separates commands with `&&`; a lone `&` calls the
+            // command named after it, or runs the one before in the background
+            if std::mem::take(&mut lexer.ampersand) && c == '&' {
+                self.end_command();
+                continue;
+            }
+            // cmd.exe's `%NAME%` runs to the next `%`
+            if syntax == Syntax::Cmd && lexer.expansion.is_some() {
+                if c == '%' {
+                    let token = word(&mut lexer.current, at);
+                    token.text.push(c);
+                    token.end = next;
+                    lexer.close_expansion();
+                    continue;
+                }
+            } else if let Some((start, braced)) = lexer.expansion {
+                // `$NAME` runs to the first character that can't be in a name,
+                // `${NAME}` to the closing brace, and `$?`, `$1` are one
+                // character; PowerShell's `$env:NAME` takes the colon
+                let len = lexer.current.as_ref().map_or(0, |token| token.text.len());
+                let first = len == start + 1;
+                if first && c == '(' {
+                    // `$(` substitutes a command, not a variable
+                    lexer.expansion = None;
+                    if let Some(token) = &mut lexer.current {
+                        token.text.pop();
+                    }
+                    self.open(')', at, true);
+                    continue;
+                } else if first && c == '{' {
+                    lexer.expansion = Some((start, true));
+                } else if braced {
+                    if c == '}' {
+                        let token = word(&mut lexer.current, at);
+                        token.text.push(c);
+                        token.end = next;
+                        lexer.close_expansion();
+                        continue;
+                    }
+                } else if first && (c.is_ascii_digit() || "?$#!@*-".contains(c)) {
+                    let token = word(&mut lexer.current, at);
+                    token.text.push(c);
+                    token.end = next;
+                    lexer.close_expansion();
+                    continue;
+                } else if !(c.is_ascii_alphanumeric() || c == '_' || scope(lexer, syntax, c)) {
+                    lexer.close_expansion();
+                }
+            }
+            // `2>&1` and `>|` redirect rather than separate commands
+            let redirect = lexer
+                .current
+                .as_ref()
+                .is_some_and(|token| token.text.ends_with(['>', '<']));
+            match (lexer.quote, c) {
+                (Some('\''), '\'') | (Some('"'), '"') => {
+                    lexer.quote = None;
+                    if syntax == Syntax::PowerShell {
+                        lexer.reopen = Some((next, c));
+                    }
+                }
+                (Some('"') | None, c)
+                    if c == syntax.escape() && (syntax != Syntax::Cmd || lexer.quote.is_none()) =>
+                {
+                    lexer.escape = true;
+                    word(&mut lexer.current, at);
+                }
+                (Some('"') | None, '%') if syntax == Syntax::Cmd && lexer.expansion.is_none() => {
+                    let token = word(&mut lexer.current, at);
+                    lexer.expansion = Some((token.text.len(), false));
+                    token.text.push(c);
+                }
+                (Some('"') | None, '$') if syntax != Syntax::Cmd && lexer.expansion.is_none() => {
+                    let token = word(&mut lexer.current, at);
+                    lexer.expansion = Some((token.text.len(), false));
+                    token.text.push(c);
+                }
+                (Some('"') | None, '`') if syntax == Syntax::Posix => {
+                    if self.frames.last().is_some_and(|frame| frame.closer == '`') {
+                        self.close();
+                    } else {
+                        self.open('`', at, true);
+                        continue;
+                    }
+                }
+                (None, ')') => {
+                    if self.frames.last().is_some_and(|frame| frame.closer == ')') {
+                        self.close();
+                    } else {
+                        self.end_command();
+                        continue;
+                    }
+                }
+                (Some(_), _) => word(&mut lexer.current, at).text.push(c),
+                // cmd.exe has no single quotes, and `;` doesn't end a command
+                (None, '\'' | ';') if syntax == Syntax::Cmd => {
+                    word(&mut lexer.current, at).text.push(c)
+                }
+                (None, '\'' | '"') => {
+                    lexer.quote = Some(c);
+                    word(&mut lexer.current, at);
+                }
+                (None, '(') => {
+                    self.open(')', at, false);
+                    continue;
+                }
+                (None, '|' | '&') if redirect => word(&mut lexer.current, at).text.push(c),
+                (None, '&') if syntax == Syntax::PowerShell => {
+                    lexer.ampersand = true;
+                    if let Some(token) = lexer.current.take() {
+                        self.tokens.push(token);
+                    }
+                    continue;
+                }
+                (None, '|' | '&' | ';' | '\n') => {
+                    self.end_command();
+                    continue;
+                }
+                (None, c) if c.is_whitespace() => {
+                    lexer.expansion = None;
+                    if let Some(token) = lexer.current.take() {
+                        self.tokens.push(token);
+                    }
+                    continue;
+                }
+                (None, c) => word(&mut lexer.current, at).text.push(c),
+            }
+            if let Some(token) = &mut self.lexer.current {
+                token.end = next;
+            }
+        }
+    }
+
+    /// Start a command nested in the one being typed, ending at `closer`. A
+    /// substitution at `at` is part of a word of the enclosing command.
+    fn open(&mut self, closer: char, at: usize, substitution: bool) {
+        if substitution {
+            word(&mut self.lexer.current, at);
+        } else if let Some(token) = self.lexer.current.take() {
+            self.tokens.push(token);
+        }
+        self.lexer.expansion = None;
+        self.frames.push(Frame {
+            closer,
+            quote: self.lexer.quote.take(),
+            tokens: std::mem::take(&mut self.tokens),
+            current: self.lexer.current.take(),
+        });
+    }
+
+    /// End the innermost nested command, back to the one it interrupted
+    fn close(&mut self) {
+        self.end_command();
+        if let Some(frame) = self.frames.pop() {
+            self.tokens = frame.tokens;
+            self.lexer.current = frame.current;
+            self.lexer.quote = frame.quote;
+        }
+    }
+
+    /// End the command being typed; the next word starts another
+    fn end_command(&mut self) {
+        self.lexer.expansion = None;
+        if let Some(token) = self.lexer.current.take() {
+            self.tokens.push(token);
+        }
+        if !self.tokens.is_empty() {
+            self.commands.push(std::mem::take(&mut self.tokens));
+        }
+    }
+}
+
+/// `tokens` with a leading abbreviation replaced by the words it expands to.
+///
+/// Expanded words all start where the abbreviation did, and placeholders are
+/// replaced by their defaults, so completion continues as if the user had
+/// typed the expansion.
+pub fn expand_abbreviation(
+    tokens: &[Token],
+    abbreviations: &BTreeMap<String, String>,
+) -> Vec<Token> {
+    let Some((first, rest)) = tokens.split_first() else {
+        return Vec::new();
+    };
+    let Some(expansion) = abbreviations.get(&first.text) else {
+        return tokens.to_vec();
+    };
+    let expanded = ParseState::new(&snippet::Snippet::parse(expansion).text);
+    expanded
+        .tokens
+        .into_iter()
+        .chain(expanded.lexer.current)
+        .map(|token| Token {
+            start: first.start,
+            end: first.end,
+            ..token
+        })
+        .chain(rest.iter().cloned())
+        .collect()
+}
+
+/// Whether `c` continues the name of the expansion being read past what
+/// `$NAME` allows: the colon of PowerShell's `$env:NAME`
+fn scope(lexer: &Lexer, syntax: Syntax, c: char) -> bool {
+    syntax == Syntax::PowerShell
+        && c == ':'
+        && lexer
+            .expansion
+            .zip(lexer.current.as_ref())
+            .is_some_and(|((start, _), token)| token.text[start + 1..].eq_ignore_ascii_case("env"))
+}
+
+/// The word being built, starting one at `at` if needed
+fn word(current: &mut Option<Token>, at: usize) -> &mut Token {
+    current.get_or_insert_with(|| Token {
+        start: at,
+        end: at,
+        ..Token::default()
+    })
+}
+
+/// Parse state of the last buffer seen from each shell session
+#[derive(Debug, Default)]
+pub struct SessionCache {
+    sessions: Mutex<HashMap<String, (u64, ParseState)>>,
+    /// Monotonic counter used to find the least recently used session
+    clock: AtomicU64,
+}
+
+impl SessionCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse `buffer` in `syntax` for `session`, resuming from that session's
+    /// previous buffer when possible, and hand the result to `f`. Only the
+    /// lexing holds the lock shared by all sessions; `f` gets a copy.
+    pub fn parse<R>(
+        &self,
+        session: &str,
+        buffer: &str,
+        syntax: Syntax,
+        f: impl FnOnce(&ParseState) -> R,
+    ) -> R {
+        let tick = self.clock.fetch_add(1, Ordering::Relaxed);
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+
+        if !sessions.contains_key(session) && sessions.len() >= MAX_SESSIONS {
+            let oldest = sessions
+                .iter()
+                .min_by_key(|(_, (used, _))| *used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                sessions.remove(&oldest);
+            }
+        }
+
+        let (used, state) = sessions
+            .entry(session.to_string())
+            .or_insert_with(|| (tick, ParseState::default()));
+        *used = tick;
+        if state.syntax != syntax {
+            *state = ParseState::with_syntax("", syntax);
+        }
+        state.advance(buffer);
+        let state = state.clone();
+        drop(sessions);
+        f(&state)
+    }
 }
 
-/// Tokenize a command buffer into parts
-#[allow(dead_code)]
-fn tokenize(buffer: &str) -> Vec<&str> {
-    buffer.split_whitespace().collect()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The words of the command at the end of `buffer`, the one being typed
+    /// included
+    fn words(buffer: &str, syntax: Syntax) -> Vec<String> {
+        let state = ParseState::with_syntax(buffer, syntax);
+        state
+            .tokens()
+            .iter()
+            .chain(state.current_token())
+            .map(|token| token.text.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_backslash_in_double_quotes_escapes_only_specials() {
+        assert_eq!(words(r#"echo "a\b""#, Syntax::Posix), ["echo", r"a\b"]);
+        assert_eq!(
+            words(r#"echo "\$HOME \" \\ \`""#, Syntax::Posix),
+            ["echo", r#"$HOME " \ `"#]
+        );
+    }
+
+    #[test]
+    fn test_backslash_outside_quotes_escapes_anything() {
+        assert_eq!(
+            words(r"echo a\b a\ b \'", Syntax::Posix),
+            ["echo", "ab", "a b", "'"]
+        );
+    }
+
+    #[test]
+    fn test_backslash_in_single_quotes_is_literal() {
+        assert_eq!(
+            words(r"echo 'a\b' 'c\\'", Syntax::Posix),
+            ["echo", r"a\b", r"c\\"]
+        );
+    }
+
+    /// The words of `buffer` with the part of the buffer each spans
+    fn spans(buffer: &str) -> Vec<(String, &str)> {
+        let state = ParseState::new(buffer);
+        state
+            .tokens()
+            .iter()
+            .chain(state.current_token())
+            .map(|token| (token.text.clone(), &buffer[token.span()]))
+            .collect()
+    }
+
+    #[test]
+    fn test_quoted_word_spans_its_quotes() {
+        assert_eq!(
+            spans(r#"git commit -m "fix bug" 'x y'z"#),
+            [
+                ("git".to_string(), "git"),
+                ("commit".to_string(), "commit"),
+                ("-m".to_string(), "-m"),
+                ("fix bug".to_string(), r#""fix bug""#),
+                ("x yz".to_string(), "'x y'z"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_multibyte_word_spans_byte_range() {
+        assert_eq!(
+            spans(r#"echo é "ü x""#),
+            [
+                ("echo".to_string(), "echo"),
+                ("é".to_string(), "é"),
+                ("ü x".to_string(), r#""ü x""#),
+            ]
+        );
+        let state = ParseState::new(r#"echo é "ü x""#);
+        assert_eq!(state.current_token().unwrap().span(), 8..14);
+    }
+
+    #[test]
+    fn test_expansion_name_ends_at_first_non_name_char() {
+        let state = ParseState::new(r#"echo $HOME/bin ${USER}x $1a "$PWD" '$NO' \$NO "#);
+        let expansions: Vec<_> = state
+            .tokens()
+            .iter()
+            .map(|token| (token.text.as_str(), token.expansions.first().cloned()))
+            .collect();
+        assert_eq!(
+            expansions,
+            [
+                ("echo", None),
+                ("$HOME/bin", Some(0..5)),
+                ("${USER}x", Some(0..7)),
+                ("$1a", Some(0..2)),
+                ("$PWD", Some(0..4)),
+                ("$NO", None),
+                ("$NO", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expand_known_variables_are_replaced() {
+        let state = ParseState::new("ls $HOME/$UNSET/${HOME}x ");
+        let token = &state.tokens()[1];
+        let env = BTreeMap::from([("HOME".to_string(), "/home/me".to_string())]);
+        assert_eq!(
+            token.expand(token.text.len(), &env),
+            "/home/me/$UNSET//home/mex"
+        );
+        // An expansion reaching past `end` is kept as typed
+        assert_eq!(token.expand(3, &env), "$HO");
+    }
+
+    #[test]
+    fn test_cursor_inside_word_gives_partial_word() {
+        let state = ParseState::new(r#"git checkout "feat"#);
+        let current = state.current_token().unwrap();
+        assert_eq!((current.text.as_str(), current.span()), ("feat", 13..18));
+        assert_eq!(state.lexer.quote, Some('"'));
+        // An expansion still being typed isn't one yet
+        let state = ParseState::new("echo $HO");
+        assert_eq!(state.current_token().unwrap().expansions, []);
+        // Trailing whitespace means the next word hasn't started
+        assert!(ParseState::new("git ").current_token().is_none());
+    }
+
+    #[test]
+    fn test_whole_word_continues_past_cursor() {
+        let state = ParseState::new("git chec");
+        let word = state.whole_word("kout main").unwrap();
+        assert_eq!((word.text.as_str(), word.span()), ("checkout", 4..12));
+        assert!(ParseState::new("git ").whole_word("main").is_none());
+    }
+
+    #[test]
+    fn test_backslash_newline_is_removed() {
+        assert_eq!(
+            words("echo a\\\nb \\\n c \"d\\\ne\"", Syntax::Posix),
+            ["echo", "ab", "c", "de"]
+        );
+        let state = ParseState::new("git \\\n");
+        assert_eq!(state.tokens().len(), 1);
+        assert!(state.current_token().is_none());
+    }
+
+    #[test]
+    fn test_powershell_backtick_escapes_next_char() {
+        assert_eq!(
+            words(r#"Write-Host `"a`" `$HOME a`` b`;c"#, Syntax::PowerShell),
+            ["Write-Host", r#""a""#, "$HOME", "a`", "b;c"]
+        );
+        // A backslash is a path separator, not an escape
+        assert_eq!(
+            words(r"cd C:\Users\me", Syntax::PowerShell),
+            ["cd", r"C:\Users\me"]
+        );
+    }
+
+    #[test]
+    fn test_powershell_doubled_quotes_are_literal() {
+        assert_eq!(
+            words(r#"echo 'it''s' "say ""hi""" ''"#, Syntax::PowerShell),
+            ["echo", "it's", r#"say "hi""#, ""]
+        );
+        // Resumed between the two quotes, the second still reopens
+        let mut state = ParseState::with_syntax("echo 'it'", Syntax::PowerShell);
+        state.advance("echo 'it''s");
+        assert_eq!(state.current_token().unwrap().text, "it's");
+    }
+
+    #[test]
+    fn test_powershell_env_expansion_includes_scope() {
+        let state = ParseState::with_syntax(r"ls $env:USERPROFILE\bin ", Syntax::PowerShell);
+        let token = &state.tokens()[1];
+        assert_eq!(token.expansions.first(), Some(&(0..16)));
+        let env = BTreeMap::from([("USERPROFILE".to_string(), r"C:\Users\me".to_string())]);
+        assert_eq!(token.expand(token.text.len(), &env), r"C:\Users\me\bin");
+    }
+
+    #[test]
+    fn test_cmd_caret_escapes_outside_double_quotes() {
+        assert_eq!(
+            words(r#"echo a^&b "x^y" ^"q"#, Syntax::Cmd),
+            ["echo", "a&b", "x^y", r#""q"#]
+        );
+    }
+
+    #[test]
+    fn test_cmd_single_quotes_and_semicolons_are_literal() {
+        assert_eq!(
+            words("echo 'a b' c;d", Syntax::Cmd),
+            ["echo", "'a", "b'", "c;d"]
+        );
+    }
+
+    #[test]
+    fn test_cmd_percent_expansion_ends_at_next_percent() {
+        let state = ParseState::with_syntax(r#"dir %USERPROFILE%\bin "%TEMP%" 50% "#, Syntax::Cmd);
+        let expansions: Vec<_> = state
+            .tokens()
+            .iter()
+            .map(|token| (token.text.as_str(), token.expansions.first().cloned()))
+            .collect();
+        assert_eq!(
+            expansions,
+            [
+                ("dir", None),
+                (r"%USERPROFILE%\bin", Some(0..13)),
+                ("%TEMP%", Some(0..6)),
+                ("50%", None),
+            ]
+        );
+    }
+
+    fn texts(state: &ParseState) -> Vec<String> {
+        state
+            .tokens()
+            .iter()
+            .chain(state.current_token())
+            .map(|token| token.text.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_session_resumes_from_its_own_buffer() {
+        let cache = SessionCache::new();
+        cache.parse("a", "git com", Syntax::Posix, |_| ());
+        cache.parse("b", "ls -", Syntax::Posix, |_| ());
+        let words = cache.parse("a", "git commit -m \"x", Syntax::Posix, texts);
+        assert_eq!(words, ["git", "commit", "-m", "x"]);
+        // An edit that isn't an extension starts over
+        let words = cache.parse("a", "git status", Syntax::Posix, texts);
+        assert_eq!(words, ["git", "status"]);
+        assert_eq!(
+            cache.parse("b", "ls -la", Syntax::Posix, texts),
+            ["ls", "-la"]
+        );
+    }
+
+    #[test]
+    fn test_session_syntax_change_restarts_parse() {
+        let cache = SessionCache::new();
+        cache.parse("a", "echo a", Syntax::Posix, |_| ());
+        let words = cache.parse("a", r"echo a\b", Syntax::PowerShell, texts);
+        assert_eq!(words, ["echo", r"a\b"]);
+    }
+
+    #[test]
+    fn test_session_cache_full_drops_least_recently_used() {
+        let cache = SessionCache::new();
+        for session in 0..MAX_SESSIONS {
+            cache.parse(&session.to_string(), "git", Syntax::Posix, |_| ());
+        }
+        // Session 0 is used again, so session 1 is the oldest
+        cache.parse("0", "git", Syntax::Posix, |_| ());
+        cache.parse("new", "git", Syntax::Posix, |_| ());
+        let sessions = cache.sessions.lock().unwrap();
+        assert_eq!(sessions.len(), MAX_SESSIONS);
+        assert!(sessions.contains_key("0"));
+        assert!(!sessions.contains_key("1"));
+    }
+
+    #[test]
+    fn test_session_parse_inside_a_parse_gets_its_own_words() {
+        let cache = SessionCache::new();
+        let words = cache.parse("a", "git ", Syntax::Posix, |_| {
+            cache.parse("b", "ls", Syntax::Posix, texts)
+        });
+        assert_eq!(words, ["ls"]);
+    }
+
+    #[test]
+    fn test_resumed_state_parses_like_fresh_state() {
+        let buffer = r#"git commit -m "fix a\b" --author \"me"#;
+        for split in (0..=buffer.len()).filter(|&at| buffer.is_char_boundary(at)) {
+            let mut state = ParseState::new(&buffer[..split]);
+            assert!(state.advance(buffer));
+            let fresh = ParseState::new(buffer);
+            assert_eq!(state.tokens(), fresh.tokens(), "split at {split}");
+            assert_eq!(
+                state.current_token(),
+                fresh.current_token(),
+                "split at {split}"
+            );
+        }
+    }
 }
@@ -0,0 +1,98 @@
+//! Placeholders in abbreviation expansions.
+//!
+//! An expansion can mark spots for the user to fill in: `$1`, `$2`, … or
+//! `${1:default}` with a default value. Fields are visited in numeric order,
+//! then in order of appearance. A `$` not followed by a digit or `{digit` is
+//! left alone, so `$HOME` stays as written.
+
+use std::ops::Range;
+
+/// Expansion text with placeholder markers removed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snippet {
+    /// Text with each placeholder replaced by its default (or nothing)
+    pub text: String,
+    /// Byte ranges of the placeholders in `text`, in tab order
+    pub fields: Vec<Range<usize>>,
+}
+
+impl Snippet {
+    pub fn parse(template: &str) -> Self {
+        let mut text = String::new();
+        let mut fields: Vec<(u32, Range<usize>)> = Vec::new();
+        let mut rest = template;
+
+        while let Some(at) = rest.find('$') {
+            text.push_str(&rest[..at]);
+            rest = &rest[at..];
+            match placeholder(rest) {
+                Some((number, default, len)) => {
+                    let start = text.len();
+                    text.push_str(default);
+                    fields.push((number, start..text.len()));
+                    rest = &rest[len..];
+                }
+                None => {
+                    text.push('$');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        text.push_str(rest);
+
+        // Stable, so repeated numbers keep their order of appearance
+        fields.sort_by_key(|(number, _)| *number);
+        Self {
+            text,
+            fields: fields.into_iter().map(|(_, range)| range).collect(),
+        }
+    }
+
+    /// Whether the expansion has anything to fill in
+    pub fn has_fields(&self) -> bool {
+        !self.fields.is_empty()
+    }
+
+    /// Replace the contents of field `index` with `value`
+    pub fn set(&mut self, index: usize, value: &str) {
+        let Some(range) = self.fields.get(index).cloned() else {
+            return;
+        };
+        self.text.replace_range(range.clone(), value);
+        let shift = value.len() as isize - range.len() as isize;
+        for (i, field) in self.fields.iter_mut().enumerate() {
+            if i == index {
+                *field = range.start..range.start + value.len();
+            } else if field.start >= range.end {
+                field.start = field.start.saturating_add_signed(shift);
+                field.end = field.end.saturating_add_signed(shift);
+            }
+        }
+    }
+
+    /// Current contents of field `index`
+    pub fn value(&self, index: usize) -> &str {
+        self.fields
+            .get(index)
+            .map_or("", |range| &self.text[range.clone()])
+    }
+}
+
+/// `$N` or `${N:default}` at the start of `text`: its number, default, and length
+fn placeholder(text: &str) -> Option<(u32, &str, usize)> {
+    let after = &text[1..];
+    if let Some(inner) = after.strip_prefix('{') {
+        let close = inner.find('}')?;
+        let body = &inner[..close];
+        let (number, default) = body.split_once(':').unwrap_or((body, ""));
+        let number = number.parse().ok().filter(|_| is_digits(number))?;
+        return Some((number, default, close + 3));
+    }
+    let digits = after.len() - after.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let number = after[..digits].parse().ok()?;
+    Some((number, "", digits + 1))
+}
+
+fn is_digits(text: &str) -> bool {
+    !text.is_empty() && text.chars().all(|c| c.is_ascii_digit())
+}
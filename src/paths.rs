@@ -0,0 +1,105 @@
+//! On-disk locations used by the daemon, client, and spec loader.
+//!
+//! Follows the XDG base-directory spec, with the usual platform equivalents
+//! on macOS when the XDG variables are unset:
+//!
+//! | Kind    | XDG variable       | Linux fallback   | macOS fallback                  |
+//! | ------- | ------------------ | ---------------- | ------------------------------- |
+//! | config  | `XDG_CONFIG_HOME`  | `~/.config`      | `~/.config`                     |
+//! | cache   | `XDG_CACHE_HOME`   | `~/.cache`       | `~/Library/Caches`              |
+//! | data    | `XDG_DATA_HOME`    | `~/.local/share` | `~/Library/Application Support` |
+//! | state   | `XDG_STATE_HOME`   | `~/.local/state` | `~/Library/Logs`                |
+//! | runtime | `XDG_RUNTIME_DIR`  | `$TMPDIR`        | `$TMPDIR`                       |
+//!
+//! Data shared by all users of a machine, such as specs an administrator
+//! provisions for a fleet, lives under each of `XDG_DATA_DIRS`
+//! (`/usr/local/share:/usr/share` when unset) and is only ever read.
+
+use std::path::PathBuf;
+
+/// Directory name used under each base directory
+const APP_DIR: &str = "autocomplete-rs";
+
+/// Directory holding `config.toml` and user specs
+pub fn config_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config", ".config").map(|dir| dir.join(APP_DIR))
+}
+
+/// Directory for regenerable data (indexes, generator caches)
+pub fn cache_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_CACHE_HOME", ".cache", "Library/Caches").map(|dir| dir.join(APP_DIR))
+}
+
+/// Directory for persistent user data (history, statistics)
+pub fn data_dir() -> Option<PathBuf> {
+    xdg_dir(
+        "XDG_DATA_HOME",
+        ".local/share",
+        "Library/Application Support",
+    )
+    .map(|dir| dir.join(APP_DIR))
+}
+
+/// Directories of read-only data shared by all users, most important first
+pub fn system_data_dirs() -> Vec<PathBuf> {
+    let dirs = std::env::var_os("XDG_DATA_DIRS")
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".into());
+    std::env::split_paths(&dirs)
+        .filter(|dir| dir.is_absolute())
+        .map(|dir| dir.join(APP_DIR))
+        .collect()
+}
+
+/// Directory for logs and other state worth keeping across restarts
+pub fn state_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_STATE_HOME", ".local/state", "Library/Logs").map(|dir| dir.join(APP_DIR))
+}
+
+/// Daemon log file
+pub fn log_file() -> Option<PathBuf> {
+    state_dir().map(|dir| dir.join("daemon.log"))
+}
+
+/// Default config file location
+pub fn config_file() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("config.toml"))
+}
+
+/// Default daemon socket.
+///
+/// Lives in `$XDG_RUNTIME_DIR` when available, which is private to the user
+/// and cleared on logout. Otherwise falls back to a per-user name in the
+/// temp directory.
+pub fn socket_path() -> PathBuf {
+    match absolute_env("XDG_RUNTIME_DIR") {
+        Some(dir) => dir.join(format!("{}.sock", APP_DIR)),
+        None => {
+            let user = std::env::var("USER").unwrap_or_else(|_| "default".to_string());
+            std::env::temp_dir().join(format!("{}-{}.sock", APP_DIR, user))
+        }
+    }
+}
+
+/// The user's home directory
+pub fn home_dir() -> Option<PathBuf> {
+    absolute_env("HOME")
+}
+
+/// Read a path from the environment, ignoring relative values as the XDG spec requires
+fn absolute_env(var: &str) -> Option<PathBuf> {
+    std::env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+}
+
+fn xdg_dir(var: &str, unix_default: &str, macos_default: &str) -> Option<PathBuf> {
+    absolute_env(var).or_else(|| {
+        let relative = if cfg!(target_os = "macos") {
+            macos_default
+        } else {
+            unix_default
+        };
+        home_dir().map(|home| home.join(relative))
+    })
+}
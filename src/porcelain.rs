@@ -0,0 +1,166 @@
+//! Suggestions as plain text records, for scripts and pickers.
+//!
+//! `complete --format` prints every suggestion through a template such as
+//! `text\tdesc` instead of showing the popup. Field values never contain
+//! the characters records are split on: tabs, newlines and other control
+//! characters in them become spaces.
+
+use crate::daemon::{self, Suggestion};
+use anyhow::{Context, Result, bail};
+use std::collections::BTreeMap;
+use unicode_width::UnicodeWidthStr;
+
+/// A value of each suggestion a template can name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Field {
+    /// The text shown and matched
+    Text,
+    /// The description
+    Desc,
+    /// Where the suggestion came from (`spec`, `files`, `history`, ...)
+    Source,
+    /// What goes into the buffer, which differs from the text for templates
+    Insert,
+}
+
+impl Field {
+    const ALL: [(&str, Field); 4] = [
+        ("text", Field::Text),
+        ("desc", Field::Desc),
+        ("source", Field::Source),
+        ("insert", Field::Insert),
+    ];
+
+    fn named(name: &str) -> Result<Self> {
+        Self::ALL
+            .iter()
+            .find(|(field, _)| *field == name)
+            .map(|(_, field)| *field)
+            .with_context(|| {
+                let names: Vec<_> = Self::ALL.iter().map(|(name, _)| *name).collect();
+                format!("unknown field `{name}`; expected {}", names.join(", "))
+            })
+    }
+
+    fn value(self, suggestion: &Suggestion) -> String {
+        match self {
+            Field::Text => suggestion.text.clone(),
+            Field::Desc => suggestion.description.clone(),
+            Field::Source => serde_json::to_value(suggestion.source)
+                .ok()
+                .and_then(|value| value.as_str().map(str::to_string))
+                .unwrap_or_default(),
+            Field::Insert => suggestion.insertion().to_string(),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Part {
+    Field(Field),
+    Literal(String),
+}
+
+/// How each suggestion is printed
+#[derive(Debug)]
+pub struct Format {
+    parts: Vec<Part>,
+    /// Columns each field is padded or cut to
+    widths: BTreeMap<Field, usize>,
+    /// Ends every record
+    terminator: char,
+}
+
+impl Format {
+    /// Parse a template, `FIELD=COLUMNS` widths and whether records end in
+    /// NUL rather than a newline.
+    ///
+    /// Words in the template name fields; `\t`, `\n`, `\0` and `\\` are
+    /// escapes and anything else is printed as is.
+    pub fn parse(template: &str, widths: &[String], null: bool) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c.is_ascii_alphabetic() {
+                let mut name = String::from(c);
+                while let Some(&c) = chars.peek().filter(|c| c.is_ascii_alphabetic()) {
+                    name.push(c);
+                    chars.next();
+                }
+                if !literal.is_empty() {
+                    parts.push(Part::Literal(std::mem::take(&mut literal)));
+                }
+                parts.push(Part::Field(Field::named(&name)?));
+            } else if c == '\\' {
+                literal.push(match chars.next() {
+                    Some('t') => '\t',
+                    Some('n') => '\n',
+                    Some('0') => '\0',
+                    Some('\\') => '\\',
+                    Some(other) => bail!("unknown escape `\\{other}` in the format"),
+                    None => bail!("the format ends in a lone `\\`"),
+                });
+            } else {
+                literal.push(c);
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+
+        let widths = widths
+            .iter()
+            .map(|width| {
+                let (name, columns) = width
+                    .split_once('=')
+                    .with_context(|| format!("expected FIELD=COLUMNS, got `{width}`"))?;
+                let columns = columns
+                    .parse()
+                    .with_context(|| format!("invalid width `{columns}` for {name}"))?;
+                Ok((Field::named(name)?, columns))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            parts,
+            widths,
+            terminator: if null { '\0' } else { '\n' },
+        })
+    }
+
+    /// `suggestion` as one record, terminator included
+    pub fn record(&self, suggestion: &Suggestion) -> String {
+        let mut record = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => record.push_str(text),
+                Part::Field(field) => {
+                    let value: String = field
+                        .value(suggestion)
+                        .chars()
+                        .map(|c| if c.is_control() { ' ' } else { c })
+                        .collect();
+                    match self.widths.get(field) {
+                        Some(&width) => record.push_str(&pad(&value, width)),
+                        None => record.push_str(&value),
+                    }
+                }
+            }
+        }
+        record.push(self.terminator);
+        record
+    }
+}
+
+/// `text` padded with spaces or cut with an ellipsis to `width` columns
+fn pad(text: &str, width: usize) -> String {
+    let used = text.width();
+    if used > width {
+        let cut = daemon::truncate(text, width, "…");
+        let padding = width.saturating_sub(cut.width());
+        cut + &" ".repeat(padding)
+    } else {
+        text.to_string() + &" ".repeat(width - used)
+    }
+}
@@ -0,0 +1,233 @@
+//! `autocomplete-rs report`: a diagnostic bundle to attach to bug reports.
+//!
+//! Everything goes into one tar archive of plain-text files so users can
+//! read exactly what they are about to share. Home directory, user name and
+//! host name are replaced with placeholders in every file, and log and trace
+//! lines that look like they hold a credential are withheld.
+
+use crate::config::{self, inspect};
+use crate::daemon::CompletionRequest;
+use crate::{client, i18n, install, paths, specs, stats};
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+mod tar;
+
+/// Daemon log lines included in the bundle
+const LOG_LINES: usize = 500;
+
+/// Build the bundle and write it to `output` (or a timestamped file in the
+/// current directory), returning its path and the files it contains
+pub async fn create(
+    output: Option<PathBuf>,
+    socket: &Path,
+    trace: Option<&str>,
+) -> Result<(PathBuf, Vec<&'static str>)> {
+    let redactor = Redactor::from_env();
+    let mut files: Vec<(&'static str, String)> = vec![
+        ("system.txt", system_info(socket).await),
+        ("config.txt", config_info()),
+        ("daemon.log", daemon_log()),
+    ];
+    if let Some(buffer) = trace {
+        files.push(("trace.txt", reproduction_trace(socket, buffer).await));
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    let output =
+        output.unwrap_or_else(|| PathBuf::from(format!("autocomplete-rs-report-{}.tar", now)));
+    let file = std::fs::File::create(&output)
+        .with_context(|| format!("Failed to create {}", output.display()))?;
+    let mut archive = tar::Builder::new(std::io::BufWriter::new(file), now);
+    for (name, contents) in &files {
+        archive.append(
+            &format!("autocomplete-rs-report/{}", name),
+            redactor.redact(contents).as_bytes(),
+        )?;
+    }
+    archive.finish()?;
+
+    Ok((output, files.into_iter().map(|(name, _)| name).collect()))
+}
+
+async fn system_info(socket: &Path) -> String {
+    let env = |name: &str| std::env::var(name).unwrap_or_else(|_| "(unset)".to_string());
+    let uname = std::process::Command::new("uname")
+        .arg("-srm")
+        .output()
+        .ok()
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .unwrap_or_else(|| "(unavailable)".to_string());
+    let daemon = match tokio::net::UnixStream::connect(socket).await {
+        Ok(_) => "running",
+        Err(_) if socket.exists() => "not responding (stale socket)",
+        Err(_) => "not running",
+    };
+    let show = |path: Option<PathBuf>| {
+        path.map(|path| path.display().to_string())
+            .unwrap_or_else(|| "(unknown)".to_string())
+    };
+
+    format!(
+        "autocomplete-rs {}\n\
+         os: {} {} ({})\n\
+         shell: {} (SHELL={})\n\
+         terminal: TERM={} COLORTERM={} TERM_PROGRAM={}\n\
+         locale: {}\n\
+         daemon: {} on {}\n\
+         config dir: {}\n\
+         cache dir: {}\n\
+         data dir: {}\n\
+         system spec dirs: {}\n\
+         log file: {}\n",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        uname,
+        install::invoking_shell(),
+        env("SHELL"),
+        env("TERM"),
+        env("COLORTERM"),
+        env("TERM_PROGRAM"),
+        i18n::env_locale().unwrap_or_else(|| "(unset)".to_string()),
+        daemon,
+        socket.display(),
+        show(paths::config_dir()),
+        show(paths::cache_dir()),
+        show(paths::data_dir()),
+        match specs::system_dirs() {
+            dirs if dirs.is_empty() => "(none)".to_string(),
+            dirs => dirs
+                .iter()
+                .map(|dir| dir.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+        },
+        show(paths::log_file()),
+    )
+}
+
+/// Effective config with provenance, plus any problems `config check` finds
+fn config_info() -> String {
+    let mut out = match config::Config::load_layered(None, serde_json::Value::Null) {
+        Ok(loaded) => inspect::render(&loaded),
+        Err(e) => format!("# Failed to load config: {:#}\n", e),
+    };
+    if let Some(contents) = paths::config_file().and_then(|path| std::fs::read_to_string(path).ok())
+    {
+        let issues = inspect::check(&contents);
+        if !issues.is_empty() {
+            out.push_str("\n# config check:\n");
+            for issue in issues {
+                out.push_str(&format!("# {}\n", issue));
+            }
+        }
+    }
+    out
+}
+
+/// The last [`LOG_LINES`] lines of the daemon log
+fn daemon_log() -> String {
+    let Some(contents) = paths::log_file().and_then(|path| std::fs::read_to_string(path).ok())
+    else {
+        return "(no daemon log found)\n".to_string();
+    };
+    let lines: Vec<&str> = contents.lines().collect();
+    let start = lines.len().saturating_sub(LOG_LINES);
+    withhold_private(&lines[start..].join("\n")) + "\n"
+}
+
+/// `text` with the lines that look like they hold a credential withheld
+fn withhold_private(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            if stats::is_private(line.trim_start()) {
+                "<line withheld: it looks like it holds a credential>"
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Send a completion request for `buffer` and record what came back
+async fn reproduction_trace(socket: &Path, buffer: &str) -> String {
+    let request = CompletionRequest {
+        locale: i18n::env_locale(),
+        ..CompletionRequest::new(buffer, buffer.len())
+    };
+    let request_json = serde_json::to_string_pretty(&request).unwrap_or_default();
+    let started = Instant::now();
+    let response = match client::request(socket, &request).await {
+        Ok(response) => serde_json::to_string_pretty(&response).unwrap_or_default(),
+        Err(e) => format!("error: {:#}", e),
+    };
+    let trace = format!(
+        "request:\n{}\n\nresponse ({:?}):\n{}\n",
+        request_json,
+        started.elapsed(),
+        response
+    );
+    withhold_private(&trace) + "\n"
+}
+
+/// Replaces identifying strings with placeholders
+struct Redactor {
+    replacements: Vec<(String, &'static str)>,
+}
+
+impl Redactor {
+    fn from_env() -> Self {
+        let hostname = std::env::var("HOSTNAME").ok().or_else(|| {
+            std::fs::read_to_string("/etc/hostname")
+                .ok()
+                .map(|name| name.trim().to_string())
+        });
+        let mut replacements: Vec<(String, &'static str)> = [
+            (std::env::var("HOME").ok(), "~"),
+            (std::env::var("USER").ok(), "<user>"),
+            (hostname, "<host>"),
+        ]
+        .into_iter()
+        .filter_map(|(value, placeholder)| Some((value?, placeholder)))
+        // Very short values would redact unrelated text
+        .filter(|(value, _)| value.len() >= 3 && value != "/")
+        .collect();
+        // Longest first, so the home directory goes before the user name inside it
+        replacements.sort_by_key(|(value, _)| std::cmp::Reverse(value.len()));
+        Self { replacements }
+    }
+
+    fn redact(&self, text: &str) -> String {
+        self.replacements
+            .iter()
+            .fold(text.to_string(), |text, (value, placeholder)| {
+                text.replace(value.as_str(), placeholder)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_withhold_private_drops_lines_with_credentials() {
+        let log = "INFO Received request: cursor=4, length=4\n\
+                   DEBUG Request buffer: 'curl -H \"Authorization: Bearer abc\"'\n\
+                   \"buffer\": \"mysql --password=hunter2\",\n\
+                   INFO Shutting down";
+        assert_eq!(
+            withhold_private(log),
+            "INFO Received request: cursor=4, length=4\n\
+             <line withheld: it looks like it holds a credential>\n\
+             <line withheld: it looks like it holds a credential>\n\
+             INFO Shutting down"
+        );
+    }
+}
@@ -0,0 +1,65 @@
+//! Minimal writer for uncompressed ustar archives, enough to bundle a few
+//! text files that `tar -xf` and archive viewers can open.
+
+use std::io::{self, Write};
+
+const BLOCK: usize = 512;
+
+/// Writes regular files into a tar stream
+pub struct Builder<W: Write> {
+    inner: W,
+    mtime: u64,
+}
+
+impl<W: Write> Builder<W> {
+    /// Start an archive whose entries all carry modification time `mtime`
+    pub fn new(inner: W, mtime: u64) -> Self {
+        Self { inner, mtime }
+    }
+
+    /// Add a file named `name` (at most 100 bytes) with `contents`
+    pub fn append(&mut self, name: &str, contents: &[u8]) -> io::Result<()> {
+        if name.len() > 100 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("tar entry name too long: {}", name),
+            ));
+        }
+
+        let mut header = [0u8; BLOCK];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        octal(&mut header[100..108], 0o644);
+        octal(&mut header[108..116], 0);
+        octal(&mut header[116..124], 0);
+        octal(&mut header[124..136], contents.len() as u64);
+        octal(&mut header[136..148], self.mtime);
+        header[156] = b'0';
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+
+        // The checksum is computed with its own field set to spaces
+        header[148..156].fill(b' ');
+        let checksum: u64 = header.iter().map(|&b| u64::from(b)).sum();
+        octal(&mut header[148..155], checksum);
+
+        self.inner.write_all(&header)?;
+        self.inner.write_all(contents)?;
+        let padding = (BLOCK - contents.len() % BLOCK) % BLOCK;
+        self.inner.write_all(&[0u8; BLOCK][..padding])
+    }
+
+    /// Write the end-of-archive marker and return the underlying writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.inner.write_all(&[0u8; BLOCK * 2])?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+/// Zero-padded octal number followed by a NUL, filling `field`
+fn octal(field: &mut [u8], value: u64) {
+    let digits = field.len() - 1;
+    let text = format!("{:0width$o}", value, width = digits);
+    field[..digits].copy_from_slice(&text.as_bytes()[text.len() - digits..]);
+    field[digits] = 0;
+}
@@ -0,0 +1,74 @@
+//! The bundled specs, as packed by the build script.
+//!
+//! The bundle is one embedded blob: a sorted index of command names followed
+//! by their MessagePack specs (see `bundle` in `build.rs` for the layout).
+//! Finding a spec is a binary search over the index, and nothing is decoded
+//! until a spec is asked for, so startup costs the same with two specs or
+//! thousands.
+
+/// First bytes of the bundle, versioning its layout
+const MAGIC: &[u8; 8] = b"ACSPEC01";
+/// Bytes per index entry: name offset and length, spec offset and length
+const ENTRY_LEN: usize = 16;
+/// Magic and entry count
+const HEADER_LEN: usize = MAGIC.len() + 4;
+
+static BYTES: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/specs.bundle"));
+
+/// Index over the embedded bundle
+#[derive(Debug, Clone, Copy)]
+pub struct Bundle {
+    bytes: &'static [u8],
+    count: usize,
+}
+
+impl Bundle {
+    /// The bundle embedded in the binary
+    pub fn embedded() -> Self {
+        // Written by build.rs in the same build, so a mismatch is a bug
+        Self::parse(BYTES).expect("spec bundle built with a different layout")
+    }
+
+    fn parse(bytes: &'static [u8]) -> Option<Self> {
+        if !bytes.starts_with(MAGIC) {
+            return None;
+        }
+        let count = read_u32(bytes, MAGIC.len())? as usize;
+        (bytes.len() >= HEADER_LEN + count * ENTRY_LEN).then_some(Self { bytes, count })
+    }
+
+    /// MessagePack spec for `command`
+    pub fn get(&self, command: &str) -> Option<&'static [u8]> {
+        let (mut low, mut high) = (0, self.count);
+        while low < high {
+            let mid = (low + high) / 2;
+            match self.name(mid)?.cmp(command) {
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+                std::cmp::Ordering::Equal => return self.field(mid, 1),
+            }
+        }
+        None
+    }
+
+    /// Commands with a bundled spec, in order
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        (0..self.count).filter_map(|i| self.name(i))
+    }
+
+    fn name(&self, entry: usize) -> Option<&'static str> {
+        std::str::from_utf8(self.field(entry, 0)?).ok()
+    }
+
+    /// Field 0 (the name) or 1 (the spec) of index entry `entry`
+    fn field(&self, entry: usize, field: usize) -> Option<&'static [u8]> {
+        let at = HEADER_LEN + entry * ENTRY_LEN + field * 8;
+        let offset = read_u32(self.bytes, at)? as usize;
+        let len = read_u32(self.bytes, at + 4)? as usize;
+        self.bytes.get(offset..offset + len)
+    }
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
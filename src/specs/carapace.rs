@@ -0,0 +1,403 @@
+//! Specs in carapace's YAML format, converted to Fig's.
+//!
+//! Carapace describes flags as `name: description` pairs, with the flag's
+//! behavior in suffixes of its name:
+//!
+//! ```yaml
+//! name: deploy
+//! aliases: [dp]
+//! description: Deploy the app
+//! flags:
+//!   -e, --env=: Target environment    # `=` takes a value, `?` an optional one
+//!   -v, --verbose*: More output       # `*` repeatable, `!` required, `&` hidden
+//! persistentflags:
+//!   --dry-run: Only print what would change
+//! exclusiveflags:
+//!   - [verbose, quiet]
+//! completion:
+//!   flag:
+//!     env: [staging, production]
+//!   positional:
+//!     - ["$files"]
+//!   positionalany: ["$(deploy releases)"]
+//! commands:
+//!   - name: rollback
+//! ```
+//!
+//! Completion values are suggestions (`value\tdescription`), `$files`,
+//! `$directories`, or `$(command)` whose output lines are suggestions. Other
+//! carapace macros have no equivalent here and are left out.
+
+use super::yaml;
+use anyhow::Result;
+use serde_json::{Map, Value, json};
+
+/// Characters after a flag's name describing it: `=` takes a value, `?` an
+/// optional one, `*` repeatable, `!` required, `&` hidden
+const MODIFIERS: &[char] = &['=', '?', '*', '!', '&'];
+
+/// Parse a carapace spec into the JSON the spec model reads
+pub fn parse(input: &str) -> Result<Value> {
+    Ok(command(&yaml::parse(input)?))
+}
+
+/// A Fig subcommand from a carapace command
+fn command(carapace: &Value) -> Value {
+    let mut fig = Map::new();
+    let name = carapace
+        .get("name")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    let aliases = strings(carapace.get("aliases"));
+    fig.insert(
+        "name".to_string(),
+        if aliases.is_empty() {
+            json!(name)
+        } else {
+            json!(
+                [name]
+                    .into_iter()
+                    .chain(aliases.iter().map(String::as_str))
+                    .collect::<Vec<_>>()
+            )
+        },
+    );
+    // Block scalars (`description: |`) end in a newline
+    if let Some(description) = carapace.get("description").and_then(Value::as_str) {
+        fig.insert("description".to_string(), json!(description.trim()));
+    }
+    if carapace.get("hidden").and_then(Value::as_bool) == Some(true) {
+        fig.insert("hidden".to_string(), json!(true));
+    }
+
+    let completion = carapace.get("completion");
+    let flag_values = completion.and_then(|completion| completion.get("flag"));
+    let mut options: Vec<Value> = Vec::new();
+    for (field, persistent) in [("flags", false), ("persistentflags", true)] {
+        let Some(Value::Object(flags)) = carapace.get(field) else {
+            continue;
+        };
+        for (spelling, details) in flags {
+            options.push(option(spelling, details, persistent, flag_values));
+        }
+    }
+    exclusive(&mut options, carapace.get("exclusiveflags"));
+    if !options.is_empty() {
+        fig.insert("options".to_string(), Value::Array(options));
+    }
+
+    let mut args: Vec<Value> = match completion.and_then(|completion| completion.get("positional"))
+    {
+        Some(Value::Array(positional)) => positional
+            .iter()
+            .enumerate()
+            .map(|(i, values)| arg(&format!("arg{}", i + 1), values))
+            .collect(),
+        _ => Vec::new(),
+    };
+    if let Some(any) = completion.and_then(|completion| completion.get("positionalany")) {
+        let mut arg = arg("args", any);
+        arg["isVariadic"] = json!(true);
+        arg["isOptional"] = json!(true);
+        args.push(arg);
+    }
+    if !args.is_empty() {
+        fig.insert("args".to_string(), Value::Array(args));
+    }
+
+    if let Some(Value::Array(commands)) = carapace.get("commands") {
+        fig.insert(
+            "subcommands".to_string(),
+            Value::Array(commands.iter().map(command).collect()),
+        );
+    }
+    Value::Object(fig)
+}
+
+/// A Fig option from a carapace flag such as `-o, --output=`
+fn option(spelling: &str, details: &Value, persistent: bool, values: Option<&Value>) -> Value {
+    let names: Vec<&str> = spelling
+        .split(',')
+        .map(|name| name.trim().trim_end_matches(MODIFIERS))
+        .filter(|name| !name.is_empty())
+        .collect();
+
+    let mut fig = Map::new();
+    fig.insert(
+        "name".to_string(),
+        match names.as_slice() {
+            [name] => json!(name),
+            names => json!(names),
+        },
+    );
+    // `--env: Target environment`, or in newer specs `--env: {description: ...}`
+    let description = details
+        .as_str()
+        .or_else(|| details.get("description").and_then(Value::as_str));
+    if let Some(description) = description.filter(|description| !description.is_empty()) {
+        fig.insert("description".to_string(), json!(description));
+    }
+    let modifiers = modifiers(spelling);
+    let has = |modifier: char| modifiers.contains(modifier);
+    if has('=') || has('?') {
+        // Values are keyed by the flag's longest name, without dashes
+        let key = names
+            .iter()
+            .max_by_key(|name| name.len())
+            .map(|name| name.trim_start_matches('-'))
+            .unwrap_or_default();
+        let mut arg = match values.and_then(|values| values.get(key)) {
+            Some(values) => arg(key, values),
+            None => json!({ "name": key }),
+        };
+        if has('?') {
+            arg["isOptional"] = json!(true);
+        }
+        fig.insert("args".to_string(), arg);
+    }
+    if persistent {
+        fig.insert("isPersistent".to_string(), json!(true));
+    }
+    if has('*') {
+        fig.insert("isRepeatable".to_string(), json!(true));
+    }
+    if has('!') {
+        fig.insert("isRequired".to_string(), json!(true));
+    }
+    if has('&') {
+        fig.insert("hidden".to_string(), json!(true));
+    }
+    Value::Object(fig)
+}
+
+/// The modifier characters at the end of a flag spelling
+fn modifiers(spelling: &str) -> &str {
+    let spelling = spelling.trim_end();
+    &spelling[spelling.trim_end_matches(MODIFIERS).len()..]
+}
+
+/// A Fig argument named `name` completing carapace's completion `values`
+fn arg(name: &str, values: &Value) -> Value {
+    let mut fig = Map::new();
+    fig.insert("name".to_string(), json!(name));
+    let mut suggestions = Vec::new();
+    let mut generators = Vec::new();
+    for value in strings(Some(values)) {
+        if value == "$files" || value.starts_with("$files(") {
+            fig.insert("template".to_string(), json!("filepaths"));
+        } else if value == "$directories" {
+            fig.insert("template".to_string(), json!("folders"));
+        } else if let Some(script) = value
+            .strip_prefix("$(")
+            .and_then(|script| script.strip_suffix(')'))
+        {
+            generators.push(json!({ "script": script }));
+        } else if !value.starts_with('$') {
+            // `value\tdescription`, optionally followed by a style
+            let mut parts = value.split('\t');
+            let text = parts.next().unwrap_or_default();
+            suggestions.push(match parts.next() {
+                Some(description) => json!({ "name": text, "description": description }),
+                None => json!(text),
+            });
+        }
+    }
+    if !suggestions.is_empty() {
+        fig.insert("suggestions".to_string(), Value::Array(suggestions));
+    }
+    if !generators.is_empty() {
+        fig.insert("generators".to_string(), Value::Array(generators));
+    }
+    Value::Object(fig)
+}
+
+/// Mark the options of each group in `exclusiveflags` as exclusive of the
+/// others in it; groups name flags by their longest name, without dashes
+fn exclusive(options: &mut [Value], groups: Option<&Value>) {
+    let Some(Value::Array(groups)) = groups else {
+        return;
+    };
+    let names = |option: &Value| strings(option.get("name"));
+    for group in groups {
+        let members = strings(Some(group));
+        // The option spelled like each member, and its own first name
+        let spelled: Vec<(usize, String)> = members
+            .iter()
+            .filter_map(|member| {
+                options.iter().position(|option| {
+                    names(option)
+                        .iter()
+                        .any(|name| name.trim_start_matches('-') == member)
+                })
+            })
+            .map(|at| {
+                (
+                    at,
+                    names(&options[at]).into_iter().last().unwrap_or_default(),
+                )
+            })
+            .collect();
+        for (at, own) in &spelled {
+            let others: Vec<&str> = spelled
+                .iter()
+                .filter(|(_, name)| name != own)
+                .map(|(_, name)| name.as_str())
+                .collect();
+            if others.is_empty() {
+                continue;
+            }
+            let option = &mut options[*at];
+            let mut exclusive = strings(option.get("exclusiveOn"));
+            exclusive.extend(others.iter().map(|name| name.to_string()));
+            option["exclusiveOn"] = json!(exclusive);
+        }
+    }
+}
+
+/// The strings in a list, or a single string as a list of one
+fn strings(value: Option<&Value>) -> Vec<String> {
+    match value {
+        Some(Value::String(text)) => vec![text.clone()],
+        Some(Value::Array(items)) => items
+            .iter()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEC: &str = r#"name: deploy
+aliases: [dp]
+description: |
+  Deploy the app
+flags:
+  -e, --env=: Target environment
+  -t, --tag?: Image tag
+  -v, --verbose*: More output
+  -q, --quiet: Less output
+  --token!&: API token
+  --format=: {description: Output format}
+persistentflags:
+  --dry-run: Only print what would change
+exclusiveflags:
+  - [verbose, quiet]
+completion:
+  flag:
+    env: ["staging\tPre-release", production]
+    format: [json, "$unknown"]
+  positional:
+    - ["$files"]
+    - ["$directories"]
+  positionalany: ["$(deploy releases)"]
+commands:
+  - name: rollback
+    hidden: true
+"#;
+
+    fn option<'a>(spec: &'a Value, name: &str) -> &'a Value {
+        spec["options"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|option| strings(option.get("name")).iter().any(|n| n == name))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_command() {
+        let spec = parse(SPEC).unwrap();
+        assert_eq!(spec["name"], json!(["deploy", "dp"]));
+        assert_eq!(spec["description"], "Deploy the app");
+        assert_eq!(
+            spec["subcommands"],
+            json!([{ "name": "rollback", "hidden": true }])
+        );
+    }
+
+    #[test]
+    fn test_flag_modifiers() {
+        let spec = parse(SPEC).unwrap();
+        assert_eq!(
+            option(&spec, "--env"),
+            &json!({
+                "name": ["-e", "--env"],
+                "description": "Target environment",
+                "args": {
+                    "name": "env",
+                    "suggestions": [
+                        { "name": "staging", "description": "Pre-release" },
+                        "production",
+                    ],
+                },
+            })
+        );
+        assert_eq!(
+            option(&spec, "--tag")["args"],
+            json!({ "name": "tag", "isOptional": true })
+        );
+        assert_eq!(option(&spec, "--verbose")["isRepeatable"], true);
+        let token = option(&spec, "--token");
+        assert_eq!(token["isRequired"], true);
+        assert_eq!(token["hidden"], true);
+        assert!(token.get("args").is_none());
+        assert_eq!(option(&spec, "--dry-run")["isPersistent"], true);
+    }
+
+    #[test]
+    fn test_flag_details_as_mapping() {
+        let spec = parse(SPEC).unwrap();
+        let format = option(&spec, "--format");
+        assert_eq!(format["description"], "Output format");
+        // Macros without an equivalent are left out
+        assert_eq!(format["args"]["suggestions"], json!(["json"]));
+    }
+
+    #[test]
+    fn test_exclusive_flags() {
+        let spec = parse(SPEC).unwrap();
+        assert_eq!(
+            option(&spec, "--verbose")["exclusiveOn"],
+            json!(["--quiet"])
+        );
+        assert_eq!(
+            option(&spec, "--quiet")["exclusiveOn"],
+            json!(["--verbose"])
+        );
+        assert!(option(&spec, "--env").get("exclusiveOn").is_none());
+    }
+
+    #[test]
+    fn test_positional_completions() {
+        let spec = parse(SPEC).unwrap();
+        assert_eq!(
+            spec["args"],
+            json!([
+                { "name": "arg1", "template": "filepaths" },
+                { "name": "arg2", "template": "folders" },
+                {
+                    "name": "args",
+                    "generators": [{ "script": "deploy releases" }],
+                    "isVariadic": true,
+                    "isOptional": true,
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_converted_spec_loads() {
+        let spec = crate::specs::decode(parse(SPEC).unwrap(), None).unwrap();
+        assert_eq!(spec.subcommands.len(), 1);
+        assert_eq!(spec.options.len(), 7);
+    }
+
+    #[test]
+    fn test_invalid_yaml_is_an_error() {
+        assert!(parse("name: x\n  bad: indent\n").is_err());
+    }
+}
@@ -0,0 +1,113 @@
+//! Converting Fig specs into JSON specs (`spec convert`).
+//!
+//! The build script bundles withfig/autocomplete with an exporter that runs
+//! on Node; the same exporter is embedded here, so specs kept outside that
+//! repository, such as a company's private ones, can be converted into the
+//! user's spec directory without rebuilding. Fig specs are modules that
+//! compute parts of themselves, so they are evaluated rather than parsed:
+//! compiled `.js` specs work with any Node, `.ts` sources with a Node that
+//! strips types (22.6 and later).
+
+use anyhow::{Context, Result, bail};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The exporter build.rs runs, see `scripts/fig-specs.mjs`
+const EXPORTER: &str = include_str!("../../scripts/fig-specs.mjs");
+
+/// What converting wrote, and what it left alone
+#[derive(Debug, Default)]
+pub struct Converted {
+    /// Specs written to the output directory
+    pub written: Vec<PathBuf>,
+    /// Specs not written because the output directory has one already
+    pub existing: Vec<PathBuf>,
+}
+
+/// Convert the Fig specs in `dir` (or its `build/` directory, for a checkout
+/// whose specs have been compiled) to `<command>.json` files in `out`,
+/// replacing those already there only with `force`
+pub fn convert(dir: &Path, out: &Path, force: bool) -> Result<Converted> {
+    let dir = if dir.join("build").is_dir() {
+        dir.join("build")
+    } else {
+        dir.to_path_buf()
+    };
+    let work = std::env::temp_dir().join(format!("autocomplete-rs-convert-{}", std::process::id()));
+    let result = export(&dir, &work).and_then(|exported| install(&exported, out, force));
+    let _ = std::fs::remove_dir_all(&work);
+    result
+}
+
+/// Run the exporter on `dir`, into a directory under `work`
+fn export(dir: &Path, work: &Path) -> Result<PathBuf> {
+    std::fs::create_dir_all(work)
+        .with_context(|| format!("Failed to create {}", work.display()))?;
+    let exporter = work.join("fig-specs.mjs");
+    std::fs::write(&exporter, EXPORTER)
+        .with_context(|| format!("Failed to write {}", exporter.display()))?;
+    let exported = work.join("specs");
+
+    let typescript = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read {}", dir.display()))?
+        .flatten()
+        .any(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            name.ends_with(".ts") && !name.ends_with(".d.ts")
+        });
+    let mut node = Command::new("node");
+    if typescript {
+        node.arg("--experimental-strip-types");
+    }
+    // The exporter reports what it skipped on stderr, which is left to the user
+    let status = node
+        .arg(&exporter)
+        .arg(dir)
+        .arg(&exported)
+        .status()
+        .context("Failed to run node, which converting Fig specs needs")?;
+    if !status.success() {
+        if typescript {
+            bail!(
+                "Converting the specs in {} failed; TypeScript specs need Node 22.6 or later, \
+                 or compile them first (npm run build) and convert the output",
+                dir.display()
+            );
+        }
+        bail!("Converting the specs in {} failed", dir.display());
+    }
+    Ok(exported)
+}
+
+/// Write the exported specs into `out`
+fn install(exported: &Path, out: &Path, force: bool) -> Result<Converted> {
+    let mut specs: Vec<PathBuf> = std::fs::read_dir(exported)
+        .with_context(|| format!("Failed to read {}", exported.display()))?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    specs.sort();
+
+    std::fs::create_dir_all(out).with_context(|| format!("Failed to create {}", out.display()))?;
+    let mut converted = Converted::default();
+    for spec in specs {
+        let Some(name) = spec.file_name() else {
+            continue;
+        };
+        let target = out.join(name);
+        if target.exists() && !force {
+            converted.existing.push(target);
+            continue;
+        }
+        // Indented, as the specs are there to be read and edited
+        let json = std::fs::read(&spec)?;
+        let value: serde_json::Value = serde_json::from_slice(&json)
+            .with_context(|| format!("Invalid JSON in {}", spec.display()))?;
+        std::fs::write(&target, serde_json::to_string_pretty(&value)? + "\n")
+            .with_context(|| format!("Failed to write {}", target.display()))?;
+        converted.written.push(target);
+    }
+    Ok(converted)
+}
@@ -0,0 +1,787 @@
+//! Standalone shell completion scripts compiled from specs, for machines
+//! where the daemon can't run.
+//!
+//! The zsh script is a compsys function built on `_arguments`, to put on
+//! `fpath` as `_<command>` or to source; the bash one registers a
+//! `complete -F` function. Both cover subcommands, options and their values,
+//! and positional arguments. Fixed suggestions and path templates are
+//! compiled in, while generators run their script at completion time, as the
+//! daemon would. Flags excluding each other are only known to zsh, and bash
+//! falls back to file names when nothing else applies.
+
+use super::{Arg, Generator, Spec, SpecOption, SpecSuggestion, Subcommand, Template};
+use crate::i18n::{DEFAULT_LOCALE, LocalizedText};
+use std::fmt::Write;
+
+/// A subcommand with the names leading to it
+struct Node<'a, 's> {
+    /// Names from the command down: `["git", "remote", "add"]`
+    path: Vec<&'a str>,
+    command: &'a Subcommand<'s>,
+    /// Its own options, then the persistent ones of the commands above it
+    options: Vec<&'a SpecOption<'s>>,
+}
+
+impl Node<'_, '_> {
+    /// Name of the shell function completing this subcommand
+    fn function(&self) -> String {
+        let words: Vec<String> = self
+            .path
+            .iter()
+            .map(|name| {
+                name.chars()
+                    .map(|c| {
+                        if c.is_ascii_alphanumeric() || c == '-' {
+                            c
+                        } else {
+                            '_'
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        format!("_{}", words.join("_"))
+    }
+
+    fn visible_options(&self) -> impl Iterator<Item = &SpecOption<'_>> {
+        self.options.iter().copied().filter(|option| !option.hidden)
+    }
+}
+
+/// `spec` and every subcommand below it, depth first
+fn nodes<'a, 's>(command: &'a str, spec: &'a Spec<'s>) -> Vec<Node<'a, 's>> {
+    let mut nodes = Vec::new();
+    collect(vec![command], spec, &[], &mut nodes);
+    nodes
+}
+
+fn collect<'a, 's>(
+    path: Vec<&'a str>,
+    command: &'a Subcommand<'s>,
+    inherited: &[&'a SpecOption<'s>],
+    nodes: &mut Vec<Node<'a, 's>>,
+) {
+    let mut options: Vec<&SpecOption> = command.options.iter().collect();
+    // A subcommand's own option wins over an inherited one spelled the same
+    options.extend(inherited.iter().copied().filter(|option| {
+        !command
+            .options
+            .iter()
+            .any(|own| own.name.iter().any(|name| option.is_named(name)))
+    }));
+    let persistent: Vec<&SpecOption> = options
+        .iter()
+        .copied()
+        .filter(|option| option.is_persistent)
+        .collect();
+    nodes.push(Node {
+        path: path.clone(),
+        command,
+        options,
+    });
+    for subcommand in &command.subcommands {
+        let Some(name) = subcommand.name.first() else {
+            continue;
+        };
+        let mut path = path.clone();
+        path.push(name);
+        collect(path, subcommand, &persistent, nodes);
+    }
+}
+
+/// A zsh completion function for `command` from its spec
+pub fn zsh(command: &str, spec: &Spec<'_>) -> String {
+    let nodes = nodes(command, spec);
+    let mut out = format!(
+        "#compdef {command}\n\n# Generated by `autocomplete-rs spec export --shell zsh {command}`\n"
+    );
+    // Value completions too involved for an `_arguments` action
+    let mut helpers = String::new();
+    for node in &nodes {
+        zsh_function(node, &mut out, &mut helpers);
+    }
+    out.push_str(&helpers);
+    let main = nodes[0].function();
+    let _ = write!(
+        out,
+        "\nif [[ \"$funcstack[1]\" == \"{main}\" ]]; then\n    {main} \"$@\"\nelse\n    compdef {main} {}\nfi\n",
+        quote(command)
+    );
+    out
+}
+
+fn zsh_function(node: &Node<'_, '_>, out: &mut String, helpers: &mut String) {
+    let function = node.function();
+    let mut helper_count = 0;
+    let mut action = |arg: &Arg<'_>| {
+        let (action, helper) = zsh_action(arg);
+        match helper {
+            Some(body) => {
+                helper_count += 1;
+                let name = format!("{function}__value{helper_count}");
+                let _ = write!(helpers, "\n{name}() {{\n{body}}}\n");
+                name
+            }
+            None => action,
+        }
+    };
+
+    let mut specs = Vec::new();
+    for option in node.visible_options() {
+        let description = description(option.description.as_ref());
+        // `_arguments` matches spellings literally, so all of each are listed
+        let mut excluded: Vec<&str> = option
+            .exclusive_on
+            .iter()
+            .flat_map(
+                |name| match node.options.iter().find(|other| other.is_named(name)) {
+                    Some(other) => other.name.iter().map(|name| name.as_ref()).collect(),
+                    None => vec![name.as_ref()],
+                },
+            )
+            .collect();
+        // Exclusion goes both ways, as when completing through the daemon
+        excluded.extend(
+            node.options
+                .iter()
+                .filter(|other| other.exclusive_on.iter().any(|name| option.is_named(name)))
+                .flat_map(|other| other.name.iter().map(|name| name.as_ref())),
+        );
+        if !option.is_repeatable {
+            excluded.extend(option.name.iter().map(|name| name.as_ref()));
+        }
+        excluded.sort_unstable();
+        excluded.dedup();
+        let mut values = String::new();
+        for (i, arg) in option.args.iter().enumerate() {
+            let optional = i == 0 && arg.is_optional;
+            let _ = write!(
+                values,
+                "{}{}:{}",
+                if optional { "::" } else { ":" },
+                zsh_message(arg),
+                action(arg)
+            );
+        }
+        for name in &option.name {
+            let mut spec = String::new();
+            if !excluded.is_empty() {
+                let _ = write!(spec, "({})", excluded.join(" "));
+            }
+            if option.is_repeatable {
+                spec.push('*');
+            }
+            spec.push_str(name);
+            if !option.args.is_empty() {
+                // `--name=value` or `--name value`; `-nvalue` or `-n value`
+                if name.starts_with("--") {
+                    spec.push('=');
+                } else if name.chars().count() == 2 {
+                    spec.push('+');
+                }
+            }
+            if !description.is_empty() {
+                let _ = write!(spec, "[{}]", escape(&description, "[]\\"));
+            }
+            spec.push_str(&values);
+            specs.push(quote(&spec));
+        }
+    }
+
+    let subcommands = &node.command.subcommands;
+    if subcommands.is_empty() {
+        let args = &node.command.args;
+        for (i, arg) in args.iter().enumerate() {
+            let position = if arg.is_variadic {
+                "*".to_string()
+            } else {
+                (i + 1).to_string()
+            };
+            let colons = if arg.is_optional && !arg.is_variadic {
+                "::"
+            } else {
+                ":"
+            };
+            specs.push(quote(&format!(
+                "{position}{colons}{}:{}",
+                zsh_message(arg),
+                action(arg)
+            )));
+        }
+    } else {
+        specs.push(quote("1: :->command"));
+        specs.push(quote("*:: :->args"));
+    }
+
+    let _ = write!(
+        out,
+        "\n{function}() {{\n    local curcontext=\"$curcontext\" state line\n    typeset -A opt_args\n\n    _arguments -C -S"
+    );
+    for spec in &specs {
+        let _ = write!(out, " \\\n        {spec}");
+    }
+    out.push_str(" \\\n        && return\n");
+
+    if !subcommands.is_empty() {
+        let path = node.path.join(" ");
+        let context = node.path.join("-");
+        let _ = write!(
+            out,
+            "\n    case $state in\n        (command)\n            local -a commands\n            commands=(\n"
+        );
+        for subcommand in subcommands.iter().filter(|subcommand| !subcommand.hidden) {
+            let description = description(subcommand.description.as_ref());
+            for name in &subcommand.name {
+                let _ = writeln!(
+                    out,
+                    "                {}",
+                    describe_entry(name, &description)
+                );
+            }
+        }
+        let _ = write!(
+            out,
+            "            )\n            _describe -t commands {} commands\n            ;;\n        (args)\n            curcontext=\"${{curcontext%:*:*}}:{context}-$line[1]:\"\n            case $line[1] in\n",
+            quote(&format!("{path} command"))
+        );
+        for subcommand in subcommands {
+            let Some(first) = subcommand.name.first() else {
+                continue;
+            };
+            let mut path = node.path.clone();
+            path.push(first);
+            let target = Node {
+                path,
+                command: subcommand,
+                options: Vec::new(),
+            };
+            let names: Vec<String> = subcommand.name.iter().map(|name| quote(name)).collect();
+            let _ = writeln!(
+                out,
+                "                ({}) {} ;;",
+                names.join("|"),
+                target.function()
+            );
+        }
+        out.push_str("            esac\n            ;;\n    esac\n");
+    }
+    out.push_str("}\n");
+}
+
+/// The `_arguments` action completing `arg`, or the body of a helper
+/// function to call instead
+fn zsh_action(arg: &Arg<'_>) -> (String, Option<String>) {
+    let templates = templates(arg);
+    let scripts: Vec<&Generator> = arg
+        .generators
+        .iter()
+        .filter(|generator| !generator.script.is_empty())
+        .collect();
+    let described = arg
+        .suggestions
+        .iter()
+        .any(|suggestion| matches!(suggestion, SpecSuggestion::Described { .. }));
+
+    match (
+        arg.suggestions.is_empty(),
+        templates.as_slice(),
+        scripts.is_empty(),
+    ) {
+        (true, [], true) => return (String::new(), None),
+        (true, [template], true) => return (files_function(*template).to_string(), None),
+        (false, [], true) if !described => {
+            let words: Vec<String> = suggestion_names(arg)
+                .iter()
+                .map(|name| escape(name, " ()[]:\\$\"`"))
+                .collect();
+            return (format!("({})", words.join(" ")), None);
+        }
+        _ => {}
+    }
+
+    let mut body = String::from("    local -a values\n");
+    if !arg.suggestions.is_empty() {
+        body.push_str("    values=(\n");
+        for suggestion in &arg.suggestions {
+            let (names, description) = match suggestion {
+                SpecSuggestion::Name(name) => (std::slice::from_ref(name), String::new()),
+                SpecSuggestion::Described {
+                    name,
+                    description: text,
+                } => (name.as_slice(), description(text.as_ref())),
+            };
+            for name in names {
+                let _ = writeln!(body, "        {}", describe_entry(name, &description));
+            }
+        }
+        let _ = writeln!(
+            body,
+            "    )\n    _describe -t values {} values",
+            quote(&message(arg))
+        );
+    }
+    for template in templates {
+        let _ = writeln!(body, "    {}", files_function(template));
+    }
+    for generator in scripts {
+        let filter = parse_filter(generator);
+        let output = format!(
+            "\"$({} 2>/dev/null{})\"",
+            script(generator),
+            filter.as_deref().unwrap_or_default()
+        );
+        let split = match generator.split_on.as_deref() {
+            _ if filter.is_some() => format!("${{(f){output}}}"),
+            None | Some("\n") => format!("${{(f){output}}}"),
+            Some(separator) => match [':', '|', '#', '%']
+                .into_iter()
+                .find(|c| !separator.contains(*c))
+            {
+                Some(delimiter) => format!("${{(s{delimiter}{separator}{delimiter}){output}}}"),
+                None => format!("${{(f){output}}}"),
+            },
+        };
+        let _ = writeln!(body, "    values=({split})\n    compadd -a values");
+    }
+    (String::new(), Some(body))
+}
+
+fn files_function(template: Template) -> &'static str {
+    match template {
+        Template::Filepaths => "_files",
+        Template::Folders => "_files -/",
+    }
+}
+
+/// `_describe` entry `name:description`, quoted
+fn describe_entry(name: &str, description: &str) -> String {
+    let name = escape(name, ":\\");
+    if description.is_empty() {
+        quote(&name)
+    } else {
+        quote(&format!("{name}:{description}"))
+    }
+}
+
+/// The message shown while completing `arg`, escaped for `_arguments`
+fn zsh_message(arg: &Arg<'_>) -> String {
+    escape(&message(arg), ":\\")
+}
+
+/// A bash completion function for `command` from its spec
+pub fn bash(command: &str, spec: &Spec<'_>) -> String {
+    let nodes = nodes(command, spec);
+    let main = nodes[0].function();
+    let mut out = format!(
+        "# Generated by `autocomplete-rs spec export --shell bash {command}`\n\n{main}() {{\n"
+    );
+    out.push_str(
+        "    local cur=\"${COMP_WORDS[COMP_CWORD]}\" prev=\"${COMP_WORDS[COMP_CWORD-1]}\"\n",
+    );
+    let _ = writeln!(out, "    local path={main} args=0 i");
+
+    // Walk the completed words to the subcommand and argument being completed
+    out.push_str("    for ((i = 1; i < COMP_CWORD; i++)); do\n        case \"$path:$args:${COMP_WORDS[i]}\" in\n");
+    for node in &nodes {
+        let function = node.function();
+        for subcommand in &node.command.subcommands {
+            let Some(first) = subcommand.name.first() else {
+                continue;
+            };
+            let mut path = node.path.clone();
+            path.push(first);
+            let target = Node {
+                path,
+                command: subcommand,
+                options: Vec::new(),
+            }
+            .function();
+            let patterns: Vec<String> = subcommand
+                .name
+                .iter()
+                .map(|name| quote(&format!("{function}:0:{name}")))
+                .collect();
+            let _ = writeln!(
+                out,
+                "            {}) path={target} args=0 ;;",
+                patterns.join(" | ")
+            );
+        }
+        let patterns: Vec<String> = node
+            .options
+            .iter()
+            .filter(|option| option.args.first().is_some_and(|arg| !arg.is_optional))
+            .flat_map(|option| &option.name)
+            .map(|name| {
+                format!(
+                    "{}*{}",
+                    quote(&format!("{function}:")),
+                    quote(&format!(":{name}"))
+                )
+            })
+            .collect();
+        if !patterns.is_empty() {
+            let _ = writeln!(out, "            {}) i=$((i + 1)) ;;", patterns.join(" | "));
+        }
+    }
+    out.push_str(
+        "            *:*:-*) ;;\n            *) args=$((args + 1)) ;;\n        esac\n    done\n\n",
+    );
+
+    out.push_str("    COMPREPLY=()\n    case \"$path\" in\n");
+    for node in &nodes {
+        let _ = writeln!(out, "        {})", node.function());
+        let values: Vec<(&SpecOption, &Arg)> = node
+            .options
+            .iter()
+            .filter_map(|option| option.args.first().map(|arg| (*option, arg)))
+            .collect();
+        if !values.is_empty() {
+            out.push_str("            case \"$prev\" in\n");
+            for (option, arg) in values {
+                let names: Vec<String> = option.name.iter().map(|name| quote(name)).collect();
+                let _ = writeln!(out, "                {})", names.join(" | "));
+                for statement in bash_statements(arg) {
+                    let _ = writeln!(out, "                    {statement}");
+                }
+                out.push_str("                    return\n                    ;;\n");
+            }
+            out.push_str("            esac\n");
+        }
+        let flags: Vec<&str> = node
+            .visible_options()
+            .flat_map(|option| &option.name)
+            .map(|name| name.as_ref())
+            .collect();
+        if !flags.is_empty() {
+            let _ = writeln!(
+                out,
+                "            if [[ $cur == -* ]]; then\n                COMPREPLY=($(compgen -W {} -- \"$cur\"))\n                return\n            fi",
+                quote(&flags.join(" "))
+            );
+        }
+
+        // Subcommands share the first position with the first argument
+        let subcommands: Vec<&str> = node
+            .command
+            .subcommands
+            .iter()
+            .filter(|subcommand| !subcommand.hidden)
+            .flat_map(|subcommand| &subcommand.name)
+            .map(|name| name.as_ref())
+            .collect();
+        let args = &node.command.args;
+        let mut positions: Vec<(String, Vec<String>)> = args
+            .iter()
+            .enumerate()
+            .map(|(i, arg)| {
+                let position = if arg.is_variadic {
+                    "*".to_string()
+                } else {
+                    i.to_string()
+                };
+                (position, bash_statements(arg))
+            })
+            .collect();
+        if !subcommands.is_empty() {
+            let words = format!(
+                "COMPREPLY+=($(compgen -W {} -- \"$cur\"))",
+                quote(&subcommands.join(" "))
+            );
+            match positions.first_mut() {
+                Some((position, statements)) if position == "0" => statements.insert(0, words),
+                _ => positions.insert(0, ("0".to_string(), vec![words])),
+            }
+        }
+        let positions: Vec<_> = positions
+            .into_iter()
+            .filter(|(_, statements)| !statements.is_empty())
+            .collect();
+        if !positions.is_empty() {
+            out.push_str("            case \"$args\" in\n");
+            for (position, statements) in positions {
+                let _ = writeln!(out, "                {position})");
+                for statement in statements {
+                    let _ = writeln!(out, "                    {statement}");
+                }
+                out.push_str("                    ;;\n");
+            }
+            out.push_str("            esac\n");
+        }
+        out.push_str("            ;;\n");
+    }
+    out.push_str("    esac\n}\n\n");
+    let _ = writeln!(out, "complete -o default -F {main} {}", quote(command));
+    out
+}
+
+/// Commands adding the completions of `arg` to `COMPREPLY`
+fn bash_statements(arg: &Arg<'_>) -> Vec<String> {
+    let mut statements = Vec::new();
+    let names = suggestion_names(arg);
+    if !names.is_empty() {
+        statements.push(format!(
+            "COMPREPLY+=($(compgen -W {} -- \"$cur\"))",
+            quote(&names.join(" "))
+        ));
+    }
+    for template in templates(arg) {
+        let flag = match template {
+            Template::Filepaths => "-f",
+            Template::Folders => "-d",
+        };
+        statements.push("compopt -o filenames 2>/dev/null".to_string());
+        statements.push(format!("COMPREPLY+=($(compgen {flag} -- \"$cur\"))"));
+    }
+    for generator in arg
+        .generators
+        .iter()
+        .filter(|generator| !generator.script.is_empty())
+    {
+        // compgen splits words on whitespace; other single-character
+        // separators are turned into newlines
+        let split = match generator.split_on.as_deref() {
+            _ if generator.parse.is_some() => parse_filter(generator).unwrap_or_default(),
+            Some(separator) if separator.chars().count() == 1 && separator != "\n" => {
+                format!(" | tr {} '\\n'", quote(separator))
+            }
+            _ => String::new(),
+        };
+        statements.push(format!(
+            "COMPREPLY+=($(compgen -W \"$({} 2>/dev/null{split})\" -- \"$cur\"))",
+            script(generator)
+        ));
+    }
+    statements
+}
+
+/// The paths `arg` completes, folders left out when files are wanted too
+fn templates(arg: &Arg<'_>) -> Vec<Template> {
+    let mut templates: Vec<Template> = arg
+        .template
+        .iter()
+        .chain(
+            arg.generators
+                .iter()
+                .flat_map(|generator| &generator.template),
+        )
+        .copied()
+        .collect();
+    if templates.contains(&Template::Filepaths) {
+        templates.retain(|&template| template == Template::Filepaths);
+    }
+    templates.dedup();
+    templates
+}
+
+/// Names of the fixed suggestions of `arg`
+fn suggestion_names<'a>(arg: &'a Arg<'_>) -> Vec<&'a str> {
+    arg.suggestions
+        .iter()
+        .flat_map(|suggestion| match suggestion {
+            SpecSuggestion::Name(name) => std::slice::from_ref(name),
+            SpecSuggestion::Described { name, .. } => name.as_slice(),
+        })
+        .map(|name| name.as_ref())
+        .collect()
+}
+
+/// Prints a name per line from the output of a generator with `parse`, set
+/// with `-v`: `rs` (`splitOn`, lines when empty), `skip`, `p` (the prefix),
+/// `sep` (the column separator, whitespace when empty) and `name` (the
+/// column). Separators are matched literally, as the daemon does.
+const PARSE_AWK: &str = r#"function col(s, i,  k) {
+    if (sep == "") { n = split(s, f); return i < n ? f[i + 1] : "" }
+    while (i-- > 0) { k = index(s, sep); if (!k) return ""; s = substr(s, k + length(sep)) }
+    k = index(s, sep); return k ? substr(s, 1, k - 1) : s
+}
+function piece(s,  c) {
+    gsub(/^[ \t]+|[ \t]+$/, "", s)
+    if (seen++ < skip) return
+    if (p != "" && index(s, p) == 1) s = substr(s, length(p) + 1)
+    c = col(s, name); gsub(/^[ \t]+|[ \t]+$/, "", c)
+    if (c != "") print c
+}
+{
+    if (rs == "") { piece($0); next }
+    s = $0
+    while ((k = index(s, rs)) > 0) { piece(substr(s, 1, k - 1)); s = substr(s, k + length(rs)) }
+    piece(s)
+}"#;
+
+/// The pipeline stage after a generator's script that leaves one name per
+/// line for a generator with `parse`; descriptions aren't kept
+fn parse_filter(generator: &Generator<'_>) -> Option<String> {
+    let parse = generator.parse.as_ref()?;
+    // `-v` expands backslash escapes
+    let var = |value: &str| quote(&value.replace('\\', "\\\\"));
+    let split_on = generator.split_on.as_deref().filter(|&split| split != "\n");
+    Some(format!(
+        " | awk -v rs={} -v skip={} -v p={} -v sep={} -v name={} {}",
+        var(split_on.unwrap_or_default()),
+        parse.skip,
+        var(parse.strip_prefix.as_deref().unwrap_or_default()),
+        var(parse.columns.as_deref().unwrap_or_default()),
+        parse.name,
+        quote(PARSE_AWK)
+    ))
+}
+
+/// The shell command line of a generator, as `sh -c` would run it
+fn script(generator: &Generator<'_>) -> String {
+    match generator.script.as_slice() {
+        [line] => format!("sh -c {}", quote(line)),
+        argv => argv
+            .iter()
+            .map(|part| quote(part))
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+fn message(arg: &Arg<'_>) -> String {
+    arg.name.as_deref().unwrap_or("value").to_string()
+}
+
+/// First line of a description, in English
+fn description(text: Option<&LocalizedText>) -> String {
+    text.map(|text| text.get(DEFAULT_LOCALE))
+        .and_then(|text| text.lines().next())
+        .unwrap_or_default()
+        .trim()
+        .to_string()
+}
+
+/// `text` with a backslash before each of `special`
+fn escape(text: &str, special: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if special.contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// `text` in single quotes, for both shells
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::process::Command;
+
+    fn spec() -> Spec<'static> {
+        super::super::decode(
+            json!({
+                "name": "tool",
+                "options": [
+                    { "name": ["-v", "--verbose"], "description": "More output", "isRepeatable": true },
+                    { "name": "--quiet", "description": "Less [output]", "exclusiveOn": ["-v"] },
+                    {
+                        "name": ["-C", "--dir"],
+                        "description": "Run in a directory\nfor real",
+                        "isPersistent": true,
+                        "args": { "name": "dir", "template": "folders" },
+                    },
+                    { "name": "--secret", "hidden": true },
+                ],
+                "subcommands": [
+                    {
+                        "name": ["remote", "r"],
+                        "description": "Manage remotes",
+                        "subcommands": [
+                            {
+                                "name": "add",
+                                "description": "Add one",
+                                "args": [{ "name": "name" }, { "name": "url", "isOptional": true }],
+                            },
+                            {
+                                "name": "remove",
+                                "args": {
+                                    "name": "remote",
+                                    "generators": { "script": ["git", "remote"] },
+                                },
+                            },
+                        ],
+                    },
+                    {
+                        "name": "log",
+                        "options": [{
+                            "name": "--format",
+                            "args": {
+                                "name": "format",
+                                "suggestions": [
+                                    { "name": "oneline", "description": "One line: short" },
+                                    "full",
+                                ],
+                            },
+                        }],
+                        "args": { "name": "paths", "isVariadic": true, "template": "filepaths" },
+                    },
+                    { "name": "debug", "hidden": true },
+                ],
+            }),
+            None,
+        )
+        .unwrap()
+    }
+
+    /// Exit status and output of `shell -c script`, `None` when the shell
+    /// isn't installed
+    fn run(shell: &str, script: &str) -> Option<(bool, String)> {
+        let output = Command::new(shell).arg("-c").arg(script).output().ok()?;
+        let text = String::from_utf8_lossy(&output.stdout).into_owned()
+            + &String::from_utf8_lossy(&output.stderr);
+        Some((output.status.success(), text))
+    }
+
+    #[test]
+    fn test_zsh_script_matches_golden() {
+        assert_eq!(zsh("tool", &spec()), include_str!("testdata/tool.zsh"));
+    }
+
+    #[test]
+    fn test_bash_script_matches_golden() {
+        assert_eq!(bash("tool", &spec()), include_str!("testdata/tool.bash"));
+    }
+
+    #[test]
+    fn test_scripts_are_valid_syntax() {
+        let checks = [
+            ("bash", bash("tool", &spec())),
+            ("zsh", zsh("tool", &spec())),
+        ];
+        for (shell, script) in checks {
+            let check = format!("{shell} -n <<'SCRIPT'\n{script}\nSCRIPT");
+            if let Some((ok, output)) = run(shell, &check) {
+                assert!(ok, "{shell} rejected the script: {output}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_bash_script_completes() {
+        let script = bash("tool", &spec());
+        let complete = |words: &str| {
+            let run_it = format!(
+                "{script}\nCOMP_WORDS=({words}); COMP_CWORD=$((${{#COMP_WORDS[@]}} - 1))\n\
+                 _tool; printf '%s\\n' \"${{COMPREPLY[@]}}\""
+            );
+            let (ok, output) = run("bash", &run_it).expect("bash is installed");
+            assert!(ok, "{output}");
+            output.lines().map(str::to_string).collect::<Vec<_>>()
+        };
+        assert_eq!(complete("tool ''"), ["remote", "r", "log"]);
+        assert_eq!(complete("tool --"), ["--verbose", "--quiet", "--dir"]);
+        assert_eq!(complete("tool r ''"), ["add", "remove"]);
+        // The persistent option's value is skipped on the way
+        assert_eq!(complete("tool -C /tmp remote a"), ["add"]);
+        assert_eq!(complete("tool log --format ''"), ["oneline", "full"]);
+        assert_eq!(complete("tool log -"), ["--format", "-C", "--dir"]);
+    }
+}
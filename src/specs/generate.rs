@@ -0,0 +1,670 @@
+//! Specs drafted from a command's `--help` output.
+//!
+//! Help text has no fixed format, so this is a heuristic: lines starting
+//! with `-` in an options section become options (with an argument when a
+//! placeholder such as `<FILE>` or `=NAME` follows the flag), names at the
+//! start of lines in a commands section become subcommands, and the
+//! placeholders of the usage line become positional arguments. Subcommands
+//! are asked for their own help in turn. The result is a starting point to
+//! edit, not a finished spec.
+
+use anyhow::{Context, Result};
+use serde_json::{Map, Value, json};
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// How long one `--help` run may take
+const HELP_TIMEOUT: Duration = Duration::from_secs(5);
+/// Subcommands whose help is fetched, per level
+const MAX_SUBCOMMANDS: usize = 100;
+
+/// Kind of section a help line belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    /// Before any heading, or under one this doesn't understand
+    Other,
+    Usage,
+    Options,
+    Commands,
+}
+
+/// Draft a spec for `command` from its help output, descending `depth`
+/// levels of subcommands
+pub fn generate(command: &str, depth: usize) -> Result<Value> {
+    let help = help(&[command])
+        .with_context(|| format!("Got no help text from `{command} --help` or `{command} -h`"))?;
+    let mut spec = parse(command, &help);
+    if depth > 0 {
+        describe_subcommands(&mut spec, &[command], depth);
+    }
+    super::decode(spec.clone(), None).context("Generated a spec the daemon couldn't load")?;
+    Ok(spec)
+}
+
+/// Fill in the subcommands of `spec`, the subcommand at `path`, from their
+/// own help output
+fn describe_subcommands(spec: &mut Value, path: &[&str], depth: usize) {
+    let Some(Value::Array(subcommands)) = spec.get_mut("subcommands") else {
+        return;
+    };
+    for subcommand in subcommands.iter_mut().take(MAX_SUBCOMMANDS) {
+        let Some(name) = subcommand
+            .get("name")
+            .and_then(|name| {
+                name.as_array()
+                    .and_then(|names| names.first())
+                    .or(Some(name))
+            })
+            .and_then(Value::as_str)
+            .map(str::to_string)
+        else {
+            continue;
+        };
+        if name == "help" {
+            continue;
+        }
+        let mut sub_path = path.to_vec();
+        sub_path.push(&name);
+        let Some(help) = help(&sub_path) else {
+            continue;
+        };
+        let mut parsed = parse(&name, &help);
+        if depth > 1 {
+            describe_subcommands(&mut parsed, &sub_path, depth - 1);
+        }
+        // The parent's listing has the aliases and the one-line description
+        if let (Value::Object(target), Value::Object(parsed)) = (&mut *subcommand, parsed) {
+            for (key, value) in parsed {
+                if key != "name" && key != "description" {
+                    target.insert(key, value);
+                }
+            }
+        }
+    }
+}
+
+/// Help text of the command at `path`, from `--help` or else `-h`
+fn help(path: &[&str]) -> Option<String> {
+    ["--help", "-h"].iter().find_map(|flag| {
+        run(path, flag).map(|text| clean(&text)).filter(|text| {
+            text.to_ascii_lowercase().contains("usage")
+                || text.lines().any(|line| line.trim_start().starts_with('-'))
+        })
+    })
+}
+
+/// Output of `path... flag`, stdout or, when that is empty, stderr
+fn run(path: &[&str], flag: &str) -> Option<String> {
+    let (program, args) = path.split_first()?;
+    let mut child = Command::new(program)
+        .args(args)
+        .arg(flag)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        // Keep help plain and on stdout rather than in a pager
+        .env("PAGER", "cat")
+        .env("MANPAGER", "cat")
+        .env("GIT_PAGER", "cat")
+        .env("NO_COLOR", "1")
+        .env("COLUMNS", "200")
+        .env("MANWIDTH", "200")
+        .spawn()
+        .ok()?;
+
+    // Read both pipes on threads so a chatty command can't fill one and block
+    let mut stdout = child.stdout.take()?;
+    let mut stderr = child.stderr.take()?;
+    let out = std::thread::spawn(move || {
+        let mut text = String::new();
+        let _ = stdout.read_to_string(&mut text);
+        text
+    });
+    let err = std::thread::spawn(move || {
+        let mut text = String::new();
+        let _ = stderr.read_to_string(&mut text);
+        text
+    });
+    let deadline = Instant::now() + HELP_TIMEOUT;
+    loop {
+        match child.try_wait() {
+            Ok(Some(_)) => break,
+            Ok(None) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(10)),
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+        }
+    }
+    let (out, err) = (out.join().ok()?, err.join().ok()?);
+    Some(if out.trim().is_empty() { err } else { out })
+}
+
+/// Remove colors and the backspace overstrike `man` uses for bold
+fn clean(text: &str) -> String {
+    let mut cleaned = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\u{1b}' => {
+                // CSI sequence: ESC [ parameters final-byte
+                if chars.next_if_eq(&'[').is_some() {
+                    for c in chars.by_ref() {
+                        if c.is_ascii_alphabetic() {
+                            break;
+                        }
+                    }
+                }
+            }
+            '\u{8}' => {
+                cleaned.pop();
+            }
+            c => cleaned.push(c),
+        }
+    }
+    cleaned
+}
+
+/// Spec for the command `name` from its help text
+pub(super) fn parse(name: &str, help: &str) -> Value {
+    let mut options: Vec<Value> = Vec::new();
+    let mut subcommands: Vec<Value> = Vec::new();
+    let mut args: Vec<Value> = Vec::new();
+    let mut description = None;
+    let mut section = Section::Other;
+
+    // Whether the usage line was read, and whether a section other than the
+    // usage has started
+    let mut usage_read = false;
+    let mut past_usage = false;
+
+    let lines: Vec<&str> = help.lines().collect();
+    for (i, line) in lines.iter().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        if let Some(kind) = heading(line) {
+            section = kind;
+            // `Usage: cmd [OPTIONS] <FILE>` carries the usage on the heading line
+            if kind == Section::Usage {
+                if let Some((_, usage)) = trimmed.split_once(':')
+                    && !usage_read
+                    && !usage.trim().is_empty()
+                {
+                    args = usage_args(usage);
+                    usage_read = true;
+                }
+            } else {
+                past_usage = true;
+            }
+            continue;
+        }
+        // Options are often listed right under the usage, with no heading
+        if trimmed.starts_with('-') && section != Section::Commands {
+            if let Some(option) = option(trimmed, lines.get(i + 1).copied(), line) {
+                merge_option(&mut options, option);
+            }
+            continue;
+        }
+        match section {
+            Section::Usage if !usage_read => {
+                args = usage_args(trimmed);
+                usage_read = true;
+            }
+            Section::Commands => {
+                if let Some(subcommand) = subcommand(line) {
+                    subcommands.push(subcommand);
+                }
+            }
+            // The first unindented sentence around the usage describes the command
+            Section::Usage | Section::Other
+                if !past_usage && description.is_none() && indent(line) == 0 =>
+            {
+                description = Some(first_sentence(trimmed));
+            }
+            _ => {}
+        }
+    }
+
+    let mut spec = Map::new();
+    spec.insert("name".to_string(), json!(name));
+    if let Some(description) = description {
+        spec.insert("description".to_string(), json!(description));
+    }
+    if !subcommands.is_empty() {
+        spec.insert("subcommands".to_string(), Value::Array(subcommands));
+    }
+    if !options.is_empty() {
+        spec.insert("options".to_string(), Value::Array(options));
+    }
+    if !args.is_empty() {
+        spec.insert("args".to_string(), Value::Array(args));
+    }
+    Value::Object(spec)
+}
+
+/// Kind of section a heading line starts, if it is one
+fn heading(line: &str) -> Option<Section> {
+    // Headings start in the first column (or just after, in man output)
+    let indent = line.len() - line.trim_start().len();
+    let trimmed = line.trim();
+    if indent > 3 || trimmed.starts_with('-') {
+        return None;
+    }
+    let (title, rest) = match trimmed.split_once(':') {
+        Some((title, rest)) => (title, rest),
+        // `OPTIONS` in man pages
+        None if trimmed.chars().all(|c| c.is_ascii_uppercase() || c == ' ') => (trimmed, ""),
+        None => return None,
+    };
+    let title = title.to_ascii_lowercase();
+    if title == "usage" || title == "synopsis" {
+        return Some(Section::Usage);
+    }
+    // `Clone a repository (see also: git help clone)` is not a heading
+    if !rest.trim().is_empty() || title.split_whitespace().count() > 10 {
+        return None;
+    }
+    if title.contains("command") {
+        Some(Section::Commands)
+    } else if title.contains("option") || title.contains("flag") || title.contains("argument") {
+        Some(Section::Options)
+    } else {
+        Some(Section::Other)
+    }
+}
+
+/// Positional arguments from the placeholders of a usage line:
+/// `<FILE>`, `[DIR]...`
+fn usage_args(usage: &str) -> Vec<Value> {
+    let mut args = Vec::new();
+    let mut previous = "";
+    for word in usage.split_whitespace() {
+        // `-o <FILE>` is the value of an option
+        let option = previous.trim_matches(|c| matches!(c, '[' | ']' | '(' | ')'));
+        let value = option.starts_with('-') && option != "--";
+        previous = word;
+        if value {
+            continue;
+        }
+        // `<FILE>...`, and `[<FILE>...]` in mdoc synopses
+        let variadic = word.contains("...");
+        let word = word.replace("...", "");
+        let word = word.as_str();
+        let optional = word.starts_with('[');
+        let inner = word.trim_matches(|c| matches!(c, '[' | ']'));
+        let name = match inner
+            .strip_prefix('<')
+            .and_then(|rest| rest.strip_suffix('>'))
+        {
+            Some(name) => name,
+            // `[DIR]`, as clap and GNU write optional arguments
+            None if optional && is_placeholder(inner) => inner,
+            None => continue,
+        };
+        // `[OPTIONS]` and `<COMMAND>` are covered by options and subcommands
+        let lower = name.to_ascii_lowercase();
+        if lower.contains("option") || lower.contains("command") || name.starts_with('-') {
+            continue;
+        }
+        args.push(arg(name, optional, variadic));
+    }
+    args
+}
+
+/// A positional or option argument named `name`, completing paths when the
+/// name suggests them
+fn arg(name: &str, optional: bool, variadic: bool) -> Value {
+    let lower = name.to_ascii_lowercase();
+    let mut arg = Map::new();
+    arg.insert("name".to_string(), json!(lower));
+    if optional {
+        arg.insert("isOptional".to_string(), json!(true));
+    }
+    if variadic {
+        arg.insert("isVariadic".to_string(), json!(true));
+    }
+    if lower.contains("dir") || lower.contains("folder") {
+        arg.insert("template".to_string(), json!("folders"));
+    } else if lower.contains("file") || lower.contains("path") {
+        arg.insert("template".to_string(), json!("filepaths"));
+    }
+    Value::Object(arg)
+}
+
+/// An option from a help line such as `-o, --output <FILE>  Write to FILE`,
+/// with the description from the next line when it is on its own
+fn option(trimmed: &str, next: Option<&str>, line: &str) -> Option<Value> {
+    let (flags, description) = split_columns(trimmed);
+    // `-b     : issue warnings` in Python's help
+    let (flags, description) = match flags.split_once(" : ") {
+        Some((flags, rest)) => (flags, Some(rest.trim())),
+        None => (flags, description.map(|d| d.trim_start_matches(": "))),
+    };
+    let mut names = Vec::new();
+    let mut value = None;
+    let mut words = flags
+        .split([',', ' '])
+        .filter(|word| !word.is_empty() && *word != "|")
+        .peekable();
+    while let Some(word) = words.next() {
+        if !word.starts_with('-') {
+            continue;
+        }
+        let (flag, attached) = match word.find(['=', '<', '[']) {
+            Some(at) => (&word[..at], Some(&word[at..])),
+            None => (word, None),
+        };
+        // `--[no-]color` names both spellings
+        let flag = flag.trim_end_matches(['.', ':', ';']);
+        if flag.len() < 2 || flag == "--" || !flag.chars().nth(1).is_some_and(is_flag_char) {
+            continue;
+        }
+        names.push(flag.to_string());
+        let placeholder = attached.map(str::to_string).or_else(|| {
+            words
+                .next_if(|next| is_placeholder(next))
+                .map(str::to_string)
+        });
+        if let Some(placeholder) = placeholder {
+            value.get_or_insert(placeholder);
+        }
+    }
+    if names.is_empty() {
+        return None;
+    }
+
+    let description = match description {
+        Some(description) => Some(description.to_string()),
+        // Man pages put the description on the following, deeper indented line
+        None => next
+            .filter(|next| indent(next) > indent(line) && !next.trim().starts_with('-'))
+            .map(|next| next.trim().to_string()),
+    };
+    let mut option = Map::new();
+    option.insert(
+        "name".to_string(),
+        match names.as_slice() {
+            [name] => json!(name),
+            names => json!(names),
+        },
+    );
+    if let Some(description) = description.filter(|d| !d.is_empty()) {
+        option.insert(
+            "description".to_string(),
+            json!(first_sentence(&description)),
+        );
+    }
+    if let Some(value) = value {
+        let optional = value.starts_with('[') || value.starts_with("[=");
+        let name = value.trim_matches(|c: char| matches!(c, '=' | '<' | '>' | '[' | ']'));
+        if !name.is_empty() {
+            option.insert("args".to_string(), arg(name, optional, false));
+        }
+    }
+    Some(Value::Object(option))
+}
+
+/// Add `option`, or merge it into an earlier entry listing the same flag
+fn merge_option(options: &mut Vec<Value>, option: Value) {
+    let names = |option: &Value| -> Vec<String> {
+        match option.get("name") {
+            Some(Value::String(name)) => vec![name.clone()],
+            Some(Value::Array(names)) => names
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect(),
+            _ => Vec::new(),
+        }
+    };
+    let new = names(&option);
+    if options
+        .iter()
+        .any(|existing| names(existing).iter().any(|name| new.contains(name)))
+    {
+        return;
+    }
+    options.push(option);
+}
+
+/// A subcommand from a help line such as `  build, b    Compile the package`
+fn subcommand(line: &str) -> Option<Value> {
+    if indent(line) == 0 {
+        return None;
+    }
+    let (names, description) = split_columns(line.trim());
+    let names: Vec<&str> = names
+        .split([',', '|'])
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .collect();
+    if names.is_empty()
+        || !names.iter().all(|name| {
+            name.chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphanumeric())
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_:.".contains(c))
+        })
+    {
+        return None;
+    }
+    let mut subcommand = Map::new();
+    subcommand.insert(
+        "name".to_string(),
+        match names.as_slice() {
+            [name] => json!(name),
+            names => json!(names),
+        },
+    );
+    if let Some(description) = description {
+        subcommand.insert(
+            "description".to_string(),
+            json!(first_sentence(description)),
+        );
+    }
+    Some(Value::Object(subcommand))
+}
+
+/// Split `text` at its first gap of two or more spaces (or a tab) into the
+/// term and its description
+fn split_columns(text: &str) -> (&str, Option<&str>) {
+    let gap = text.find("  ").into_iter().chain(text.find('\t')).min();
+    match gap {
+        Some(at) => {
+            let description = text[at..].trim();
+            (
+                &text[..at],
+                (!description.is_empty()).then_some(description),
+            )
+        }
+        None => (text, None),
+    }
+}
+
+/// The description up to its first full stop
+fn first_sentence(text: &str) -> String {
+    let end = text
+        .match_indices(". ")
+        .map(|(at, _)| at)
+        .chain([text.len().saturating_sub(1)])
+        .find(|&at| {
+            // A stop ends a word, unlike the one in `starting with .`
+            text[at..].starts_with('.')
+                && text[..at].ends_with(|c: char| c.is_alphanumeric() || c == ')')
+        })
+        .unwrap_or(text.len());
+    text[..end].to_string()
+}
+
+/// Placeholders for an option's value: `FILE`, `<path>`, `{a,b}`
+fn is_placeholder(word: &str) -> bool {
+    word.starts_with('<')
+        || word.starts_with('{')
+        || word.starts_with('[') && !word.starts_with("[-")
+        || (word.len() > 1
+            && word
+                .chars()
+                .all(|c| c.is_ascii_uppercase() || c == '_' || c == '-'))
+}
+
+fn is_flag_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '?' || c == '['
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLAP: &str = "\
+A tool for testing. It does things.
+
+Usage: tool [OPTIONS] <FILE> [DIR]... <COMMAND>
+
+Commands:
+  build, b  Compile the package. Slowly
+  run       Run it
+  help      Print this message
+
+Arguments:
+  <FILE>  Input file
+
+Options:
+  -o, --output <PATH>  Write to PATH
+      --color[=<WHEN>]  When to use colors
+  -v, --verbose...     More output
+  -h, --help           Print help
+";
+
+    #[test]
+    fn test_parse_clap_help() {
+        assert_eq!(
+            parse("tool", CLAP),
+            json!({
+                "name": "tool",
+                "description": "A tool for testing",
+                "subcommands": [
+                    { "name": ["build", "b"], "description": "Compile the package" },
+                    { "name": "run", "description": "Run it" },
+                    { "name": "help", "description": "Print this message" },
+                ],
+                "options": [
+                    {
+                        "name": ["-o", "--output"],
+                        "description": "Write to PATH",
+                        "args": { "name": "path", "template": "filepaths" },
+                    },
+                    {
+                        "name": "--color",
+                        "description": "When to use colors",
+                        "args": { "name": "when", "isOptional": true },
+                    },
+                    { "name": ["-v", "--verbose"], "description": "More output" },
+                    { "name": ["-h", "--help"], "description": "Print help" },
+                ],
+                "args": [
+                    { "name": "file", "template": "filepaths" },
+                    { "name": "dir", "isOptional": true, "isVariadic": true, "template": "folders" },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_gnu_help() {
+        let help = "\
+Usage: ls [OPTION]... [FILE]...
+List information about the FILEs.
+
+  -a, --all                  do not ignore entries starting with .
+      --block-size=SIZE      scale sizes by SIZE
+  -I, --ignore=PATTERN       do not list entries matching PATTERN
+";
+        let spec = parse("ls", help);
+        assert_eq!(spec["description"], "List information about the FILEs");
+        assert_eq!(
+            spec["options"],
+            json!([
+                { "name": ["-a", "--all"], "description": "do not ignore entries starting with ." },
+                { "name": "--block-size", "description": "scale sizes by SIZE", "args": { "name": "size" } },
+                { "name": ["-I", "--ignore"], "description": "do not list entries matching PATTERN", "args": { "name": "pattern" } },
+            ])
+        );
+        assert_eq!(
+            spec["args"],
+            json!([{ "name": "file", "isOptional": true, "isVariadic": true, "template": "filepaths" }])
+        );
+    }
+
+    #[test]
+    fn test_parse_man_style_descriptions_on_next_line() {
+        let help = "\
+OPTIONS
+       -n, --lines=NUM
+              output the last NUM lines
+       --follow
+";
+        assert_eq!(
+            parse("tail", help)["options"],
+            json!([
+                { "name": ["-n", "--lines"], "description": "output the last NUM lines", "args": { "name": "num" } },
+                { "name": "--follow" },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_skips_option_values_in_usage_and_repeated_flags() {
+        let help = "\
+usage: tool [-C <path>] <target>
+    -C <path>   Run in path
+    -C <path>   Listed again
+";
+        let spec = parse("tool", help);
+        assert_eq!(spec["args"], json!([{ "name": "target" }]));
+        assert_eq!(spec["options"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_heading() {
+        assert_eq!(heading("Usage: x"), Some(Section::Usage));
+        assert_eq!(heading("SYNOPSIS"), Some(Section::Usage));
+        assert_eq!(heading("Available Commands:"), Some(Section::Commands));
+        assert_eq!(heading("Global Flags:"), Some(Section::Options));
+        assert_eq!(heading("EXAMPLES"), Some(Section::Other));
+        assert_eq!(heading("  --verbose:"), None);
+        assert_eq!(
+            heading("Clone a repository (see also: git help clone)"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_clean_removes_colors_and_overstrike() {
+        assert_eq!(clean("\u{1b}[1mbold\u{1b}[0m"), "bold");
+        assert_eq!(clean("N\u{8}NA\u{8}AM\u{8}ME\u{8}E"), "NAME");
+    }
+
+    #[test]
+    fn test_first_sentence() {
+        assert_eq!(first_sentence("Do it. Then more."), "Do it");
+        assert_eq!(
+            first_sentence("ignore names starting with ."),
+            "ignore names starting with ."
+        );
+    }
+
+    #[test]
+    fn test_generated_spec_loads() {
+        super::super::decode(parse("tool", CLAP), None).unwrap();
+    }
+}
@@ -0,0 +1,497 @@
+//! Specs read from man pages, for commands on `PATH` without a Fig or user
+//! spec.
+//!
+//! The roff source (man or mdoc macros) is rendered to text laid out like
+//! `--help` output, one `term  description` line per tagged paragraph, and
+//! read with the same heuristics as [`spec generate`](super::generate). Text
+//! in italics, which man pages use for placeholders, is written as
+//! `<placeholder>` so option arguments and the synopsis' positional
+//! arguments are recognized.
+
+use super::generate;
+use crate::index::describe::{man_page, parse_name_section, unroff};
+use serde_json::{Value, json};
+
+/// Indentation of rendered paragraphs, as `man` lays them out
+const INDENT: &str = "       ";
+
+/// Spec for `command` from its man page; `None` when it has no man page or
+/// the page lists no options or subcommands
+pub fn spec(command: &str) -> Option<Value> {
+    let source = man_page(command)?;
+    let mut spec = generate::parse(command, &render(&source));
+    if spec.get("options").is_none() && spec.get("subcommands").is_none() {
+        return None;
+    }
+    // The synopsis is often the only unindented text, so take the NAME line
+    spec["description"] = match parse_name_section(&source) {
+        Some(summary) => json!(summary),
+        None => Value::Null,
+    };
+    if let Value::Object(fields) = &mut spec {
+        fields.retain(|_, value| !value.is_null());
+    }
+    Some(spec)
+}
+
+/// Help-like text from roff source
+fn render(source: &str) -> String {
+    let mut page = Page::default();
+    // After `.TP` the next line is the tag
+    let mut expect_tag = false;
+    for line in source.lines() {
+        let line = strip_comment(line);
+        let request = line
+            .strip_prefix('.')
+            .or_else(|| line.strip_prefix('\''))
+            .map(|rest| {
+                let rest = rest.trim_start();
+                rest.split_once(char::is_whitespace)
+                    .map_or((rest, ""), |(name, args)| (name, args.trim()))
+            });
+
+        if expect_tag {
+            let tag = match request {
+                Some((name, args)) => inline(name, args, true),
+                None if !line.trim().is_empty() => Some(text(line, true)),
+                None => None,
+            };
+            if let Some(tag) = tag {
+                page.tag(tag);
+                expect_tag = false;
+            }
+            continue;
+        }
+        let Some((name, args)) = request else {
+            page.text(text(line, page.in_synopsis));
+            continue;
+        };
+        match name {
+            "SH" | "SS" | "Sh" | "Ss" => page.heading(&unroff(&unquote(args).join(" "))),
+            "TP" | "TQ" => {
+                page.start_item();
+                expect_tag = true;
+            }
+            "IP" => {
+                page.start_item();
+                if let Some(tag) = unquote(args).first() {
+                    page.tag(text(tag, true));
+                }
+            }
+            "It" => {
+                page.start_item();
+                page.tag(mdoc(args, true));
+            }
+            // mdoc's prologue: date, title and operating system
+            "Dd" | "Dt" | "Os" => {}
+            "PP" | "LP" | "P" | "Pp" | "HP" | "Bl" | "El" | "RS" | "RE" | "sp" | "br" => {
+                page.flush();
+            }
+            name => {
+                if let Some(rendered) = inline(name, args, page.in_synopsis) {
+                    page.text(rendered);
+                }
+            }
+        }
+    }
+    page.flush();
+    page.out
+}
+
+/// Rendered text, paragraph by paragraph
+#[derive(Default)]
+struct Page {
+    out: String,
+    /// Tag of the paragraph being collected
+    tag: Option<String>,
+    /// Text of the paragraph being collected
+    words: Vec<String>,
+    in_synopsis: bool,
+}
+
+impl Page {
+    fn heading(&mut self, title: &str) {
+        self.flush();
+        self.in_synopsis = title.eq_ignore_ascii_case("synopsis");
+        self.out.push_str(title);
+        self.out.push_str(":\n");
+    }
+
+    /// Start a tagged paragraph; a tag with no text yet, as in `.TQ` or
+    /// items separated by `.PD 0`, shares the next paragraph's text
+    fn start_item(&mut self) {
+        if self.tag.is_none() || !self.words.is_empty() {
+            self.flush();
+        }
+    }
+
+    fn tag(&mut self, tag: String) {
+        if tag.trim().is_empty() {
+            return;
+        }
+        match &mut self.tag {
+            Some(existing) => {
+                existing.push_str(", ");
+                existing.push_str(&tag);
+            }
+            None => self.tag = Some(tag),
+        }
+    }
+
+    fn text(&mut self, text: String) {
+        if !text.trim().is_empty() {
+            self.words.push(text);
+        }
+    }
+
+    /// Write the paragraph collected so far as one line
+    fn flush(&mut self) {
+        let text = collapse(&self.words.join(" "));
+        self.words.clear();
+        let line = match self.tag.take() {
+            Some(tag) if text.is_empty() => collapse(&tag),
+            Some(tag) => format!("{}  {}", collapse(&tag), text),
+            None if text.is_empty() => return,
+            None => text,
+        };
+        self.out.push_str(INDENT);
+        self.out.push_str(&line);
+        self.out.push('\n');
+    }
+}
+
+/// Text of a font or mdoc macro line, `None` for requests that print nothing
+fn inline(name: &str, args: &str, placeholders: bool) -> Option<String> {
+    let args = unquote(args);
+    let italic = |arg: &str| {
+        if placeholders {
+            format!("<{}>", text(arg, false))
+        } else {
+            text(arg, false)
+        }
+    };
+    let rendered = match name {
+        "B" | "R" | "SM" | "SB" => args
+            .iter()
+            .map(|arg| text(arg, placeholders))
+            .collect::<Vec<_>>()
+            .join(" "),
+        "I" => args
+            .iter()
+            .map(|arg| italic(arg))
+            .collect::<Vec<_>>()
+            .join(" "),
+        // Alternating fonts, with no space between the parts
+        "BR" | "RB" | "BI" | "IB" | "IR" | "RI" => {
+            let fonts: Vec<char> = name.chars().collect();
+            args.iter()
+                .enumerate()
+                .map(|(i, arg)| {
+                    let rendered = match fonts[i % 2] {
+                        'I' => italic(arg),
+                        _ => text(arg, placeholders),
+                    };
+                    // Quoted parts keep their spaces: `.BR \-v ", " \-\-verbose`
+                    let before = if arg.starts_with(' ') { " " } else { "" };
+                    let after = if arg.ends_with(' ') { " " } else { "" };
+                    format!("{before}{rendered}{after}")
+                })
+                .collect()
+        }
+        // mdoc's semantic macros are capitalized words: `Fl`, `Ar`, `Nm`
+        name if is_mdoc_macro(name) => mdoc(&format!("{name} {}", args.join(" ")), placeholders),
+        _ => return None,
+    };
+    Some(rendered)
+}
+
+/// Text of an mdoc macro line such as `Fl o Ar file`
+fn mdoc(line: &str, placeholders: bool) -> String {
+    let mut out = String::new();
+    // Whether the next word joins the previous one without a space
+    let mut attach = false;
+    let mut closing = Vec::new();
+    let mut current = "";
+    let words = unquote(line);
+    let mut words = words.iter().peekable();
+    while let Some(word) = words.next() {
+        let word = word.as_str();
+        if is_mdoc_macro(word) {
+            current = word;
+            match word {
+                "Ns" => attach = true,
+                "Op" | "Oo" => {
+                    push(&mut out, "[", attach);
+                    attach = true;
+                    if word == "Op" {
+                        closing.push("]");
+                    }
+                }
+                "Oc" => {
+                    out.push(']');
+                    attach = false;
+                }
+                "Fl" if words.peek().is_none_or(|next| is_mdoc_macro(next)) => {
+                    push(&mut out, "-", attach);
+                    attach = false;
+                }
+                "Ar" if words.peek().is_none_or(|next| is_mdoc_macro(next)) => {
+                    push(
+                        &mut out,
+                        if placeholders { "<file>" } else { "file" },
+                        attach,
+                    );
+                    attach = false;
+                }
+                _ => {}
+            }
+            continue;
+        }
+        let rendered = match current {
+            _ if word == "..." => word.to_string(),
+            "Fl" => format!("-{}", text(word, false)),
+            "Ar" if placeholders => format!("<{}>", text(word, false)),
+            _ => text(word, false),
+        };
+        // Closing punctuation stays on the word it follows
+        let punctuation = matches!(word, "," | "." | ";" | ":" | ")" | "]" | "|" | "...");
+        push(&mut out, &rendered, attach || punctuation);
+        attach = false;
+    }
+    for close in closing {
+        out.push_str(close);
+    }
+    out
+}
+
+fn push(out: &mut String, word: &str, attach: bool) {
+    if !attach && !out.is_empty() && !out.ends_with(['[', '(']) {
+        out.push(' ');
+    }
+    out.push_str(word);
+}
+
+/// Two letters, the first capitalized: `Fl`, `Ar`, `Op`
+fn is_mdoc_macro(word: &str) -> bool {
+    let mut chars = word.chars();
+    matches!(
+        (chars.next(), chars.next(), chars.next()),
+        (Some(first), Some(second), None) if first.is_ascii_uppercase() && second.is_ascii_lowercase()
+    )
+}
+
+/// Plain text of a roff line, italics written as `<placeholder>` when asked
+fn text(line: &str, placeholders: bool) -> String {
+    if !placeholders {
+        return unroff(line);
+    }
+    let mut marked = String::new();
+    let mut italic = false;
+    let mut rest = line;
+    while let Some(at) = rest.find("\\f") {
+        marked.push_str(&rest[..at]);
+        let after = &rest[at + 2..];
+        let (font, len) = if let Some(name) = after.strip_prefix('[') {
+            let end = name.find(']').unwrap_or(name.len());
+            (&name[..end], end + 2)
+        } else if let Some(name) = after.strip_prefix('(') {
+            (name.get(..2).unwrap_or(name), 3)
+        } else {
+            (after.get(..1).unwrap_or(after), 1)
+        };
+        let now_italic = font.ends_with('I');
+        if now_italic && !italic {
+            marked.push('<');
+        } else if !now_italic && italic {
+            marked.push('>');
+        }
+        italic = now_italic;
+        rest = after.get(len..).unwrap_or("");
+    }
+    marked.push_str(rest);
+    if italic {
+        marked.push('>');
+    }
+    unroff(&marked.replace("<>", ""))
+}
+
+/// A macro's arguments, double quotes grouping words
+fn unquote(args: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    for c in args.chars() {
+        match c {
+            '"' => {
+                if quoted {
+                    words.push(std::mem::take(&mut word));
+                }
+                quoted = !quoted;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            c => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// The line without its `\"` comment
+fn strip_comment(line: &str) -> &str {
+    match line.find("\\\"") {
+        Some(at) => &line[..at],
+        None => line,
+    }
+}
+
+fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAN: &str = r#".\" Generated page
+.TH TOOL 1
+.SH NAME
+tool \- do things
+.SH SYNOPSIS
+.B tool
+[\fIoptions\fR] \fIfile\fR...
+.SH OPTIONS
+.TP
+.BR \-v ", " \-\-verbose
+Print more.
+Even more.
+.TP
+.BI \-o " output"
+Write to
+.I output
+instead.
+.PD 0
+.TP
+.B \-q
+.TP
+.B \-\-quiet
+Print less. \" not shown
+.IP "\fB\-n\fR \fIcount\fR" 4
+Stop after
+.I count
+lines.
+.SH COMMANDS
+.TP
+.B run
+Run it.
+"#;
+
+    const MDOC: &str = r#".Dd January 1, 2024
+.Dt TOOL 1
+.Sh NAME
+.Nm tool
+.Nd do things
+.Sh SYNOPSIS
+.Nm
+.Op Fl ab
+.Op Fl f Ar file
+.Ar path ...
+.Sh DESCRIPTION
+.Bl -tag -width Ds
+.It Fl a
+All of them.
+.It Fl f Ar file
+Read from
+.Ar file .
+.It Fl -
+End options.
+.El
+"#;
+
+    #[test]
+    fn test_render_man_macros() {
+        assert_eq!(
+            render(MAN),
+            "\
+NAME:
+       tool - do things
+SYNOPSIS:
+       tool [<options>] <file>...
+OPTIONS:
+       -v, --verbose  Print more. Even more.
+       -o <output>  Write to output instead.
+       -q, --quiet  Print less.
+       -n <count>  Stop after count lines.
+COMMANDS:
+       run  Run it.
+"
+        );
+    }
+
+    #[test]
+    fn test_render_mdoc_macros() {
+        assert_eq!(
+            render(MDOC),
+            "\
+NAME:
+       tool do things
+SYNOPSIS:
+       [-ab] [-f <file>] <path>...
+DESCRIPTION:
+       -a  All of them.
+       -f <file>  Read from file.
+       --  End options.
+"
+        );
+    }
+
+    #[test]
+    fn test_rendered_page_reads_as_help() {
+        let spec = generate::parse("tool", &render(MAN));
+        assert_eq!(
+            spec["options"],
+            json!([
+                { "name": ["-v", "--verbose"], "description": "Print more" },
+                { "name": "-o", "description": "Write to output instead", "args": { "name": "output" } },
+                { "name": ["-q", "--quiet"], "description": "Print less" },
+                { "name": "-n", "description": "Stop after count lines", "args": { "name": "count" } },
+            ])
+        );
+        assert_eq!(
+            spec["subcommands"],
+            json!([{ "name": "run", "description": "Run it" }])
+        );
+        assert_eq!(
+            spec["args"],
+            json!([{ "name": "file", "isVariadic": true, "template": "filepaths" }])
+        );
+
+        let spec = generate::parse("tool", &render(MDOC));
+        assert_eq!(
+            spec["options"],
+            json!([
+                { "name": "-a", "description": "All of them" },
+                { "name": "-f", "description": "Read from file", "args": { "name": "file", "template": "filepaths" } },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_text_marks_italics() {
+        assert_eq!(text(r"\fB\-o\fP \fIfile\fR", true), "-o <file>");
+        assert_eq!(text(r"\f(BIname\fR", true), "<name>");
+        assert_eq!(text(r"\f[I]x\f[]", true), "<x>");
+        assert_eq!(text(r"\fIfile\fR", false), "file");
+    }
+
+    #[test]
+    fn test_unquote_groups_words() {
+        assert_eq!(unquote(r#"-o " output" x"#), ["-o", " output", "x"]);
+        assert_eq!(unquote("a  b"), ["a", "b"]);
+    }
+}
@@ -0,0 +1,449 @@
+//! Completion spec lookup and caching.
+//!
+//! # Ownership model
+//!
+//! Specs are stored as MessagePack bytes and deserialized without copying:
+//! every name, value and script in the runtime model is a `Cow<'a, str>`
+//! that borrows from the byte buffer it was decoded from (descriptions, which
+//! may carry translations, are owned). Bundled specs are decoded straight
+//! from the `&'static` bytes embedded in the binary, so even the largest
+//! specs (aws, gcloud) cost one allocation per node rather than one per
+//! string. Specs read from disk at runtime are decoded into owned data with
+//! [`Spec::into_owned`], which turns every borrowed string into an owned one.
+//!
+//! Bundled specs are JSON files in `specs/`, converted to MessagePack and
+//! packed into a single indexed blob by the build script. Specs in the user's
+//! `specs` config directory (JSON in the same format, [TOML](toml), or
+//! [carapace](carapace) YAML) take
+//! precedence over bundled ones, and patches there amend either kind (see
+//! [`patch`]). Between the two sit the [system spec directories](system_dirs)
+//! an administrator may provision, laid out like the user's and read-only.
+//! The user may [prefer](prefer) another source than the first for a command.
+
+use crate::paths;
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+use tracing::warn;
+
+mod bundle;
+mod carapace;
+pub mod convert;
+pub mod export;
+pub mod generate;
+pub mod man;
+mod model;
+mod patch;
+pub mod prefer;
+pub mod resolve;
+mod toml;
+pub mod validate;
+pub mod yaml;
+
+use bundle::Bundle;
+pub use model::*;
+pub use prefer::SpecSource;
+
+/// File name suffix of a patch to a spec
+const PATCH_SUFFIX: &str = ".patch.json";
+/// Extensions of user spec files, in order of precedence
+const USER_SPEC_EXTENSIONS: &[&str] = &["json", "toml", "yaml"];
+/// Extensions of the user's own specs, over imported carapace ones
+const OWN_SPEC_EXTENSIONS: &[&str] = &["json", "toml"];
+
+/// Completion spec for a single command: the command as the root subcommand
+pub type Spec<'a> = Subcommand<'a>;
+
+impl<'a> Spec<'a> {
+    /// Decode a spec from MessagePack, borrowing strings from `bytes`
+    pub fn from_msgpack(bytes: &'a [u8]) -> Result<Self> {
+        Ok(rmp_serde::from_slice(bytes)?)
+    }
+}
+
+/// Spec stored in the cache; borrows only from `'static` bundled bytes
+pub type CachedSpec = Arc<Spec<'static>>;
+
+/// Loads specs on demand and keeps them in memory for the daemon's lifetime
+#[derive(Debug, Default)]
+pub struct SpecStore {
+    /// Loaded specs by command name; `None` records a known miss
+    cache: RwLock<HashMap<String, Option<CachedSpec>>>,
+    /// Specs read from man pages, kept apart since only commands on `PATH`
+    /// fall back to them
+    man_pages: RwLock<HashMap<String, Option<CachedSpec>>>,
+}
+
+impl SpecStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Spec for `command`, loading it on first use
+    pub fn get(&self, command: &str) -> Option<CachedSpec> {
+        if let Some(cached) = self.read_cache().get(command) {
+            return cached.clone();
+        }
+        let spec = load(command).map(|mut spec| {
+            spec.retain_available();
+            Arc::new(spec)
+        });
+        self.write_cache().insert(command.to_string(), spec.clone());
+        spec
+    }
+
+    /// Spec for `command` read from its man page, for commands without a
+    /// Fig or user spec
+    pub fn man_page_spec(&self, command: &str) -> Option<CachedSpec> {
+        let cached = self
+            .man_pages
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(command)
+            .cloned();
+        if let Some(cached) = cached {
+            return cached;
+        }
+        let spec = man::spec(command)
+            .and_then(|spec| match decode(spec, None) {
+                Ok(spec) => Some(spec),
+                Err(e) => {
+                    warn!(
+                        "Ignoring the spec read from {}'s man page: {:#}",
+                        command, e
+                    );
+                    None
+                }
+            })
+            .map(Arc::new);
+        self.man_pages
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(command.to_string(), spec.clone());
+        spec
+    }
+
+    /// Whether `command`'s spec (or its absence) is already known, so
+    /// [`get`](Self::get) won't touch the disk
+    pub fn is_cached(&self, command: &str) -> bool {
+        self.read_cache().contains_key(command)
+    }
+
+    /// Eagerly load the specs for `commands`, returning how many were found
+    pub fn preload<'a>(&self, commands: impl IntoIterator<Item = &'a str>) -> usize {
+        commands
+            .into_iter()
+            .filter(|command| self.get(command).is_some())
+            .count()
+    }
+
+    /// Forget loaded specs, so edited user specs and man pages are read again
+    pub fn clear(&self) {
+        self.write_cache().clear();
+        self.man_pages
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+
+    /// Forget the loaded spec for `command`, so it is read again next time
+    pub fn invalidate(&self, command: &str) {
+        self.write_cache().remove(command);
+    }
+
+    fn read_cache(&self) -> std::sync::RwLockReadGuard<'_, HashMap<String, Option<CachedSpec>>> {
+        // A panic while holding the lock leaves the map itself intact
+        self.cache.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write_cache(&self) -> std::sync::RwLockWriteGuard<'_, HashMap<String, Option<CachedSpec>>> {
+        self.cache.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Names of all commands with a bundled, system or user spec
+pub fn available() -> Vec<String> {
+    let mut names: BTreeSet<String> = Bundle::embedded().names().map(str::to_string).collect();
+    for dir in spec_dirs() {
+        names.extend(
+            spec_files(&dir)
+                .into_iter()
+                .filter(|(_, is_patch)| !is_patch)
+                .map(|(name, _)| name),
+        );
+    }
+    names.into_iter().collect()
+}
+
+/// Commands the user has written a spec or a patch for
+pub fn user_commands() -> BTreeSet<String> {
+    user_dir()
+        .map(|dir| spec_files(&dir))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect()
+}
+
+/// Command of every spec and patch in `dir`, and whether it is a patch
+fn spec_files(dir: &Path) -> Vec<(String, bool)> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| user_file(&entry.file_name().into_string().ok()?))
+        .collect()
+}
+
+/// Command a file in the user's spec directory is for, and whether it is a
+/// patch; `None` for files that are neither specs nor patches
+fn user_file(name: &str) -> Option<(String, bool)> {
+    if let Some(command) = name.strip_suffix(PATCH_SUFFIX) {
+        return Some((command.to_string(), true));
+    }
+    let (command, extension) = name.rsplit_once('.')?;
+    USER_SPEC_EXTENSIONS
+        .contains(&extension)
+        .then(|| (command.to_string(), false))
+}
+
+/// State of the user's spec directory, compared between scans to find the
+/// specs and patches that were written or deleted
+#[derive(Debug, Default)]
+pub struct UserSpecs {
+    /// Modification time and size of each spec and patch, by file name
+    files: BTreeMap<String, (Option<SystemTime>, u64)>,
+}
+
+impl UserSpecs {
+    pub fn scan() -> Self {
+        let mut files = BTreeMap::new();
+        if let Some(entries) = user_dir().and_then(|dir| std::fs::read_dir(dir).ok()) {
+            for entry in entries.flatten() {
+                let Ok(name) = entry.file_name().into_string() else {
+                    continue;
+                };
+                if user_file(&name).is_none() {
+                    continue;
+                }
+                let Ok(meta) = entry.metadata() else {
+                    continue;
+                };
+                files.insert(name, (meta.modified().ok(), meta.len()));
+            }
+        }
+        Self { files }
+    }
+
+    /// Commands whose spec or patch was added, changed or removed since
+    /// `earlier`, and whether the set of spec files changed
+    pub fn changes_since(&self, earlier: &Self) -> (BTreeSet<String>, bool) {
+        let mut commands = BTreeSet::new();
+        let mut added_or_removed = false;
+        let names = self.files.keys().chain(earlier.files.keys());
+        for name in names {
+            let (now, before) = (self.files.get(name), earlier.files.get(name));
+            if now == before {
+                continue;
+            }
+            let Some((command, is_patch)) = user_file(name) else {
+                continue;
+            };
+            added_or_removed |= !is_patch && (now.is_none() || before.is_none());
+            commands.insert(command);
+        }
+        (commands, added_or_removed)
+    }
+}
+
+/// Where the spec for a command comes from
+#[derive(Debug, Default)]
+pub struct Origin {
+    /// Every spec there is for the command, the one used first
+    pub sources: Vec<(SpecSource, Option<PathBuf>)>,
+    /// The source the user chose with `spec prefer`, if it has a spec
+    pub preferred: Option<SpecSource>,
+    /// The patch applied to whichever spec is used, the user's or else a
+    /// system one
+    pub patch: Option<PathBuf>,
+}
+
+/// Where the spec for `command` comes from
+pub fn origin(command: &str) -> Origin {
+    let chosen = prefer::preferences().get(command).copied();
+    let sources = ranked_sources(command, chosen);
+    Origin {
+        preferred: chosen.filter(|chosen| sources.iter().any(|(source, _)| source == chosen)),
+        sources,
+        patch: patch_file(command),
+    }
+}
+
+/// Every spec there is for `command`, in order of precedence, with the file
+/// each is read from; the bundled one has none
+pub fn sources(command: &str) -> Vec<(SpecSource, Option<PathBuf>)> {
+    let mut found = Vec::new();
+    if let Some(dir) = user_dir() {
+        if let Some(path) = spec_file(&dir, command, OWN_SPEC_EXTENSIONS) {
+            found.push((SpecSource::User, Some(path)));
+        }
+        if let Some(path) = spec_file(&dir, command, &["yaml"]) {
+            found.push((SpecSource::Carapace, Some(path)));
+        }
+    }
+    if let Some(path) = system_dirs().iter().find_map(|dir| user_spec(dir, command)) {
+        found.push((SpecSource::System, Some(path)));
+    }
+    if Bundle::embedded().get(command).is_some() {
+        found.push((SpecSource::Bundled, None));
+    }
+    found
+}
+
+/// [`sources`] with `chosen` first, if it has a spec
+fn ranked_sources(command: &str, chosen: Option<SpecSource>) -> Vec<(SpecSource, Option<PathBuf>)> {
+    let mut found = sources(command);
+    if let Some(chosen) = chosen {
+        // Stable, so the others keep their precedence
+        found.sort_by_key(|(source, _)| *source != chosen);
+    }
+    found
+}
+
+/// Directory of user specs, `<command>.json`, `.toml` or `.yaml` each, and
+/// patches to specs, `<command>.patch.json`
+pub fn user_dir() -> Option<PathBuf> {
+    paths::config_dir().map(|dir| dir.join("specs"))
+}
+
+/// Directories of specs and patches shared by all users, laid out like
+/// [`user_dir`]: `autocomplete-rs/specs` in each of the
+/// [system data directories](paths::system_data_dirs) that exists
+pub fn system_dirs() -> Vec<PathBuf> {
+    paths::system_data_dirs()
+        .into_iter()
+        .map(|dir| dir.join("specs"))
+        .filter(|dir| dir.is_dir())
+        .collect()
+}
+
+/// The user's spec directory, then the system ones, in order of precedence
+fn spec_dirs() -> Vec<PathBuf> {
+    user_dir().into_iter().chain(system_dirs()).collect()
+}
+
+/// The patch for `command` from the first spec directory with one
+fn patch_file(command: &str) -> Option<PathBuf> {
+    spec_dirs()
+        .into_iter()
+        .map(|dir| dir.join(format!("{command}{PATCH_SUFFIX}")))
+        .find(|path| path.is_file())
+}
+
+/// Load the spec for `command` from the first of its [sources](ranked_sources)
+/// that has a valid one, with the patch for it applied
+fn load(command: &str) -> Option<Spec<'static>> {
+    let patch = patch_file(command).and_then(|path| match read_json(&path) {
+        Ok(patch) => Some(patch),
+        Err(e) => {
+            warn!("Ignoring spec patch {}: {:#}", path.display(), e);
+            None
+        }
+    });
+
+    let chosen = prefer::preferences().get(command).copied();
+    for (_, path) in ranked_sources(command, chosen) {
+        let Some(path) = path else {
+            let bytes = Bundle::embedded().get(command)?;
+            let spec = match &patch {
+                Some(patch) => rmp_serde::from_slice(bytes)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|spec| decode(spec, Some(patch))),
+                None => Spec::from_msgpack(bytes),
+            };
+            match spec {
+                Ok(spec) => return Some(spec),
+                Err(e) => warn!("Failed to load bundled spec for {}: {:#}", command, e),
+            }
+            continue;
+        };
+        let spec = match &patch {
+            None if path.extension().is_some_and(|ext| ext == "json") => load_user(&path),
+            patch => read_spec(&path).and_then(|spec| decode(spec, patch.as_ref())),
+        };
+        match spec {
+            Ok(spec) => return Some(spec),
+            Err(e) => warn!("Ignoring spec {}: {:#}", path.display(), e),
+        }
+    }
+    None
+}
+
+/// The spec file for `command` in the spec directory `dir`, if there is one
+fn user_spec(dir: &Path, command: &str) -> Option<PathBuf> {
+    spec_file(dir, command, USER_SPEC_EXTENSIONS)
+}
+
+/// The first spec file for `command` in `dir` with one of `extensions`
+fn spec_file(dir: &Path, command: &str, extensions: &[&str]) -> Option<PathBuf> {
+    extensions
+        .iter()
+        .map(|extension| dir.join(format!("{command}.{extension}")))
+        .find(|path| path.is_file())
+}
+
+/// Decode a JSON spec file straight into the model
+fn load_user(path: &Path) -> Result<Spec<'static>> {
+    let bytes = std::fs::read(path)?;
+    let spec: Spec<'_> = serde_json::from_slice(&bytes).context("Invalid spec")?;
+    Ok(spec.into_owned())
+}
+
+fn read_json(path: &Path) -> Result<serde_json::Value> {
+    let bytes = std::fs::read(path)?;
+    serde_json::from_slice(&bytes).context("Invalid JSON")
+}
+
+/// The spec `command` gets before the user's patch, as JSON
+fn base_value(command: &str) -> Result<Option<serde_json::Value>> {
+    let chosen = prefer::preferences().get(command).copied();
+    match ranked_sources(command, chosen).into_iter().next() {
+        Some((_, Some(path))) => read_spec(&path).map(Some),
+        Some((_, None)) => Bundle::embedded()
+            .get(command)
+            .map(|bytes| Ok(rmp_serde::from_slice(bytes)?))
+            .transpose(),
+        None => Ok(None),
+    }
+}
+
+/// A user spec file as JSON, whichever format it is written in
+fn read_spec(path: &Path) -> Result<serde_json::Value> {
+    if path.extension().is_some_and(|ext| ext == "toml") {
+        let contents = std::fs::read_to_string(path)?;
+        return toml::parse(&contents).context("Invalid TOML");
+    }
+    if path.extension().is_some_and(|ext| ext == "yaml") {
+        let contents = std::fs::read_to_string(path)?;
+        return carapace::parse(&contents).context("Invalid carapace spec");
+    }
+    read_json(path)
+}
+
+/// `spec` with `patch`, if any, merged in
+fn decode(mut spec: serde_json::Value, patch: Option<&serde_json::Value>) -> Result<Spec<'static>> {
+    if let Some(patch) = patch {
+        patch::apply(&mut spec, patch);
+    }
+    let bytes = serde_json::to_vec(&spec)?;
+    let context = if patch.is_some() {
+        "Invalid spec after patching"
+    } else {
+        "Invalid spec"
+    };
+    let spec: Spec<'_> = serde_json::from_slice(&bytes).context(context)?;
+    Ok(spec.into_owned())
+}
@@ -0,0 +1,665 @@
+//! Completion spec data model, mirroring Fig's completion spec format.
+//!
+//! Field names are Fig's (`isRepeatable`, `exclusiveOn`, ...), and the places
+//! where Fig accepts either a single value or a list (`name`, `args`,
+//! `template`, `generators`) accept both. `postProcess` and other JavaScript
+//! functions have no equivalent; generators split their output instead.
+
+use crate::i18n::LocalizedText;
+use serde::de::{self, Deserializer, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fmt;
+use std::ops::Range;
+
+/// A command and everything below it; the root of a spec is the command itself
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Subcommand<'a> {
+    /// Names the subcommand is invoked by, aliases after the first
+    #[serde(borrow, deserialize_with = "names")]
+    pub name: Vec<Cow<'a, str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<LocalizedText>,
+    #[serde(borrow, skip_serializing_if = "Vec::is_empty")]
+    pub subcommands: Vec<Subcommand<'a>>,
+    #[serde(borrow, skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<SpecOption<'a>>,
+    #[serde(
+        borrow,
+        deserialize_with = "one_or_many",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub args: Vec<Arg<'a>>,
+    /// Not suggested, but still recognized when typed
+    #[serde(skip_serializing_if = "is_false")]
+    pub hidden: bool,
+    /// Platforms the subcommand exists on; all of them when empty
+    #[serde(
+        borrow,
+        deserialize_with = "names",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub available_on: Vec<Cow<'a, str>>,
+}
+
+/// A flag, Fig's `Option`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SpecOption<'a> {
+    /// Spellings of the flag: `["-m", "--message"]`
+    #[serde(borrow, deserialize_with = "names")]
+    pub name: Vec<Cow<'a, str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<LocalizedText>,
+    /// Values the flag takes
+    #[serde(
+        borrow,
+        deserialize_with = "one_or_many",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub args: Vec<Arg<'a>>,
+    /// Also valid in every subcommand below the one declaring it
+    #[serde(skip_serializing_if = "is_false")]
+    pub is_persistent: bool,
+    /// May be given more than once (Fig also allows a maximum count)
+    #[serde(deserialize_with = "repeatable", skip_serializing_if = "is_false")]
+    pub is_repeatable: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    pub is_required: bool,
+    /// Flags this one can't be combined with
+    #[serde(borrow, skip_serializing_if = "Vec::is_empty")]
+    pub exclusive_on: Vec<Cow<'a, str>>,
+    /// Flags that must be given before this one makes sense
+    #[serde(borrow, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<Cow<'a, str>>,
+    #[serde(skip_serializing_if = "is_false")]
+    pub hidden: bool,
+    /// Platforms the flag exists on; all of them when empty
+    #[serde(
+        borrow,
+        deserialize_with = "names",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub available_on: Vec<Cow<'a, str>>,
+}
+
+/// A positional value, of a subcommand or an option
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Arg<'a> {
+    #[serde(borrow, skip_serializing_if = "Option::is_none")]
+    pub name: Option<Cow<'a, str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<LocalizedText>,
+    #[serde(skip_serializing_if = "is_false")]
+    pub is_optional: bool,
+    /// Takes any number of values
+    #[serde(skip_serializing_if = "is_false")]
+    pub is_variadic: bool,
+    /// Fixed values: strings, or `{ "name": ..., "description": ... }`
+    #[serde(borrow, skip_serializing_if = "Vec::is_empty")]
+    pub suggestions: Vec<SpecSuggestion<'a>>,
+    #[serde(
+        deserialize_with = "one_or_many",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub template: Vec<Template>,
+    #[serde(
+        borrow,
+        deserialize_with = "one_or_many",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub generators: Vec<Generator<'a>>,
+}
+
+/// A fixed value offered for an argument
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SpecSuggestion<'a> {
+    Name(#[serde(borrow)] Cow<'a, str>),
+    Described {
+        #[serde(borrow, deserialize_with = "names")]
+        name: Vec<Cow<'a, str>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        description: Option<LocalizedText>,
+    },
+}
+
+/// Paths an argument or generator completes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Template {
+    Filepaths,
+    Folders,
+}
+
+/// A command whose output lists suggestions
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Generator<'a> {
+    /// Argument vector, or a single string run with `sh -c`
+    #[serde(borrow, deserialize_with = "names")]
+    pub script: Vec<Cow<'a, str>>,
+    /// Separator between suggestions in the output; a newline when unset
+    #[serde(borrow, skip_serializing_if = "Option::is_none")]
+    pub split_on: Option<Cow<'a, str>>,
+    /// Paths to complete instead of, or besides, running a script
+    #[serde(
+        deserialize_with = "one_or_many",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub template: Vec<Template>,
+    /// Milliseconds the script may run, instead of the configured timeout
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub script_timeout: Option<u64>,
+    /// How each piece of the output becomes a suggestion; the whole piece
+    /// is its name when unset
+    #[serde(borrow, skip_serializing_if = "Option::is_none")]
+    pub parse: Option<Parse<'a>>,
+    /// How long the output is reused; for the configured time, in the same
+    /// directory, when unset
+    #[serde(borrow, skip_serializing_if = "Option::is_none")]
+    pub cache: Option<GeneratorCache<'a>>,
+}
+
+/// Fig's `cache`: reusing a generator's output instead of running it again
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct GeneratorCache<'a> {
+    /// Milliseconds the output is reused for; the configured time when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u64>,
+    pub strategy: CacheStrategy,
+    /// Keep an output per working directory, for scripts that depend on it
+    pub cache_by_directory: bool,
+    /// Share the output with the generators of the same key, which otherwise
+    /// is the script
+    #[serde(borrow, skip_serializing_if = "Option::is_none")]
+    pub cache_key: Option<Cow<'a, str>>,
+}
+
+/// What a generator with an expired output gives
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CacheStrategy {
+    /// Nothing until it has run again
+    #[default]
+    MaxAge,
+    /// The expired output, while it runs again in the background
+    StaleWhileRevalidate,
+}
+
+/// Columns to take a generator's suggestions from, in place of Fig's
+/// JavaScript `postProcess`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Parse<'a> {
+    /// Pieces to leave out at the start, like the header of a table
+    #[serde(skip_serializing_if = "is_zero")]
+    pub skip: usize,
+    /// Removed from the start of each piece, like the `* ` before git's
+    /// current branch
+    #[serde(borrow, skip_serializing_if = "Option::is_none")]
+    pub strip_prefix: Option<Cow<'a, str>>,
+    /// Separator between the columns of a piece; runs of whitespace when unset
+    #[serde(borrow, skip_serializing_if = "Option::is_none")]
+    pub columns: Option<Cow<'a, str>>,
+    /// Column of the name, counting from 0
+    #[serde(skip_serializing_if = "is_zero")]
+    pub name: usize,
+    /// Column the description starts at, running to the end of the piece
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<usize>,
+}
+
+impl<'a> Subcommand<'a> {
+    /// Detach the spec from the buffer it was decoded from
+    pub fn into_owned(self) -> Subcommand<'static> {
+        Subcommand {
+            name: owned_all(self.name),
+            description: self.description,
+            subcommands: self
+                .subcommands
+                .into_iter()
+                .map(Subcommand::into_owned)
+                .collect(),
+            options: self
+                .options
+                .into_iter()
+                .map(SpecOption::into_owned)
+                .collect(),
+            args: self.args.into_iter().map(Arg::into_owned).collect(),
+            hidden: self.hidden,
+            available_on: owned_all(self.available_on),
+        }
+    }
+
+    /// Whether `word` invokes this subcommand
+    pub fn is_named(&self, word: &str) -> bool {
+        self.name.iter().any(|name| name == word)
+    }
+
+    /// Drop the subcommands and flags below this one that don't exist on
+    /// the platform the daemon runs on
+    pub fn retain_available(&mut self) {
+        self.subcommands
+            .retain(|subcommand| available_here(&subcommand.available_on));
+        self.options
+            .retain(|option| available_here(&option.available_on));
+        for subcommand in &mut self.subcommands {
+            subcommand.retain_available();
+        }
+    }
+}
+
+impl<'a> SpecOption<'a> {
+    pub fn into_owned(self) -> SpecOption<'static> {
+        SpecOption {
+            name: owned_all(self.name),
+            description: self.description,
+            args: self.args.into_iter().map(Arg::into_owned).collect(),
+            is_persistent: self.is_persistent,
+            is_repeatable: self.is_repeatable,
+            is_required: self.is_required,
+            exclusive_on: owned_all(self.exclusive_on),
+            depends_on: owned_all(self.depends_on),
+            hidden: self.hidden,
+            available_on: owned_all(self.available_on),
+        }
+    }
+
+    /// Whether `flag` is one of this option's spellings
+    pub fn is_named(&self, flag: &str) -> bool {
+        self.name.iter().any(|name| name == flag)
+    }
+
+    /// The spelling that stands for all of them: the first long one, else the first
+    pub fn key(&self) -> &str {
+        self.name
+            .iter()
+            .find(|name| name.starts_with("--"))
+            .or_else(|| self.name.first())
+            .map_or("", |name| name.as_ref())
+    }
+}
+
+impl<'a> Arg<'a> {
+    pub fn into_owned(self) -> Arg<'static> {
+        Arg {
+            name: self.name.map(|name| Cow::Owned(name.into_owned())),
+            description: self.description,
+            is_optional: self.is_optional,
+            is_variadic: self.is_variadic,
+            suggestions: self
+                .suggestions
+                .into_iter()
+                .map(SpecSuggestion::into_owned)
+                .collect(),
+            template: self.template,
+            generators: self
+                .generators
+                .into_iter()
+                .map(Generator::into_owned)
+                .collect(),
+        }
+    }
+}
+
+impl<'a> SpecSuggestion<'a> {
+    pub fn into_owned(self) -> SpecSuggestion<'static> {
+        match self {
+            SpecSuggestion::Name(name) => SpecSuggestion::Name(Cow::Owned(name.into_owned())),
+            SpecSuggestion::Described { name, description } => SpecSuggestion::Described {
+                name: owned_all(name),
+                description,
+            },
+        }
+    }
+}
+
+impl<'a> Generator<'a> {
+    pub fn into_owned(self) -> Generator<'static> {
+        Generator {
+            script: owned_all(self.script),
+            split_on: self.split_on.map(|sep| Cow::Owned(sep.into_owned())),
+            template: self.template,
+            script_timeout: self.script_timeout,
+            parse: self.parse.map(Parse::into_owned),
+            cache: self.cache.map(|cache| GeneratorCache {
+                cache_key: cache.cache_key.map(|key| Cow::Owned(key.into_owned())),
+                ..cache
+            }),
+        }
+    }
+
+    /// The suggestions in a run's `output`: names and their descriptions
+    pub fn items<'o>(&self, output: &'o str) -> Vec<(&'o str, Option<&'o str>)> {
+        let separator = self.split_on.as_deref().unwrap_or("\n");
+        let pieces = output.split(separator).map(str::trim);
+        let Some(parse) = &self.parse else {
+            return pieces
+                .filter(|piece| !piece.is_empty())
+                .map(|piece| (piece, None))
+                .collect();
+        };
+        pieces
+            .skip(parse.skip)
+            .filter_map(|piece| {
+                let piece = match &parse.strip_prefix {
+                    Some(prefix) => piece.strip_prefix(prefix.as_ref()).unwrap_or(piece),
+                    None => piece,
+                };
+                let columns = column_spans(piece, parse.columns.as_deref());
+                let name = piece[columns.get(parse.name)?.clone()].trim();
+                let description = parse
+                    .description
+                    .and_then(|index| columns.get(index))
+                    .map(|column| piece[column.start..].trim())
+                    .filter(|description| !description.is_empty());
+                (!name.is_empty()).then_some((name, description))
+            })
+            .collect()
+    }
+}
+
+impl Parse<'_> {
+    pub fn into_owned(self) -> Parse<'static> {
+        Parse {
+            skip: self.skip,
+            strip_prefix: self.strip_prefix.map(|s| Cow::Owned(s.into_owned())),
+            columns: self.columns.map(|s| Cow::Owned(s.into_owned())),
+            name: self.name,
+            description: self.description,
+        }
+    }
+}
+
+/// Names `availableOn` understands: an operating system as Rust names it,
+/// or a family of them
+pub const PLATFORMS: &[&str] = &[
+    "linux", "macos", "windows", "freebsd", "netbsd", "openbsd", "android", "ios", "unix",
+];
+
+/// Whether something limited to `platforms` exists where the daemon runs
+fn available_here(platforms: &[Cow<'_, str>]) -> bool {
+    platforms.is_empty()
+        || platforms.iter().any(|platform| {
+            platform == std::env::consts::OS || platform == std::env::consts::FAMILY
+        })
+}
+
+/// Byte ranges of the columns of `piece`, separated by `separator` or by
+/// runs of whitespace
+fn column_spans(piece: &str, separator: Option<&str>) -> Vec<Range<usize>> {
+    match separator {
+        Some(separator) if !separator.is_empty() => {
+            let mut start = 0;
+            let mut columns = Vec::new();
+            for (at, _) in piece.match_indices(separator) {
+                columns.push(start..at);
+                start = at + separator.len();
+            }
+            columns.push(start..piece.len());
+            columns
+        }
+        _ => {
+            let mut columns: Vec<Range<usize>> = Vec::new();
+            let mut in_word = false;
+            for (at, c) in piece.char_indices() {
+                match (in_word, c.is_whitespace()) {
+                    (false, false) => columns.push(at..at + c.len_utf8()),
+                    (true, false) => {
+                        if let Some(column) = columns.last_mut() {
+                            column.end = at + c.len_utf8();
+                        }
+                    }
+                    _ => {}
+                }
+                in_word = !c.is_whitespace();
+            }
+            columns
+        }
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+fn is_zero(value: &usize) -> bool {
+    *value == 0
+}
+
+fn owned_all(strings: Vec<Cow<'_, str>>) -> Vec<Cow<'static, str>> {
+    strings
+        .into_iter()
+        .map(|s| Cow::Owned(s.into_owned()))
+        .collect()
+}
+
+/// A string or a list of strings, borrowing from the input when it can
+fn names<'de: 'a, 'a, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Cow<'a, str>>, D::Error> {
+    struct Names;
+
+    impl<'de> Visitor<'de> for Names {
+        type Value = Vec<Cow<'de, str>>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a string or a list of strings")
+        }
+
+        fn visit_borrowed_str<E: de::Error>(self, v: &'de str) -> Result<Self::Value, E> {
+            Ok(vec![Cow::Borrowed(v)])
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+            Ok(vec![Cow::Owned(v.to_string())])
+        }
+
+        fn visit_string<E: de::Error>(self, v: String) -> Result<Self::Value, E> {
+            Ok(vec![Cow::Owned(v)])
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut names = Vec::new();
+            while let Some(name) = seq.next_element::<BorrowedStr<'de>>()? {
+                names.push(name.0);
+            }
+            Ok(names)
+        }
+    }
+
+    deserializer.deserialize_any(Names)
+}
+
+/// A string element that borrows when it can; plain `Cow` always copies
+struct BorrowedStr<'a>(Cow<'a, str>);
+
+impl<'de> Deserialize<'de> for BorrowedStr<'de> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Wrapper<'a>(#[serde(borrow)] Cow<'a, str>);
+        Wrapper::deserialize(deserializer).map(|wrapper| BorrowedStr(wrapper.0))
+    }
+}
+
+/// A single value or a list of them
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged, bound(deserialize = "T: Deserialize<'de>"))]
+    enum OneOrMany<T> {
+        Many(Vec<T>),
+        One(T),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::Many(values) => values,
+        OneOrMany::One(value) => vec![value],
+    })
+}
+
+/// `isRepeatable` is `true`, or the number of times the flag may be given
+fn repeatable<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repeatable {
+        Flag(bool),
+        Count(u64),
+    }
+
+    Ok(match Repeatable::deserialize(deserializer)? {
+        Repeatable::Flag(flag) => flag,
+        Repeatable::Count(count) => count > 1,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generator(json: &str) -> Generator<'_> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_deserialize_single_values_as_lists() {
+        let json = r#"{
+            "name": "tool",
+            "options": [{ "name": "-v", "args": { "name": "x", "template": "folders" } }],
+            "args": [{ "generators": { "script": "ls" } }]
+        }"#;
+        let spec: Subcommand = serde_json::from_str(json).unwrap();
+        assert_eq!(spec.name, ["tool"]);
+        assert_eq!(spec.options[0].name, ["-v"]);
+        assert_eq!(spec.options[0].args[0].template, [Template::Folders]);
+        assert_eq!(spec.args[0].generators[0].script, ["ls"]);
+    }
+
+    #[test]
+    fn test_deserialize_names_borrow_from_input() {
+        let json = r#"{ "name": ["remote", "rm"], "subcommands": [{ "name": "add" }] }"#;
+        let spec: Subcommand = serde_json::from_str(json).unwrap();
+        assert!(
+            spec.name
+                .iter()
+                .all(|name| matches!(name, Cow::Borrowed(_)))
+        );
+        assert!(matches!(spec.subcommands[0].name[0], Cow::Borrowed(_)));
+        // Escapes can't be borrowed
+        let spec: Subcommand = serde_json::from_str(r#"{ "name": "a\"b" }"#).unwrap();
+        assert!(matches!(&spec.name[0], Cow::Owned(name) if name == "a\"b"));
+    }
+
+    #[test]
+    fn test_deserialize_repeatable_count() {
+        let option = |json| {
+            serde_json::from_str::<SpecOption>(json)
+                .unwrap()
+                .is_repeatable
+        };
+        assert!(option(r#"{ "name": "-v", "isRepeatable": true }"#));
+        assert!(option(r#"{ "name": "-v", "isRepeatable": 3 }"#));
+        assert!(!option(r#"{ "name": "-v", "isRepeatable": 1 }"#));
+        assert!(!option(r#"{ "name": "-v" }"#));
+    }
+
+    #[test]
+    fn test_deserialize_suggestion_forms() {
+        let arg: Arg = serde_json::from_str(
+            r#"{ "suggestions": ["a", { "name": ["b", "bee"], "description": "B" }] }"#,
+        )
+        .unwrap();
+        assert!(matches!(&arg.suggestions[0], SpecSuggestion::Name(name) if name == "a"));
+        assert!(matches!(
+            &arg.suggestions[1],
+            SpecSuggestion::Described { name, description: Some(_) } if name == &["b", "bee"]
+        ));
+    }
+
+    #[test]
+    fn test_serialize_round_trip_skips_defaults() {
+        let json = r#"{"name":["tool"],"options":[{"name":["-v"],"isRepeatable":true}]}"#;
+        let spec: Subcommand = serde_json::from_str(json).unwrap();
+        assert_eq!(serde_json::to_string(&spec).unwrap(), json);
+    }
+
+    #[test]
+    fn test_option_key_prefers_long_name() {
+        let option: SpecOption = serde_json::from_str(r#"{ "name": ["-o", "--output"] }"#).unwrap();
+        assert_eq!(option.key(), "--output");
+        assert!(option.is_named("-o"));
+        let option: SpecOption = serde_json::from_str(r#"{ "name": "-x" }"#).unwrap();
+        assert_eq!(option.key(), "-x");
+    }
+
+    #[test]
+    fn test_retain_available_drops_other_platforms() {
+        let json = format!(
+            r#"{{
+                "name": "tool",
+                "options": [{{ "name": "--here", "availableOn": "{os}" }}, {{ "name": "--never", "availableOn": ["plan9"] }}],
+                "subcommands": [
+                    {{ "name": "gone", "availableOn": "plan9" }},
+                    {{ "name": "kept", "options": [{{ "name": "--no", "availableOn": "plan9" }}] }}
+                ]
+            }}"#,
+            os = std::env::consts::OS
+        );
+        let mut spec: Subcommand = serde_json::from_str(&json).unwrap();
+        spec.retain_available();
+        assert_eq!(spec.options.len(), 1);
+        assert_eq!(spec.subcommands.len(), 1);
+        assert!(spec.subcommands[0].options.is_empty());
+    }
+
+    #[test]
+    fn test_items_splits_output() {
+        let lines = generator(r#"{ "script": "x" }"#);
+        assert_eq!(
+            lines.items("main\n  dev \n\n"),
+            [("main", None), ("dev", None)]
+        );
+        let commas = generator(r#"{ "script": "x", "splitOn": "," }"#);
+        assert_eq!(commas.items("a, b,"), [("a", None), ("b", None)]);
+    }
+
+    #[test]
+    fn test_items_parse_columns() {
+        let branches = generator(
+            r#"{ "script": "x", "parse": { "stripPrefix": "* ", "name": 0, "description": 2 } }"#,
+        );
+        assert_eq!(
+            branches.items("* main  abc123 Fix the build\n  dev   def456\n"),
+            [("main", Some("Fix the build")), ("dev", None)]
+        );
+        let table = generator(
+            r#"{ "script": "x", "parse": { "skip": 1, "columns": "|", "name": 1, "description": 2 } }"#,
+        );
+        assert_eq!(
+            table.items("ID|NAME|STATUS\n1|web|up | healthy\n2||down"),
+            [("web", Some("up | healthy"))]
+        );
+    }
+
+    #[test]
+    fn test_column_spans() {
+        assert_eq!(column_spans("  a  bc ", None), [2..3, 5..7]);
+        assert_eq!(column_spans("a::b::", Some("::")), [0..1, 3..4, 6..6]);
+        assert_eq!(column_spans("é ü", None), [0..2, 3..5]);
+    }
+
+    #[test]
+    fn test_into_owned_keeps_everything() {
+        let json = r#"{"name":["tool"],"args":[{"name":"x","generators":[{"script":["ls"],"splitOn":",","parse":{"stripPrefix":"* "},"cache":{"strategy":"stale-while-revalidate","cacheByDirectory":true,"cacheKey":"k"}}]}]}"#;
+        let spec: Subcommand = serde_json::from_str(json).unwrap();
+        let owned = spec.into_owned();
+        assert_eq!(serde_json::to_string(&owned).unwrap(), json);
+    }
+}
@@ -0,0 +1,81 @@
+//! User patches to specs.
+//!
+//! A patch is a partial spec merged into the one it fixes, so a single
+//! option can be changed without copying the whole spec:
+//!
+//! - fields replace the spec's, and objects (an option's `args`, translated
+//!   descriptions) merge recursively; `null` removes a field
+//! - `subcommands` and `options` entries are matched by any of their names
+//!   and merged into the entry they match, or added when nothing matches;
+//!   an entry with `"remove": true` takes the match out instead
+
+use serde_json::{Map, Value};
+
+/// Lists whose entries are matched by name rather than replaced whole
+const NAMED_LISTS: &[&str] = &["subcommands", "options"];
+
+/// Merge `patch` into `spec`
+pub fn apply(spec: &mut Value, patch: &Value) {
+    let (Value::Object(spec), Value::Object(patch)) = (spec, patch) else {
+        return;
+    };
+    merge(spec, patch);
+}
+
+fn merge(target: &mut Map<String, Value>, patch: &Map<String, Value>) {
+    for (key, value) in patch {
+        match (target.get_mut(key), value) {
+            (_, Value::Null) => {
+                target.remove(key);
+            }
+            (Some(Value::Array(entries)), Value::Array(patches))
+                if NAMED_LISTS.contains(&key.as_str()) =>
+            {
+                merge_named(entries, patches);
+            }
+            (Some(Value::Object(inner)), Value::Object(patch)) => merge(inner, patch),
+            _ => {
+                target.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+fn merge_named(entries: &mut Vec<Value>, patches: &[Value]) {
+    for patch in patches {
+        let Value::Object(patch) = patch else {
+            continue;
+        };
+        let wanted = names(patch.get("name"));
+        let found = entries.iter().position(|entry| {
+            names(entry.get("name"))
+                .iter()
+                .any(|name| wanted.contains(name))
+        });
+        let remove = patch.get("remove").is_some_and(|remove| remove == true);
+        match (found, remove) {
+            (Some(i), true) => {
+                entries.remove(i);
+            }
+            (Some(i), false) => {
+                if let Value::Object(entry) = &mut entries[i] {
+                    // The names only pick the entry, so its aliases survive
+                    let mut patch = patch.clone();
+                    patch.remove("name");
+                    merge(entry, &patch);
+                }
+            }
+            (None, false) => entries.push(Value::Object(patch.clone())),
+            (None, true) => {}
+        }
+    }
+}
+
+/// `"name"` or `["name", "alias"]`
+fn names(name: Option<&Value>) -> Vec<&str> {
+    match name {
+        Some(Value::String(name)) => vec![name],
+        Some(Value::Array(names)) => names.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    }
+}
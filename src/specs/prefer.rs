@@ -0,0 +1,104 @@
+//! Which spec wins when several sources have one for a command.
+//!
+//! A command may have a spec of the user's own, a carapace spec copied into
+//! the user's spec directory, one in a shared system directory and a bundled
+//! one. The first of them in [`SpecSource`] order is used, unless
+//! `spec prefer` recorded another choice for the command in
+//! `spec-preferences.json` in the config directory.
+
+use crate::paths;
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use tracing::warn;
+
+/// A place a spec comes from, in order of precedence
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpecSource {
+    /// A JSON or TOML spec in the user's spec directory
+    User,
+    /// A carapace YAML spec in the user's spec directory
+    Carapace,
+    /// A spec in a shared system directory
+    System,
+    /// The spec bundled with the binary
+    Bundled,
+}
+
+impl SpecSource {
+    pub const ALL: [SpecSource; 4] = [
+        SpecSource::User,
+        SpecSource::Carapace,
+        SpecSource::System,
+        SpecSource::Bundled,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SpecSource::User => "user",
+            SpecSource::Carapace => "carapace",
+            SpecSource::System => "system",
+            SpecSource::Bundled => "bundled",
+        }
+    }
+}
+
+impl std::fmt::Display for SpecSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl std::str::FromStr for SpecSource {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match Self::ALL.into_iter().find(|source| source.name() == name) {
+            Some(source) => Ok(source),
+            None => {
+                bail!("unknown spec source `{name}`; expected user, carapace, system or bundled")
+            }
+        }
+    }
+}
+
+/// Where the recorded choices are kept
+pub fn preferences_file() -> Option<PathBuf> {
+    paths::config_dir().map(|dir| dir.join("spec-preferences.json"))
+}
+
+/// The source chosen for each command that has a choice recorded
+pub fn preferences() -> BTreeMap<String, SpecSource> {
+    let Some(path) = preferences_file() else {
+        return BTreeMap::new();
+    };
+    let Ok(bytes) = std::fs::read(&path) else {
+        return BTreeMap::new();
+    };
+    serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+        warn!("Ignoring spec preferences {}: {}", path.display(), e);
+        BTreeMap::new()
+    })
+}
+
+/// Record that `command` takes its spec from `source`, or with `None` forget
+/// the choice and go by precedence again
+pub fn set_preference(command: &str, source: Option<SpecSource>) -> Result<()> {
+    let path = preferences_file().context("Could not determine the config directory")?;
+    let mut preferences = preferences();
+    match source {
+        Some(source) => preferences.insert(command.to_string(), source),
+        None => preferences.remove(command),
+    };
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let json = serde_json::to_vec_pretty(&preferences)?;
+    // Write then rename so a crash never leaves a truncated file behind
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, json).with_context(|| format!("Failed to write {}", tmp.display()))?;
+    std::fs::rename(&tmp, &path).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(())
+}
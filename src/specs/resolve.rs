@@ -0,0 +1,776 @@
+//! Walking a command line through a spec to find what can come next.
+//!
+//! The completed words after the command are fed one by one: flags are
+//! matched against the options in scope (persistent options of parent
+//! subcommands included) and consume their values, the first positional word
+//! may enter a subcommand, and the rest fill the subcommand's arguments. What
+//! is left describes the word under the cursor: a flag, a subcommand, or the
+//! value of an argument, including one attached to its flag (`--name=va`).
+
+use super::{Arg, Generator, Spec, SpecOption, SpecSuggestion, Subcommand, Template};
+use crate::daemon::{ArgKind, Suggestion, SuggestionSource};
+use crate::i18n::LocalizedText;
+use crate::parser::Token;
+
+/// What the spec offers for the word under the cursor
+#[derive(Debug, Default)]
+pub struct Resolution<'s> {
+    /// Subcommands, flags and fixed argument values
+    pub suggestions: Vec<Suggestion>,
+    /// Commands to run for the argument's values
+    pub generators: Vec<&'s Generator<'s>>,
+    /// Paths to complete, if the argument takes any
+    pub paths: Option<Template>,
+    /// Flag whose free-form value is being completed, for which values given
+    /// before may be worth suggesting
+    pub value_of: Option<&'s SpecOption<'s>>,
+}
+
+/// Resolve the word being typed, `word`, after the completed `args` (the
+/// words following the command name)
+pub fn resolve<'s>(spec: &'s Spec<'s>, args: &[Token], word: &str, locale: &str) -> Resolution<'s> {
+    let mut walk = Walk::new(spec);
+    for token in args {
+        walk.feed(&token.text);
+    }
+    walk.complete(word, locale)
+}
+
+/// What kind of word `word`, after the completed `args`, is, and the name
+/// of the argument it fills, without collecting any suggestions
+pub fn expects<'s>(spec: &'s Spec<'s>, args: &[Token], word: &str) -> (ArgKind, Option<&'s str>) {
+    let mut walk = Walk::new(spec);
+    for token in args {
+        walk.feed(&token.text);
+    }
+    let (kind, arg) = walk.expects(word);
+    (kind, arg.and_then(|arg| arg.name.as_deref()))
+}
+
+/// The free-form values given to flags in a complete command line, `args`
+/// being the words following the command name
+pub fn flag_values<'s>(spec: &'s Spec<'s>, args: &[Token]) -> Vec<(&'s SpecOption<'s>, String)> {
+    let mut walk = Walk::new(spec);
+    for token in args {
+        walk.feed(&token.text);
+    }
+    walk.values
+}
+
+/// Position in the spec after the words seen so far
+struct Walk<'s> {
+    command: &'s Subcommand<'s>,
+    /// Persistent options declared above `command`
+    inherited: Vec<&'s SpecOption<'s>>,
+    /// Flags given so far
+    given: Vec<&'s SpecOption<'s>>,
+    /// Values of the last flag still to come: the flag, and its next arg's index
+    pending: Option<(&'s SpecOption<'s>, usize)>,
+    /// Positional arguments of `command` filled so far
+    position: usize,
+    /// Whether `--` was seen, after which everything is positional
+    end_of_options: bool,
+    /// Free-form values given to flags so far
+    values: Vec<(&'s SpecOption<'s>, String)>,
+}
+
+impl<'s> Walk<'s> {
+    fn new(spec: &'s Spec<'s>) -> Self {
+        Self {
+            command: spec,
+            inherited: Vec::new(),
+            given: Vec::new(),
+            pending: None,
+            position: 0,
+            end_of_options: false,
+            values: Vec::new(),
+        }
+    }
+
+    fn feed(&mut self, token: &str) {
+        if let Some((option, next)) = self.pending {
+            if next == 0 {
+                self.given_value(option, token);
+            }
+            let args = &option.args;
+            self.pending = (next + 1 < args.len() && !args[next + 1].is_optional)
+                .then_some((option, next + 1));
+            return;
+        }
+        if !self.end_of_options && token == "--" {
+            self.end_of_options = true;
+            return;
+        }
+        if !self.end_of_options && token.len() > 1 && token.starts_with('-') {
+            self.feed_flag(token);
+            return;
+        }
+
+        if self.position == 0
+            && let Some(subcommand) = self
+                .command
+                .subcommands
+                .iter()
+                .find(|subcommand| subcommand.is_named(token))
+        {
+            self.inherited.extend(
+                self.command
+                    .options
+                    .iter()
+                    .filter(|option| option.is_persistent),
+            );
+            self.command = subcommand;
+            return;
+        }
+        let variadic = self
+            .command
+            .args
+            .get(self.position)
+            .is_some_and(|arg| arg.is_variadic);
+        if !variadic {
+            self.position += 1;
+        }
+    }
+
+    /// `--name`, `--name=value`, `-n`, or combined short flags `-abc`
+    fn feed_flag(&mut self, token: &str) {
+        if let Some((flag, value)) = token.split_once('=')
+            && token.starts_with("--")
+        {
+            // The value is attached, so nothing is pending
+            if let Some(option) = self.find(flag) {
+                self.given.push(option);
+                self.given_value(option, value);
+            }
+            return;
+        }
+        if let Some(option) = self.find(token) {
+            self.give(option);
+            return;
+        }
+        if token.starts_with("--") {
+            return;
+        }
+        let shorts = &token[1..];
+        for (i, c) in shorts.char_indices() {
+            let Some(option) = self.find(&format!("-{c}")) else {
+                break;
+            };
+            if option.args.is_empty() {
+                self.given.push(option);
+                continue;
+            }
+            // `-ofile`: the rest of the word is the value
+            let rest = &shorts[i + c.len_utf8()..];
+            if !rest.is_empty() {
+                self.given.push(option);
+                self.given_value(option, rest);
+            } else {
+                self.give(option);
+            }
+            break;
+        }
+    }
+
+    /// Record `option`, expecting its value next unless the value is optional
+    fn give(&mut self, option: &'s SpecOption<'s>) {
+        self.given.push(option);
+        if option.args.first().is_some_and(|arg| !arg.is_optional) {
+            self.pending = Some((option, 0));
+        }
+    }
+
+    /// Note `value` given to `option` when the spec can't enumerate its values
+    fn given_value(&mut self, option: &'s SpecOption<'s>, value: &str) {
+        if option.args.first().is_some_and(is_free_form) && !value.is_empty() {
+            self.values.push((option, value.to_string()));
+        }
+    }
+
+    fn options(&self) -> impl Iterator<Item = &'s SpecOption<'s>> + '_ {
+        self.command
+            .options
+            .iter()
+            .chain(self.inherited.iter().copied())
+    }
+
+    fn find(&self, flag: &str) -> Option<&'s SpecOption<'s>> {
+        self.options().find(|option| option.is_named(flag))
+    }
+
+    fn is_given(&self, flag: &str) -> bool {
+        self.given.iter().any(|option| option.is_named(flag))
+    }
+
+    /// Whether `option` may still be given: not already given (unless it
+    /// repeats), not excluded by a given flag, and with its prerequisites met
+    fn allows(&self, option: &SpecOption<'_>) -> bool {
+        let already = self.given.iter().any(|given| std::ptr::eq(*given, option));
+        if already && !option.is_repeatable {
+            return false;
+        }
+        let excluded = option.exclusive_on.iter().any(|flag| self.is_given(flag))
+            || self
+                .given
+                .iter()
+                .any(|given| given.exclusive_on.iter().any(|flag| option.is_named(flag)));
+        !excluded && option.depends_on.iter().all(|flag| self.is_given(flag))
+    }
+
+    fn complete(self, word: &str, locale: &str) -> Resolution<'s> {
+        let mut resolution = Resolution::default();
+        if let Some((option, next)) = self.pending {
+            let arg = &option.args[next];
+            resolution.add_arg(arg, locale);
+            if is_free_form(arg) {
+                if next == 0 {
+                    resolution.value_of = Some(option);
+                }
+                if names_directory(Some(option), arg) {
+                    resolution.paths = Some(Template::Folders);
+                }
+            }
+            return resolution;
+        }
+
+        if !self.end_of_options
+            && let Some((flag, _)) = word.split_once('=')
+            && flag.starts_with("--")
+        {
+            // `--name=va` completes the value, if the flag takes one
+            if let Some(option) = self.find(flag)
+                && let Some(arg) = option.args.first()
+            {
+                resolution.add_arg(arg, locale);
+                if is_free_form(arg) {
+                    resolution.value_of = Some(option);
+                    if names_directory(Some(option), arg) {
+                        resolution.paths = Some(Template::Folders);
+                    }
+                }
+            }
+            return resolution;
+        }
+
+        if !self.end_of_options && word.starts_with('-') {
+            for option in self.options() {
+                if option.hidden || !self.allows(option) {
+                    continue;
+                }
+                let Some(name) = flag_name(option, word) else {
+                    continue;
+                };
+                resolution.suggestions.push(spec_suggestion(
+                    name,
+                    option.description.as_ref(),
+                    locale,
+                ));
+            }
+            resolution.suggestions.extend(self.clustered(word, locale));
+            return resolution;
+        }
+
+        if self.position == 0 {
+            for subcommand in &self.command.subcommands {
+                if subcommand.hidden {
+                    continue;
+                }
+                if let Some(name) = subcommand.name.first() {
+                    resolution.suggestions.push(spec_suggestion(
+                        name,
+                        subcommand.description.as_ref(),
+                        locale,
+                    ));
+                }
+            }
+        }
+        let arg = self
+            .command
+            .args
+            .get(self.position)
+            .or_else(|| self.command.args.last().filter(|arg| arg.is_variadic));
+        if let Some(arg) = arg {
+            resolution.add_arg(arg, locale);
+            // Nothing says what the argument is, and it may well be a path
+            if is_free_form(arg) && arg.generators.is_empty() {
+                resolution.paths = Some(if names_directory(None, arg) {
+                    Template::Folders
+                } else {
+                    Template::Filepaths
+                });
+            }
+        }
+        resolution
+    }
+
+    /// The kind of word `word` is, following [`complete`](Self::complete),
+    /// and the argument it fills
+    fn expects(&self, word: &str) -> (ArgKind, Option<&'s Arg<'s>>) {
+        let value = |arg: &'s Arg<'s>, open| {
+            let kind = if takes_paths(arg, open) {
+                ArgKind::Path
+            } else {
+                ArgKind::Value
+            };
+            (kind, Some(arg))
+        };
+        if let Some((option, next)) = self.pending {
+            let arg = &option.args[next];
+            return value(arg, names_directory(Some(option), arg));
+        }
+        if !self.end_of_options && word.starts_with('-') {
+            if let Some((flag, _)) = word.split_once('=')
+                && flag.starts_with("--")
+            {
+                return match self
+                    .find(flag)
+                    .and_then(|option| Some((option, option.args.first()?)))
+                {
+                    Some((option, arg)) => value(arg, names_directory(Some(option), arg)),
+                    None => (ArgKind::Nothing, None),
+                };
+            }
+            return (ArgKind::Flag, None);
+        }
+        let arg = self
+            .command
+            .args
+            .get(self.position)
+            .or_else(|| self.command.args.last().filter(|arg| arg.is_variadic));
+        if self.position == 0
+            && self
+                .command
+                .subcommands
+                .iter()
+                .any(|subcommand| !subcommand.hidden)
+        {
+            return (ArgKind::Subcommand, arg);
+        }
+        match arg {
+            Some(arg) => value(arg, true),
+            None => (ArgKind::Nothing, None),
+        }
+    }
+
+    /// `word`, a cluster of short flags like `-xv`, with each short flag
+    /// that may still join it appended; nothing unless every letter of
+    /// `word` is a flag that takes no value
+    fn clustered(mut self, word: &str, locale: &str) -> Vec<Suggestion> {
+        let Some(letters) = word
+            .strip_prefix('-')
+            .filter(|letters| !letters.is_empty() && !letters.starts_with('-'))
+        else {
+            return Vec::new();
+        };
+        for c in letters.chars() {
+            match self.find(&format!("-{c}")) {
+                Some(option) if option.args.is_empty() => self.given.push(option),
+                _ => return Vec::new(),
+            }
+        }
+        let mut suggestions = Vec::new();
+        for option in self.options() {
+            if option.hidden || !self.allows(option) {
+                continue;
+            }
+            // A flag taking a value can only come last: `-xvf archive.tar`
+            let shorts = option
+                .name
+                .iter()
+                .filter_map(|name| name.strip_prefix('-'))
+                .filter(|letter| letter.chars().count() == 1 && *letter != "-");
+            for letter in shorts {
+                suggestions.push(spec_suggestion(
+                    &format!("{word}{letter}"),
+                    option.description.as_ref(),
+                    locale,
+                ));
+            }
+        }
+        suggestions
+    }
+}
+
+impl<'s> Resolution<'s> {
+    fn add_arg(&mut self, arg: &'s Arg<'s>, locale: &str) {
+        for suggestion in &arg.suggestions {
+            let (name, description) = match suggestion {
+                SpecSuggestion::Name(name) => (Some(name), None),
+                SpecSuggestion::Described { name, description } => {
+                    (name.first(), description.as_ref())
+                }
+            };
+            if let Some(name) = name {
+                self.suggestions
+                    .push(spec_suggestion(name, description, locale));
+            }
+        }
+        let templates = arg.template.iter().chain(
+            arg.generators
+                .iter()
+                .flat_map(|generator| &generator.template),
+        );
+        for &template in templates {
+            // Files include folders
+            if self.paths != Some(Template::Filepaths) {
+                self.paths = Some(template);
+            }
+        }
+        self.generators.extend(
+            arg.generators
+                .iter()
+                .filter(|generator| !generator.script.is_empty()),
+        );
+    }
+}
+
+/// Whether `arg` completes paths: it says so, or it is `open` to them (a
+/// positional argument, or an option's directory) and says nothing about its
+/// values at all
+fn takes_paths(arg: &Arg<'_>, open: bool) -> bool {
+    !arg.template.is_empty()
+        || arg
+            .generators
+            .iter()
+            .any(|generator| !generator.template.is_empty())
+        || (open && is_free_form(arg) && arg.generators.is_empty())
+}
+
+/// Whether the argument or its option is named for a directory, as in
+/// `--output-dir <DIR>`, so that it completes folders when the spec gives
+/// no template
+fn names_directory(option: Option<&SpecOption<'_>>, arg: &Arg<'_>) -> bool {
+    let is_directory = |name: &str| {
+        let name = name.trim_start_matches('-').to_ascii_lowercase();
+        ["dir", "directory", "folder"]
+            .iter()
+            .any(|suffix| name.ends_with(suffix))
+    };
+    arg.name.as_deref().is_some_and(is_directory)
+        || option.is_some_and(|option| option.name.iter().any(|name| is_directory(name)))
+}
+
+/// Whether `arg` takes values the spec doesn't list: no fixed suggestions
+/// and no paths
+fn is_free_form(arg: &Arg<'_>) -> bool {
+    arg.suggestions.is_empty()
+        && arg.template.is_empty()
+        && arg
+            .generators
+            .iter()
+            .all(|generator| generator.template.is_empty())
+}
+
+/// The spelling of `option` to offer for `word`: the first one it is a prefix
+/// of, or else the long one for fuzzy matching to judge
+fn flag_name<'s>(option: &'s SpecOption<'s>, word: &str) -> Option<&'s str> {
+    let name = option
+        .name
+        .iter()
+        .find(|name| name.starts_with(word))
+        .or_else(|| option.name.iter().find(|name| name.starts_with("--")))
+        .or_else(|| option.name.first())?;
+    Some(name)
+}
+
+fn spec_suggestion(text: &str, description: Option<&LocalizedText>, locale: &str) -> Suggestion {
+    Suggestion {
+        text: text.to_string(),
+        description: description
+            .map(|description| description.get(locale).to_string())
+            .unwrap_or_default(),
+        source: SuggestionSource::Spec,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::ParseState;
+    use serde_json::json;
+
+    fn spec() -> Spec<'static> {
+        super::super::decode(
+            json!({
+                "name": "tool",
+                "options": [
+                    { "name": ["-v", "--verbose"], "isRepeatable": true },
+                    { "name": ["-q", "--quiet"], "exclusiveOn": ["--verbose"] },
+                    { "name": ["-C", "--config"], "isPersistent": true, "args": { "name": "file" } },
+                    { "name": "--color", "args": { "name": "when", "suggestions": ["auto", "never"] } },
+                    { "name": "--level", "args": { "name": "level", "isOptional": true } },
+                    { "name": "--secret", "hidden": true },
+                ],
+                "subcommands": [
+                    {
+                        "name": ["remote", "rm"],
+                        "description": "Manage remotes",
+                        "options": [{ "name": "--tags" }],
+                        "subcommands": [
+                            {
+                                "name": "add",
+                                "args": [
+                                    { "name": "name" },
+                                    { "name": "url", "generators": { "script": ["tool", "urls"] } },
+                                ],
+                            },
+                            { "name": "show", "args": { "name": "remote", "isOptional": true, "suggestions": ["origin"] } },
+                        ],
+                    },
+                    {
+                        "name": "cp",
+                        "options": [{ "name": ["-t", "--target-dir"], "args": { "name": "path" } }],
+                        "args": [
+                            { "name": "source", "isVariadic": true, "template": "filepaths" },
+                        ],
+                    },
+                    {
+                        "name": "checkout",
+                        "args": {
+                            "name": "branch",
+                            "generators": [
+                                { "script": "git branch" },
+                                { "template": "folders" },
+                            ],
+                        },
+                    },
+                    { "name": "debug", "hidden": true },
+                ],
+            }),
+            None,
+        )
+        .unwrap()
+    }
+
+    /// The completed words after the command in `line`, and the word being typed
+    fn split(line: &str) -> (Vec<Token>, String) {
+        let state = ParseState::new(line);
+        let args = state.tokens()[1..].to_vec();
+        let word = state
+            .current_token()
+            .map(|token| token.text.clone())
+            .unwrap_or_default();
+        (args, word)
+    }
+
+    fn resolved(spec: &Spec<'_>, line: &str) -> Vec<String> {
+        let (args, word) = split(line);
+        resolve(spec, &args, &word, "en")
+            .suggestions
+            .into_iter()
+            .map(|suggestion| suggestion.text)
+            .collect()
+    }
+
+    fn expected(spec: &Spec<'_>, line: &str) -> (ArgKind, Option<String>) {
+        let (args, word) = split(line);
+        let (kind, name) = expects(spec, &args, &word);
+        (kind, name.map(str::to_string))
+    }
+
+    #[test]
+    fn test_resolve_top_level_offers_visible_subcommands() {
+        let spec = spec();
+        assert_eq!(resolved(&spec, "tool "), ["remote", "cp", "checkout"]);
+        assert_eq!(expected(&spec, "tool "), (ArgKind::Subcommand, None));
+    }
+
+    #[test]
+    fn test_resolve_nested_subcommands_and_aliases() {
+        let spec = spec();
+        assert_eq!(resolved(&spec, "tool remote "), ["add", "show"]);
+        assert_eq!(resolved(&spec, "tool rm "), ["add", "show"]);
+        assert_eq!(resolved(&spec, "tool rm show "), ["origin"]);
+        // Past the first positional, no subcommand is entered
+        assert_eq!(
+            expected(&spec, "tool rm add origin "),
+            (ArgKind::Value, Some("url".to_string()))
+        );
+        assert_eq!(
+            expected(&spec, "tool rm add origin url "),
+            (ArgKind::Nothing, None)
+        );
+    }
+
+    #[test]
+    fn test_resolve_flags_offer_those_still_allowed() {
+        let spec = spec();
+        assert_eq!(
+            resolved(&spec, "tool --"),
+            ["--verbose", "--quiet", "--config", "--color", "--level"]
+        );
+        // Repeatable flags stay; exclusive ones go both ways
+        assert_eq!(
+            resolved(&spec, "tool --verbose --"),
+            ["--verbose", "--config", "--color", "--level"]
+        );
+        assert_eq!(
+            resolved(&spec, "tool -q --"),
+            ["--config", "--color", "--level"]
+        );
+        assert_eq!(expected(&spec, "tool -"), (ArgKind::Flag, None));
+    }
+
+    #[test]
+    fn test_resolve_flag_value_is_consumed() {
+        let spec = spec();
+        assert_eq!(resolved(&spec, "tool --color "), ["auto", "never"]);
+        assert_eq!(
+            resolved(&spec, "tool --color auto "),
+            ["remote", "cp", "checkout"]
+        );
+        // An optional value isn't waited for
+        assert_eq!(
+            resolved(&spec, "tool --level "),
+            ["remote", "cp", "checkout"]
+        );
+    }
+
+    #[test]
+    fn test_resolve_attached_flag_values() {
+        let spec = spec();
+        assert_eq!(resolved(&spec, "tool --color=n"), ["auto", "never"]);
+        assert_eq!(
+            resolved(&spec, "tool --color=auto "),
+            ["remote", "cp", "checkout"]
+        );
+        assert_eq!(
+            expected(&spec, "tool --config="),
+            (ArgKind::Value, Some("file".to_string()))
+        );
+        assert_eq!(expected(&spec, "tool --unknown="), (ArgKind::Nothing, None));
+        // `-Cfile` carries its value too
+        assert_eq!(
+            resolved(&spec, "tool -Cfile "),
+            ["remote", "cp", "checkout"]
+        );
+    }
+
+    #[test]
+    fn test_resolve_flag_values_records_free_form_values() {
+        let spec = spec();
+        let (args, _) = split("tool --config=a.toml --color auto -C b.toml remote ");
+        let values: Vec<(&str, String)> = flag_values(&spec, &args)
+            .into_iter()
+            .map(|(option, value)| (option.key(), value))
+            .collect();
+        assert_eq!(
+            values,
+            [
+                ("--config", "a.toml".to_string()),
+                ("--config", "b.toml".to_string())
+            ]
+        );
+        let (args, word) = split("tool --config ");
+        let resolution = resolve(&spec, &args, &word, "en");
+        assert_eq!(resolution.value_of.map(SpecOption::key), Some("--config"));
+    }
+
+    #[test]
+    fn test_resolve_persistent_options_reach_subcommands() {
+        let spec = spec();
+        assert_eq!(resolved(&spec, "tool remote add --"), ["--config"]);
+        assert_eq!(resolved(&spec, "tool remote --"), ["--tags", "--config"]);
+        // Non-persistent parent options don't
+        assert_eq!(
+            expected(&spec, "tool remote --color "),
+            (ArgKind::Subcommand, None)
+        );
+        assert_eq!(resolved(&spec, "tool remote -C x.toml "), ["add", "show"]);
+    }
+
+    #[test]
+    fn test_resolve_variadic_argument_keeps_taking_words() {
+        let spec = spec();
+        let (args, word) = split("tool cp a b c ");
+        let resolution = resolve(&spec, &args, &word, "en");
+        assert_eq!(resolution.paths, Some(Template::Filepaths));
+        assert_eq!(
+            expected(&spec, "tool cp a b c "),
+            (ArgKind::Path, Some("source".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_resolve_optional_argument_and_end_of_options() {
+        let spec = spec();
+        assert_eq!(
+            expected(&spec, "tool rm show "),
+            (ArgKind::Value, Some("remote".to_string()))
+        );
+        assert_eq!(
+            expected(&spec, "tool rm show origin "),
+            (ArgKind::Nothing, None)
+        );
+        // After `--`, a dash starts a positional
+        assert_eq!(
+            expected(&spec, "tool cp -- -"),
+            (ArgKind::Path, Some("source".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_resolve_generators_and_templates_are_dispatched() {
+        let spec = spec();
+        let (args, word) = split("tool checkout ");
+        let resolution = resolve(&spec, &args, &word, "en");
+        let scripts: Vec<_> = resolution
+            .generators
+            .iter()
+            .map(|generator| generator.script.join(" "))
+            .collect();
+        // The template-only generator gives paths, not a script to run
+        assert_eq!(scripts, ["git branch"]);
+        assert_eq!(resolution.paths, Some(Template::Folders));
+
+        let (args, word) = split("tool rm add origin ");
+        let resolution = resolve(&spec, &args, &word, "en");
+        assert_eq!(resolution.generators.len(), 1);
+        assert_eq!(resolution.paths, None);
+    }
+
+    #[test]
+    fn test_resolve_free_form_values_fall_back_to_paths() {
+        let spec = spec();
+        let (args, word) = split("tool rm add ");
+        assert_eq!(
+            resolve(&spec, &args, &word, "en").paths,
+            Some(Template::Filepaths)
+        );
+        // An option named for a directory completes folders
+        let (args, word) = split("tool cp --target-dir ");
+        assert_eq!(
+            resolve(&spec, &args, &word, "en").paths,
+            Some(Template::Folders)
+        );
+        assert_eq!(
+            expected(&spec, "tool cp --target-dir "),
+            (ArgKind::Path, Some("path".to_string()))
+        );
+        // A flag's own free-form value isn't a path otherwise
+        assert_eq!(
+            expected(&spec, "tool --config "),
+            (ArgKind::Value, Some("file".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_resolve_clustered_short_flags() {
+        let spec = spec();
+        // The flags themselves, then the cluster with each that may join
+        // it; `-q` excludes `-v`
+        assert_eq!(
+            resolved(&spec, "tool -v"),
+            [
+                "-v", "--quiet", "--config", "--color", "--level", "-vv", "-vC"
+            ]
+        );
+        // `x` is no flag, so there is no cluster to extend
+        assert!(
+            !resolved(&spec, "tool -vx")
+                .iter()
+                .any(|text| text.starts_with("-vx"))
+        );
+    }
+}
@@ -0,0 +1,132 @@
+# Generated by `autocomplete-rs spec export --shell bash tool`
+
+_tool() {
+    local cur="${COMP_WORDS[COMP_CWORD]}" prev="${COMP_WORDS[COMP_CWORD-1]}"
+    local path=_tool args=0 i
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        case "$path:$args:${COMP_WORDS[i]}" in
+            '_tool:0:remote' | '_tool:0:r') path=_tool_remote args=0 ;;
+            '_tool:0:log') path=_tool_log args=0 ;;
+            '_tool:0:debug') path=_tool_debug args=0 ;;
+            '_tool:'*':-C' | '_tool:'*':--dir') i=$((i + 1)) ;;
+            '_tool_remote:0:add') path=_tool_remote_add args=0 ;;
+            '_tool_remote:0:remove') path=_tool_remote_remove args=0 ;;
+            '_tool_remote:'*':-C' | '_tool_remote:'*':--dir') i=$((i + 1)) ;;
+            '_tool_remote_add:'*':-C' | '_tool_remote_add:'*':--dir') i=$((i + 1)) ;;
+            '_tool_remote_remove:'*':-C' | '_tool_remote_remove:'*':--dir') i=$((i + 1)) ;;
+            '_tool_log:'*':--format' | '_tool_log:'*':-C' | '_tool_log:'*':--dir') i=$((i + 1)) ;;
+            '_tool_debug:'*':-C' | '_tool_debug:'*':--dir') i=$((i + 1)) ;;
+            *:*:-*) ;;
+            *) args=$((args + 1)) ;;
+        esac
+    done
+
+    COMPREPLY=()
+    case "$path" in
+        _tool)
+            case "$prev" in
+                '-C' | '--dir')
+                    compopt -o filenames 2>/dev/null
+                    COMPREPLY+=($(compgen -d -- "$cur"))
+                    return
+                    ;;
+            esac
+            if [[ $cur == -* ]]; then
+                COMPREPLY=($(compgen -W '-v --verbose --quiet -C --dir' -- "$cur"))
+                return
+            fi
+            case "$args" in
+                0)
+                    COMPREPLY+=($(compgen -W 'remote r log' -- "$cur"))
+                    ;;
+            esac
+            ;;
+        _tool_remote)
+            case "$prev" in
+                '-C' | '--dir')
+                    compopt -o filenames 2>/dev/null
+                    COMPREPLY+=($(compgen -d -- "$cur"))
+                    return
+                    ;;
+            esac
+            if [[ $cur == -* ]]; then
+                COMPREPLY=($(compgen -W '-C --dir' -- "$cur"))
+                return
+            fi
+            case "$args" in
+                0)
+                    COMPREPLY+=($(compgen -W 'add remove' -- "$cur"))
+                    ;;
+            esac
+            ;;
+        _tool_remote_add)
+            case "$prev" in
+                '-C' | '--dir')
+                    compopt -o filenames 2>/dev/null
+                    COMPREPLY+=($(compgen -d -- "$cur"))
+                    return
+                    ;;
+            esac
+            if [[ $cur == -* ]]; then
+                COMPREPLY=($(compgen -W '-C --dir' -- "$cur"))
+                return
+            fi
+            ;;
+        _tool_remote_remove)
+            case "$prev" in
+                '-C' | '--dir')
+                    compopt -o filenames 2>/dev/null
+                    COMPREPLY+=($(compgen -d -- "$cur"))
+                    return
+                    ;;
+            esac
+            if [[ $cur == -* ]]; then
+                COMPREPLY=($(compgen -W '-C --dir' -- "$cur"))
+                return
+            fi
+            case "$args" in
+                0)
+                    COMPREPLY+=($(compgen -W "$('git' 'remote' 2>/dev/null)" -- "$cur"))
+                    ;;
+            esac
+            ;;
+        _tool_log)
+            case "$prev" in
+                '--format')
+                    COMPREPLY+=($(compgen -W 'oneline full' -- "$cur"))
+                    return
+                    ;;
+                '-C' | '--dir')
+                    compopt -o filenames 2>/dev/null
+                    COMPREPLY+=($(compgen -d -- "$cur"))
+                    return
+                    ;;
+            esac
+            if [[ $cur == -* ]]; then
+                COMPREPLY=($(compgen -W '--format -C --dir' -- "$cur"))
+                return
+            fi
+            case "$args" in
+                *)
+                    compopt -o filenames 2>/dev/null
+                    COMPREPLY+=($(compgen -f -- "$cur"))
+                    ;;
+            esac
+            ;;
+        _tool_debug)
+            case "$prev" in
+                '-C' | '--dir')
+                    compopt -o filenames 2>/dev/null
+                    COMPREPLY+=($(compgen -d -- "$cur"))
+                    return
+                    ;;
+            esac
+            if [[ $cur == -* ]]; then
+                COMPREPLY=($(compgen -W '-C --dir' -- "$cur"))
+                return
+            fi
+            ;;
+    esac
+}
+
+complete -o default -F _tool 'tool'
@@ -0,0 +1,62 @@
+//! Specs written in TOML, for users who'd rather not write Fig's JSON.
+//!
+//! The schema is the JSON one with snake_case field names, so a spec maps
+//! onto TOML tables naturally:
+//!
+//! ```toml
+//! name = "deploy"
+//! description = "Deploy the app"
+//!
+//! [[options]]
+//! name = ["-e", "--env"]
+//! args = { name = "env", suggestions = ["staging", "production"] }
+//!
+//! [[subcommands]]
+//! name = ["rollback", "rb"]
+//!
+//! [[subcommands.args]]
+//! name = "release"
+//! generators = { script = ["deploy", "releases"] }
+//! ```
+//!
+//! Fields are renamed to Fig's camelCase (`is_optional` → `isOptional`)
+//! everywhere except inside descriptions, whose keys are locales.
+
+use crate::config::toml;
+use anyhow::Result;
+use serde_json::{Map, Value};
+
+/// Parse a TOML spec into the JSON the spec model reads
+pub fn parse(input: &str) -> Result<Value> {
+    Ok(to_fig(toml::parse(input)?))
+}
+
+fn to_fig(value: Value) -> Value {
+    match value {
+        Value::Object(fields) => Value::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| match key.as_str() {
+                    "description" => (key, value),
+                    _ => (camel_case(&key), to_fig(value)),
+                })
+                .collect::<Map<_, _>>(),
+        ),
+        Value::Array(items) => Value::Array(items.into_iter().map(to_fig).collect()),
+        value => value,
+    }
+}
+
+/// `exclusive_on` → `exclusiveOn`
+fn camel_case(key: &str) -> String {
+    let mut parts = key.split('_');
+    let mut camel = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            camel.extend(first.to_uppercase());
+            camel.push_str(chars.as_str());
+        }
+    }
+    camel
+}
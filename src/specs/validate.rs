@@ -0,0 +1,311 @@
+//! Checking spec files before the daemon loads them.
+//!
+//! The daemon skips a spec it can't decode with a warning in its log, and
+//! ignores fields it doesn't know, so a typo like `subcomands` silently
+//! loses part of a spec. Validation reports both, plus references between
+//! options that point nowhere.
+
+use super::{PATCH_SUFFIX, PLATFORMS, USER_SPEC_EXTENSIONS, decode, read_spec};
+use anyhow::{Context, Result, bail};
+use serde_json::Value;
+use std::path::Path;
+
+/// Fields of each kind of spec node the spec engine reads
+const SUBCOMMAND_FIELDS: &[&str] = &[
+    "name",
+    "description",
+    "subcommands",
+    "options",
+    "args",
+    "hidden",
+    "availableOn",
+];
+const OPTION_FIELDS: &[&str] = &[
+    "name",
+    "description",
+    "args",
+    "isPersistent",
+    "isRepeatable",
+    "isRequired",
+    "exclusiveOn",
+    "dependsOn",
+    "hidden",
+    "availableOn",
+];
+const ARG_FIELDS: &[&str] = &[
+    "name",
+    "description",
+    "isOptional",
+    "isVariadic",
+    "suggestions",
+    "template",
+    "generators",
+];
+const GENERATOR_FIELDS: &[&str] = &[
+    "script",
+    "splitOn",
+    "template",
+    "scriptTimeout",
+    "parse",
+    "cache",
+];
+const PARSE_FIELDS: &[&str] = &["skip", "stripPrefix", "columns", "name", "description"];
+const CACHE_FIELDS: &[&str] = &["ttl", "strategy", "cacheByDirectory", "cacheKey"];
+const SUGGESTION_FIELDS: &[&str] = &["name", "description"];
+
+/// Fig fields the spec engine has no use for, accepted without a word so
+/// exported Fig specs validate
+const FIG_FIELDS: &[&str] = &[
+    "additionalSuggestions",
+    "custom",
+    "debounce",
+    "default",
+    "deprecated",
+    "displayName",
+    "filterStrategy",
+    "generateSpec",
+    "getQueryTerm",
+    "icon",
+    "insertValue",
+    "isCommand",
+    "isDangerous",
+    "isModule",
+    "isScript",
+    "loadSpec",
+    "parserDirectives",
+    "postProcess",
+    "previewComponent",
+    "priority",
+    "replaceValue",
+    "requiresEquals",
+    "requiresSeparator",
+    "trigger",
+    "type",
+];
+
+/// Problem found in a spec file
+#[derive(Debug)]
+pub struct Issue {
+    /// Where in the spec: `subcommands[commit].options[--amend]`
+    pub path: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "`{}`: {}", self.path, self.message)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Node {
+    Subcommand,
+    Option,
+    Arg,
+    Generator,
+    Parse,
+    Cache,
+    Suggestion,
+}
+
+impl Node {
+    fn fields(self) -> &'static [&'static str] {
+        match self {
+            Node::Subcommand => SUBCOMMAND_FIELDS,
+            Node::Option => OPTION_FIELDS,
+            Node::Arg => ARG_FIELDS,
+            Node::Generator => GENERATOR_FIELDS,
+            Node::Parse => PARSE_FIELDS,
+            Node::Cache => CACHE_FIELDS,
+            Node::Suggestion => SUGGESTION_FIELDS,
+        }
+    }
+
+    /// Kind of the nodes under `field`
+    fn child(self, field: &str) -> Option<Node> {
+        match (self, field) {
+            (Node::Subcommand, "subcommands") => Some(Node::Subcommand),
+            (Node::Subcommand, "options") => Some(Node::Option),
+            (Node::Subcommand | Node::Option, "args") => Some(Node::Arg),
+            (Node::Arg, "generators") => Some(Node::Generator),
+            (Node::Generator, "parse") => Some(Node::Parse),
+            (Node::Generator, "cache") => Some(Node::Cache),
+            (Node::Arg, "suggestions") => Some(Node::Suggestion),
+            _ => None,
+        }
+    }
+}
+
+/// Validate the spec or patch at `path`.
+///
+/// Fails when the daemon couldn't load it at all; otherwise returns what it
+/// would silently ignore or can't make sense of.
+pub fn check(path: &Path) -> Result<Vec<Issue>> {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    let patch_of = name.strip_suffix(PATCH_SUFFIX);
+    let is_spec = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| USER_SPEC_EXTENSIONS.contains(&ext));
+    if patch_of.is_none() && !is_spec {
+        bail!("Not a spec file: expected <command>.json, .toml, .yaml or <command>{PATCH_SUFFIX}");
+    }
+
+    let value = read_spec(path)?;
+    let mut issues = Vec::new();
+    unknown_fields(
+        &value,
+        Node::Subcommand,
+        "",
+        patch_of.is_some(),
+        &mut issues,
+    );
+
+    let spec = match patch_of {
+        Some(command) => {
+            let Some(mut base) = super::base_value(command)? else {
+                bail!("There is no spec for `{command}` to patch");
+            };
+            super::patch::apply(&mut base, &value);
+            base
+        }
+        None => value,
+    };
+    decode(spec.clone(), None).context("The daemon would skip this file")?;
+    references(&spec, "", &[], &mut issues);
+    Ok(issues)
+}
+
+/// Report fields the spec engine doesn't read; `remove` is allowed in patches
+fn unknown_fields(value: &Value, node: Node, path: &str, patch: bool, issues: &mut Vec<Issue>) {
+    let Value::Object(fields) = value else {
+        return;
+    };
+    for (field, child) in fields {
+        if node.fields().contains(&field.as_str())
+            || FIG_FIELDS.contains(&field.as_str())
+            || (patch && field == "remove")
+        {
+            if let Some(kind) = node.child(field) {
+                let items = match child {
+                    Value::Array(items) => items.as_slice(),
+                    single => std::slice::from_ref(single),
+                };
+                for (i, item) in items.iter().enumerate() {
+                    let path = format!("{}[{}]", join(path, field), label(item, i));
+                    unknown_fields(item, kind, &path, patch, issues);
+                }
+            }
+            continue;
+        }
+        issues.push(Issue {
+            path: join(path, field),
+            message: "unknown field, ignored".to_string(),
+        });
+    }
+}
+
+/// Report unnamed entries, platforms `availableOn` doesn't know, and
+/// `exclusiveOn` or `dependsOn` flags that no option of the subcommand or its
+/// parents has
+fn references(subcommand: &Value, path: &str, inherited: &[String], issues: &mut Vec<Issue>) {
+    let options = entries(subcommand, "options");
+    let mut flags = inherited.to_vec();
+    flags.extend(options.iter().flat_map(names));
+
+    for (i, option) in options.iter().enumerate() {
+        let option_path = format!("{}[{}]", join(path, "options"), label(option, i));
+        if names(option).is_empty() {
+            issues.push(unnamed(&option_path));
+        }
+        platforms(option, &option_path, issues);
+        for field in ["exclusiveOn", "dependsOn"] {
+            for flag in entries(option, field).iter().filter_map(Value::as_str) {
+                if !flags.iter().any(|known| known == flag) {
+                    issues.push(Issue {
+                        path: join(&option_path, field),
+                        message: format!("`{flag}` is not an option here"),
+                    });
+                }
+            }
+        }
+    }
+    for (i, child) in entries(subcommand, "subcommands").iter().enumerate() {
+        let child_path = format!("{}[{}]", join(path, "subcommands"), label(child, i));
+        if names(child).is_empty() {
+            issues.push(unnamed(&child_path));
+        }
+        platforms(child, &child_path, issues);
+        references(child, &child_path, &flags, issues);
+    }
+}
+
+/// Report platforms in an entry's `availableOn` that no system goes by
+fn platforms(value: &Value, path: &str, issues: &mut Vec<Issue>) {
+    for platform in entries(value, "availableOn")
+        .iter()
+        .filter_map(Value::as_str)
+    {
+        if !PLATFORMS.contains(&platform) {
+            issues.push(Issue {
+                path: join(path, "availableOn"),
+                message: format!(
+                    "`{platform}` is not a platform; expected one of {}",
+                    PLATFORMS.join(", ")
+                ),
+            });
+        }
+    }
+}
+
+fn unnamed(path: &str) -> Issue {
+    Issue {
+        path: path.to_string(),
+        message: "has no name, so it can never be suggested".to_string(),
+    }
+}
+
+/// The list under `field`, a single value counting as a list of one
+fn entries<'a>(value: &'a Value, field: &str) -> &'a [Value] {
+    match value.get(field) {
+        Some(Value::Array(items)) => items,
+        Some(single) => std::slice::from_ref(single),
+        None => &[],
+    }
+}
+
+/// Spellings in an entry's `name`, a string or a list of them
+fn names(value: &Value) -> Vec<String> {
+    match value.get("name") {
+        Some(Value::String(name)) => vec![name.clone()],
+        Some(Value::Array(names)) => names
+            .iter()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// An entry's first name, or its position when it has none
+fn label(value: &Value, index: usize) -> String {
+    names(value)
+        .into_iter()
+        .next()
+        .unwrap_or_else(|| index.to_string())
+}
+
+fn join(path: &str, field: &str) -> String {
+    if path.is_empty() {
+        field.to_string()
+    } else {
+        format!("{path}.{field}")
+    }
+}
@@ -0,0 +1,625 @@
+//! Minimal YAML reader for carapace specs and compose files.
+//!
+//! Supports the subset spec files use: block mappings and sequences
+//! (including mappings as sequence items), flow sequences and mappings
+//! (`[a, b]`, `{a: b}`), plain, single- and double-quoted scalars, literal
+//! and folded block scalars (`|`, `>`), and comments. Anchors, tags and
+//! multiple documents are not supported. Like the TOML reader, the document
+//! is converted into a `serde_json::Value`.
+
+use serde_json::{Map, Number, Value};
+use thiserror::Error;
+
+/// Error raised when a spec file is not valid YAML, or uses YAML this
+/// reader doesn't support
+#[derive(Debug, Error)]
+#[error("line {line}: {message}")]
+pub struct YamlError {
+    /// 1-based line number where the error occurred
+    pub line: usize,
+    /// Human readable description
+    pub message: String,
+}
+
+/// Parse a YAML document into a JSON value tree
+pub fn parse(input: &str) -> Result<Value, YamlError> {
+    let mut parser = Parser {
+        lines: input
+            .lines()
+            .enumerate()
+            .map(|(index, raw)| Line {
+                number: index + 1,
+                indent: raw.len() - raw.trim_start_matches(' ').len(),
+                content: raw.trim_start_matches(' ').to_string(),
+            })
+            .collect(),
+        pos: 0,
+    };
+    parser.skip_blank();
+    if parser.pos < parser.lines.len() && parser.lines[parser.pos].content.trim() == "---" {
+        parser.pos += 1;
+        parser.skip_blank();
+    }
+    let Some(first) = parser.current() else {
+        return Ok(Value::Null);
+    };
+    let indent = first.indent;
+    let value = parser.block(indent)?;
+    parser.skip_blank();
+    if let Some(line) = parser.current() {
+        return Err(error(line.number, "unexpected indentation"));
+    }
+    Ok(value)
+}
+
+struct Line {
+    number: usize,
+    indent: usize,
+    content: String,
+}
+
+struct Parser {
+    lines: Vec<Line>,
+    pos: usize,
+}
+
+impl Parser {
+    fn current(&self) -> Option<&Line> {
+        self.lines.get(self.pos)
+    }
+
+    /// Move past empty and comment-only lines
+    fn skip_blank(&mut self) {
+        while let Some(line) = self.current() {
+            let content = line.content.trim();
+            if !content.is_empty() && !content.starts_with('#') {
+                break;
+            }
+            self.pos += 1;
+        }
+    }
+
+    /// The mapping or sequence whose lines start at column `indent`
+    fn block(&mut self, indent: usize) -> Result<Value, YamlError> {
+        match self.current() {
+            Some(line) if is_item(&line.content) => self.sequence(indent),
+            Some(_) => self.mapping(indent),
+            None => Ok(Value::Null),
+        }
+    }
+
+    fn sequence(&mut self, indent: usize) -> Result<Value, YamlError> {
+        let mut items = Vec::new();
+        loop {
+            self.skip_blank();
+            let Some(line) = self.current() else { break };
+            if line.indent != indent || !is_item(&line.content) {
+                break;
+            }
+            let number = line.number;
+            let rest = line.content[1..].trim_start_matches(' ');
+            if strip_comment(rest).trim().is_empty() {
+                // The item is the block on the following lines
+                self.pos += 1;
+                self.skip_blank();
+                items.push(match self.current() {
+                    Some(next) if next.indent > indent => {
+                        let indent = next.indent;
+                        self.block(indent)?
+                    }
+                    _ => Value::Null,
+                });
+                continue;
+            }
+            let column = indent + (line.content.len() - rest.len());
+            if is_item(rest) || split_key(rest).is_some() {
+                // `- key: value` starts a mapping (or `- - x` a sequence)
+                // at the column after the dash
+                let rest = rest.to_string();
+                let line = &mut self.lines[self.pos];
+                line.indent = column;
+                line.content = rest;
+                items.push(self.block(column)?);
+                continue;
+            }
+            let source = rest.to_string();
+            self.pos += 1;
+            items.push(self.inline(&source, indent, number)?);
+        }
+        Ok(Value::Array(items))
+    }
+
+    fn mapping(&mut self, indent: usize) -> Result<Value, YamlError> {
+        let mut map = Map::new();
+        loop {
+            self.skip_blank();
+            let Some(line) = self.current() else { break };
+            if line.indent != indent || is_item(&line.content) {
+                if line.indent > indent {
+                    return Err(error(line.number, "unexpected indentation"));
+                }
+                break;
+            }
+            let number = line.number;
+            let (key, rest) =
+                split_key(&line.content).ok_or_else(|| error(number, "expected `key: value`"))?;
+            let key = scalar_key(key, number)?;
+            let rest = rest.to_string();
+            self.pos += 1;
+            let value = if strip_comment(&rest).trim().is_empty() {
+                self.skip_blank();
+                match self.current() {
+                    Some(next) if next.indent > indent => {
+                        let indent = next.indent;
+                        self.block(indent)?
+                    }
+                    // A sequence may sit at the same column as its key
+                    Some(next) if next.indent == indent && is_item(&next.content) => {
+                        self.sequence(indent)?
+                    }
+                    _ => Value::Null,
+                }
+            } else {
+                self.inline(&rest, indent, number)?
+            };
+            map.insert(key, value);
+        }
+        Ok(Value::Object(map))
+    }
+
+    /// A value written after `key:` or `- `, reading further lines for block
+    /// scalars and flow collections that span lines
+    fn inline(&mut self, source: &str, indent: usize, number: usize) -> Result<Value, YamlError> {
+        let source = source.trim();
+        if let Some(style) = source.chars().next().filter(|c| matches!(c, '|' | '>')) {
+            return Ok(Value::String(self.block_scalar(
+                style,
+                &source[1..],
+                indent,
+            )));
+        }
+        let mut source = strip_comment(source).trim().to_string();
+        if source.starts_with(['[', '{']) {
+            while !is_balanced(&source) {
+                let Some(line) = self.current() else {
+                    return Err(error(number, "unterminated flow collection"));
+                };
+                source.push(' ');
+                source.push_str(strip_comment(&line.content).trim());
+                self.pos += 1;
+            }
+        }
+        let mut cursor = Cursor {
+            chars: source.chars().collect(),
+            pos: 0,
+            line: number,
+        };
+        let value = cursor.value(false)?;
+        cursor.skip_spaces();
+        if cursor.pos < cursor.chars.len() {
+            return Err(error(number, "unexpected characters after value"));
+        }
+        Ok(value)
+    }
+
+    /// The lines of a `|` (kept) or `>` (folded) block scalar
+    fn block_scalar(&mut self, style: char, header: &str, indent: usize) -> String {
+        let keep_newline = !header.trim_start().starts_with('-');
+        let mut lines: Vec<String> = Vec::new();
+        let mut content_indent = None;
+        while let Some(line) = self.current() {
+            if line.content.trim().is_empty() {
+                lines.push(String::new());
+                self.pos += 1;
+                continue;
+            }
+            if line.indent <= indent {
+                break;
+            }
+            let strip = *content_indent.get_or_insert(line.indent);
+            let extra = line.indent.saturating_sub(strip);
+            lines.push(format!("{}{}", " ".repeat(extra), line.content));
+            self.pos += 1;
+        }
+        while lines.last().is_some_and(String::is_empty) {
+            lines.pop();
+        }
+        let mut text = match style {
+            '|' => lines.join("\n"),
+            _ => lines
+                .split(String::is_empty)
+                .map(|paragraph| paragraph.join(" "))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        };
+        if keep_newline && !text.is_empty() {
+            text.push('\n');
+        }
+        text
+    }
+}
+
+/// Whether `content` is a sequence item: `- x` or a lone `-`
+fn is_item(content: &str) -> bool {
+    content == "-" || content.starts_with("- ")
+}
+
+/// Split `key: value` at the first colon followed by a space or the end of
+/// the line, outside quotes and brackets
+fn split_key(content: &str) -> Option<(&str, &str)> {
+    let mut quote = None;
+    let mut depth = 0usize;
+    for (i, c) in content.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') if i == 0 => quote = Some(c),
+            (None, '[' | '{') if i == 0 || depth > 0 => depth += 1,
+            (None, ']' | '}') if depth > 0 => depth -= 1,
+            (None, '#') if i > 0 && content[..i].ends_with(' ') => return None,
+            (None, ':') if depth == 0 => {
+                let rest = &content[i + 1..];
+                if rest.is_empty() || rest.starts_with([' ', '\t']) {
+                    return Some((&content[..i], rest));
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Mapping keys are strings, quoted or not
+fn scalar_key(key: &str, line: usize) -> Result<String, YamlError> {
+    let key = key.trim();
+    if key.starts_with(['"', '\'']) {
+        let mut cursor = Cursor {
+            chars: key.chars().collect(),
+            pos: 0,
+            line,
+        };
+        return cursor.quoted();
+    }
+    Ok(key.to_string())
+}
+
+/// `source` without a trailing ` # comment`, outside quotes; escaped quotes,
+/// `\"` and `''`, don't end a string
+fn strip_comment(source: &str) -> &str {
+    let mut quote = None;
+    let mut previous = ' ';
+    let mut escaped = false;
+    for (i, c) in source.char_indices() {
+        match (quote, c) {
+            (Some(_), _) if escaped => escaped = false,
+            (Some('"'), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'')
+                if matches!(previous, ' ' | '[' | '{' | ',' | ':') || previous == c =>
+            {
+                quote = Some(c)
+            }
+            (None, '#') if previous == ' ' || i == 0 => return &source[..i],
+            _ => {}
+        }
+        previous = c;
+    }
+    source
+}
+
+/// Whether every bracket opened in `source` is closed, outside quotes
+fn is_balanced(source: &str) -> bool {
+    let mut depth = 0i32;
+    let mut quote = None;
+    let mut escaped = false;
+    for c in source.chars() {
+        match (quote, c) {
+            (Some(_), _) if escaped => escaped = false,
+            (Some('"'), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '[' | '{') => depth += 1,
+            (None, ']' | '}') => depth -= 1,
+            _ => {}
+        }
+    }
+    depth <= 0
+}
+
+fn error(line: usize, message: &str) -> YamlError {
+    YamlError {
+        line,
+        message: message.to_string(),
+    }
+}
+
+/// Reader for one inline value
+struct Cursor {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Cursor {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    /// A value; `in_flow` ends plain scalars at `,`, `]` and `}`
+    fn value(&mut self, in_flow: bool) -> Result<Value, YamlError> {
+        self.skip_spaces();
+        match self.peek() {
+            Some('[') => self.flow_sequence(),
+            Some('{') => self.flow_mapping(),
+            Some('"' | '\'') => self.quoted().map(Value::String),
+            _ => Ok(plain(&self.plain(in_flow))),
+        }
+    }
+
+    fn flow_sequence(&mut self) -> Result<Value, YamlError> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        loop {
+            self.skip_spaces();
+            match self.peek() {
+                Some(']') => {
+                    self.pos += 1;
+                    return Ok(Value::Array(items));
+                }
+                Some(',') => self.pos += 1,
+                Some(_) => items.push(self.value(true)?),
+                None => return Err(error(self.line, "unterminated flow sequence")),
+            }
+        }
+    }
+
+    fn flow_mapping(&mut self) -> Result<Value, YamlError> {
+        self.pos += 1;
+        let mut map = Map::new();
+        loop {
+            self.skip_spaces();
+            match self.peek() {
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(Value::Object(map));
+                }
+                Some(',') => self.pos += 1,
+                Some('"' | '\'') => {
+                    let key = self.quoted()?;
+                    self.entry_value(&mut map, key)?;
+                }
+                Some(_) => {
+                    let start = self.pos;
+                    while self.peek().is_some_and(|c| !matches!(c, ':' | ',' | '}')) {
+                        self.pos += 1;
+                    }
+                    let key: String = self.chars[start..self.pos].iter().collect();
+                    self.entry_value(&mut map, key.trim().to_string())?;
+                }
+                None => return Err(error(self.line, "unterminated flow mapping")),
+            }
+        }
+    }
+
+    /// The `: value` after a flow mapping key; a key alone maps to null
+    fn entry_value(&mut self, map: &mut Map<String, Value>, key: String) -> Result<(), YamlError> {
+        self.skip_spaces();
+        let value = if self.peek() == Some(':') {
+            self.pos += 1;
+            self.value(true)?
+        } else {
+            Value::Null
+        };
+        map.insert(key, value);
+        Ok(())
+    }
+
+    fn quoted(&mut self) -> Result<String, YamlError> {
+        let quote = self.peek().unwrap_or('"');
+        self.pos += 1;
+        let mut text = String::new();
+        loop {
+            let Some(c) = self.peek() else {
+                return Err(error(self.line, "unterminated string"));
+            };
+            self.pos += 1;
+            match c {
+                // `''` is an escaped quote in single-quoted strings
+                '\'' if quote == '\'' => {
+                    if self.peek() == Some('\'') {
+                        self.pos += 1;
+                        text.push('\'');
+                    } else {
+                        return Ok(text);
+                    }
+                }
+                '"' if quote == '"' => return Ok(text),
+                '\\' if quote == '"' => {
+                    let escaped = self.peek();
+                    self.pos += 1;
+                    match escaped {
+                        Some('n') => text.push('\n'),
+                        Some('t') => text.push('\t'),
+                        Some('0') => text.push('\0'),
+                        Some(c) => text.push(c),
+                        None => return Err(error(self.line, "unterminated string")),
+                    }
+                }
+                c => text.push(c),
+            }
+        }
+    }
+
+    fn plain(&mut self, in_flow: bool) -> String {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if in_flow && matches!(c, ',' | ']' | '}') {
+                break;
+            }
+            self.pos += 1;
+        }
+        self.chars[start..self.pos]
+            .iter()
+            .collect::<String>()
+            .trim()
+            .to_string()
+    }
+}
+
+/// A plain scalar: null, a boolean, a number, or a string
+fn plain(text: &str) -> Value {
+    match text {
+        "" | "~" | "null" | "Null" | "NULL" => Value::Null,
+        "true" | "True" | "TRUE" => Value::Bool(true),
+        "false" | "False" | "FALSE" => Value::Bool(false),
+        _ => {
+            if let Ok(int) = text.parse::<i64>() {
+                return Value::Number(int.into());
+            }
+            if let Some(float) = text.parse::<f64>().ok().and_then(Number::from_f64)
+                && text.contains('.')
+            {
+                return Value::Number(float);
+            }
+            Value::String(text.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_nested_mappings_and_sequences() {
+        let input = "\
+name: app
+flags:
+  --verbose: More output
+commands:
+  - name: run
+    aliases: [r]
+  - name: stop
+list:
+- a
+- b
+";
+        assert_eq!(
+            parse(input).unwrap(),
+            json!({
+                "name": "app",
+                "flags": { "--verbose": "More output" },
+                "commands": [
+                    { "name": "run", "aliases": ["r"] },
+                    { "name": "stop" },
+                ],
+                "list": ["a", "b"],
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_scalars() {
+        let input = "a: 1\nb: 1.5\nc: true\nd: ~\ne: 1.0.0\nf: -\ng:\n";
+        assert_eq!(
+            parse(input).unwrap(),
+            json!({ "a": 1, "b": 1.5, "c": true, "d": null, "e": "1.0.0", "f": "-", "g": null })
+        );
+    }
+
+    #[test]
+    fn test_parse_quoted_scalars() {
+        let input = r#"double: "a \"b\"\tc: # d"
+single: 'it''s # here'
+"quoted: key": x
+"#;
+        assert_eq!(
+            parse(input).unwrap(),
+            json!({ "double": "a \"b\"\tc: # d", "single": "it's # here", "quoted: key": "x" })
+        );
+    }
+
+    #[test]
+    fn test_parse_comments() {
+        let input = "# heading\n---\nurl: http://x#y # trailing\n\n  # indented\nnext: 2\n";
+        assert_eq!(
+            parse(input).unwrap(),
+            json!({ "url": "http://x#y", "next": 2 })
+        );
+    }
+
+    #[test]
+    fn test_parse_flow_collections() {
+        let input = "flags: {-e=: Env, -v: [a, 'b, c']}\nmulti: [\n  one,\n  two,\n]\nempty: []\n";
+        assert_eq!(
+            parse(input).unwrap(),
+            json!({
+                "flags": { "-e=": "Env", "-v": ["a", "b, c"] },
+                "multi": ["one", "two"],
+                "empty": [],
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_block_scalars() {
+        let input = "\
+literal: |
+  first
+    indented
+
+  last
+folded: >-
+  one
+  two
+
+  three
+after: x
+";
+        assert_eq!(
+            parse(input).unwrap(),
+            json!({
+                "literal": "first\n  indented\n\nlast\n",
+                "folded": "one two\nthree",
+                "after": "x",
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_sequence_at_key_column() {
+        let input = "items:\n- x\n- - nested\n  - pair\nnext: y\n";
+        assert_eq!(
+            parse(input).unwrap(),
+            json!({ "items": ["x", ["nested", "pair"]], "next": "y" })
+        );
+    }
+
+    #[test]
+    fn test_parse_empty_document() {
+        assert_eq!(parse("# nothing\n").unwrap(), Value::Null);
+    }
+
+    #[test]
+    fn test_parse_errors_report_their_line() {
+        assert_eq!(parse("a: 1\n  b: 2\n").unwrap_err().line, 2);
+        assert_eq!(parse("a: 1\nnot a mapping\n").unwrap_err().line, 2);
+        assert_eq!(parse("a: [1,\n2\n").unwrap_err().line, 1);
+        assert_eq!(parse("a: \"open\n").unwrap_err().line, 1);
+    }
+
+    #[test]
+    fn test_parse_escaped_quote_in_flow_collection() {
+        let input = "a: [\"x\\\"]\", y,\n  z]\n";
+        assert_eq!(parse(input).unwrap(), json!({ "a": ["x\"]", "y", "z"] }));
+    }
+}
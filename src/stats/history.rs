@@ -0,0 +1,261 @@
+//! Executed commands with the context they ran in, for predicting what the
+//! user will run next.
+//!
+//! Each entry records where a command was run (the directory, and the kind of
+//! project it belongs to) and at what hour of the day. Only commands that
+//! succeeded are kept, and [private](super::is_private) ones never are.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use super::{DAY, WEEK, is_private, unix_now};
+use crate::paths;
+
+/// Entries kept before the least valuable are dropped, unless
+/// [`CommandHistory::with_max_entries`] says otherwise
+const MAX_ENTRIES: usize = 2000;
+/// Entries scored between checks of the prediction deadline
+const DEADLINE_CHECK_INTERVAL: usize = 256;
+/// Parent directories searched for a project marker
+const MAX_PROJECT_DEPTH: usize = 8;
+
+/// Files identifying the kind of project a directory belongs to
+const PROJECT_MARKERS: &[(&str, &str)] = &[
+    ("package.json", "node"),
+    ("Cargo.toml", "rust"),
+    ("go.mod", "go"),
+    ("pyproject.toml", "python"),
+    ("requirements.txt", "python"),
+    ("Gemfile", "ruby"),
+    ("pom.xml", "java"),
+    ("build.gradle", "java"),
+    ("composer.json", "php"),
+    ("mix.exs", "elixir"),
+];
+
+/// One command as run in one directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub command: String,
+    pub dir: PathBuf,
+    /// Kind of project `dir` belongs to (`node`, `rust`), if any
+    pub project: Option<String>,
+    /// Runs per hour of the local day
+    pub hours: [u32; 24],
+    /// Unix timestamp (seconds) of the latest run
+    pub last_used: u64,
+}
+
+/// A command predicted for the current context
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Prediction {
+    pub command: String,
+    /// Whether the command was run in this very directory
+    pub same_dir: bool,
+}
+
+/// Recorded commands, persisted in the data directory
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CommandHistory {
+    entries: Vec<Entry>,
+    /// Last command recorded per shell session, so a command reported with
+    /// every request until the next one runs is only counted once
+    #[serde(skip)]
+    recorded: HashMap<String, String>,
+    /// Entries kept when recording, `MAX_ENTRIES` when unset
+    #[serde(skip)]
+    max_entries: Option<usize>,
+}
+
+impl CommandHistory {
+    /// Default location of the history file
+    pub fn default_path() -> Option<PathBuf> {
+        paths::data_dir().map(|dir| dir.join("history.json"))
+    }
+
+    /// Load history from `path`, starting fresh if the file is missing or unreadable
+    pub fn load(path: &Path) -> Self {
+        std::fs::read(path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    /// Keep at most `max_entries` entries as commands are recorded
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_vec(self)?;
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, json)
+            .with_context(|| format!("Failed to write history file: {}", tmp.display()))?;
+        std::fs::rename(&tmp, path)
+            .with_context(|| format!("Failed to write history file: {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Record that `session` ran `command` successfully in `dir`
+    pub fn record(&mut self, session: &str, command: &str, dir: &Path) {
+        if is_private(command) || command.trim().is_empty() {
+            return;
+        }
+        if self
+            .recorded
+            .get(session)
+            .is_some_and(|last| last == command)
+        {
+            return;
+        }
+        self.recorded
+            .insert(session.to_string(), command.to_string());
+
+        let command = command.trim();
+        let now = unix_now();
+        let hour = local_hour(now);
+        match self
+            .entries
+            .iter_mut()
+            .find(|entry| entry.command == command && entry.dir == dir)
+        {
+            Some(entry) => {
+                entry.hours[hour] += 1;
+                entry.last_used = now;
+            }
+            None => {
+                let mut hours = [0; 24];
+                hours[hour] = 1;
+                self.entries.push(Entry {
+                    command: command.to_string(),
+                    dir: dir.to_path_buf(),
+                    project: project_kind(dir),
+                    hours,
+                    last_used: now,
+                });
+            }
+        }
+
+        let max_entries = self.max_entries.unwrap_or(MAX_ENTRIES);
+        if self.entries.len() > max_entries {
+            self.truncate(max_entries, now);
+        }
+    }
+
+    /// Forget entries last run before unix time `cutoff`, then all but the
+    /// `max_entries` most valuable; returns how many were forgotten
+    pub fn prune(&mut self, max_entries: usize, cutoff: u64) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|entry| entry.last_used >= cutoff);
+        if self.entries.len() > max_entries {
+            self.truncate(max_entries, unix_now());
+        }
+        before - self.entries.len()
+    }
+
+    /// Keep the `max_entries` entries with the best score
+    fn truncate(&mut self, max_entries: usize, now: u64) {
+        self.entries
+            .sort_by_key(|entry| std::cmp::Reverse(entry_score(entry, now)));
+        self.entries.truncate(max_entries);
+    }
+
+    /// Up to `limit` commands likely to be run next in `dir` at this time of day.
+    ///
+    /// Commands run in `dir` itself count most, then those run in other
+    /// projects of the same kind; runs near the current hour count double.
+    /// Scoring stops at `deadline`, ranking whatever was scored by then.
+    pub fn predict(&self, dir: &Path, limit: usize, deadline: Instant) -> Vec<Prediction> {
+        let now = unix_now();
+        let hour = local_hour(now);
+        let project = project_kind(dir);
+
+        let mut scores: HashMap<&str, (u64, bool)> = HashMap::new();
+        for (i, entry) in self.entries.iter().enumerate() {
+            if i % DEADLINE_CHECK_INTERVAL == 0 && Instant::now() >= deadline {
+                break;
+            }
+            let same_dir = entry.dir == dir;
+            let place = if same_dir {
+                4
+            } else if project.is_some() && entry.project == project {
+                2
+            } else {
+                continue;
+            };
+            // The hour itself and its neighbours, wrapping around midnight
+            let near: u64 = [23, 0, 1]
+                .iter()
+                .map(|offset| u64::from(entry.hours[(hour + offset) % 24]))
+                .sum();
+            let score = (entry_score(entry, now) + near) * place;
+            let slot = scores.entry(entry.command.as_str()).or_default();
+            slot.0 += score;
+            slot.1 |= same_dir;
+        }
+
+        let mut ranked: Vec<(&str, (u64, bool))> = scores.into_iter().collect();
+        ranked.sort_by(|a, b| b.1.0.cmp(&a.1.0).then(a.0.cmp(b.0)));
+        ranked
+            .into_iter()
+            .take(limit)
+            .map(|(command, (_, same_dir))| Prediction {
+                command: command.to_string(),
+                same_dir,
+            })
+            .collect()
+    }
+}
+
+/// Total runs, weighted by how recently the command was last run
+fn entry_score(entry: &Entry, now: u64) -> u64 {
+    let runs: u64 = entry.hours.iter().map(|&count| u64::from(count)).sum();
+    let age = now.saturating_sub(entry.last_used);
+    let weight = if age <= DAY {
+        4
+    } else if age <= WEEK {
+        2
+    } else {
+        1
+    };
+    runs * weight
+}
+
+/// Kind of project `dir` is in, from the nearest marker file above it
+fn project_kind(dir: &Path) -> Option<String> {
+    dir.ancestors().take(MAX_PROJECT_DEPTH).find_map(|dir| {
+        PROJECT_MARKERS
+            .iter()
+            .find(|(marker, _)| dir.join(marker).is_file())
+            .map(|(_, kind)| kind.to_string())
+    })
+}
+
+/// Hour of the local day (0-23) at unix time `secs`
+#[cfg(unix)]
+fn local_hour(secs: u64) -> usize {
+    let time = secs as libc::time_t;
+    // SAFETY: `localtime_r` only writes to the `tm` we pass it
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return utc_hour(secs);
+    }
+    usize::try_from(tm.tm_hour).unwrap_or(0) % 24
+}
+
+#[cfg(not(unix))]
+fn local_hour(secs: u64) -> usize {
+    utc_hour(secs)
+}
+
+fn utc_hour(secs: u64) -> usize {
+    (secs / 3600 % 24) as usize
+}
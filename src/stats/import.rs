@@ -0,0 +1,272 @@
+//! Reading existing shell history into the usage stats, so ranking is good
+//! from the first day instead of after weeks of use.
+
+use super::{UsageStats, is_private};
+use crate::{parser, paths};
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// Shell history formats that can be imported
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum HistorySource {
+    Zsh,
+    Bash,
+    Fish,
+    Atuin,
+}
+
+impl std::fmt::Display for HistorySource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            HistorySource::Zsh => "zsh",
+            HistorySource::Bash => "bash",
+            HistorySource::Fish => "fish",
+            HistorySource::Atuin => "atuin",
+        })
+    }
+}
+
+impl HistorySource {
+    pub const ALL: [HistorySource; 4] = [Self::Zsh, Self::Bash, Self::Fish, Self::Atuin];
+
+    /// Where this shell keeps its history by default
+    pub fn default_file(self) -> Option<PathBuf> {
+        let home = paths::home_dir()?;
+        let data = paths::data_dir().and_then(|dir| Some(dir.parent()?.to_path_buf()));
+        match self {
+            // HISTFILE is rarely exported, but honor it when it is
+            HistorySource::Zsh | HistorySource::Bash => {
+                let name = if self == HistorySource::Zsh {
+                    ".zsh_history"
+                } else {
+                    ".bash_history"
+                };
+                std::env::var_os("HISTFILE")
+                    .map(PathBuf::from)
+                    .filter(|path| path_matches(path, self))
+                    .or_else(|| Some(home.join(name)))
+            }
+            HistorySource::Fish => data.map(|dir| dir.join("fish/fish_history")),
+            HistorySource::Atuin => data.map(|dir| dir.join("atuin/history.db")),
+        }
+    }
+}
+
+/// Whether `HISTFILE` plausibly belongs to `source`, since both shells read it
+fn path_matches(path: &Path, source: HistorySource) -> bool {
+    let name = path.to_string_lossy();
+    match source {
+        HistorySource::Zsh => name.contains("zsh"),
+        _ => !name.contains("zsh"),
+    }
+}
+
+/// One executed command, with its unix time when the history records it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    pub command: String,
+    pub time: Option<u64>,
+}
+
+/// Outcome of importing one history file
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    /// Commands found in the file
+    pub entries: usize,
+    /// Commands added to the stats
+    pub imported: usize,
+    /// Commands skipped by the privacy filter
+    pub private: usize,
+}
+
+/// Read the history in `path`, calling `progress` with the fraction done
+pub fn read(
+    source: HistorySource,
+    path: &Path,
+    progress: &mut dyn FnMut(f64),
+) -> Result<Vec<HistoryEntry>> {
+    let parse = match source {
+        HistorySource::Zsh => parse_zsh,
+        HistorySource::Bash => parse_bash,
+        HistorySource::Fish => parse_fish,
+        // A SQLite database; ask the sqlite3 CLI instead of linking SQLite
+        HistorySource::Atuin => {
+            progress(0.0);
+            let entries = read_atuin(path)?;
+            progress(1.0);
+            return Ok(entries);
+        }
+    };
+
+    let bytes =
+        std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let text = match source {
+        HistorySource::Zsh => unmetafy(&bytes),
+        _ => String::from_utf8_lossy(&bytes).into_owned(),
+    };
+    let total = text.len().max(1) as f64;
+    let (mut done, mut next) = (0, 0);
+    let mut report = |line: &str| {
+        done += line.len() + 1;
+        // Every 64 KiB is plenty for a progress bar
+        if done >= next {
+            progress(done as f64 / total);
+            next = done + 64 * 1024;
+        }
+    };
+
+    let entries = parse(&text, &mut report);
+    progress(1.0);
+    Ok(entries)
+}
+
+/// Add `entries` to `stats`, skipping private commands
+pub fn ingest(stats: &mut UsageStats, entries: &[HistoryEntry]) -> ImportSummary {
+    let mut summary = ImportSummary {
+        entries: entries.len(),
+        ..ImportSummary::default()
+    };
+    for entry in entries {
+        if is_private(&entry.command) {
+            summary.private += 1;
+            continue;
+        }
+        let commands = parser::commands(&entry.command);
+        let Some(command) = commands.first().and_then(|words| words.first()) else {
+            continue;
+        };
+        stats.record_at(&command.text, entry.time.unwrap_or(0));
+        summary.imported += 1;
+    }
+    summary
+}
+
+/// zsh stores bytes ≥ 0x83 as 0x83 followed by the byte XOR 32
+fn unmetafy(bytes: &[u8]) -> String {
+    const META: u8 = 0x83;
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut iter = bytes.iter();
+    while let Some(&byte) = iter.next() {
+        if byte == META {
+            if let Some(&next) = iter.next() {
+                out.push(next ^ 32);
+            }
+        } else {
+            out.push(byte);
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Plain lines, or `: <time>:<duration>;<command>` with `EXTENDED_HISTORY`;
+/// a trailing backslash continues the command on the next line
+fn parse_zsh(text: &str, report: &mut dyn FnMut(&str)) -> Vec<HistoryEntry> {
+    let mut entries: Vec<HistoryEntry> = Vec::new();
+    let mut continued = false;
+    for line in text.lines() {
+        report(line);
+        if continued && let Some(last) = entries.last_mut() {
+            last.command.push('\n');
+            last.command
+                .push_str(line.strip_suffix('\\').unwrap_or(line));
+            continued = line.ends_with('\\');
+            continue;
+        }
+        continued = line.ends_with('\\');
+        let line = line.strip_suffix('\\').unwrap_or(line);
+        let extended = line
+            .strip_prefix(": ")
+            .and_then(|rest| rest.split_once(';'))
+            .and_then(|(meta, command)| {
+                let time = meta.split(':').next()?.trim().parse().ok()?;
+                Some((time, command))
+            });
+        entries.push(match extended {
+            Some((time, command)) => HistoryEntry {
+                command: command.to_string(),
+                time: Some(time),
+            },
+            None => HistoryEntry {
+                command: line.to_string(),
+                time: None,
+            },
+        });
+    }
+    entries.retain(|entry| !entry.command.trim().is_empty());
+    entries
+}
+
+/// Plain lines, each optionally preceded by a `#<time>` line with `HISTTIMEFORMAT`
+fn parse_bash(text: &str, report: &mut dyn FnMut(&str)) -> Vec<HistoryEntry> {
+    let mut entries = Vec::new();
+    let mut time = None;
+    for line in text.lines() {
+        report(line);
+        if let Some(stamp) = line.strip_prefix('#')
+            && let Ok(stamp) = stamp.trim().parse()
+        {
+            time = Some(stamp);
+            continue;
+        }
+        if !line.trim().is_empty() {
+            entries.push(HistoryEntry {
+                command: line.to_string(),
+                time: time.take(),
+            });
+        }
+    }
+    entries
+}
+
+/// fish's YAML-like format: `- cmd: <command>` followed by `  when: <time>`
+fn parse_fish(text: &str, report: &mut dyn FnMut(&str)) -> Vec<HistoryEntry> {
+    let mut entries: Vec<HistoryEntry> = Vec::new();
+    for line in text.lines() {
+        report(line);
+        if let Some(command) = line.strip_prefix("- cmd: ") {
+            entries.push(HistoryEntry {
+                // Newlines and backslashes are escaped in the file
+                command: command.replace("\\n", "\n").replace("\\\\", "\\"),
+                time: None,
+            });
+        } else if let Some(when) = line.trim_start().strip_prefix("when: ")
+            && let Some(last) = entries.last_mut()
+        {
+            last.time = when.trim().parse().ok();
+        }
+    }
+    entries
+}
+
+/// Commands from atuin's database; timestamps are stored in nanoseconds
+fn read_atuin(path: &Path) -> Result<Vec<HistoryEntry>> {
+    if !path.exists() {
+        anyhow::bail!("{} does not exist", path.display());
+    }
+    let output = std::process::Command::new("sqlite3")
+        .arg("-readonly")
+        .arg("-separator")
+        .arg("\t")
+        .arg("-newline")
+        .arg("\0")
+        .arg(path)
+        .arg("SELECT timestamp / 1000000000, command FROM history WHERE deleted_at IS NULL")
+        .output()
+        .context("Importing atuin history needs the sqlite3 command")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "sqlite3 failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .split('\0')
+        .filter_map(|row| {
+            let (time, command) = row.split_once('\t')?;
+            Some(HistoryEntry {
+                command: command.to_string(),
+                time: time.parse().ok(),
+            })
+        })
+        .collect())
+}
@@ -0,0 +1,77 @@
+//! How long completions and generators take on this machine, for finding the
+//! sources worth disabling or caching harder.
+//!
+//! Only the latest [`MAX_SAMPLES`] timings of each command or generator are
+//! kept, so the percentiles follow changes such as a repository growing or a
+//! cluster moving further away.
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Timings kept per command or generator
+pub const MAX_SAMPLES: usize = 200;
+
+/// Recent timings of one command's completions or one generator's runs
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Latency {
+    /// Latest timings in microseconds, oldest first
+    samples: VecDeque<u32>,
+    /// How many of the sampled requests were served from cached data, for
+    /// sources that cache
+    cache_hits: VecDeque<bool>,
+}
+
+impl Latency {
+    /// Record one timing, and whether that request was served from cache
+    pub fn record(&mut self, elapsed: Duration, cache_hit: Option<bool>) {
+        if self.samples.len() >= MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples
+            .push_back(u32::try_from(elapsed.as_micros()).unwrap_or(u32::MAX));
+        if let Some(hit) = cache_hit {
+            if self.cache_hits.len() >= MAX_SAMPLES {
+                self.cache_hits.pop_front();
+            }
+            self.cache_hits.push_back(hit);
+        }
+    }
+
+    /// Number of timings kept
+    pub fn count(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// 95th percentile of the kept timings
+    pub fn p95(&self) -> Option<Duration> {
+        self.percentile(95)
+    }
+
+    /// Median of the kept timings
+    pub fn p50(&self) -> Option<Duration> {
+        self.percentile(50)
+    }
+
+    /// Share of sampled requests served from cache, `None` for sources that
+    /// don't cache
+    pub fn cache_hit_rate(&self) -> Option<f64> {
+        if self.cache_hits.is_empty() {
+            return None;
+        }
+        let hits = self.cache_hits.iter().filter(|&&hit| hit).count();
+        Some(hits as f64 / self.cache_hits.len() as f64)
+    }
+
+    /// Nearest-rank percentile
+    fn percentile(&self, pct: usize) -> Option<Duration> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<u32> = self.samples.iter().copied().collect();
+        sorted.sort_unstable();
+        let rank = (sorted.len() * pct).div_ceil(100).max(1);
+        Some(Duration::from_micros(u64::from(sorted[rank - 1])))
+    }
+}
@@ -0,0 +1,409 @@
+//! Local usage statistics, persisted in the data directory.
+//!
+//! Nothing here leaves the machine; the counts are used to tune daemon
+//! behavior such as which specs to warm up at startup, and the flag values
+//! seen to suggest them again. Commands that look like they carry
+//! credentials are never stored, see [`is_private`].
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::RetentionConfig;
+use crate::paths;
+use history::CommandHistory;
+use latency::Latency;
+
+pub mod history;
+pub mod import;
+pub mod latency;
+
+/// Substrings (matched case-insensitively) of commands that likely carry a secret
+const PRIVATE_PATTERNS: &[&str] = &[
+    "password",
+    "passwd",
+    "secret",
+    "token=",
+    "token ",
+    "api_key",
+    "apikey",
+    "authorization:",
+    "bearer ",
+];
+
+const DAY: u64 = 24 * 60 * 60;
+const WEEK: u64 = 7 * DAY;
+const MONTH: u64 = 30 * DAY;
+
+/// Values remembered per flag before the least recently used is forgotten
+const MAX_VALUES_PER_FLAG: usize = 20;
+/// Suggestions remembered per context before the least frecent is forgotten
+const MAX_ACCEPTED_PER_CONTEXT: usize = 50;
+
+/// Per-command request counts and timings
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UsageStats {
+    /// Completion requests seen per command name
+    pub commands: BTreeMap<String, u64>,
+    /// Unix timestamp (seconds) of the latest request per command name
+    pub last_used: BTreeMap<String, u64>,
+    /// Completion timings per command name
+    pub completions: BTreeMap<String, Latency>,
+    /// Run timings per generator command line
+    pub generators: BTreeMap<String, Latency>,
+    /// Values given to free-form flags, keyed by `command --flag`, with the
+    /// unix timestamp (seconds) each was last given
+    pub flag_values: BTreeMap<String, BTreeMap<String, u64>>,
+    /// Visits per directory the shell changed to
+    pub directories: BTreeMap<String, Visits>,
+    /// Suggestions picked from the menu, keyed by the words they were
+    /// picked after (`git checkout`)
+    pub accepted: BTreeMap<String, BTreeMap<String, Visits>>,
+}
+
+/// How often a directory was visited or a suggestion picked, and when last
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Visits {
+    pub count: u64,
+    /// Unix timestamp (seconds) of the latest visit
+    pub last: u64,
+}
+
+impl UsageStats {
+    /// Default location of the stats file
+    pub fn default_path() -> Option<PathBuf> {
+        paths::data_dir().map(|dir| dir.join("stats.json"))
+    }
+
+    /// Load stats from `path`, starting fresh if the file is missing or unreadable
+    pub fn load(path: &Path) -> Self {
+        std::fs::read(path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_vec(self)?;
+        // Write then rename so a crash never leaves a truncated file behind
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, json)
+            .with_context(|| format!("Failed to write stats file: {}", tmp.display()))?;
+        std::fs::rename(&tmp, path)
+            .with_context(|| format!("Failed to write stats file: {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Record one completion request for `command`
+    pub fn record_command(&mut self, command: &str) {
+        self.record_at(command, unix_now());
+    }
+
+    /// Record one use of `command` at unix time `when`
+    pub fn record_at(&mut self, command: &str, when: u64) {
+        *self.commands.entry(command.to_string()).or_insert(0) += 1;
+        let last = self.last_used.entry(command.to_string()).or_insert(0);
+        *last = (*last).max(when);
+    }
+
+    /// Record how long completing `command` took, and whether its spec and
+    /// paths came from cache
+    pub fn record_completion(&mut self, command: &str, elapsed: Duration, cache_hit: bool) {
+        self.completions
+            .entry(command.to_string())
+            .or_default()
+            .record(elapsed, Some(cache_hit));
+    }
+
+    /// Record how long one run of the generator `script` took
+    pub fn record_generator(&mut self, script: &str, elapsed: Duration) {
+        self.generators
+            .entry(script.to_string())
+            .or_default()
+            .record(elapsed, None);
+    }
+
+    /// Remember that `value` was given to `flag` of `command`
+    pub fn record_flag_value(&mut self, command: &str, flag: &str, value: &str) {
+        let values = self
+            .flag_values
+            .entry(format!("{command} {flag}"))
+            .or_default();
+        values.insert(value.to_string(), unix_now());
+        if values.len() > MAX_VALUES_PER_FLAG
+            && let Some(oldest) = values
+                .iter()
+                .min_by_key(|(_, when)| **when)
+                .map(|(value, _)| value.clone())
+        {
+            values.remove(&oldest);
+        }
+    }
+
+    /// Values given to `flag` of `command` before, most recent first
+    pub fn flag_values(&self, command: &str, flag: &str) -> Vec<&str> {
+        let Some(values) = self.flag_values.get(&format!("{command} {flag}")) else {
+            return Vec::new();
+        };
+        let mut values: Vec<(&String, &u64)> = values.iter().collect();
+        values.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        values
+            .into_iter()
+            .map(|(value, _)| value.as_str())
+            .collect()
+    }
+
+    /// How likely `command` is to be wanted: its use count, weighted up when
+    /// it was used recently and down when it hasn't been used in a long time
+    pub fn frecency(&self, command: &str) -> u64 {
+        let Some(&count) = self.commands.get(command) else {
+            return 0;
+        };
+        count * recency_weight(self.last_used.get(command).copied().unwrap_or(0))
+    }
+
+    /// Remember that `text` was picked from the menu after `context`
+    pub fn record_accepted(&mut self, context: &str, text: &str) {
+        let accepted = self.accepted.entry(context.to_string()).or_default();
+        let uses = accepted.entry(text.to_string()).or_default();
+        uses.count += 1;
+        uses.last = unix_now();
+        if accepted.len() > MAX_ACCEPTED_PER_CONTEXT
+            && let Some(least) = accepted
+                .iter()
+                .min_by_key(|(_, uses)| (uses.count * recency_weight(uses.last), uses.last))
+                .map(|(text, _)| text.clone())
+        {
+            accepted.remove(&least);
+        }
+    }
+
+    /// How likely `text` is to be picked after `context`: how often it was,
+    /// weighted like [`frecency`](Self::frecency)
+    pub fn accepted_frecency(&self, context: &str, text: &str) -> u64 {
+        self.accepted
+            .get(context)
+            .and_then(|accepted| accepted.get(text))
+            .map_or(0, |uses| uses.count * recency_weight(uses.last))
+    }
+
+    /// Record a visit of the directory `dir`
+    pub fn record_visit(&mut self, dir: &str) {
+        let visits = self.directories.entry(dir.to_string()).or_default();
+        visits.count += 1;
+        visits.last = unix_now();
+    }
+
+    /// Visited directories, the most frecent first
+    pub fn frecent_directories(&self) -> Vec<(&str, Visits)> {
+        let mut dirs: Vec<(&str, Visits, u64)> = self
+            .directories
+            .iter()
+            .map(|(dir, &visits)| {
+                (
+                    dir.as_str(),
+                    visits,
+                    visits.count * recency_weight(visits.last),
+                )
+            })
+            .collect();
+        dirs.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(b.0)));
+        dirs.into_iter()
+            .map(|(dir, visits, _)| (dir, visits))
+            .collect()
+    }
+
+    /// Forget commands last used before unix time `cutoff`, then all but the
+    /// `max_commands` most frecent; returns how many were forgotten
+    pub fn prune(&mut self, max_commands: usize, cutoff: u64) -> usize {
+        let before = self.commands.len();
+        let last_used = &self.last_used;
+        self.commands
+            .retain(|command, _| last_used.get(command).copied().unwrap_or(0) >= cutoff);
+        if self.commands.len() > max_commands {
+            let mut ranked: Vec<(u64, String)> = self
+                .commands
+                .keys()
+                .map(|command| (self.frecency(command), command.clone()))
+                .collect();
+            ranked.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+            for (_, command) in ranked.into_iter().skip(max_commands) {
+                self.commands.remove(&command);
+            }
+        }
+        let commands = &self.commands;
+        self.last_used
+            .retain(|command, _| commands.contains_key(command));
+        self.completions
+            .retain(|command, _| commands.contains_key(command));
+        // Generator timings have no date; keep the most sampled ones
+        if self.generators.len() > max_commands {
+            let mut ranked: Vec<(usize, String)> = self
+                .generators
+                .iter()
+                .map(|(script, latency)| (latency.count(), script.clone()))
+                .collect();
+            ranked.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+            for (_, script) in ranked.into_iter().skip(max_commands) {
+                self.generators.remove(&script);
+            }
+        }
+        for values in self.flag_values.values_mut() {
+            values.retain(|_, &mut when| when >= cutoff);
+        }
+        self.flag_values.retain(|_, values| !values.is_empty());
+        if self.flag_values.len() > max_commands {
+            let mut ranked: Vec<(u64, String)> = self
+                .flag_values
+                .iter()
+                .map(|(flag, values)| (values.values().copied().max().unwrap_or(0), flag.clone()))
+                .collect();
+            ranked.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+            for (_, flag) in ranked.into_iter().skip(max_commands) {
+                self.flag_values.remove(&flag);
+            }
+        }
+        for accepted in self.accepted.values_mut() {
+            accepted.retain(|_, uses| uses.last >= cutoff);
+        }
+        self.accepted.retain(|_, accepted| !accepted.is_empty());
+        if self.accepted.len() > max_commands {
+            let mut ranked: Vec<(u64, String)> = self
+                .accepted
+                .iter()
+                .map(|(context, accepted)| {
+                    let last = accepted.values().map(|uses| uses.last).max();
+                    (last.unwrap_or(0), context.clone())
+                })
+                .collect();
+            ranked.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+            for (_, context) in ranked.into_iter().skip(max_commands) {
+                self.accepted.remove(&context);
+            }
+        }
+        self.directories.retain(|_, visits| visits.last >= cutoff);
+        if self.directories.len() > max_commands {
+            let forgotten: Vec<String> = self
+                .frecent_directories()
+                .into_iter()
+                .skip(max_commands)
+                .map(|(dir, _)| dir.to_string())
+                .collect();
+            for dir in forgotten {
+                self.directories.remove(&dir);
+            }
+        }
+        before - self.commands.len()
+    }
+
+    /// The `limit` most frequently completed commands, most used first
+    pub fn top_commands(&self, limit: usize) -> Vec<&str> {
+        let mut commands: Vec<(&String, &u64)> = self.commands.iter().collect();
+        commands.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        commands
+            .into_iter()
+            .take(limit)
+            .map(|(command, _)| command.as_str())
+            .collect()
+    }
+}
+
+/// The `limit` entries of `timings` with the worst p95, slowest first
+pub fn slowest(timings: &BTreeMap<String, Latency>, limit: usize) -> Vec<(&str, &Latency)> {
+    let mut slowest: Vec<(&str, &Latency)> = timings
+        .iter()
+        .filter(|(_, latency)| latency.count() > 0)
+        .map(|(name, latency)| (name.as_str(), latency))
+        .collect();
+    slowest.sort_by(|a, b| b.1.p95().cmp(&a.1.p95()).then_with(|| a.0.cmp(b.0)));
+    slowest.truncate(limit);
+    slowest
+}
+
+/// What [`prune`] forgot
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Pruned {
+    pub commands: usize,
+    pub history_entries: usize,
+}
+
+impl std::fmt::Display for Pruned {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Forgot {} command(s) and {} history entr{}",
+            self.commands,
+            self.history_entries,
+            if self.history_entries == 1 {
+                "y"
+            } else {
+                "ies"
+            }
+        )
+    }
+}
+
+/// Apply the `[retention]` limits to the usage stats and command history
+pub fn prune(
+    stats: &mut UsageStats,
+    history: &mut CommandHistory,
+    retention: &RetentionConfig,
+) -> Pruned {
+    let cutoff = match retention.max_age_days {
+        0 => 0,
+        days => unix_now().saturating_sub(days * DAY),
+    };
+    Pruned {
+        commands: stats.prune(retention.max_commands, cutoff),
+        history_entries: history.prune(retention.max_history_entries, cutoff),
+    }
+}
+
+/// Whether `command` must not be stored: it starts with a space (the shell's
+/// `HIST_IGNORE_SPACE` convention) or looks like it contains a credential
+pub fn is_private(command: &str) -> bool {
+    let lower = command.to_ascii_lowercase();
+    command.starts_with(' ')
+        || PRIVATE_PATTERNS
+            .iter()
+            .any(|pattern| lower.contains(pattern))
+}
+
+/// `command` as it may be logged or shared: a placeholder when it is
+/// [private](is_private)
+pub fn loggable(command: &str) -> &str {
+    if is_private(command) {
+        "<private>"
+    } else {
+        command
+    }
+}
+
+/// How much a use at unix time `last` counts: more when recent, less when
+/// long ago
+fn recency_weight(last: u64) -> u64 {
+    let age = unix_now().saturating_sub(last);
+    if age <= DAY {
+        8
+    } else if age <= WEEK {
+        4
+    } else if age <= MONTH {
+        2
+    } else {
+        1
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
@@ -0,0 +1,162 @@
+//! Putting the terminal back however the popup ends.
+//!
+//! The popup switches the terminal to raw mode and the alternate screen.
+//! Returning, failing and unwinding from a panic all drop the guard, which
+//! switches back. A signal gives no such chance: SIGINT, SIGTERM, SIGHUP or
+//! SIGQUIT would end the process with the shell's prompt stuck behind the
+//! popup and keys no longer echoed. While the guard is alive those signals are
+//! caught, and the handler restores the saved terminal settings with
+//! async-signal-safe calls only (`tcsetattr` and `write`) before dying of the
+//! signal as it would have. A panic restores the terminal before its message
+//! is printed, so the message ends up on the main screen where it stays
+//! readable.
+
+use anyhow::Result;
+use crossterm::terminal::disable_raw_mode;
+
+/// Leave the alternate screen and show the cursor
+const RESTORE: &[u8] = b"\x1b[?1049l\x1b[?25h";
+
+/// Restores the terminal when dropped; create it before changing any settings
+pub struct TerminalGuard {
+    #[cfg(unix)]
+    _signals: signals::Handlers,
+}
+
+impl TerminalGuard {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            #[cfg(unix)]
+            _signals: signals::Handlers::install()?,
+        })
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        // Also keeps crossterm's own record of the mode right
+        let _ = disable_raw_mode();
+        #[cfg(unix)]
+        signals::restore();
+        #[cfg(not(unix))]
+        {
+            use std::io::Write;
+            let mut out = std::io::stdout();
+            let _ = out.write_all(RESTORE);
+            let _ = out.flush();
+        }
+    }
+}
+
+#[cfg(unix)]
+mod signals {
+    use super::RESTORE;
+    use anyhow::{Context, Result};
+    use std::fs::{File, OpenOptions};
+    use std::os::fd::AsRawFd;
+    use std::sync::atomic::{AtomicI32, Ordering};
+    use std::sync::{Once, OnceLock};
+
+    /// Signals that end the process and are caught while the popup is shown
+    const FATAL: [libc::c_int; 4] = [libc::SIGINT, libc::SIGTERM, libc::SIGHUP, libc::SIGQUIT];
+
+    /// Terminal settings from before the popup; written once, then only read,
+    /// which is safe from a signal handler
+    static SAVED: OnceLock<libc::termios> = OnceLock::new();
+    /// The terminal's file descriptor while there is something to restore,
+    /// -1 otherwise
+    static TTY: AtomicI32 = AtomicI32::new(-1);
+    /// The panic hook is installed once, however often the popup is shown
+    static PANIC_HOOK: Once = Once::new();
+
+    /// Handlers for [`FATAL`] and the terminal they restore, reinstating the
+    /// previous handlers when dropped
+    pub struct Handlers {
+        /// Kept open until the handlers that write to it are gone
+        _tty: File,
+        previous: Vec<(libc::c_int, libc::sighandler_t)>,
+    }
+
+    impl Handlers {
+        pub fn install() -> Result<Self> {
+            let tty = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open("/dev/tty")
+                .context("Failed to open /dev/tty")?;
+            let fd = tty.as_raw_fd();
+            // SAFETY: `tcgetattr` only writes to the `termios` we pass it
+            let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+            if unsafe { libc::tcgetattr(fd, &mut termios) } != 0 {
+                return Err(std::io::Error::last_os_error())
+                    .context("Failed to read the terminal settings");
+            }
+            let _ = SAVED.set(termios);
+            TTY.store(fd, Ordering::SeqCst);
+            PANIC_HOOK.call_once(|| {
+                let previous = std::panic::take_hook();
+                std::panic::set_hook(Box::new(move |info| {
+                    restore();
+                    previous(info);
+                }));
+            });
+
+            let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            let mut previous = Vec::new();
+            for signal in FATAL {
+                // SAFETY: the handler only makes async-signal-safe calls
+                let before = unsafe { libc::signal(signal, handler) };
+                if before == libc::SIG_IGN {
+                    // Ignored signals (`nohup`) stay ignored
+                    unsafe {
+                        libc::signal(signal, libc::SIG_IGN);
+                    }
+                } else {
+                    previous.push((signal, before));
+                }
+            }
+            Ok(Self {
+                _tty: tty,
+                previous,
+            })
+        }
+    }
+
+    impl Drop for Handlers {
+        fn drop(&mut self) {
+            restore();
+            for &(signal, previous) in &self.previous {
+                // SAFETY: reinstates the handler that was there before
+                unsafe {
+                    libc::signal(signal, previous);
+                }
+            }
+        }
+    }
+
+    /// Put back the saved settings and the main screen, once
+    pub fn restore() {
+        let fd = TTY.swap(-1, Ordering::SeqCst);
+        if fd < 0 {
+            return;
+        }
+        // SAFETY: both calls are async-signal-safe, and `fd` stays open while
+        // the handlers are installed
+        unsafe {
+            libc::write(fd, RESTORE.as_ptr().cast(), RESTORE.len());
+            if let Some(termios) = SAVED.get() {
+                libc::tcsetattr(fd, libc::TCSANOW, termios);
+            }
+        }
+    }
+
+    extern "C" fn on_signal(signal: libc::c_int) {
+        restore();
+        // SAFETY: dying of the signal with its default action, which is what
+        // would have happened without the handler
+        unsafe {
+            libc::signal(signal, libc::SIG_DFL);
+            libc::raise(signal);
+        }
+    }
+}
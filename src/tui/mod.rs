@@ -1,30 +1,293 @@
-use crate::daemon::Suggestion;
-use anyhow::Result;
+use crate::daemon::{Suggestion, TerminalInfo};
+use crate::parser::snippet::Snippet;
+use anyhow::{Context, Result};
 use crossterm::{
     ExecutableCommand,
     event::{self, Event, KeyCode},
-    terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
+    terminal::{EnterAlternateScreen, enable_raw_mode},
 };
 use ratatui::{
     Terminal,
     backend::CrosstermBackend,
     layout::{Constraint, Direction, Layout},
-    style::{Color, Modifier, Style},
+    style::{Modifier, Style},
+    symbols::border,
     text::{Line, Span},
-    widgets::{Block, Borders, List, ListItem},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
 };
-use std::io;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+
+mod guard;
+mod plain;
+mod sections;
+pub mod theme;
+
+use sections::{Row, Sections};
+
+pub use theme::{THEMES, Theme};
+
+/// How often the popup checks for new pages while the daemon is still producing them
+const FEED_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Fewer rows than this can't fit a bordered list, so the popup shows one
+/// suggestion at a time instead
+const MIN_LIST_ROWS: u16 = 5;
+
+/// Border drawn with ASCII only, for terminals without Unicode support
+const ASCII_BORDER: border::Set = border::Set {
+    top_left: "+",
+    top_right: "+",
+    bottom_left: "+",
+    bottom_right: "+",
+    vertical_left: "|",
+    vertical_right: "|",
+    horizontal_top: "-",
+    horizontal_bottom: "-",
+};
+
+/// Size and capabilities of the terminal the client runs in
+pub fn detect_terminal() -> TerminalInfo {
+    // COLUMNS/LINES cover the case where no tty is reachable
+    let env_size = |name: &str, fallback: u16| {
+        std::env::var(name)
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(fallback)
+    };
+    let (columns, rows) = crossterm::terminal::size()
+        .unwrap_or_else(|_| (env_size("COLUMNS", 80), env_size("LINES", 24)));
+    let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+    let term = std::env::var("TERM").unwrap_or_default();
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .find_map(|name| std::env::var(name).ok().filter(|value| !value.is_empty()))
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    TerminalInfo {
+        columns,
+        rows,
+        truecolor: matches!(colorterm.as_str(), "truecolor" | "24bit"),
+        // The Linux console has a tiny font without most symbols
+        unicode: (locale.contains("utf-8") || locale.contains("utf8")) && term != "linux",
+    }
+}
 
 pub struct CompletionUI {
     suggestions: Vec<Suggestion>,
+    /// The suggestions by kind, when of more than one
+    sections: Sections,
+    /// Lines of the list, as laid out by `sections`
+    rows: Vec<Row>,
+    /// Index into `rows`
     selected: usize,
+    terminal: TerminalInfo,
+    /// Placeholders being filled in after picking an abbreviation
+    filling: Option<Fill>,
+    /// Source of later pages, while there are more
+    feed: Option<Feed>,
+    /// Whether a page was asked for and hasn't arrived yet
+    awaiting_page: bool,
+    /// Draw on /dev/tty instead of stdout
+    on_tty: bool,
+    /// List the suggestions as numbered plain text instead
+    plain: bool,
+    theme: Theme,
+    /// Settings being tried out, when run by `tui-test`
+    preview: Option<Preview>,
+}
+
+/// Settings cycled through while previewing the popup with sample suggestions
+#[derive(Debug, Clone)]
+pub struct Preview {
+    /// Index into [`THEMES`]
+    pub theme: usize,
+    /// Trigger keys to choose from, as (label, zsh bindkey sequence)
+    pub triggers: Vec<(String, String)>,
+    /// Index into `triggers`
+    pub trigger: usize,
+    /// Show the one-line layout of short terminals
+    pub compact: bool,
+}
+
+/// Later pages of suggestions, delivered while the popup is open
+pub struct Feed {
+    /// Pages in order; disconnected once there are no more
+    pub pages: Receiver<Vec<Suggestion>>,
+    /// Asks for the next page
+    pub wants: UnboundedSender<()>,
+}
+
+/// Progress through the placeholders of a selected expansion
+struct Fill {
+    /// Selected suggestion, whose text is replaced when filling is done
+    suggestion: Suggestion,
+    snippet: Snippet,
+    /// Placeholder being edited
+    field: usize,
+    /// Whether anything was typed into the field yet; the first key replaces the default
+    edited: bool,
+}
+
+impl Fill {
+    fn focus(&mut self, field: usize) {
+        self.field = field;
+        self.edited = false;
+    }
+
+    fn finish(mut self) -> Suggestion {
+        self.suggestion.text = self.snippet.text;
+        self.suggestion
+    }
 }
 
 impl CompletionUI {
-    pub fn new(suggestions: Vec<Suggestion>) -> Self {
+    pub fn new(suggestions: Vec<Suggestion>, terminal: TerminalInfo) -> Self {
+        let mut sections = Sections::default();
+        sections.add(&suggestions);
+        let rows = sections.rows();
+        // The first suggestion rather than the header above it
+        let selected = rows
+            .iter()
+            .position(|row| matches!(row, Row::Item(_)))
+            .unwrap_or_default();
         Self {
             suggestions,
-            selected: 0,
+            sections,
+            rows,
+            selected,
+            terminal,
+            filling: None,
+            feed: None,
+            awaiting_page: false,
+            on_tty: false,
+            plain: false,
+            theme: Theme::named(THEMES[0], terminal.truecolor),
+            preview: None,
+        }
+    }
+
+    /// Color the popup with `theme`
+    pub fn with_theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Cycle through the settings in `preview` with keys: `t` theme, `k`
+    /// trigger key, `l` layout. Enter returns the selection to save them.
+    pub fn with_preview(mut self, preview: Preview) -> Self {
+        self.theme = Theme::named(THEMES[preview.theme], self.terminal.truecolor);
+        self.preview = Some(preview);
+        self
+    }
+
+    /// The settings as last left in the preview
+    pub fn preview(&self) -> Option<&Preview> {
+        self.preview.as_ref()
+    }
+
+    /// Draw on the controlling terminal rather than stdout, so stdout carries
+    /// nothing but the selection however the shell captures it
+    pub fn on_tty(mut self, on_tty: bool) -> Self {
+        self.on_tty = on_tty;
+        self
+    }
+
+    /// Write a numbered list and read the number of the choice, for screen
+    /// readers and terminals that can't draw the popup
+    pub fn plain(mut self, plain: bool) -> Self {
+        self.plain = plain;
+        self
+    }
+
+    /// Append pages from `feed` as they arrive
+    pub fn with_feed(mut self, feed: Feed) -> Self {
+        self.feed = Some(feed);
+        self
+    }
+
+    /// Take the pages that arrived, and ask for another once the selection
+    /// is within a screen of the end
+    fn pull_pages(&mut self) {
+        let Some(feed) = &self.feed else {
+            return;
+        };
+        loop {
+            match feed.pages.try_recv() {
+                Ok(page) => {
+                    self.suggestions.extend(page);
+                    self.awaiting_page = false;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.feed = None;
+                    break;
+                }
+            }
+        }
+        self.sections.add(&self.suggestions);
+        self.relayout();
+        let Some(feed) = &self.feed else {
+            return;
+        };
+        let margin = usize::from(self.terminal.rows);
+        if !self.awaiting_page && self.selected + margin >= self.rows.len() {
+            self.awaiting_page = feed.wants.send(()).is_ok();
+        }
+    }
+
+    /// Lay the rows out again after sections changed, keeping the selection
+    /// on the same suggestion, or on the header of its section once collapsed
+    fn relayout(&mut self) {
+        let current = self.rows.get(self.selected).copied();
+        self.rows = self.sections.rows();
+        let section = current.and_then(|row| self.sections.section_of(row));
+        self.selected = current
+            .and_then(|row| self.rows.iter().position(|&other| other == row))
+            .or_else(|| {
+                let header = Row::Header(section?);
+                self.rows.iter().position(|&row| row == header)
+            })
+            .unwrap_or_default();
+    }
+
+    /// The suggestion under the cursor, unless it's on a header
+    fn current(&self) -> Option<&Suggestion> {
+        match self.rows.get(self.selected)? {
+            Row::Item(item) => self.suggestions.get(*item),
+            Row::Header(_) => None,
+        }
+    }
+
+    /// Collapse or expand `section`
+    fn set_collapsed(&mut self, section: usize, collapsed: bool) {
+        self.sections.set_collapsed(section, collapsed);
+        self.relayout();
+    }
+
+    /// Move to the next section, or the previous one, wrapping around: onto
+    /// its first suggestion, or its header when collapsed
+    fn jump_section(&mut self, forward: bool) {
+        let Some(current) = self
+            .rows
+            .get(self.selected)
+            .and_then(|&row| self.sections.section_of(row))
+        else {
+            return;
+        };
+        let count = self.sections.sections();
+        let target = if forward {
+            (current + 1) % count
+        } else {
+            (current + count - 1) % count
+        };
+        let header = Row::Header(target);
+        if let Some(row) = self.rows.iter().position(|&row| row == header) {
+            let expanded = !self.sections.is_collapsed(target);
+            self.selected = row + usize::from(expanded);
         }
     }
 
@@ -34,22 +297,29 @@ impl CompletionUI {
         if self.suggestions.is_empty() {
             return Ok(None);
         }
+        if self.plain {
+            return self.run_plain();
+        }
 
-        // Setup terminal
+        // Setup terminal; the guard undoes it however the popup ends
+        let _guard = guard::TerminalGuard::new()?;
         enable_raw_mode()?;
-        let mut stdout = io::stdout();
-        stdout.execute(EnterAlternateScreen)?;
-        let backend = CrosstermBackend::new(stdout);
+        // Key events are read from /dev/tty already when stdin isn't a terminal
+        let mut out: Box<dyn Write + Send> = if self.on_tty {
+            Box::new(
+                OpenOptions::new()
+                    .write(true)
+                    .open("/dev/tty")
+                    .context("Failed to open /dev/tty")?,
+            )
+        } else {
+            Box::new(io::stdout())
+        };
+        out.execute(EnterAlternateScreen)?;
+        let backend = CrosstermBackend::new(out);
         let mut terminal = Terminal::new(backend)?;
 
-        let result = self.run_app(&mut terminal);
-
-        // Restore terminal
-        disable_raw_mode()?;
-        terminal.backend_mut().execute(LeaveAlternateScreen)?;
-        terminal.show_cursor()?;
-
-        result
+        self.run_app(&mut terminal)
     }
 
     fn run_app<B: ratatui::backend::Backend>(
@@ -57,81 +327,351 @@ impl CompletionUI {
         terminal: &mut Terminal<B>,
     ) -> Result<Option<Suggestion>> {
         loop {
+            self.pull_pages();
             terminal.draw(|f| self.ui(f))?;
 
-            if let Event::Key(key) = event::read()? {
+            if self.feed.is_some() && !event::poll(FEED_POLL_INTERVAL)? {
+                continue;
+            }
+            let key = match event::read()? {
+                Event::Key(key) => key,
+                // The next draw lays the popup out for the new size
+                Event::Resize(columns, rows) => {
+                    self.terminal.columns = columns;
+                    self.terminal.rows = rows;
+                    continue;
+                }
+                _ => continue,
+            };
+            if let Some(mut fill) = self.filling.take() {
                 match key.code {
-                    KeyCode::Esc => return Ok(None),
-                    KeyCode::Enter => {
-                        return Ok(Some(self.suggestions[self.selected].clone()));
+                    // Back to the list
+                    KeyCode::Esc => {}
+                    KeyCode::Enter => return Ok(Some(fill.finish())),
+                    KeyCode::Tab if fill.field + 1 >= fill.snippet.fields.len() => {
+                        return Ok(Some(fill.finish()));
+                    }
+                    KeyCode::Tab => {
+                        fill.focus(fill.field + 1);
+                        self.filling = Some(fill);
                     }
-                    KeyCode::Down => {
-                        // Wrap around to beginning
-                        self.selected = (self.selected + 1) % self.suggestions.len();
+                    KeyCode::BackTab => {
+                        fill.focus(fill.field.saturating_sub(1));
+                        self.filling = Some(fill);
                     }
-                    KeyCode::Up => {
-                        // Wrap around to end
-                        if self.selected == 0 {
-                            self.selected = self.suggestions.len() - 1;
+                    KeyCode::Backspace => {
+                        let mut value = fill.snippet.value(fill.field).to_string();
+                        value.pop();
+                        fill.snippet.set(fill.field, &value);
+                        fill.edited = true;
+                        self.filling = Some(fill);
+                    }
+                    KeyCode::Char(c) => {
+                        let mut value = if fill.edited {
+                            fill.snippet.value(fill.field).to_string()
                         } else {
-                            self.selected -= 1;
+                            String::new()
+                        };
+                        value.push(c);
+                        fill.snippet.set(fill.field, &value);
+                        fill.edited = true;
+                        self.filling = Some(fill);
+                    }
+                    _ => self.filling = Some(fill),
+                }
+                continue;
+            }
+
+            if let Some(preview) = &mut self.preview {
+                match key.code {
+                    KeyCode::Char('t') => {
+                        preview.theme = (preview.theme + 1) % THEMES.len();
+                        self.theme = Theme::named(THEMES[preview.theme], self.terminal.truecolor);
+                        continue;
+                    }
+                    KeyCode::Char('k') => {
+                        preview.trigger = (preview.trigger + 1) % preview.triggers.len().max(1);
+                        continue;
+                    }
+                    KeyCode::Char('l') => {
+                        preview.compact = !preview.compact;
+                        continue;
+                    }
+                    // Sample suggestions aren't inserted anywhere
+                    KeyCode::Enter => {
+                        if let Some(suggestion) = self.current() {
+                            return Ok(Some(suggestion.clone()));
                         }
                     }
                     _ => {}
                 }
             }
+
+            let section = self
+                .rows
+                .get(self.selected)
+                .and_then(|&row| self.sections.section_of(row));
+            match key.code {
+                KeyCode::Esc => return Ok(None),
+                KeyCode::Enter => {
+                    let Some(suggestion) = self.current().cloned() else {
+                        // Enter on a header folds or unfolds its section
+                        if let Some(section) = section {
+                            self.set_collapsed(section, !self.sections.is_collapsed(section));
+                        }
+                        continue;
+                    };
+                    if !suggestion.source.is_snippet() {
+                        return Ok(Some(suggestion));
+                    }
+                    let snippet = Snippet::parse(&suggestion.text);
+                    if !snippet.has_fields() {
+                        return Ok(Some(Suggestion {
+                            text: snippet.text,
+                            ..suggestion
+                        }));
+                    }
+                    self.filling = Some(Fill {
+                        suggestion,
+                        snippet,
+                        field: 0,
+                        edited: false,
+                    });
+                }
+                KeyCode::Down => self.select_next(),
+                KeyCode::Tab if self.is_compact() => self.select_next(),
+                KeyCode::BackTab if self.is_compact() => self.select_previous(),
+                KeyCode::Tab => self.jump_section(true),
+                KeyCode::BackTab => self.jump_section(false),
+                KeyCode::Up => self.select_previous(),
+                KeyCode::Left => {
+                    if let Some(section) = section {
+                        self.set_collapsed(section, true);
+                    }
+                }
+                KeyCode::Right => {
+                    if let Some(section) = section {
+                        self.set_collapsed(section, false);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Whether the terminal is too short for the bordered list
+    fn is_compact(&self) -> bool {
+        self.terminal.rows < MIN_LIST_ROWS || self.preview.as_ref().is_some_and(|p| p.compact)
+    }
+
+    fn select_next(&mut self) {
+        // Wrap around to beginning
+        self.step(|selected, len| (selected + 1) % len);
+    }
+
+    fn select_previous(&mut self) {
+        // Wrap around to end
+        self.step(|selected, len| (selected + len - 1) % len);
+    }
+
+    /// Move the selection with `next` until it's on a row that can be
+    /// selected: any row in the list, but only suggestions in the one line
+    fn step(&mut self, next: impl Fn(usize, usize) -> usize) {
+        let len = self.rows.len();
+        for _ in 0..len {
+            self.selected = next(self.selected, len);
+            if !self.is_compact() || matches!(self.rows[self.selected], Row::Item(_)) {
+                break;
+            }
         }
     }
 
     fn ui(&self, f: &mut ratatui::Frame) {
+        // The preview's settings and keys go on a line of their own
+        let status = if self.preview.is_some() { 1 } else { 0 };
         let chunks = Layout::default()
             .direction(Direction::Vertical)
-            .constraints([Constraint::Min(0)])
+            .constraints([Constraint::Min(0), Constraint::Length(status)])
             .split(f.area());
+        if let Some(preview) = &self.preview {
+            f.render_widget(Paragraph::new(self.preview_line(preview)), chunks[1]);
+        }
 
-        let items: Vec<ListItem> = self
-            .suggestions
-            .iter()
-            .enumerate()
-            .map(|(i, suggestion)| {
-                let is_selected = i == self.selected;
-
-                // Build the line with text and description
-                let mut spans = vec![Span::styled(
-                    &suggestion.text,
-                    if is_selected {
-                        Style::default()
-                            .fg(Color::Yellow)
-                            .add_modifier(Modifier::BOLD)
+        // One unbordered line, cycled through with Tab or the arrows
+        if self.is_compact() {
+            let line = match &self.filling {
+                Some(fill) => self.fill_line(fill),
+                None => {
+                    let (open, close) = if self.terminal.unicode {
+                        ("‹", "›")
                     } else {
-                        Style::default().fg(Color::White)
-                    },
-                )];
-
-                // Add description if present
-                if !suggestion.description.is_empty() {
-                    spans.push(Span::raw(" - "));
-                    spans.push(Span::styled(
-                        &suggestion.description,
-                        if is_selected {
-                            Style::default().fg(Color::Yellow)
-                        } else {
-                            Style::default().fg(Color::Gray)
-                        },
-                    ));
+                        ("<", ">")
+                    };
+                    let mut line = self.row_line(self.rows[self.selected], true);
+                    let is_item = |row: &&Row| matches!(row, Row::Item(_));
+                    let position = self.rows[..=self.selected].iter().filter(is_item).count();
+                    let total = self.rows.iter().filter(is_item).count();
+                    line.spans.insert(
+                        0,
+                        Span::styled(
+                            format!("{open}{position}/{total}{close} "),
+                            Style::default().fg(self.theme.border),
+                        ),
+                    );
+                    line
                 }
+            };
+            f.render_widget(Paragraph::new(line), chunks[0]);
+            return;
+        }
 
-                ListItem::new(Line::from(spans))
-            })
+        if let Some(fill) = &self.filling {
+            f.render_widget(self.fill_view(fill), chunks[0]);
+            return;
+        }
+
+        let items: Vec<ListItem> = self
+            .rows
+            .iter()
+            .enumerate()
+            .map(|(i, &row)| ListItem::new(self.row_line(row, i == self.selected)))
             .collect();
 
+        let mut block = Block::default().borders(Borders::ALL);
+        if !self.terminal.unicode {
+            block = block.border_set(ASCII_BORDER);
+        }
+        let title = if self.sections.is_grouped() {
+            "Completions: Tab next section, Left/Right fold"
+        } else {
+            "Completions"
+        };
         let list = List::new(items).block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title("Completions")
-                .style(Style::default().fg(Color::Cyan)),
+            block
+                .title(title)
+                .style(Style::default().fg(self.theme.border)),
         );
 
-        f.render_widget(list, chunks[0]);
+        // Scroll to keep the selection visible
+        let mut state = ListState::default().with_selected(Some(self.selected));
+        f.render_stateful_widget(list, chunks[0], &mut state);
+    }
+
+    /// The line for `row` of the list
+    fn row_line(&self, row: Row, is_selected: bool) -> Line<'_> {
+        match row {
+            Row::Item(item) => self.suggestion_line(&self.suggestions[item], is_selected),
+            Row::Header(section) => self.header_line(section, is_selected),
+        }
+    }
+
+    /// A section's kind and count, marked as folded or unfolded
+    fn header_line(&self, section: usize, is_selected: bool) -> Line<'static> {
+        let collapsed = self.sections.is_collapsed(section);
+        let marker = match (collapsed, self.terminal.unicode) {
+            (true, true) => "▸",
+            (false, true) => "▾",
+            (true, false) => "+",
+            (false, false) => "-",
+        };
+        let style = if is_selected {
+            Style::default().fg(self.theme.selected)
+        } else {
+            Style::default().fg(self.theme.border)
+        };
+        Line::from(Span::styled(
+            format!(
+                "{marker} {} ({})",
+                self.sections.kind(section).label(),
+                self.sections.count(section)
+            ),
+            style.add_modifier(Modifier::BOLD),
+        ))
+    }
+
+    /// A suggestion's text, followed by its description if it has one
+    fn suggestion_line<'a>(&self, suggestion: &'a Suggestion, is_selected: bool) -> Line<'a> {
+        let mut spans = vec![Span::styled(
+            &suggestion.text,
+            if is_selected {
+                Style::default()
+                    .fg(self.theme.selected)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(self.theme.text)
+            },
+        )];
+
+        if !suggestion.description.is_empty() {
+            spans.push(Span::raw(" - "));
+            spans.push(Span::styled(
+                &suggestion.description,
+                if is_selected {
+                    Style::default().fg(self.theme.selected)
+                } else {
+                    Style::default().fg(self.theme.description)
+                },
+            ));
+        }
+
+        Line::from(spans)
+    }
+
+    /// The expansion being filled in, in a bordered box
+    fn fill_view<'a>(&self, fill: &'a Fill) -> List<'a> {
+        let mut block = Block::default().borders(Borders::ALL);
+        if !self.terminal.unicode {
+            block = block.border_set(ASCII_BORDER);
+        }
+        List::new([ListItem::new(self.fill_line(fill))]).block(
+            block
+                .title("Fill in: Tab next, Shift+Tab back, Enter done")
+                .style(Style::default().fg(self.theme.border)),
+        )
+    }
+
+    /// The expansion with the placeholder being edited highlighted
+    fn fill_line<'a>(&self, fill: &'a Fill) -> Line<'a> {
+        let text = &fill.snippet.text;
+        let range = fill.snippet.fields[fill.field].clone();
+        // An empty placeholder still needs something to highlight
+        let field = if range.is_empty() {
+            " "
+        } else {
+            &text[range.clone()]
+        };
+        Line::from(vec![
+            Span::styled(&text[..range.start], Style::default().fg(self.theme.text)),
+            Span::styled(
+                field,
+                Style::default()
+                    .fg(self.theme.selected)
+                    .add_modifier(Modifier::BOLD | Modifier::REVERSED),
+            ),
+            Span::styled(&text[range.end..], Style::default().fg(self.theme.text)),
+        ])
+    }
+
+    /// The previewed settings, and the keys that change them
+    fn preview_line(&self, preview: &Preview) -> Line<'static> {
+        let trigger = preview
+            .triggers
+            .get(preview.trigger)
+            .map_or("", |(label, _)| label.as_str());
+        let layout = if self.is_compact() { "line" } else { "list" };
+        let key = |key: &'static str| Span::styled(key, Style::default().fg(self.theme.selected));
+        let text = |text: String| Span::styled(text, Style::default().fg(self.theme.text));
+        Line::from(vec![
+            key("t"),
+            text(format!(" theme: {}  ", THEMES[preview.theme])),
+            key("k"),
+            text(format!(" trigger: {trigger}  ")),
+            key("l"),
+            text(format!(" layout: {layout}  ")),
+            key("Enter"),
+            text(" save  ".to_string()),
+            key("Esc"),
+            text(" quit".to_string()),
+        ])
     }
 }
@@ -0,0 +1,136 @@
+//! The popup as plain text, for screen readers and dumb terminals.
+//!
+//! Suggestions are written as a numbered list, one per line, with no colors,
+//! box drawing or cursor movement, and the choice is read from stdin as the
+//! number of a suggestion. The placeholders of a snippet are then asked for
+//! one at a time.
+
+use super::CompletionUI;
+use crate::daemon::Suggestion;
+use crate::parser::snippet::Snippet;
+use anyhow::Result;
+use std::fs::OpenOptions;
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::RecvError;
+
+impl CompletionUI {
+    /// List the suggestions and return the one whose number is typed; an
+    /// empty line or the end of input cancels
+    pub(super) fn run_plain(&mut self) -> Result<Option<Suggestion>> {
+        // The list goes to the terminal, or stderr without one, as stdout
+        // carries nothing but the selection
+        let mut out: Box<dyn Write> = match OpenOptions::new().write(true).open("/dev/tty") {
+            Ok(tty) => Box::new(tty),
+            Err(_) => Box::new(io::stderr()),
+        };
+        let mut input = io::stdin().lock();
+
+        let mut listed = 0;
+        loop {
+            for (number, suggestion) in self.suggestions.iter().enumerate().skip(listed) {
+                writeln!(out, "{}", line(number + 1, suggestion))?;
+            }
+            listed = self.suggestions.len();
+            let more = if self.feed.is_some() {
+                ", m for more"
+            } else {
+                ""
+            };
+            write!(out, "Choose 1 to {listed}{more}, or Enter to cancel: ")?;
+            out.flush()?;
+
+            let Some(answer) = read_line(&mut input)? else {
+                return Ok(None);
+            };
+            match answer.as_str() {
+                "" => return Ok(None),
+                "m" if self.feed.is_some() => {
+                    if !self.next_page() {
+                        writeln!(out, "No more suggestions")?;
+                    }
+                }
+                answer => match answer.parse::<usize>() {
+                    Ok(number) if (1..=listed).contains(&number) => {
+                        let suggestion = self.suggestions[number - 1].clone();
+                        return fill_in(suggestion, &mut out, &mut input);
+                    }
+                    _ => writeln!(out, "No suggestion numbered {answer}")?,
+                },
+            }
+        }
+    }
+
+    /// Wait for the next page of suggestions; `false` when there is none
+    fn next_page(&mut self) -> bool {
+        let Some(feed) = &self.feed else {
+            return false;
+        };
+        if feed.wants.send(()).is_err() {
+            self.feed = None;
+            return false;
+        }
+        match feed.pages.recv() {
+            Ok(page) if !page.is_empty() => {
+                self.suggestions.extend(page);
+                true
+            }
+            Ok(_) => false,
+            Err(RecvError) => {
+                self.feed = None;
+                false
+            }
+        }
+    }
+}
+
+/// `3. checkout - Switch branches`
+fn line(number: usize, suggestion: &Suggestion) -> String {
+    if suggestion.description.is_empty() {
+        format!("{number}. {}", suggestion.text)
+    } else {
+        format!("{number}. {} - {}", suggestion.text, suggestion.description)
+    }
+}
+
+/// Ask for the value of each placeholder of a snippet; an empty answer keeps
+/// the default, the end of input cancels
+fn fill_in(
+    suggestion: Suggestion,
+    out: &mut dyn Write,
+    input: &mut dyn BufRead,
+) -> Result<Option<Suggestion>> {
+    if !suggestion.source.is_snippet() {
+        return Ok(Some(suggestion));
+    }
+    let mut snippet = Snippet::parse(&suggestion.text);
+    let count = snippet.fields.len();
+    if count > 0 {
+        writeln!(out, "Fill in: {}", snippet.text)?;
+    }
+    for field in 0..count {
+        match snippet.value(field) {
+            "" => write!(out, "Value {} of {count}: ", field + 1)?,
+            default => write!(out, "Value {} of {count} [{default}]: ", field + 1)?,
+        }
+        out.flush()?;
+        let Some(value) = read_line(input)? else {
+            return Ok(None);
+        };
+        if !value.is_empty() {
+            snippet.set(field, &value);
+        }
+    }
+    Ok(Some(Suggestion {
+        text: snippet.text,
+        ..suggestion
+    }))
+}
+
+/// One line of input without its line ending; `None` at the end of input
+fn read_line(input: &mut dyn BufRead) -> Result<Option<String>> {
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim().to_string()))
+}
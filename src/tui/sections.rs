@@ -0,0 +1,152 @@
+//! Sections of the popup's list, one per kind of suggestion.
+//!
+//! When the suggestions mix kinds, say a command's subcommands, its options
+//! and files, the list shows them kind by kind under a header with their
+//! count. Sections come in the order of their best ranked suggestion and can
+//! be collapsed, so a long list can be skimmed a section at a time. A list of
+//! a single kind has no headers.
+
+use crate::daemon::{Suggestion, SuggestionSource};
+
+/// What a section holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Commands,
+    Arguments,
+    Options,
+    Files,
+    History,
+    Snippets,
+}
+
+impl Kind {
+    pub fn of(suggestion: &Suggestion) -> Self {
+        match suggestion.source {
+            SuggestionSource::Correction if suggestion.text.starts_with('-') => Kind::Options,
+            SuggestionSource::Command | SuggestionSource::Correction => Kind::Commands,
+            SuggestionSource::Spec if suggestion.text.starts_with('-') => Kind::Options,
+            SuggestionSource::Spec => Kind::Arguments,
+            SuggestionSource::Guessed => Kind::Options,
+            SuggestionSource::Files | SuggestionSource::CdPath | SuggestionSource::Visited => {
+                Kind::Files
+            }
+            SuggestionSource::History | SuggestionSource::Prediction => Kind::History,
+            SuggestionSource::Abbreviation | SuggestionSource::Example => Kind::Snippets,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Kind::Commands => "Commands",
+            Kind::Arguments => "Arguments",
+            Kind::Options => "Options",
+            Kind::Files => "Files",
+            Kind::History => "History",
+            Kind::Snippets => "Snippets",
+        }
+    }
+}
+
+/// A line of the list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Row {
+    /// The header of a section, by index
+    Header(usize),
+    /// A suggestion, by index
+    Item(usize),
+}
+
+#[derive(Debug)]
+struct Section {
+    kind: Kind,
+    /// Indexes of the suggestions, best ranked first
+    items: Vec<usize>,
+    collapsed: bool,
+}
+
+/// The suggestions sorted into sections
+#[derive(Debug, Default)]
+pub struct Sections {
+    sections: Vec<Section>,
+    /// How many suggestions were sorted in so far
+    added: usize,
+}
+
+impl Sections {
+    /// Sort in the suggestions after those added before, as pages arrive
+    pub fn add(&mut self, suggestions: &[Suggestion]) {
+        for (index, suggestion) in suggestions.iter().enumerate().skip(self.added) {
+            let kind = Kind::of(suggestion);
+            match self
+                .sections
+                .iter_mut()
+                .find(|section| section.kind == kind)
+            {
+                Some(section) => section.items.push(index),
+                None => self.sections.push(Section {
+                    kind,
+                    items: vec![index],
+                    collapsed: false,
+                }),
+            }
+        }
+        self.added = suggestions.len();
+    }
+
+    /// Whether there is more than one section, and so headers
+    pub fn is_grouped(&self) -> bool {
+        self.sections.len() > 1
+    }
+
+    /// The lines of the list: every section's header followed by its
+    /// suggestions unless collapsed, or just the suggestions when ungrouped
+    pub fn rows(&self) -> Vec<Row> {
+        let mut rows = Vec::with_capacity(self.added + self.sections.len());
+        for (index, section) in self.sections.iter().enumerate() {
+            if self.is_grouped() {
+                rows.push(Row::Header(index));
+                if section.collapsed {
+                    continue;
+                }
+            }
+            rows.extend(section.items.iter().map(|&item| Row::Item(item)));
+        }
+        rows
+    }
+
+    /// The section `row` is in, when grouped
+    pub fn section_of(&self, row: Row) -> Option<usize> {
+        if !self.is_grouped() {
+            return None;
+        }
+        match row {
+            Row::Header(section) => Some(section),
+            Row::Item(item) => self
+                .sections
+                .iter()
+                .position(|section| section.items.contains(&item)),
+        }
+    }
+
+    /// Number of sections
+    pub fn sections(&self) -> usize {
+        self.sections.len()
+    }
+
+    pub fn kind(&self, section: usize) -> Kind {
+        self.sections[section].kind
+    }
+
+    /// Number of suggestions in `section`
+    pub fn count(&self, section: usize) -> usize {
+        self.sections[section].items.len()
+    }
+
+    pub fn is_collapsed(&self, section: usize) -> bool {
+        self.sections[section].collapsed
+    }
+
+    pub fn set_collapsed(&mut self, section: usize, collapsed: bool) {
+        self.sections[section].collapsed = collapsed;
+    }
+}
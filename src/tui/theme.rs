@@ -0,0 +1,60 @@
+//! Popup colors for the `[theme]` names.
+//!
+//! The built-in themes are the Catppuccin flavors. Their colors need a
+//! truecolor terminal; elsewhere the popup keeps to the basic ANSI colors,
+//! with darker ones for the light flavor.
+
+use ratatui::style::Color;
+
+/// Built-in theme names, darkest first except for the light `latte`
+pub const THEMES: [&str; 4] = ["mocha", "macchiato", "frappe", "latte"];
+
+/// Colors of the popup's parts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    /// Border, title and the `‹1/9›` counter
+    pub border: Color,
+    /// Suggestion text
+    pub text: Color,
+    /// Descriptions
+    pub description: Color,
+    /// The selected suggestion and the placeholder being filled in
+    pub selected: Color,
+}
+
+impl Theme {
+    /// The theme called `name`, `mocha` if there's none by that name
+    pub fn named(name: &str, truecolor: bool) -> Self {
+        let light = name == "latte";
+        if !truecolor {
+            return if light {
+                Self {
+                    border: Color::Blue,
+                    text: Color::Black,
+                    description: Color::DarkGray,
+                    selected: Color::Magenta,
+                }
+            } else {
+                Self {
+                    border: Color::Cyan,
+                    text: Color::White,
+                    description: Color::Gray,
+                    selected: Color::Yellow,
+                }
+            };
+        }
+        // Lavender, text, subtext 0 and yellow of each flavor
+        let [border, text, description, selected] = match name {
+            "macchiato" => [0xb7bdf8, 0xcad3f5, 0xa5adcb, 0xeed49f],
+            "frappe" => [0xbabbf1, 0xc6d0f5, 0xa5adce, 0xe5c890],
+            "latte" => [0x7287fd, 0x4c4f69, 0x6c6f85, 0xdf8e1d],
+            _ => [0xb4befe, 0xcdd6f4, 0xa6adc8, 0xf9e2af],
+        };
+        Self {
+            border: Color::from_u32(border),
+            text: Color::from_u32(text),
+            description: Color::from_u32(description),
+            selected: Color::from_u32(selected),
+        }
+    }
+}